@@ -0,0 +1,36 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! Regenerate `eunomia-bpf.h` from the exported functions, if feature `generate-header` is enabled
+//!
+//! The header is checked in, so regular builds won't touch the source tree
+
+#[cfg(feature = "generate-header")]
+fn generate_header() -> Result<(), String> {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").map_err(|e| e.to_string())?;
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+        .map_err(|e| format!("Failed to read cbindgen.toml: {e}"))?;
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .map_err(|e| format!("Failed to generate the C header: {e}"))?
+        .write_to_file(format!("{crate_dir}/eunomia-bpf.h"));
+    Ok(())
+}
+
+fn main() {
+    #[cfg(feature = "generate-header")]
+    {
+        println!("cargo:rerun-if-changed=src");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        if let Err(e) = generate_header() {
+            println!("cargo:warning={e}, eunomia-bpf.h is not updated");
+        }
+    }
+    #[cfg(not(feature = "generate-header"))]
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -1,84 +1,189 @@
+/* SPDX-License-Identifier: MIT
+ *
+ * Copyright (c) 2023, eunomia-bpf
+ * All rights reserved.
+ *
+ * Generated by cbindgen from bpf-loader-c-wrapper. Do not edit.
+ */
+
 #ifndef EUNOMIA_C_H_
 #define EUNOMIA_C_H_
 
 #include <stddef.h>
-
-#ifdef __cplusplus
-extern "C" {
-#endif
+#include <stdint.h>
 enum export_format_type {
     EXPORT_PLAIN_TEXT,
     EXPORT_JSON,
     EXPORT_RAW_EVENT,
 };
-struct eunomia_bpf;
-struct eunomia_polling_handle;
+
+/// Major version of the C ABI. Bumped on incompatible changes
+#define EUNOMIA_ABI_VERSION_MAJOR 1
+
+/// Minor version of the C ABI. Bumped when functions are added
+#define EUNOMIA_ABI_VERSION_MINOR 0
+
+/// A wrapper aroung PollingHandle. Opaque to C
+typedef struct eunomia_polling_handle eunomia_polling_handle;
+
+/// A wrapper around skeletons. Opaque to C
+typedef struct eunomia_bpf eunomia_bpf;
+
+/// The exporter of one map, used by `wait_and_poll_events_to_multiple_handlers`
+typedef struct eunomia_map_exporter {
+  /// Name of the map. NULL to apply to the maps not listed
+  const char *map_name;
+  /// One of `enum export_format_type`
+  int format;
+  /// The handler: (ctx, data, size). NULL to ignore the data of the map
+  void (*handler)(void*, const char*, size_t);
+  /// Will be passed to the handler
+  void *ctx;
+} eunomia_map_exporter;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
 /// create a new eunomia bpf program from a json file
-struct eunomia_bpf* open_eunomia_skel_from_json(const char* json_data,
-                                                const char* bpf_object_buffer,
+struct eunomia_bpf *open_eunomia_skel_from_json(const char *json_data,
+                                                const char *bpf_object_buffer,
                                                 size_t object_size,
-                                                char* btf_archive_path);
+                                                char *btf_archive_path);
+
 /// create a new eunomia bpf program from a json file
-struct eunomia_bpf* open_eunomia_skel_from_json_package(const char* json_data);
+struct eunomia_bpf *open_eunomia_skel_from_json_package(const char *json_data);
+
 /// create a new eunomia bpf program from a json with btf archive
-struct eunomia_bpf* open_eunomia_skel_from_json_package_with_btf(
-    const char* json_data,
-    char* btf_archive_path);
+struct eunomia_bpf *open_eunomia_skel_from_json_package_with_btf(const char *json_data,
+                                                                 char *btf_archive_path);
+
+/// create a new eunomia bpf program from a json with runner config (in json) and btf archive
+/// The runner config overrides the config file and env vars, it could be null
+struct eunomia_bpf *open_eunomia_skel_from_json_package_with_config(const char *json_data,
+                                                                    const char *config_json,
+                                                                    const char *btf_archive_path);
+
 /// create a new eunomia bpf program from a json with args
-struct eunomia_bpf* open_eunomia_skel_from_json_package_with_args(
-    const char* json_data,
-    char** args,
-    int argc,
-    char* btf_archive_path);
+struct eunomia_bpf *open_eunomia_skel_from_json_package_with_args(const char *json_data,
+                                                                  char **args,
+                                                                  int argc,
+                                                                  char *btf_archive_path);
+
 /// @brief start running the ebpf program
 /// @details load and attach the ebpf program to the kernel to run the ebpf
 /// program if the ebpf program has maps to export to user space, you need to
 /// call the wait and export.
-int load_and_attach_eunomia_skel(struct eunomia_bpf* prog);
+int load_and_attach_eunomia_skel(struct eunomia_bpf *prog);
 
 /// @brief wait for the program to exit and receive data from export maps and
 /// send to handlers
 /// @details if the program has a ring buffer or perf event to export data
 /// to user space, the program will help load the map info and poll the
 /// events automatically.
-int wait_and_poll_events_to_handler(struct eunomia_bpf* prog,
-                                    enum export_format_type type,
-                                    void (*handler)(void*,
-                                                    const char*,
-                                                    size_t size),
-                                    void* ctx);
+int wait_and_poll_events_to_handler(struct eunomia_bpf *prog,
+                                    enum export_format_type ty,
+                                    void (*handler)(void*, const char*, size_t),
+                                    void *ctx);
+
+/// @brief wait for the program to exit, and send data of each export map to its own handler
+/// @details `exporters` is an array of `count` elements. An element with a NULL
+/// `map_name` applies to maps not listed. Maps without an exporter, or with a
+/// NULL handler, are polled but not exported. Use `[trace_pipe]` as the map
+/// name to receive bpf_printk output. Only works on programs with multiple
+/// export types enabled.
+int wait_and_poll_events_to_multiple_handlers(struct eunomia_bpf *prog,
+                                              const struct eunomia_map_exporter *exporters,
+                                              size_t count);
 
 /// @brief stop, detach, and free the memory
 /// @warning this function will free the memory of the program
 /// it's not reenter-able, and you should not use the program after this
 /// function.
-void destroy_eunomia_skel(struct eunomia_bpf* prog);
+void destroy_eunomia_skel(struct eunomia_bpf *prog);
 
 /// @brief get fd of ebpf program or map by name
-int get_bpf_fd(struct eunomia_bpf* prog, const char* name);
+int get_bpf_fd(struct eunomia_bpf *prog, const char *name);
 
 /// @brief stop, detach, but not clean the memory
-void stop_ebpf_program(struct eunomia_bpf* prog);
+void stop_ebpf_program(struct eunomia_bpf *prog);
+
 /// @brief free the memory of the program
-void free_bpf_skel(struct eunomia_bpf* prog);
+void free_bpf_skel(struct eunomia_bpf *prog);
+
 /// @brief merge json config and args and return the new config
-int parse_args_to_json_config(const char* json_config,
-                              char** args,
+int parse_args_to_json_config(const char *json_data,
+                              char **args,
                               int argc,
-                              char* out_buffer,
+                              char *out_buffer,
                               size_t out_buffer_size);
+
 /// @brief create a polling handle from a ready-to-poll eunomia
-eunomia_polling_handle* handle_create(struct eunomia_bpf* prog);
+struct eunomia_polling_handle *handle_create(struct eunomia_bpf *prog);
+
 /// @brief pause or resume the poller
-void handle_set_pause_state(eunomia_polling_handle* handle, uint8_t pause);
+void handle_set_pause_state(struct eunomia_polling_handle *handle, uint8_t state);
+
 /// @brief Terminate the poller
-void handle_terminate(eunomia_polling_handle* handle);
+void handle_terminate(struct eunomia_polling_handle *handle);
+
 /// @brief Destroy the handler
-void handle_destroy(eunomia_polling_handle* handle);
-/// @brief Get the error message
-void get_error_message(char* str_out, size_t buf_size);
+void handle_destroy(struct eunomia_polling_handle *handle);
+
+/// @brief Get the error message of the last failed call on this thread
+/// @deprecated use `eunomia_last_error`, which doesn't truncate
+void get_error_message(char *str_out, size_t buf_size);
+
+/// @brief Get the error message of the last failed call on this thread
+/// @details The string is owned by the library, and stays valid until the next
+/// failed call on the same thread
+const char *eunomia_last_error(void);
+
+/// @brief Get the version of the C ABI that the library implements
+/// @details `(EUNOMIA_ABI_VERSION_MAJOR << 16) | EUNOMIA_ABI_VERSION_MINOR`.
+/// Compare the major version with the header's before using the library
+uint32_t eunomia_abi_version(void);
+
+/// @brief get the value of a global variable (in .rodata or .bss) as json
+/// @return 0 on success, -1 on failure (including a too small buffer)
+int get_global_variable(struct eunomia_bpf *prog,
+                        const char *name,
+                        char *out_buffer,
+                        size_t out_buffer_size);
+
+/// @brief set the value of a global variable in .bss, with the value in json
+/// @details variables in .rodata are frozen after loading, so they can't be set
+/// @return 0 on success, -1 on failure
+int set_global_variable(struct eunomia_bpf *prog, const char *name, const char *value_json);
+
+/// @brief look up a key in a map. Both the key and the value are in json
+/// @return 0 if found, 1 if the key doesn't exist, -1 on failure (including a
+/// too small buffer)
+int map_lookup_json(struct eunomia_bpf *prog,
+                    const char *map_name,
+                    const char *key_json,
+                    char *out_buffer,
+                    size_t out_buffer_size);
+
+/// @brief insert or update a key in a map. Both the key and the value are in json
+/// @return 0 on success, -1 on failure
+int map_update_json(struct eunomia_bpf *prog,
+                    const char *map_name,
+                    const char *key_json,
+                    const char *value_json);
+
+/// @brief list programs of the loaded skeleton, as a json array of
+/// `{"name", "section", "prog_type", "fd"}`
+/// @return 0 on success, -1 on failure (including a too small buffer)
+int list_programs_json(struct eunomia_bpf *prog, char *out_buffer, size_t out_buffer_size);
+
+/// @brief list maps of the loaded skeleton, as a json array of
+/// `{"name", "map_type", "key_size", "value_size", "max_entries", "fd"}`
+/// @return 0 on success, -1 on failure (including a too small buffer)
+int list_maps_json(struct eunomia_bpf *prog, char *out_buffer, size_t out_buffer_size);
+
 #ifdef __cplusplus
-}
-#endif
+} // extern "C"
+#endif // __cplusplus
 
-#endif
+#endif /* EUNOMIA_C_H_ */
@@ -4,13 +4,13 @@
 //! All rights reserved.
 //!
 
-use anyhow::{anyhow, Result};
-use bpf_loader_lib::serde::Deserialize;
+use anyhow::{anyhow, bail, Context, Result};
+use bpf_loader_lib::{export_event::ExportFormatType, meta::RunnerConfig, serde::Deserialize};
 use std::{
     any::type_name,
-    ffi::{c_char, CStr},
+    ffi::{c_char, c_int, CStr},
 };
-pub(crate) unsafe fn convert_args(args: &[*const c_char]) -> Result<Vec<&str>> {
+pub(crate) unsafe fn convert_args(args: &[*mut c_char]) -> Result<Vec<&str>> {
     let mut ret = vec![];
     for arg in args.iter() {
         let arg = unsafe { CStr::from_ptr(*arg) }.to_str()?;
@@ -44,3 +44,44 @@ pub(crate) fn load_null_ptr_to_option_string(s: *const c_char) -> Result<Option<
         )
     })
 }
+
+/// Load the runner config from the config file and env vars. `config_json`, if not null, is the highest layer
+pub(crate) fn load_runner_config(config_json: *const c_char) -> Result<RunnerConfig> {
+    let top_layer = if config_json.is_null() {
+        RunnerConfig::default()
+    } else {
+        load_object::<RunnerConfig>(config_json)?
+    };
+    RunnerConfig::load_layered(None, top_layer)
+        .with_context(|| anyhow!("Failed to load runner config"))
+}
+
+/// Convert `enum export_format_type` in the header
+pub(crate) fn convert_export_format(ty: c_int) -> Result<ExportFormatType> {
+    Ok(match ty {
+        0 => ExportFormatType::PlainText,
+        1 => ExportFormatType::Json,
+        2 => ExportFormatType::RawEvent,
+        s => bail!("Invalid export format type: {}", s),
+    })
+}
+
+/// Copy the string with a trailing zero into the buffer. Fails if the buffer is too small, instead of truncating
+pub(crate) fn write_to_buffer(
+    s: &str,
+    out_buffer: *mut c_char,
+    out_buffer_size: usize,
+) -> Result<()> {
+    let bytes = s.as_bytes();
+    if bytes.len() + 1 > out_buffer_size {
+        bail!(
+            "Buffer is too small: {} bytes needed, but only {} provided",
+            bytes.len() + 1,
+            out_buffer_size
+        );
+    }
+    let out = unsafe { std::slice::from_raw_parts_mut(out_buffer as *mut u8, out_buffer_size) };
+    out[..bytes.len()].copy_from_slice(bytes);
+    out[bytes.len()] = 0;
+    Ok(())
+}
@@ -9,28 +9,42 @@
 
 use std::{
     cell::RefCell,
-    ffi::{c_char, c_int, c_void, CStr},
+    collections::HashMap,
+    ffi::{c_char, c_int, c_void, CStr, CString},
     fmt::Display,
-    ptr::null,
+    ptr::{null, null_mut},
     slice,
     sync::Arc,
 };
 
 use bpf_loader_lib::{
-    export_event::{EventHandler, ExportFormatType},
+    export_event::EventHandler,
     meta::{arg_parser::UnpresentVariableAction, ComposedObject, EunomiaObjectMeta},
     skeleton::builder::BpfSkeletonBuilder,
 };
-use helper::{convert_args, load_null_ptr_to_option_string, load_object};
-use wrapper::{HandleWrapper, SkeletonWrapper};
+use helper::{
+    convert_args, convert_export_format, load_null_ptr_to_option_string, load_object,
+    load_runner_config, write_to_buffer,
+};
+use serde_json::Value;
+use wrapper::{CallbackEventHandler, ExportFormat, HandleWrapper, MapExporter, SkeletonWrapper};
 
 mod helper;
 mod wrapper;
+
+// Functions that were in the hand-written header keep its parameter types, so the old callers still compile
+
+/// Major version of the C ABI. Bumped on incompatible changes
+pub const EUNOMIA_ABI_VERSION_MAJOR: u32 = 1;
+/// Minor version of the C ABI. Bumped when functions are added
+pub const EUNOMIA_ABI_VERSION_MINOR: u32 = 0;
+
 thread_local! {
-    static ERROR_MESSAGE: RefCell<String> = RefCell::new(String::default());
+    static ERROR_MESSAGE: RefCell<CString> = RefCell::new(CString::default());
 }
 fn set_error_message(t: impl Display) {
-    ERROR_MESSAGE.with(|v| v.replace(t.to_string()));
+    let msg = t.to_string().replace('\0', " ");
+    ERROR_MESSAGE.with(|v| v.replace(CString::new(msg).unwrap_or_default()));
 }
 
 #[macro_export]
@@ -51,9 +65,9 @@ macro_rules! my_bail {
 /// create a new eunomia bpf program from a json file
 pub extern "C" fn open_eunomia_skel_from_json(
     json_data: *const c_char,
-    bpf_object_buffer: *const c_void,
+    bpf_object_buffer: *const c_char,
     object_size: usize,
-    btf_archive_path: *const c_char,
+    btf_archive_path: *mut c_char,
 ) -> *mut SkeletonWrapper {
     let object_buffer =
         unsafe { std::slice::from_raw_parts(bpf_object_buffer as *const u8, object_size) };
@@ -65,11 +79,16 @@ pub extern "C" fn open_eunomia_skel_from_json(
         Err(e) => my_bail!(e),
         Ok(v) => v,
     };
+    let runner_config = match load_runner_config(null()) {
+        Err(e) => my_bail!(e),
+        Ok(v) => v,
+    };
     let skel = match BpfSkeletonBuilder::from_object_meta_and_object_buffer(
         &meta,
         object_buffer,
         btf_archive_path,
     )
+    .set_runner_config(runner_config)
     .build()
     {
         Err(e) => my_bail!(format!("Failed to build: {}", e)),
@@ -84,13 +103,24 @@ pub extern "C" fn open_eunomia_skel_from_json(
 pub extern "C" fn open_eunomia_skel_from_json_package(
     json_data: *const c_char,
 ) -> *mut SkeletonWrapper {
-    open_eunomia_skel_from_json_package_with_btf(json_data, null())
+    open_eunomia_skel_from_json_package_with_btf(json_data, null_mut())
 }
 
 #[no_mangle]
 /// create a new eunomia bpf program from a json with btf archive
 pub extern "C" fn open_eunomia_skel_from_json_package_with_btf(
     json_data: *const c_char,
+    btf_archive_path: *mut c_char,
+) -> *mut SkeletonWrapper {
+    open_eunomia_skel_from_json_package_with_config(json_data, null(), btf_archive_path)
+}
+
+#[no_mangle]
+/// create a new eunomia bpf program from a json with runner config (in json) and btf archive
+/// The runner config overrides the config file and env vars, it could be null
+pub extern "C" fn open_eunomia_skel_from_json_package_with_config(
+    json_data: *const c_char,
+    config_json: *const c_char,
     btf_archive_path: *const c_char,
 ) -> *mut SkeletonWrapper {
     let package = match load_object::<ComposedObject>(json_data) {
@@ -101,8 +131,15 @@ pub extern "C" fn open_eunomia_skel_from_json_package_with_btf(
         Ok(v) => v,
         Err(e) => my_bail!(e),
     };
+    let runner_config = match load_runner_config(config_json) {
+        Err(e) => my_bail!(e),
+        Ok(v) => v,
+    };
 
-    let skel = match BpfSkeletonBuilder::from_json_package(&package, btf_archive_path).build() {
+    let skel = match BpfSkeletonBuilder::from_json_package(&package, btf_archive_path)
+        .set_runner_config(runner_config)
+        .build()
+    {
         Err(e) => my_bail!(format!("Failed to build: {}", e)),
         Ok(s) => s,
     };
@@ -114,10 +151,10 @@ pub extern "C" fn open_eunomia_skel_from_json_package_with_btf(
 /// create a new eunomia bpf program from a json with args
 pub extern "C" fn open_eunomia_skel_from_json_package_with_args(
     json_data: *const c_char,
-    args: *const *const c_char,
+    args: *mut *mut c_char,
     argc: c_int,
-    btf_archive_path: *const c_char,
-) -> *const SkeletonWrapper {
+    btf_archive_path: *mut c_char,
+) -> *mut SkeletonWrapper {
     let args = match unsafe { convert_args(slice::from_raw_parts(args, argc as usize)) } {
         Err(e) => my_bail!(e),
         Ok(v) => v,
@@ -147,7 +184,14 @@ pub extern "C" fn open_eunomia_skel_from_json_package_with_args(
         Ok(v) => v,
         Err(e) => my_bail!(e),
     };
-    let skel = match BpfSkeletonBuilder::from_json_package(&package, btf_archive_path).build() {
+    let runner_config = match load_runner_config(null()) {
+        Err(e) => my_bail!(e),
+        Ok(v) => v,
+    };
+    let skel = match BpfSkeletonBuilder::from_json_package(&package, btf_archive_path)
+        .set_runner_config(runner_config)
+        .build()
+    {
         Err(e) => my_bail!(format!("Failed to build: {}", e)),
         Ok(s) => s,
     };
@@ -176,6 +220,8 @@ pub extern "C" fn load_and_attach_eunomia_skel(prog: *mut SkeletonWrapper) -> c_
 }
 
 #[no_mangle]
+// The handler holds C pointers, and it's only called on the polling thread
+#[allow(clippy::arc_with_non_send_sync)]
 /// @brief wait for the program to exit and receive data from export maps and
 /// send to handlers
 /// @details if the program has a ring buffer or perf event to export data
@@ -183,37 +229,21 @@ pub extern "C" fn load_and_attach_eunomia_skel(prog: *mut SkeletonWrapper) -> c_
 /// events automatically.
 pub extern "C" fn wait_and_poll_events_to_handler(
     prog: *mut SkeletonWrapper,
-    ty: c_int,
-    handler: extern "C" fn(*const c_void, *const c_char, usize),
-    ctx: *const c_void,
+    ty: ExportFormat,
+    handler: extern "C" fn(*mut c_void, *const c_char, usize),
+    ctx: *mut c_void,
 ) -> c_int {
-    let ty = match ty {
-        0 => ExportFormatType::PlainText,
-        1 => ExportFormatType::Json,
-        2 => ExportFormatType::RawEvent,
-        s => my_bail_custom!(format!("Invalid export format type: {}", s), -1),
+    let ty = match convert_export_format(ty) {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(e, -1),
     };
     let prog = match unsafe { &*prog } {
         SkeletonWrapper::Loaded(prog) => prog,
         _ => my_bail_custom!("Expected a loaded skeleton", -1),
     };
-    struct LocalEventHandler {
-        callback: extern "C" fn(*const c_void, *const c_char, usize),
-        ctx: *const c_void,
-    }
-    impl EventHandler for LocalEventHandler {
-        fn handle_event(
-            &self,
-            _context: Option<std::sync::Arc<dyn std::any::Any>>,
-            data: bpf_loader_lib::export_event::ReceivedEventData,
-        ) {
-            let bytes = data.trivally_to_plain_bytes();
-            (self.callback)(self.ctx, bytes.as_ptr() as *const c_char, bytes.len());
-        }
-    }
     if let Err(e) = prog.wait_and_poll_to_handler(
         ty,
-        Some(Arc::new(LocalEventHandler {
+        Some(Arc::new(CallbackEventHandler {
             callback: handler,
             ctx,
         })),
@@ -224,6 +254,65 @@ pub extern "C" fn wait_and_poll_events_to_handler(
     0
 }
 
+#[no_mangle]
+#[allow(clippy::arc_with_non_send_sync)]
+/// @brief wait for the program to exit, and send data of each export map to its own handler
+/// @details `exporters` is an array of `count` elements. An element with a NULL
+/// `map_name` applies to maps not listed. Maps without an exporter, or with a
+/// NULL handler, are polled but not exported. Use `[trace_pipe]` as the map
+/// name to receive bpf_printk output. Only works on programs with multiple
+/// export types enabled.
+pub extern "C" fn wait_and_poll_events_to_multiple_handlers(
+    prog: *mut SkeletonWrapper,
+    exporters: *const MapExporter,
+    count: usize,
+) -> c_int {
+    let prog = match unsafe { &*prog } {
+        SkeletonWrapper::Loaded(prog) => prog,
+        _ => my_bail_custom!("Expected a loaded skeleton", -1),
+    };
+    let exporters = if count == 0 {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(exporters, count) }
+    };
+    let mut default = None;
+    let mut by_name = HashMap::new();
+    for exporter in exporters.iter() {
+        let format = match convert_export_format(exporter.format) {
+            Ok(v) => v,
+            Err(e) => my_bail_custom!(e, -1),
+        };
+        let handler = exporter.handler.map(|callback| {
+            (
+                format,
+                Arc::new(CallbackEventHandler {
+                    callback,
+                    ctx: exporter.ctx,
+                }) as Arc<dyn EventHandler>,
+            )
+        });
+        match load_null_ptr_to_option_string(exporter.map_name) {
+            Ok(Some(name)) => {
+                by_name.insert(name, handler);
+            }
+            Ok(None) => default = Some(handler),
+            Err(e) => my_bail_custom!(e, -1),
+        }
+    }
+    if let Err(e) = prog.wait_and_poll_to_handler_with_multiple_exporter(|name| {
+        by_name
+            .get(name)
+            .or(default.as_ref())
+            .cloned()
+            .flatten()
+            .map(|(format, handler)| (format, handler, None))
+    }) {
+        my_bail_custom!(e, -1);
+    }
+    0
+}
+
 #[no_mangle]
 /// @brief stop, detach, and free the memory
 /// @warning this function will free the memory of the program
@@ -265,7 +354,7 @@ pub extern "C" fn free_bpf_skel(prog: *mut SkeletonWrapper) {
 /// @brief merge json config and args and return the new config
 pub extern "C" fn parse_args_to_json_config(
     json_data: *const c_char,
-    args: *const *const c_char,
+    args: *mut *mut c_char,
     argc: c_int,
     out_buffer: *mut c_char,
     out_buffer_size: usize,
@@ -341,7 +430,8 @@ pub extern "C" fn handle_destroy(handle: *mut HandleWrapper) {
 }
 
 #[no_mangle]
-/// @brief Get the error message
+/// @brief Get the error message of the last failed call on this thread
+/// @deprecated use `eunomia_last_error`, which doesn't truncate
 pub extern "C" fn get_error_message(str_out: *mut c_char, buf_size: usize) {
     ERROR_MESSAGE.with(|v| {
         let borrow_ref = v.borrow();
@@ -355,3 +445,177 @@ pub extern "C" fn get_error_message(str_out: *mut c_char, buf_size: usize) {
         out_bytes[i] = 0;
     });
 }
+
+#[no_mangle]
+/// @brief Get the error message of the last failed call on this thread
+/// @details The string is owned by the library, and stays valid until the next
+/// failed call on the same thread
+pub extern "C" fn eunomia_last_error() -> *const c_char {
+    ERROR_MESSAGE.with(|v| v.borrow().as_ptr())
+}
+
+#[no_mangle]
+/// @brief Get the version of the C ABI that the library implements
+/// @details `(EUNOMIA_ABI_VERSION_MAJOR << 16) | EUNOMIA_ABI_VERSION_MINOR`.
+/// Compare the major version with the header's before using the library
+pub extern "C" fn eunomia_abi_version() -> u32 {
+    (EUNOMIA_ABI_VERSION_MAJOR << 16) | EUNOMIA_ABI_VERSION_MINOR
+}
+
+#[no_mangle]
+/// @brief get the value of a global variable (in .rodata or .bss) as json
+/// @return 0 on success, -1 on failure (including a too small buffer)
+pub extern "C" fn get_global_variable(
+    prog: *mut SkeletonWrapper,
+    name: *const c_char,
+    out_buffer: *mut c_char,
+    out_buffer_size: usize,
+) -> c_int {
+    let prog = match unsafe { &*prog } {
+        SkeletonWrapper::Loaded(prog) => prog,
+        _ => my_bail_custom!("Expected a loaded skeleton", -1),
+    };
+    let name = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(format!("Invalid name bytes: {}", e), -1),
+    };
+    let value = match prog.get_variable(name) {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(format!("{:#}", e), -1),
+    };
+    if let Err(e) = write_to_buffer(&value.to_string(), out_buffer, out_buffer_size) {
+        my_bail_custom!(e, -1);
+    }
+    0
+}
+
+#[no_mangle]
+/// @brief set the value of a global variable in .bss, with the value in json
+/// @details variables in .rodata are frozen after loading, so they can't be set
+/// @return 0 on success, -1 on failure
+pub extern "C" fn set_global_variable(
+    prog: *mut SkeletonWrapper,
+    name: *const c_char,
+    value_json: *const c_char,
+) -> c_int {
+    let prog = match unsafe { &*prog } {
+        SkeletonWrapper::Loaded(prog) => prog,
+        _ => my_bail_custom!("Expected a loaded skeleton", -1),
+    };
+    let name = match unsafe { CStr::from_ptr(name) }.to_str() {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(format!("Invalid name bytes: {}", e), -1),
+    };
+    let value = match load_object::<Value>(value_json) {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(e, -1),
+    };
+    if let Err(e) = prog.set_variable(name, &value) {
+        my_bail_custom!(format!("{:#}", e), -1);
+    }
+    0
+}
+
+#[no_mangle]
+/// @brief look up a key in a map. Both the key and the value are in json
+/// @return 0 if found, 1 if the key doesn't exist, -1 on failure (including a
+/// too small buffer)
+pub extern "C" fn map_lookup_json(
+    prog: *mut SkeletonWrapper,
+    map_name: *const c_char,
+    key_json: *const c_char,
+    out_buffer: *mut c_char,
+    out_buffer_size: usize,
+) -> c_int {
+    let prog = match unsafe { &*prog } {
+        SkeletonWrapper::Loaded(prog) => prog,
+        _ => my_bail_custom!("Expected a loaded skeleton", -1),
+    };
+    let map_name = match unsafe { CStr::from_ptr(map_name) }.to_str() {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(format!("Invalid name bytes: {}", e), -1),
+    };
+    let key = match load_object::<Value>(key_json) {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(e, -1),
+    };
+    let value = match prog.lookup_map(map_name, &key) {
+        Ok(Some(v)) => v,
+        Ok(None) => return 1,
+        Err(e) => my_bail_custom!(format!("{:#}", e), -1),
+    };
+    if let Err(e) = write_to_buffer(&value.to_string(), out_buffer, out_buffer_size) {
+        my_bail_custom!(e, -1);
+    }
+    0
+}
+
+#[no_mangle]
+/// @brief insert or update a key in a map. Both the key and the value are in json
+/// @return 0 on success, -1 on failure
+pub extern "C" fn map_update_json(
+    prog: *mut SkeletonWrapper,
+    map_name: *const c_char,
+    key_json: *const c_char,
+    value_json: *const c_char,
+) -> c_int {
+    let prog = match unsafe { &*prog } {
+        SkeletonWrapper::Loaded(prog) => prog,
+        _ => my_bail_custom!("Expected a loaded skeleton", -1),
+    };
+    let map_name = match unsafe { CStr::from_ptr(map_name) }.to_str() {
+        Ok(v) => v,
+        Err(e) => my_bail_custom!(format!("Invalid name bytes: {}", e), -1),
+    };
+    let (key, value) = match (
+        load_object::<Value>(key_json),
+        load_object::<Value>(value_json),
+    ) {
+        (Ok(k), Ok(v)) => (k, v),
+        (Err(e), _) | (_, Err(e)) => my_bail_custom!(e, -1),
+    };
+    if let Err(e) = prog.update_map(map_name, &key, &value) {
+        my_bail_custom!(format!("{:#}", e), -1);
+    }
+    0
+}
+
+#[no_mangle]
+/// @brief list programs of the loaded skeleton, as a json array of
+/// `{"name", "section", "prog_type", "fd"}`
+/// @return 0 on success, -1 on failure (including a too small buffer)
+pub extern "C" fn list_programs_json(
+    prog: *mut SkeletonWrapper,
+    out_buffer: *mut c_char,
+    out_buffer_size: usize,
+) -> c_int {
+    let prog = match unsafe { &*prog } {
+        SkeletonWrapper::Loaded(prog) => prog,
+        _ => my_bail_custom!("Expected a loaded skeleton", -1),
+    };
+    let json = serde_json::to_string(&prog.list_programs()).unwrap();
+    if let Err(e) = write_to_buffer(&json, out_buffer, out_buffer_size) {
+        my_bail_custom!(e, -1);
+    }
+    0
+}
+
+#[no_mangle]
+/// @brief list maps of the loaded skeleton, as a json array of
+/// `{"name", "map_type", "key_size", "value_size", "max_entries", "fd"}`
+/// @return 0 on success, -1 on failure (including a too small buffer)
+pub extern "C" fn list_maps_json(
+    prog: *mut SkeletonWrapper,
+    out_buffer: *mut c_char,
+    out_buffer_size: usize,
+) -> c_int {
+    let prog = match unsafe { &*prog } {
+        SkeletonWrapper::Loaded(prog) => prog,
+        _ => my_bail_custom!("Expected a loaded skeleton", -1),
+    };
+    let json = serde_json::to_string(&prog.list_maps()).unwrap();
+    if let Err(e) = write_to_buffer(&json, out_buffer, out_buffer_size) {
+        my_bail_custom!(e, -1);
+    }
+    0
+}
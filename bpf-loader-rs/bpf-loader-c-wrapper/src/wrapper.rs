@@ -4,10 +4,14 @@
 //! All rights reserved.
 //!
 
-use bpf_loader_lib::skeleton::{handle::PollingHandle, preload::PreLoadBpfSkeleton, BpfSkeleton};
+use std::ffi::{c_char, c_int, c_void};
 
-#[repr(C)]
-/// A wrapper around skeletons
+use bpf_loader_lib::{
+    export_event::{EventHandler, ReceivedEventData},
+    skeleton::{handle::PollingHandle, preload::PreLoadBpfSkeleton, BpfSkeleton},
+};
+
+/// A wrapper around skeletons. Opaque to C
 pub enum SkeletonWrapper {
     /// The preloaded
     PreLoad(PreLoadBpfSkeleton),
@@ -16,8 +20,43 @@ pub enum SkeletonWrapper {
     /// None, used for conversions from preload to load
     None,
 }
-#[repr(C)]
-/// A wrapper aroung PollingHandle
+/// A wrapper aroung PollingHandle. Opaque to C
 pub struct HandleWrapper {
     pub(crate) handle: PollingHandle,
 }
+
+/// The callback that receives exported data: (ctx, data, size)
+pub type EventCallback = extern "C" fn(*mut c_void, *const c_char, usize);
+
+/// `enum export_format_type` in the header. Kept as an int, so invalid values from C are rejected instead of being UB
+pub type ExportFormat = c_int;
+
+#[repr(C)]
+/// The exporter of one map, used by `wait_and_poll_events_to_multiple_handlers`
+pub struct MapExporter {
+    /// Name of the map. NULL to apply to the maps not listed
+    pub map_name: *const c_char,
+    /// One of `enum export_format_type`
+    pub format: c_int,
+    /// The handler: (ctx, data, size). NULL to ignore the data of the map
+    pub handler: Option<extern "C" fn(*mut c_void, *const c_char, usize)>,
+    /// Will be passed to the handler
+    pub ctx: *mut c_void,
+}
+
+/// Forwards events to a C callback
+pub(crate) struct CallbackEventHandler {
+    pub(crate) callback: EventCallback,
+    pub(crate) ctx: *mut c_void,
+}
+
+impl EventHandler for CallbackEventHandler {
+    fn handle_event(
+        &self,
+        _context: Option<std::sync::Arc<dyn std::any::Any>>,
+        data: ReceivedEventData,
+    ) {
+        let bytes = data.trivally_to_plain_bytes();
+        (self.callback)(self.ctx, bytes.as_ptr() as *const c_char, bytes.len());
+    }
+}
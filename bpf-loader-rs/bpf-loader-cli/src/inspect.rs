@@ -0,0 +1,131 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{fmt::Write, io::Write as _};
+
+use anyhow::{anyhow, Context, Result};
+use bpf_loader_lib::{
+    inspect::{PackageInspection, StructLayout},
+    meta::{ComposedObject, MapExportConfig},
+};
+
+/// Decode the package and print what's inside. Nothing will be loaded, so root is not required
+pub(crate) fn run_inspect(package_path: &str, json: bool) -> Result<()> {
+    let package = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(package_path)
+            .with_context(|| anyhow!("Failed to read package {}", package_path))?,
+    )
+    .with_context(|| anyhow!("Failed to parse json into ComposedObject"))?;
+    let result = package
+        .inspect()
+        .with_context(|| anyhow!("Failed to inspect the package"))?;
+    let text = if json {
+        serde_json::to_string_pretty(&result)? + "\n"
+    } else {
+        format_human(&result)?
+    };
+    std::io::stdout().lock().write_all(text.as_bytes())?;
+    Ok(())
+}
+
+fn format_layout(out: &mut String, layout: &StructLayout, indent: &str) -> std::fmt::Result {
+    writeln!(out, "{indent}{} (size {})", layout.name, layout.size)?;
+    for member in layout.members.iter() {
+        writeln!(
+            out,
+            "{indent}  +{:<4} {:<24} {} (size {})",
+            member.offset, member.name, member.ty, member.size
+        )?;
+    }
+    Ok(())
+}
+
+fn format_human(result: &PackageInspection) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "Object: {}", result.obj_name)?;
+    if let Some(doc) = &result.doc {
+        for (key, value) in [
+            ("Version", &doc.version),
+            ("Brief", &doc.brief),
+            ("Description", &doc.description),
+            ("Details", &doc.details),
+        ] {
+            if let Some(value) = value {
+                writeln!(out, "{key}: {value}")?;
+            }
+        }
+    }
+    writeln!(out, "\nPrograms:")?;
+    for prog in result.programs.iter() {
+        write!(
+            out,
+            "  {:<24} {:<16} {}",
+            prog.name, prog.prog_type, prog.attach
+        )?;
+        if !prog.link {
+            write!(out, " (no link)")?;
+        }
+        if prog.extra.as_object().is_some_and(|v| !v.is_empty()) {
+            write!(out, " {}", prog.extra)?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "\nMaps:")?;
+    for map in result.maps.iter() {
+        writeln!(
+            out,
+            "  {:<24} {:<16} key {} value {} max_entries {}{}",
+            map.name,
+            map.map_type,
+            map.key_size,
+            map.value_size,
+            map.max_entries,
+            if map.mmaped { " mmaped" } else { "" }
+        )?;
+        let export = match &map.export_config {
+            MapExportConfig::NoExport => None,
+            MapExportConfig::ExportUseBtf(id) => Some(format!("btf type {id}")),
+            MapExportConfig::ExportUseCustomMembers(_) => Some("custom members".to_string()),
+            MapExportConfig::Default => Some("default".to_string()),
+        };
+        if let Some(export) = export {
+            writeln!(out, "    export: {export}")?;
+        }
+        if let Some(sample) = &map.sample {
+            writeln!(
+                out,
+                "    sample: {:?} every {}ms, unit {}{}",
+                sample.ty,
+                sample.interval,
+                sample.unit,
+                if sample.clear_map { ", clear map" } else { "" }
+            )?;
+        }
+        if let Some(layout) = &map.export_layout {
+            format_layout(&mut out, layout, "    ")?;
+        }
+    }
+    writeln!(out, "\nGlobal variables:")?;
+    for var in result.variables.iter() {
+        write!(out, "  {:<10} {:<24} {}", var.section, var.name, var.ty)?;
+        if let Some(default) = &var.default {
+            write!(out, " = {default}")?;
+        }
+        if let Some(desc) = &var.description {
+            write!(out, "  // {desc}")?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "\nExport types:")?;
+    for layout in result.export_types.iter() {
+        format_layout(&mut out, layout, "  ")?;
+    }
+    writeln!(out, "\nELF sections:")?;
+    for section in result.sections.iter() {
+        writeln!(out, "  {:<32} {}", section.name, section.size)?;
+    }
+    Ok(out)
+}
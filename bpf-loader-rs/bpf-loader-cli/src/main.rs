@@ -4,24 +4,121 @@
 //! All rights reserved.
 //!
 
-use std::thread;
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
 
 use bpf_loader_lib::{
-    clap::{Arg, ArgAction, Command},
+    clap::{value_parser, Arg, ArgAction, Command},
     export_event::ExportFormatType,
-    meta::{arg_parser::UnpresentVariableAction, ComposedObject, EunomiaObjectMeta},
-    skeleton::builder::BpfSkeletonBuilder,
+    meta::{arg_parser::UnpresentVariableAction, ComposedObject, EunomiaObjectMeta, RunnerConfig},
+    signature::PackageSignature,
+    skeleton::{builder::BpfSkeletonBuilder, verifier::VerifierError},
 };
 
 use anyhow::{anyhow, bail, Context, Result};
-use log::info;
+use log::{error, info};
+use output::{CliEventHandler, OutputSink, RotatingFile};
 use serde_json::Value;
 use signal_hook::{
     consts::{SIGINT, SIGTSTP},
     iterator::Signals,
 };
-fn main() -> Result<()> {
+
+mod inspect;
+mod output;
+mod probe;
+mod validate;
+
+/// Stopped by a signal, `--duration` or `--max-events`
+const EXIT_SUCCESS: i32 = 0;
+/// Failed to load, attach, poll, or write the output
+const EXIT_FAILURE: i32 = 1;
+/// `--fail-if-no-events` is set, but no events were received
+const EXIT_NO_EVENTS: i32 = 3;
+/// `validate` found errors in the package
+const EXIT_INVALID_PACKAGE: i32 = 4;
+
+const EXIT_STATUS_HELP: &str = "Exit status:
+  0  Stopped by SIGINT, --duration or --max-events
+  1  Failed to load, attach, poll, or write the output
+  2  Invalid arguments
+  3  No events received, and --fail-if-no-events is set
+  4  `validate` found errors in the package";
+
+fn main() {
+    let code = match run() {
+        Ok(code) => code,
+        Err(e) => {
+            if let Some(verifier_error) = e.downcast_ref::<VerifierError>() {
+                eprintln!("{}", verifier_error.report());
+            }
+            eprintln!("Error: {e:?}");
+            EXIT_FAILURE
+        }
+    };
+    std::process::exit(code);
+}
+
+fn run() -> Result<i32> {
     let matches = Command::new(env!("CARGO_PKG_NAME"))
+        .after_help(EXIT_STATUS_HELP)
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(
+            Command::new("inspect")
+                .about("Show what's inside a package without loading it. Root is not required")
+                .arg(
+                    Arg::new("package")
+                        .help("The package json file")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the result in json")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check the package without loading it, and report problems that would fail loading. Root is not required")
+                .arg(
+                    Arg::new("package")
+                        .help("The package json file")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the diagnostics in json")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("probe")
+                .about("Probe bpf features of the running kernel, which packages may need")
+                .arg(
+                    Arg::new("helper")
+                        .long("helper")
+                        .help("Also probe this helper, for kprobe programs")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the result in json")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
         .arg(
             Arg::new("json_skeleton")
                 .action(ArgAction::Set)
@@ -49,12 +146,205 @@ fn main() -> Result<()> {
                 .help("Disable logs")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("print-kernel-debug")
+                .long("print-kernel-debug")
+                .help("Print the output of bpf_printk, read from trace_pipe")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-print-kernel-debug")
+                .long("no-print-kernel-debug")
+                .help("Don't print the output of bpf_printk, even if the config enables it")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("print-kernel-debug"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .short('c')
+                .help(
+                    "The runner config file (json). Env `EUNOMIA_CONFIG` will be used if not provided",
+                )
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .help("The output format. `raw` prints the raw events in hex")
+                .value_parser(["plain", "json", "raw"])
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("poll-timeout")
+                .long("poll-timeout")
+                .help("Poll timeout in milliseconds")
+                .value_parser(value_parser!(i32))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("verifier-log-level")
+                .long("verifier-log-level")
+                .help("Log level of the verifier. The log is always captured if a program is rejected, 1 or 2 also prints logs of the accepted ones at debug level")
+                .value_parser(value_parser!(u32))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("perf-buffer-pages")
+                .long("perf-buffer-pages")
+                .help("Pages of each perf buffer, must be a power of 2")
+                .value_parser(value_parser!(usize))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("btf")
+                .long("btf")
+                .help("Path to the BTF file of the running kernel")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("kernel-types")
+                .long("kernel-types")
+                .help("Load the kernel BTF to resolve kernel types in the exported events")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("kernel-btf-module")
+                .long("kernel-btf-module")
+                .help("Also load the BTF of this kernel module. Implies `--kernel-types`")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("log-level")
+                .long("log-level")
+                .help("Log level, e.g info, debug")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("trust-store")
+                .long("trust-store")
+                .help("A PEM public key, or a directory of them. Only packages signed by these keys will be loaded")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("signature")
+                .long("signature")
+                .help("A detached signature file, used instead of the one embedded in the package")
+                .conflicts_with("elf_file")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .short('o')
+                .help("Append the events to this file instead of stdout")
+                .value_parser(value_parser!(PathBuf))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("rotate-size")
+                .long("rotate-size")
+                .help("Rotate the output file once it's larger than this size, in bytes")
+                .value_parser(value_parser!(u64).range(1..))
+                .requires("output")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("rotate-keep")
+                .long("rotate-keep")
+                .help("How many rotated files to keep")
+                .value_parser(value_parser!(usize))
+                .default_value("5")
+                .requires("output")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("duration")
+                .long("duration")
+                .help("Exit after running for this many seconds")
+                .value_parser(value_parser!(u64).range(1..))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("max-events")
+                .long("max-events")
+                .help("Exit after receiving this many events")
+                .value_parser(value_parser!(u64).range(1..))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("fail-if-no-events")
+                .long("fail-if-no-events")
+                .help("Exit with status 3 if no events were received")
+                .action(ArgAction::SetTrue),
+        )
         .get_matches();
+    match matches.subcommand() {
+        Some(("inspect", sub)) => {
+            inspect::run_inspect(
+                sub.get_one::<String>("package").unwrap(),
+                sub.get_flag("json"),
+            )?;
+            return Ok(EXIT_SUCCESS);
+        }
+        Some(("validate", sub)) => {
+            let valid = validate::run_validate(
+                sub.get_one::<String>("package").unwrap(),
+                sub.get_flag("json"),
+            )?;
+            return Ok(if valid {
+                EXIT_SUCCESS
+            } else {
+                EXIT_INVALID_PACKAGE
+            });
+        }
+        Some(("probe", sub)) => {
+            let helpers = sub
+                .get_many::<String>("helper")
+                .map(|v| v.cloned().collect::<Vec<_>>())
+                .unwrap_or_default();
+            probe::run_probe(&helpers, sub.get_flag("json"))?;
+            return Ok(EXIT_SUCCESS);
+        }
+        _ => {}
+    }
+    let mut runner_config = RunnerConfig::load_layered(
+        matches.get_one::<String>("config").map(Path::new),
+        RunnerConfig {
+            print_kernel_debug: matches.get_flag("print-kernel-debug"),
+            poll_timeout_ms: matches.get_one::<i32>("poll-timeout").copied(),
+            verifier_log_level: matches.get_one::<u32>("verifier-log-level").copied(),
+            perf_buffer_pages: matches.get_one::<usize>("perf-buffer-pages").copied(),
+            btf_path: matches.get_one::<String>("btf").cloned(),
+            log_level: matches.get_one::<String>("log-level").cloned(),
+            trust_store: matches.get_one::<String>("trust-store").cloned(),
+            resolve_kernel_types: matches.get_flag("kernel-types").then_some(true),
+            kernel_btf_modules: matches
+                .get_many::<String>("kernel-btf-module")
+                .map(|v| v.cloned().collect())
+                .unwrap_or_default(),
+            export_format: matches
+                .get_one::<String>("format")
+                .map(|v| v.parse())
+                .transpose()?,
+            ..Default::default()
+        },
+    )
+    .with_context(|| anyhow!("Failed to load runner config"))?;
+    // The flags are the highest layer, so this overrides the config file and env vars
+    if matches.get_flag("no-print-kernel-debug") {
+        runner_config.print_kernel_debug = false;
+    }
     if !matches.get_flag("no-log") {
-        flexi_logger::Logger::try_with_env_or_str("info")?
-            .log_to_stdout()
-            .start()?;
+        flexi_logger::Logger::try_with_env_or_str(
+            runner_config.log_level.as_deref().unwrap_or("info"),
+        )?
+        .log_to_stderr()
+        .start()?;
     }
+    let export_format = runner_config
+        .export_format
+        .unwrap_or(ExportFormatType::PlainText);
     let json_skel = matches.get_one::<String>("json_skeleton").unwrap();
     let elf_file = matches.get_one::<String>("elf_file");
     let mut bpf_args = matches
@@ -62,12 +352,13 @@ fn main() -> Result<()> {
         .map(|v| v.map(|s| s.to_owned()).collect::<Vec<_>>())
         .unwrap_or_default();
     bpf_args.insert(0, "bpf-prog".into());
-    let json_content = serde_json::from_str::<Value>(
-        &std::fs::read_to_string(json_skel)
-            .with_context(|| anyhow!("Failed to read json skeleton"))?,
-    )
-    .with_context(|| anyhow!("Failed to parse json"))?;
-    let (prog_bin, mut meta) = if let Some(elf_file) = elf_file {
+    // With `--elf`, the ELF and meta are loaded separately, so signatures can't be verified
+    let (mut package, mut split) = if let Some(elf_file) = elf_file {
+        let json_content = serde_json::from_str::<Value>(
+            &std::fs::read_to_string(json_skel)
+                .with_context(|| anyhow!("Failed to read json skeleton"))?,
+        )
+        .with_context(|| anyhow!("Failed to parse json"))?;
         let elf_bin =
             std::fs::read(elf_file).with_context(|| anyhow!("Failed to read elf file"))?;
         let meta = match serde_json::from_value::<ComposedObject>(json_content.clone()) {
@@ -83,11 +374,22 @@ fn main() -> Result<()> {
             }
             Ok(v) => v.meta,
         };
-        (elf_bin, meta)
+        (None, Some((elf_bin, meta)))
     } else {
-        let data = serde_json::from_value::<ComposedObject>(json_content)
-            .with_context(|| anyhow!("Failed to parse json into ComposedObject"))?;
-        (data.bpf_object, data.meta)
+        let mut data = ComposedObject::from_reader(
+            std::fs::File::open(json_skel)
+                .with_context(|| anyhow!("Failed to read json skeleton"))?,
+        )
+        .with_context(|| anyhow!("Failed to parse json into ComposedObject"))?;
+        if let Some(path) = matches.get_one::<String>("signature") {
+            data.signature = Some(PackageSignature::from_file(path)?);
+        }
+        (Some(data), None)
+    };
+    let meta = match (package.as_mut(), split.as_mut()) {
+        (Some(package), _) => &mut package.meta,
+        (None, Some((_, meta))) => meta,
+        (None, None) => unreachable!(),
     };
 
     let bpf_parser = meta.build_argument_parser()?;
@@ -97,12 +399,45 @@ fn main() -> Result<()> {
         UnpresentVariableAction::FillWithZero,
     )?;
 
-    let skel = BpfSkeletonBuilder::from_object_meta_and_object_buffer(&meta, &prog_bin, None)
+    let builder = match (package.as_ref(), split.as_ref()) {
+        (Some(package), _) => BpfSkeletonBuilder::from_json_package(package, None),
+        (None, Some((prog_bin, meta))) => {
+            BpfSkeletonBuilder::from_object_meta_and_object_buffer(meta, prog_bin, None)
+        }
+        (None, None) => unreachable!(),
+    };
+    let skel = builder
+        .set_runner_config(runner_config)
         .build()
         .with_context(|| anyhow!("Failed to build PreLoadSkeleton"))?
         .load_and_attach()
         .with_context(|| anyhow!("Failed to load or attach the bpf skeleton"))?;
     let handle = skel.create_poll_handle();
+    let sink = match matches.get_one::<PathBuf>("output") {
+        Some(path) => OutputSink::File(RotatingFile::open(
+            path.clone(),
+            matches.get_one::<u64>("rotate-size").copied(),
+            *matches.get_one::<usize>("rotate-keep").unwrap(),
+        )?),
+        None => OutputSink::Stdout,
+    };
+    let received = Arc::new(AtomicUsize::new(0));
+    let event_handler = Arc::new(CliEventHandler {
+        sink: Mutex::new(sink),
+        received: received.clone(),
+        max_events: matches.get_one::<u64>("max-events").map(|v| *v as usize),
+        handle: handle.clone(),
+        write_failed: AtomicBool::new(false),
+    });
+    if let Some(secs) = matches.get_one::<u64>("duration").copied() {
+        let handle = handle.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_secs(secs));
+            info!("Ran for {} seconds, terminating the poller..", secs);
+            handle.set_pause(false);
+            handle.terminate();
+        });
+    }
     let mut signals = Signals::new([SIGINT, SIGTSTP])?;
     thread::spawn(move || {
         let mut paused = false;
@@ -130,7 +465,21 @@ fn main() -> Result<()> {
             }
         }
     });
-    skel.wait_and_poll_to_handler(ExportFormatType::PlainText, None, None)
+    skel.wait_and_poll_to_handler(export_format, Some(event_handler.clone()), None)
         .with_context(|| anyhow!("Failed to poll"))?;
-    Ok(())
+    if event_handler.write_failed.load(Ordering::Relaxed) {
+        error!("Stopped because the output can't be written");
+        return Ok(EXIT_FAILURE);
+    }
+    let received = received.load(Ordering::Relaxed).min(
+        matches
+            .get_one::<u64>("max-events")
+            .map(|v| *v as usize)
+            .unwrap_or(usize::MAX),
+    );
+    info!("{} events received", received);
+    if received == 0 && matches.get_flag("fail-if-no-events") {
+        return Ok(EXIT_NO_EVENTS);
+    }
+    Ok(EXIT_SUCCESS)
 }
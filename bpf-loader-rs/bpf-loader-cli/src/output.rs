@@ -0,0 +1,225 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{
+    any::Any,
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use bpf_loader_lib::{
+    export_event::{EventHandler, ReceivedEventData},
+    skeleton::handle::PollingHandle,
+};
+use log::error;
+
+/// Where the events go
+pub(crate) enum OutputSink {
+    Stdout,
+    File(RotatingFile),
+}
+
+impl OutputSink {
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        match self {
+            OutputSink::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                writeln!(stdout, "{line}")?;
+                stdout.flush()?;
+            }
+            OutputSink::File(f) => f.write_line(line)?,
+        }
+        Ok(())
+    }
+}
+
+/// A file that will be rotated once it grows larger than `max_size`
+/// The rotated ones are named as `<path>.1`, `<path>.2`, ..., the larger the older
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    /// None means never rotate
+    max_size: Option<u64>,
+    keep: usize,
+}
+
+impl RotatingFile {
+    pub(crate) fn open(path: PathBuf, max_size: Option<u64>, keep: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| anyhow!("Failed to open output file {}", path.display()))?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            written,
+            max_size,
+            keep,
+        })
+    }
+    fn rotated_path(&self, idx: usize) -> PathBuf {
+        let mut s = self.path.clone().into_os_string();
+        s.push(format!(".{idx}"));
+        s.into()
+    }
+    fn rotate(&mut self) -> Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            // Nothing to keep, just start over
+            self.file.set_len(0)?;
+        } else {
+            std::fs::remove_file(self.rotated_path(self.keep)).ok();
+            for idx in (1..self.keep).rev() {
+                let from = self.rotated_path(idx);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated_path(idx + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))
+                .with_context(|| anyhow!("Failed to rotate {}", self.path.display()))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        let len = line.len() as u64 + 1;
+        if let Some(max_size) = self.max_size {
+            if self.written > 0 && self.written + len > max_size {
+                self.rotate()?;
+            }
+        }
+        writeln!(self.file, "{line}")?;
+        self.written += len;
+        Ok(())
+    }
+}
+
+/// Formats the events, writes them to the sink, and stops the poller after `max_events` events
+///
+/// Headers and bpf_printk lines are written, but they are not counted as events
+pub(crate) struct CliEventHandler {
+    pub(crate) sink: Mutex<OutputSink>,
+    pub(crate) received: Arc<AtomicUsize>,
+    pub(crate) max_events: Option<usize>,
+    pub(crate) handle: PollingHandle,
+    /// Set if we stopped the poller because the sink is broken
+    pub(crate) write_failed: AtomicBool,
+}
+
+impl CliEventHandler {
+    /// Returns false if the sink is broken, and the poller was stopped
+    fn write_line(&self, line: &str) -> bool {
+        if let Err(e) = self.sink.lock().unwrap().write_line(line) {
+            error!("Failed to write the event: {:?}", e);
+            self.write_failed.store(true, Ordering::Relaxed);
+            self.handle.terminate();
+            return false;
+        }
+        true
+    }
+}
+
+impl EventHandler for CliEventHandler {
+    fn handle_event(&self, _context: Option<Arc<dyn Any>>, data: ReceivedEventData) {
+        let line = match data {
+            ReceivedEventData::KernelDebugText(s) => {
+                self.write_line(s);
+                return;
+            }
+            ReceivedEventData::Buffer(buf) => to_hex(buf),
+            ReceivedEventData::KeyValueBuffer { key, value } => {
+                format!("{} {}", to_hex(key), to_hex(value))
+            }
+            ReceivedEventData::PlainText(s) | ReceivedEventData::JsonText(s) => s.to_string(),
+            // Unknown kinds of data are not events we can format
+            _ => return,
+        };
+        // A poll may deliver more events after we asked the poller to stop, drop them
+        let received = self.received.fetch_add(1, Ordering::Relaxed) + 1;
+        if matches!(self.max_events, Some(max) if received > max) {
+            return;
+        }
+        if self.write_line(&line) && matches!(self.max_events, Some(max) if received >= max) {
+            self.handle.terminate();
+        }
+    }
+    fn handle_header(&self, _context: Option<Arc<dyn Any>>, header: &str) {
+        self.write_line(header);
+    }
+}
+
+fn to_hex(buf: &[u8]) -> String {
+    buf.iter()
+        .fold(String::with_capacity(buf.len() * 2), |mut s, b| {
+            write!(s, "{b:02x}").unwrap();
+            s
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::RotatingFile;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ecli-rs-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = test_dir("rotate");
+        let path = dir.join("events.log");
+        let mut file = RotatingFile::open(path.clone(), Some(10), 2).unwrap();
+        for line in ["aaaa", "bbbb", "cccc", "dddd", "eeee"] {
+            file.write_line(line).unwrap();
+        }
+        let read = |idx: Option<usize>| {
+            let path = match idx {
+                Some(idx) => file.rotated_path(idx),
+                None => path.clone(),
+            };
+            std::fs::read_to_string(path).unwrap()
+        };
+        // Two lines fit in 10 bytes. The oldest one was dropped, since only 2 rotated files are kept
+        assert_eq!(read(None), "eeee\n");
+        assert_eq!(read(Some(1)), "cccc\ndddd\n");
+        assert_eq!(read(Some(2)), "aaaa\nbbbb\n");
+        assert!(!file.rotated_path(3).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotate_without_keeping() {
+        let dir = test_dir("rotate-keep-0");
+        let path = dir.join("events.log");
+        std::fs::write(&path, "old\n").unwrap();
+        let mut file = RotatingFile::open(path.clone(), Some(8), 0).unwrap();
+        // The size of the existing content counts
+        file.write_line("aaaa").unwrap();
+        file.write_line("bbbb").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "bbbb\n");
+        assert!(!file.rotated_path(1).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
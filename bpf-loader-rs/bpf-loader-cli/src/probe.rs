@@ -0,0 +1,60 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{fmt::Write, io::Write as _};
+
+use anyhow::Result;
+use bpf_loader_lib::probe::{FeatureProber, KernelFeature};
+use serde_json::{Map, Value};
+
+/// Probe features of the running kernel, and print whether they are supported
+/// Helpers are probed for kprobe programs
+pub(crate) fn run_probe(helpers: &[String], json: bool) -> Result<()> {
+    let mut prober = FeatureProber::new();
+    let mut results = prober.probe_all();
+    for helper in helpers.iter() {
+        let feature = KernelFeature::Helper(helper.clone());
+        let supported = prober.is_supported(&feature);
+        results.push((feature, supported));
+    }
+    let text = if json {
+        let mut helper_results = Map::new();
+        let mut out = Map::new();
+        for (feature, supported) in results.into_iter() {
+            match feature {
+                KernelFeature::Helper(name) => {
+                    helper_results.insert(name, Value::Bool(supported));
+                }
+                feature => {
+                    out.insert(
+                        serde_json::to_value(&feature)?
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        Value::Bool(supported),
+                    );
+                }
+            }
+        }
+        if !helper_results.is_empty() {
+            out.insert("helpers".into(), Value::Object(helper_results));
+        }
+        serde_json::to_string_pretty(&out)? + "\n"
+    } else {
+        let mut out = String::new();
+        for (feature, supported) in results.iter() {
+            writeln!(
+                out,
+                "{}: {}",
+                feature,
+                if *supported { "yes" } else { "no" }
+            )?;
+        }
+        out
+    };
+    std::io::stdout().lock().write_all(text.as_bytes())?;
+    Ok(())
+}
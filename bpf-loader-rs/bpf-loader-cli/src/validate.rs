@@ -0,0 +1,44 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{fmt::Write, io::Write as _};
+
+use anyhow::{anyhow, Context, Result};
+use bpf_loader_lib::meta::ComposedObject;
+
+/// Validate the package without loading it, and print the diagnostics
+/// Returns whether the package is free of errors
+pub(crate) fn run_validate(package_path: &str, json: bool) -> Result<bool> {
+    let package = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(package_path)
+            .with_context(|| anyhow!("Failed to read package {}", package_path))?,
+    )
+    .with_context(|| anyhow!("Failed to parse json into ComposedObject"))?;
+    let report = package.validate();
+    let text = if json {
+        serde_json::to_string_pretty(&report)? + "\n"
+    } else {
+        let mut out = String::new();
+        for diagnostic in report.diagnostics.iter() {
+            writeln!(out, "{diagnostic}")?;
+        }
+        let errors = report
+            .diagnostics
+            .iter()
+            .filter(|v| v.severity == bpf_loader_lib::validate::Severity::Error)
+            .count();
+        writeln!(
+            out,
+            "{}: {} error(s), {} warning(s)",
+            package_path,
+            errors,
+            report.diagnostics.len() - errors
+        )?;
+        out
+    };
+    std::io::stdout().lock().write_all(text.as_bytes())?;
+    Ok(!report.has_errors())
+}
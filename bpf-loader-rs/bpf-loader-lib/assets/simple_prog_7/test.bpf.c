@@ -0,0 +1,40 @@
+// SPDX-License-Identifier: (LGPL-2.1 OR BSD-2-Clause)
+// A minimal tcp congestion control registered through struct_ops. It's the source of `test.ll`
+#include <linux/bpf.h>
+#include <bpf/bpf_helpers.h>
+
+struct sock;
+
+struct tcp_congestion_ops {
+	__u32 (*ssthresh)(struct sock *sk);
+	void (*cong_avoid)(struct sock *sk, __u32 ack, __u32 acked);
+	__u32 (*undo_cwnd)(struct sock *sk);
+	char name[16];
+};
+
+SEC("struct_ops/ca_ssthresh")
+__u32 ca_ssthresh(struct sock *sk)
+{
+	return 2;
+}
+
+SEC("struct_ops/ca_cong_avoid")
+void ca_cong_avoid(struct sock *sk, __u32 ack, __u32 acked)
+{
+}
+
+SEC("struct_ops/ca_undo_cwnd")
+__u32 ca_undo_cwnd(struct sock *sk)
+{
+	return 2;
+}
+
+SEC(".struct_ops")
+struct tcp_congestion_ops ca_test = {
+	.ssthresh = (void *)ca_ssthresh,
+	.cong_avoid = (void *)ca_cong_avoid,
+	.undo_cwnd = (void *)ca_undo_cwnd,
+	.name = "bpf_loader_ca",
+};
+
+char LICENSE[] SEC("license") = "GPL";
@@ -0,0 +1,81 @@
+; LLVM IR of test.bpf.c, with the debug info that the BPF backend turns into BTF
+target datalayout = "e-m:e-p:64:64-i64:64-i128:128-n32:64-S128"
+target triple = "bpf"
+
+%struct.tcp_congestion_ops = type { i32 (%struct.sock*)*, void (%struct.sock*, i32, i32)*, i32 (%struct.sock*)*, [16 x i8] }
+%struct.sock = type opaque
+
+@ca_test = dso_local global %struct.tcp_congestion_ops { i32 (%struct.sock*)* @ca_ssthresh, void (%struct.sock*, i32, i32)* @ca_cong_avoid, i32 (%struct.sock*)* @ca_undo_cwnd, [16 x i8] c"bpf_loader_ca\00\00\00" }, section ".struct_ops", align 8, !dbg !0
+@LICENSE = dso_local global [4 x i8] c"GPL\00", section "license", align 1, !dbg !5
+@llvm.compiler.used = appending global [5 x i8*] [i8* getelementptr inbounds ([4 x i8], [4 x i8]* @LICENSE, i32 0, i32 0), i8* bitcast (i32 (%struct.sock*)* @ca_ssthresh to i8*), i8* bitcast (void (%struct.sock*, i32, i32)* @ca_cong_avoid to i8*), i8* bitcast (i32 (%struct.sock*)* @ca_undo_cwnd to i8*), i8* bitcast (%struct.tcp_congestion_ops* @ca_test to i8*)], section "llvm.metadata"
+
+define dso_local i32 @ca_ssthresh(%struct.sock* nocapture readnone %sk) #0 section "struct_ops/ca_ssthresh" !dbg !40 {
+  call void @llvm.dbg.value(metadata %struct.sock* %sk, metadata !42, metadata !DIExpression()), !dbg !43
+  ret i32 2, !dbg !43
+}
+
+define dso_local void @ca_cong_avoid(%struct.sock* nocapture readnone %sk, i32 %ack, i32 %acked) #0 section "struct_ops/ca_cong_avoid" !dbg !50 {
+  call void @llvm.dbg.value(metadata %struct.sock* %sk, metadata !52, metadata !DIExpression()), !dbg !55
+  call void @llvm.dbg.value(metadata i32 %ack, metadata !53, metadata !DIExpression()), !dbg !55
+  call void @llvm.dbg.value(metadata i32 %acked, metadata !54, metadata !DIExpression()), !dbg !55
+  ret void, !dbg !55
+}
+
+define dso_local i32 @ca_undo_cwnd(%struct.sock* nocapture readnone %sk) #0 section "struct_ops/ca_undo_cwnd" !dbg !60 {
+  call void @llvm.dbg.value(metadata %struct.sock* %sk, metadata !62, metadata !DIExpression()), !dbg !63
+  ret i32 2, !dbg !63
+}
+
+declare void @llvm.dbg.value(metadata, metadata, metadata) #1
+
+attributes #0 = { nounwind }
+attributes #1 = { nofree nosync nounwind readnone speculatable willreturn }
+
+!llvm.dbg.cu = !{!2}
+!llvm.module.flags = !{!100, !101, !102}
+
+!0 = !DIGlobalVariableExpression(var: !1, expr: !DIExpression())
+!1 = distinct !DIGlobalVariable(name: "ca_test", scope: !2, file: !3, line: 33, type: !10, isLocal: false, isDefinition: true)
+!2 = distinct !DICompileUnit(language: DW_LANG_C99, file: !3, producer: "test.ll", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug, globals: !4, splitDebugInlining: false)
+!3 = !DIFile(filename: "test.bpf.c", directory: ".")
+!4 = !{!0, !5}
+!5 = !DIGlobalVariableExpression(var: !6, expr: !DIExpression())
+!6 = distinct !DIGlobalVariable(name: "LICENSE", scope: !2, file: !3, line: 40, type: !7, isLocal: false, isDefinition: true)
+!7 = !DICompositeType(tag: DW_TAG_array_type, baseType: !8, size: 32, elements: !9)
+!8 = !DIBasicType(name: "char", size: 8, encoding: DW_ATE_signed_char)
+!9 = !{!DISubrange(count: 4)}
+!10 = distinct !DICompositeType(tag: DW_TAG_structure_type, name: "tcp_congestion_ops", file: !3, line: 8, size: 320, elements: !11)
+!11 = !{!12, !17, !21, !22}
+!12 = !DIDerivedType(tag: DW_TAG_member, name: "ssthresh", scope: !10, file: !3, line: 9, baseType: !13, size: 64)
+!13 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !14, size: 64)
+!14 = !DISubroutineType(types: !15)
+!15 = !{!16, !30}
+!16 = !DIDerivedType(tag: DW_TAG_typedef, name: "__u32", file: !3, line: 1, baseType: !31)
+!17 = !DIDerivedType(tag: DW_TAG_member, name: "cong_avoid", scope: !10, file: !3, line: 10, baseType: !18, size: 64, offset: 64)
+!18 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !19, size: 64)
+!19 = !DISubroutineType(types: !20)
+!20 = !{null, !30, !16, !16}
+!21 = !DIDerivedType(tag: DW_TAG_member, name: "undo_cwnd", scope: !10, file: !3, line: 11, baseType: !13, size: 64, offset: 128)
+!22 = !DIDerivedType(tag: DW_TAG_member, name: "name", scope: !10, file: !3, line: 12, baseType: !23, size: 128, offset: 192)
+!23 = !DICompositeType(tag: DW_TAG_array_type, baseType: !8, size: 128, elements: !24)
+!24 = !{!DISubrange(count: 16)}
+!30 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !32, size: 64)
+!31 = !DIBasicType(name: "unsigned int", size: 32, encoding: DW_ATE_unsigned)
+!32 = !DICompositeType(tag: DW_TAG_structure_type, name: "sock", file: !3, line: 6, flags: DIFlagFwdDecl)
+!40 = distinct !DISubprogram(name: "ca_ssthresh", scope: !3, file: !3, line: 16, type: !14, scopeLine: 17, flags: DIFlagPrototyped | DIFlagAllCallsDescribed, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !2, retainedNodes: !41)
+!41 = !{!42}
+!42 = !DILocalVariable(name: "sk", arg: 1, scope: !40, file: !3, line: 16, type: !30)
+!43 = !DILocation(line: 18, column: 2, scope: !40)
+!50 = distinct !DISubprogram(name: "ca_cong_avoid", scope: !3, file: !3, line: 22, type: !19, scopeLine: 23, flags: DIFlagPrototyped | DIFlagAllCallsDescribed, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !2, retainedNodes: !51)
+!51 = !{!52, !53, !54}
+!52 = !DILocalVariable(name: "sk", arg: 1, scope: !50, file: !3, line: 22, type: !30)
+!53 = !DILocalVariable(name: "ack", arg: 2, scope: !50, file: !3, line: 22, type: !16)
+!54 = !DILocalVariable(name: "acked", arg: 3, scope: !50, file: !3, line: 22, type: !16)
+!55 = !DILocation(line: 24, column: 1, scope: !50)
+!60 = distinct !DISubprogram(name: "ca_undo_cwnd", scope: !3, file: !3, line: 27, type: !14, scopeLine: 28, flags: DIFlagPrototyped | DIFlagAllCallsDescribed, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !2, retainedNodes: !61)
+!61 = !{!62}
+!62 = !DILocalVariable(name: "sk", arg: 1, scope: !60, file: !3, line: 27, type: !30)
+!63 = !DILocation(line: 29, column: 2, scope: !60)
+!100 = !{i32 7, !"Dwarf Version", i32 5}
+!101 = !{i32 2, !"Debug Info Version", i32 3}
+!102 = !{i32 1, !"wchar_size", i32 4}
@@ -0,0 +1,12 @@
+// SPDX-License-Identifier: (LGPL-2.1 OR BSD-2-Clause)
+// A cgroup_skb program which lets every packet pass. It's the source of `test.ll`
+#include <linux/bpf.h>
+#include <bpf/bpf_helpers.h>
+
+SEC("cgroup_skb/egress")
+int cgroup_egress(struct __sk_buff *skb)
+{
+	return 1;
+}
+
+char LICENSE[] SEC("license") = "GPL";
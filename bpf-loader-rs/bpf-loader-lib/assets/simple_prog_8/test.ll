@@ -0,0 +1,42 @@
+; LLVM IR of test.bpf.c, with the debug info that the BPF backend turns into BTF
+target datalayout = "e-m:e-p:64:64-i64:64-i128:128-n32:64-S128"
+target triple = "bpf"
+
+%struct.__sk_buff = type opaque
+
+@LICENSE = dso_local global [4 x i8] c"GPL\00", section "license", align 1, !dbg !0
+@llvm.compiler.used = appending global [2 x i8*] [i8* getelementptr inbounds ([4 x i8], [4 x i8]* @LICENSE, i32 0, i32 0), i8* bitcast (i32 (%struct.__sk_buff*)* @cgroup_egress to i8*)], section "llvm.metadata"
+
+define dso_local i32 @cgroup_egress(%struct.__sk_buff* nocapture readnone %skb) #0 section "cgroup_skb/egress" !dbg !20 {
+  call void @llvm.dbg.value(metadata %struct.__sk_buff* %skb, metadata !26, metadata !DIExpression()), !dbg !27
+  ret i32 1, !dbg !27
+}
+
+declare void @llvm.dbg.value(metadata, metadata, metadata) #1
+
+attributes #0 = { nounwind }
+attributes #1 = { nofree nosync nounwind readnone speculatable willreturn }
+
+!llvm.dbg.cu = !{!2}
+!llvm.module.flags = !{!100, !101, !102}
+
+!0 = !DIGlobalVariableExpression(var: !1, expr: !DIExpression())
+!1 = distinct !DIGlobalVariable(name: "LICENSE", scope: !2, file: !3, line: 12, type: !5, isLocal: false, isDefinition: true)
+!2 = distinct !DICompileUnit(language: DW_LANG_C99, file: !3, producer: "test.ll", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug, globals: !4, splitDebugInlining: false)
+!3 = !DIFile(filename: "test.bpf.c", directory: ".")
+!4 = !{!0}
+!5 = !DICompositeType(tag: DW_TAG_array_type, baseType: !6, size: 32, elements: !7)
+!6 = !DIBasicType(name: "char", size: 8, encoding: DW_ATE_signed_char)
+!7 = !{!DISubrange(count: 4)}
+!20 = distinct !DISubprogram(name: "cgroup_egress", scope: !3, file: !3, line: 7, type: !21, scopeLine: 8, flags: DIFlagPrototyped | DIFlagAllCallsDescribed, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !2, retainedNodes: !25)
+!21 = !DISubroutineType(types: !22)
+!22 = !{!23, !24}
+!23 = !DIBasicType(name: "int", size: 32, encoding: DW_ATE_signed)
+!24 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !28, size: 64)
+!25 = !{!26}
+!26 = !DILocalVariable(name: "skb", arg: 1, scope: !20, file: !3, line: 7, type: !24)
+!27 = !DILocation(line: 9, column: 2, scope: !20)
+!28 = !DICompositeType(tag: DW_TAG_structure_type, name: "__sk_buff", file: !3, line: 7, flags: DIFlagFwdDecl)
+!100 = !{i32 7, !"Dwarf Version", i32 5}
+!101 = !{i32 2, !"Debug Info Version", i32 3}
+!102 = !{i32 1, !"wchar_size", i32 4}
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: (LGPL-2.1 OR BSD-2-Clause)
+// A sk_skb program attached to a sockmap, and a sk_lookup program attached to a network namespace. It's the source of `test.ll`
+#include <linux/bpf.h>
+#include <bpf/bpf_helpers.h>
+
+struct {
+	__uint(type, BPF_MAP_TYPE_SOCKMAP);
+	__uint(max_entries, 1);
+	__type(key, __u32);
+	__type(value, __u32);
+} sock_map SEC(".maps");
+
+SEC("sk_skb/stream_verdict")
+int sk_verdict(struct __sk_buff *skb)
+{
+	return SK_PASS;
+}
+
+SEC("sk_lookup")
+int sk_lookup_pass(struct bpf_sk_lookup *ctx)
+{
+	return SK_PASS;
+}
+
+char LICENSE[] SEC("license") = "GPL";
@@ -0,0 +1,75 @@
+; LLVM IR of test.bpf.c, with the debug info that the BPF backend turns into BTF
+target datalayout = "e-m:e-p:64:64-i64:64-i128:128-n32:64-S128"
+target triple = "bpf"
+
+%struct.anon = type { [15 x i32]*, [1 x i32]*, i32*, i32* }
+%struct.__sk_buff = type opaque
+%struct.bpf_sk_lookup = type opaque
+
+@sock_map = dso_local global %struct.anon zeroinitializer, section ".maps", align 8, !dbg !0
+@LICENSE = dso_local global [4 x i8] c"GPL\00", section "license", align 1, !dbg !5
+@llvm.compiler.used = appending global [4 x i8*] [i8* getelementptr inbounds ([4 x i8], [4 x i8]* @LICENSE, i32 0, i32 0), i8* bitcast (i32 (%struct.__sk_buff*)* @sk_verdict to i8*), i8* bitcast (i32 (%struct.bpf_sk_lookup*)* @sk_lookup_pass to i8*), i8* bitcast (%struct.anon* @sock_map to i8*)], section "llvm.metadata"
+
+define dso_local i32 @sk_verdict(%struct.__sk_buff* nocapture readnone %skb) #0 section "sk_skb/stream_verdict" !dbg !40 {
+  call void @llvm.dbg.value(metadata %struct.__sk_buff* %skb, metadata !46, metadata !DIExpression()), !dbg !47
+  ret i32 1, !dbg !47
+}
+
+define dso_local i32 @sk_lookup_pass(%struct.bpf_sk_lookup* nocapture readnone %ctx) #0 section "sk_lookup" !dbg !50 {
+  call void @llvm.dbg.value(metadata %struct.bpf_sk_lookup* %ctx, metadata !56, metadata !DIExpression()), !dbg !57
+  ret i32 1, !dbg !57
+}
+
+declare void @llvm.dbg.value(metadata, metadata, metadata) #1
+
+attributes #0 = { nounwind }
+attributes #1 = { nofree nosync nounwind readnone speculatable willreturn }
+
+!llvm.dbg.cu = !{!2}
+!llvm.module.flags = !{!100, !101, !102}
+
+!0 = !DIGlobalVariableExpression(var: !1, expr: !DIExpression())
+!1 = distinct !DIGlobalVariable(name: "sock_map", scope: !2, file: !3, line: 11, type: !10, isLocal: false, isDefinition: true)
+!2 = distinct !DICompileUnit(language: DW_LANG_C99, file: !3, producer: "test.ll", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug, globals: !4, splitDebugInlining: false)
+!3 = !DIFile(filename: "test.bpf.c", directory: ".")
+!4 = !{!0, !5}
+!5 = !DIGlobalVariableExpression(var: !6, expr: !DIExpression())
+!6 = distinct !DIGlobalVariable(name: "LICENSE", scope: !2, file: !3, line: 25, type: !7, isLocal: false, isDefinition: true)
+!7 = !DICompositeType(tag: DW_TAG_array_type, baseType: !8, size: 32, elements: !9)
+!8 = !DIBasicType(name: "char", size: 8, encoding: DW_ATE_signed_char)
+!9 = !{!DISubrange(count: 4)}
+!10 = distinct !DICompositeType(tag: DW_TAG_structure_type, file: !3, line: 6, size: 256, elements: !11)
+!11 = !{!12, !17, !21, !25}
+!12 = !DIDerivedType(tag: DW_TAG_member, name: "type", scope: !10, file: !3, line: 7, baseType: !13, size: 64)
+!13 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !14, size: 64)
+!14 = !DICompositeType(tag: DW_TAG_array_type, baseType: !15, size: 480, elements: !16)
+!15 = !DIBasicType(name: "int", size: 32, encoding: DW_ATE_signed)
+!16 = !{!DISubrange(count: 15)}
+!17 = !DIDerivedType(tag: DW_TAG_member, name: "max_entries", scope: !10, file: !3, line: 8, baseType: !18, size: 64, offset: 64)
+!18 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !19, size: 64)
+!19 = !DICompositeType(tag: DW_TAG_array_type, baseType: !15, size: 32, elements: !20)
+!20 = !{!DISubrange(count: 1)}
+!21 = !DIDerivedType(tag: DW_TAG_member, name: "key", scope: !10, file: !3, line: 9, baseType: !22, size: 64, offset: 128)
+!22 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !23, size: 64)
+!23 = !DIDerivedType(tag: DW_TAG_typedef, name: "__u32", file: !3, line: 1, baseType: !24)
+!24 = !DIBasicType(name: "unsigned int", size: 32, encoding: DW_ATE_unsigned)
+!25 = !DIDerivedType(tag: DW_TAG_member, name: "value", scope: !10, file: !3, line: 10, baseType: !22, size: 64, offset: 192)
+!40 = distinct !DISubprogram(name: "sk_verdict", scope: !3, file: !3, line: 14, type: !41, scopeLine: 15, flags: DIFlagPrototyped | DIFlagAllCallsDescribed, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !2, retainedNodes: !45)
+!41 = !DISubroutineType(types: !42)
+!42 = !{!15, !43}
+!43 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !44, size: 64)
+!44 = !DICompositeType(tag: DW_TAG_structure_type, name: "__sk_buff", file: !3, line: 14, flags: DIFlagFwdDecl)
+!45 = !{!46}
+!46 = !DILocalVariable(name: "skb", arg: 1, scope: !40, file: !3, line: 14, type: !43)
+!47 = !DILocation(line: 16, column: 2, scope: !40)
+!50 = distinct !DISubprogram(name: "sk_lookup_pass", scope: !3, file: !3, line: 20, type: !51, scopeLine: 21, flags: DIFlagPrototyped | DIFlagAllCallsDescribed, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !2, retainedNodes: !55)
+!51 = !DISubroutineType(types: !52)
+!52 = !{!15, !53}
+!53 = !DIDerivedType(tag: DW_TAG_pointer_type, baseType: !54, size: 64)
+!54 = !DICompositeType(tag: DW_TAG_structure_type, name: "bpf_sk_lookup", file: !3, line: 20, flags: DIFlagFwdDecl)
+!55 = !{!56}
+!56 = !DILocalVariable(name: "ctx", arg: 1, scope: !50, file: !3, line: 20, type: !53)
+!57 = !DILocation(line: 22, column: 2, scope: !50)
+!100 = !{i32 7, !"Dwarf Version", i32 5}
+!101 = !{i32 2, !"Debug Info Version", i32 3}
+!102 = !{i32 1, !"wchar_size", i32 4}
@@ -4,16 +4,28 @@
 //! All rights reserved.
 //!
 
-use anyhow::{anyhow, Result};
-use btf::types::Btf;
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use btf::types::{Btf, BtfFwd, BtfFwdKind, BtfType};
+use log::debug;
 use ouroboros::self_referencing;
 
-use crate::elf_container::ElfContainer;
+use crate::{
+    elf_container::ElfContainer,
+    helper::btf::{create_elf_with_btf_section, merge_split_btf, rewrite_enum64_in_raw_btf},
+};
+
+/// Where the split BTF of kernel modules are
+pub const KERNEL_MODULE_BTF_DIR: &str = "/sys/kernel/btf";
+
 /// A helper struct to solve the reference problem of btf::types::Btf
 /// This struct contains the binary of the original elf file, the ElfFile struct and Btf struct.
 /// With this we don't need to take care of the reference problem anymore
 #[self_referencing]
 pub struct BtfContainer {
+    /// BTF of the running kernel, used to resolve forward declarations
+    pub(crate) kernel_btf: Option<Arc<KernelBtf>>,
     pub(crate) elf_container: ElfContainer,
     #[borrows(elf_container)]
     #[covariant]
@@ -25,6 +37,7 @@ impl BtfContainer {
     pub fn new_from_binary(bin: &[u8]) -> Result<Self> {
         let elf = ElfContainer::new_from_binary(bin)?;
         let val = BtfContainerTryBuilder {
+            kernel_btf: None,
             elf_container: elf,
             btf_builder: |elf: &ElfContainer| {
                 Btf::load(elf.borrow_elf()).map_err(|e| anyhow!("Failed to build btf: {}", e))
@@ -33,4 +46,288 @@ impl BtfContainer {
         .try_build()?;
         Ok(val)
     }
+    /// Create a btf container from raw BTF, like the ones in `/sys/kernel/btf`
+    pub fn new_from_raw_btf(raw: &[u8]) -> Result<Self> {
+        Self::new_from_binary(&create_elf_with_btf_section(
+            &rewrite_enum64_in_raw_btf(raw)?,
+            true,
+        )?)
+    }
+    /// Resolve forward declarations in this BTF through the kernel BTF
+    pub fn set_kernel_btf(&mut self, kernel_btf: Arc<KernelBtf>) {
+        self.with_kernel_btf_mut(|v| *v = Some(kernel_btf));
+    }
+    pub(crate) fn resolver(&self) -> BtfResolver<'_> {
+        BtfResolver {
+            program: self.borrow_btf(),
+            kernel: self.borrow_kernel_btf().as_deref(),
+            source: BtfSource::Program,
+        }
+    }
+}
+
+/// BTF of the running kernel: vmlinux, and the split BTF of some modules
+///
+/// Each module is merged with vmlinux before loading, since btfdump can't load split BTF. So only load the modules you need
+pub struct KernelBtf {
+    /// vmlinux comes first
+    btfs: Vec<BtfContainer>,
+    /// (name, is_struct) of structs and unions, to where they are defined
+    composites: HashMap<(String, bool), (usize, u32)>,
+}
+
+impl KernelBtf {
+    /// Load the vmlinux BTF (raw BTF or an ELF with `.BTF`), and the split BTF of `modules` in `/sys/kernel/btf`
+    pub fn load(vmlinux_path: impl AsRef<Path>, modules: &[String]) -> Result<Self> {
+        let vmlinux_path = vmlinux_path.as_ref();
+        let vmlinux_raw = std::fs::read(vmlinux_path)
+            .with_context(|| anyhow!("Failed to read {}", vmlinux_path.display()))?;
+        let is_elf = vmlinux_raw.starts_with(b"\x7fELF");
+        let vmlinux = if is_elf {
+            BtfContainer::new_from_binary(&vmlinux_raw)
+        } else {
+            BtfContainer::new_from_raw_btf(&vmlinux_raw)
+        }
+        .with_context(|| anyhow!("Failed to load kernel BTF {}", vmlinux_path.display()))?;
+        let mut result = Self {
+            btfs: vec![],
+            composites: HashMap::default(),
+        };
+        result.add_btf(vmlinux, 0);
+        let base_type_count = result.btfs[0].borrow_btf().types().len();
+        for module in modules.iter() {
+            if is_elf {
+                bail!(
+                    "Module BTF can only be used with raw vmlinux BTF, but {} is an ELF",
+                    vmlinux_path.display()
+                );
+            }
+            let path = Path::new(KERNEL_MODULE_BTF_DIR).join(module);
+            let split = std::fs::read(&path)
+                .with_context(|| anyhow!("Failed to read BTF of module `{}`", module))?;
+            let merged = BtfContainer::new_from_raw_btf(&merge_split_btf(&vmlinux_raw, &split)?)
+                .with_context(|| anyhow!("Failed to load BTF of module `{}`", module))?;
+            result.add_btf(merged, base_type_count);
+        }
+        debug!(
+            "Loaded kernel BTF with {} modules, {} structs and unions",
+            modules.len(),
+            result.composites.len()
+        );
+        Ok(result)
+    }
+    /// Types before `start` are the ones of vmlinux, which are already indexed
+    fn add_btf(&mut self, btf: BtfContainer, start: usize) {
+        let idx = self.btfs.len();
+        for (id, ty) in btf.borrow_btf().types().iter().enumerate().skip(start) {
+            if let BtfType::Struct(comp) | BtfType::Union(comp) = ty {
+                if !comp.name.is_empty() {
+                    self.composites
+                        .entry((comp.name.to_string(), comp.is_struct))
+                        .or_insert((idx, id as u32));
+                }
+            }
+        }
+        self.btfs.push(btf);
+    }
+}
+
+/// Which BTF a type id belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum BtfSource {
+    /// The BTF of the bpf program
+    #[default]
+    Program,
+    /// The n-th BTF of `KernelBtf`
+    Kernel(usize),
+}
+
+/// Looks up types in the BTF of the program, and resolves forward declarations through the kernel BTF if it's loaded
+#[derive(Clone, Copy)]
+pub(crate) struct BtfResolver<'a> {
+    program: &'a Btf<'a>,
+    kernel: Option<&'a KernelBtf>,
+    /// Which BTF `btf()` returns
+    pub(crate) source: BtfSource,
+}
+
+impl<'a> BtfResolver<'a> {
+    /// A resolver without the kernel BTF
+    #[cfg(test)]
+    pub(crate) fn new(program: &'a Btf<'a>) -> Self {
+        Self {
+            program,
+            kernel: None,
+            source: BtfSource::Program,
+        }
+    }
+    /// The BTF that type ids are currently resolved in
+    pub(crate) fn btf(&self) -> &'a Btf<'a> {
+        match (self.source, self.kernel) {
+            (BtfSource::Kernel(idx), Some(kernel)) => kernel.btfs[idx].borrow_btf(),
+            _ => self.program,
+        }
+    }
+    /// Switch to the BTF that a type id comes from
+    pub(crate) fn with_source(&self, source: BtfSource) -> Result<Self> {
+        if let BtfSource::Kernel(idx) = source {
+            if self.kernel.map(|v| v.btfs.len()).unwrap_or(0) <= idx {
+                bail!("Kernel BTF #{} is not loaded", idx);
+            }
+        }
+        Ok(Self { source, ..*self })
+    }
+    /// Find the definition of a forward declaration in the kernel BTF
+    pub(crate) fn resolve_fwd(&self, fwd: &BtfFwd) -> Result<(Self, u32)> {
+        let kernel = self.kernel.ok_or_else(|| {
+            anyhow!(
+                "Forward declaration `{}` can't be resolved without the kernel BTF. Set `resolve_kernel_types` in the runner config to load it",
+                fwd.name
+            )
+        })?;
+        let is_struct = matches!(fwd.kind, BtfFwdKind::Struct);
+        let (idx, type_id) = kernel
+            .composites
+            .get(&(fwd.name.to_string(), is_struct))
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} `{}` is not found in the kernel BTF",
+                    if is_struct { "struct" } else { "union" },
+                    fwd.name
+                )
+            })?;
+        Ok((
+            Self {
+                source: BtfSource::Kernel(*idx),
+                ..*self
+            },
+            *type_id,
+        ))
+    }
+    /// Skip modifiers and typedefs. If it ends up with a forward declaration, resolve it
+    pub(crate) fn resolve_real_type(&self, type_id: u32) -> Result<(Self, u32)> {
+        let btf = self.btf();
+        if btf.types().get(type_id as usize).is_none() {
+            bail!("Invalid type id: {}", type_id);
+        }
+        let real = btf.skip_mods_and_typedefs(type_id);
+        match btf.type_by_id(real) {
+            BtfType::Fwd(fwd) => self.resolve_fwd(fwd),
+            _ => Ok((*self, real)),
+        }
+    }
+    /// Size of the type. Forward declarations are resolved
+    pub(crate) fn size_of(&self, type_id: u32) -> Result<u32> {
+        let (resolver, real) = self.resolve_real_type(type_id)?;
+        Ok(resolver.btf().get_size_of(real))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use btf::types::BtfType;
+
+    use crate::helper::btf::merge_split_btf;
+
+    use super::{BtfContainer, BtfSource, KernelBtf};
+
+    /// Build raw BTF with `int`, `struct foo { int a; int b; }` (or `struct foo;` if `fwd`), and `typedef struct foo foo_t`
+    fn build_raw_btf(fwd: bool) -> Vec<u8> {
+        let strs = b"\0int\0foo\0a\0b\0foo_t\0";
+        let mut types: Vec<u32> = vec![];
+        // [1] int, 4 bytes, signed, 32 bits
+        types.extend([1, 1 << 24, 4, (1 << 24) | 32]);
+        if fwd {
+            // [2] fwd struct foo
+            types.extend([5, 7 << 24, 0]);
+        } else {
+            // [2] struct foo, 8 bytes, 2 members
+            types.extend([5, (4 << 24) | 2, 8, 9, 1, 0, 11, 1, 32]);
+        }
+        // [3] typedef foo_t -> [2]
+        types.extend([13, 8 << 24, 2]);
+        let types = types
+            .into_iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<u8>>();
+        let mut raw = vec![0x9f, 0xeb, 1, 0];
+        for v in [
+            24,
+            0,
+            types.len() as u32,
+            types.len() as u32,
+            strs.len() as u32,
+        ] {
+            raw.extend(v.to_le_bytes());
+        }
+        raw.extend(types);
+        raw.extend(strs);
+        raw
+    }
+
+    #[test]
+    fn test_resolve_fwd_through_kernel_btf() {
+        let path = std::env::temp_dir().join(format!("kernel-btf-test-{}", std::process::id()));
+        std::fs::write(&path, build_raw_btf(false)).unwrap();
+        let kernel = Arc::new(KernelBtf::load(&path, &[]).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let mut program = BtfContainer::new_from_raw_btf(&build_raw_btf(true)).unwrap();
+        assert!(program.resolver().size_of(3).is_err());
+        program.set_kernel_btf(kernel);
+        let resolver = program.resolver();
+        assert_eq!(resolver.size_of(3).unwrap(), 8);
+        let (kernel_resolver, type_id) = resolver.resolve_real_type(3).unwrap();
+        assert_eq!(kernel_resolver.source, BtfSource::Kernel(0));
+        assert!(matches!(
+            kernel_resolver.btf().type_by_id(type_id),
+            BtfType::Struct(s) if s.name == "foo" && s.members.len() == 2
+        ));
+    }
+
+    #[test]
+    fn test_merge_split_btf() {
+        let base = build_raw_btf(false);
+        // Split BTF with `typedef struct foo bar_t`. Type ids and string offsets continue from the base
+        let base_strs_len = b"\0int\0foo\0a\0b\0foo_t\0".len() as u32;
+        let strs = b"bar_t\0";
+        let types = [base_strs_len, 8 << 24, 2]
+            .into_iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<u8>>();
+        let mut split = vec![0x9f, 0xeb, 1, 0];
+        for v in [
+            24,
+            0,
+            types.len() as u32,
+            types.len() as u32,
+            strs.len() as u32,
+        ] {
+            split.extend(v.to_le_bytes());
+        }
+        split.extend(types);
+        split.extend(strs);
+        let merged =
+            BtfContainer::new_from_raw_btf(&merge_split_btf(&base, &split).unwrap()).unwrap();
+        let btf = merged.borrow_btf();
+        assert!(matches!(
+            btf.type_by_id(4),
+            BtfType::Typedef(t) if t.name == "bar_t" && t.type_id == 2
+        ));
+        assert_eq!(btf.get_size_of(4), 8);
+    }
+
+    #[test]
+    fn test_load_vmlinux_btf() {
+        let path = std::path::Path::new("/sys/kernel/btf/vmlinux");
+        if !path.exists() {
+            return;
+        }
+        let kernel = KernelBtf::load(path, &[]).unwrap();
+        assert!(kernel
+            .composites
+            .contains_key(&("task_struct".to_string(), true)));
+    }
 }
@@ -4,22 +4,24 @@
 //! All rights reserved.
 //!
 
-use anyhow::{anyhow, bail, Result};
-use btf::types::{Btf, BtfType};
+use anyhow::{anyhow, bail, Context, Result};
+use btf::types::BtfType;
 use log::warn;
 
-use crate::meta::{ExportedTypesStructMemberMeta, ExportedTypesStructMeta};
+use crate::{
+    btf_container::BtfResolver,
+    meta::{ExportedTypesStructMemberMeta, ExportedTypesStructMeta},
+};
 
 use super::CheckedExportedMember;
 #[inline]
 pub(crate) fn check_export_types_btf(
     struct_meta: &ExportedTypesStructMeta,
-    btf: &Btf,
+    resolver: BtfResolver,
 ) -> Result<Vec<CheckedExportedMember>> {
-    let ty = btf
-        .types()
-        .get(struct_meta.type_id as usize)
-        .ok_or_else(|| anyhow!("type id {} is invalid", struct_meta.type_id))?;
+    let (resolver, type_id) = resolve_exported_type(resolver, struct_meta.type_id)
+        .with_context(|| anyhow!("type id {} is invalid", struct_meta.type_id))?;
+    let ty = resolver.btf().type_by_id(type_id);
     if let BtfType::Struct(st) = ty {
         if ty.name() != struct_meta.name {
             bail!(
@@ -55,7 +57,7 @@ pub(crate) fn check_export_types_btf(
             let bit_off = btf_mem.bit_offset;
 
             let bit_sz = btf_mem.bit_size;
-            let size = btf.get_size_of(btf_mem.type_id);
+            let size = resolver.size_of(btf_mem.type_id)?;
             if bit_off % 8 != 0 || bit_sz % 8 != 0 {
                 bail!(
                     "Bitfield is not supported. Member {}, bit_offset={}, bit_sz={}",
@@ -71,6 +73,7 @@ pub(crate) fn check_export_types_btf(
                 bit_offset: bit_off,
                 size: size as usize,
                 output_header_offset: 0,
+                btf_source: resolver.source,
             });
         }
         Ok(result)
@@ -80,14 +83,12 @@ pub(crate) fn check_export_types_btf(
 }
 
 pub(crate) fn check_sample_types_btf(
-    btf: &Btf,
+    resolver: BtfResolver,
     type_id: u32,
     mut members: Option<ExportedTypesStructMeta>,
 ) -> Result<Vec<CheckedExportedMember>> {
-    let ty = btf
-        .types()
-        .get(type_id as usize)
-        .ok_or_else(|| anyhow!("Invalid type id: {}", type_id))?;
+    let (resolver, type_id) = resolve_exported_type(resolver, type_id)?;
+    let ty = resolver.btf().type_by_id(type_id);
     if let Some(local_members) = members.as_ref() {
         if local_members.name != ty.name() {
             warn!(
@@ -114,7 +115,7 @@ pub(crate) fn check_sample_types_btf(
             let mem_type_id = btf_mem.type_id;
             let bit_off = btf_mem.bit_offset;
             check_and_push_export_type_btf(
-                btf,
+                resolver,
                 mem_type_id,
                 bit_off,
                 &mut result,
@@ -122,23 +123,33 @@ pub(crate) fn check_sample_types_btf(
             )?;
         }
     } else {
-        check_and_push_export_type_btf(btf, type_id, 0, &mut result, None)?;
+        check_and_push_export_type_btf(resolver, type_id, 0, &mut result, None)?;
     }
     Ok(result)
 }
 
+/// Check the type id, and resolve it through the kernel BTF if it's a forward declaration
+fn resolve_exported_type(resolver: BtfResolver, type_id: u32) -> Result<(BtfResolver, u32)> {
+    match resolver.btf().types().get(type_id as usize) {
+        Some(BtfType::Fwd(fwd)) => resolver.resolve_fwd(fwd),
+        Some(_) => Ok((resolver, type_id)),
+        None => bail!("Invalid type id: {}", type_id),
+    }
+}
+
 fn check_and_push_export_type_btf(
-    btf: &Btf,
+    resolver: BtfResolver,
     type_id: u32,
     bit_off: u32,
     out: &mut Vec<CheckedExportedMember>,
     member_meta: Option<ExportedTypesStructMemberMeta>,
 ) -> Result<()> {
-    let ty = btf
+    let ty = resolver
+        .btf()
         .types()
         .get(type_id as usize)
         .ok_or_else(|| anyhow!("Invalid type id: {}", type_id))?;
-    let size = btf.get_size_of(type_id);
+    let size = resolver.size_of(type_id)?;
     let member_meta = if let Some(meta) = member_meta {
         meta
     } else {
@@ -153,6 +164,7 @@ fn check_and_push_export_type_btf(
         bit_offset: bit_off,
         size: size as usize,
         output_header_offset: 0,
+        btf_source: resolver.source,
     });
     Ok(())
 }
@@ -161,7 +173,11 @@ fn check_and_push_export_type_btf(
 mod tests {
     use btf::types::BtfType;
 
-    use crate::{btf_container::BtfContainer, meta::EunomiaObjectMeta, tests::get_assets_dir};
+    use crate::{
+        btf_container::{BtfContainer, BtfResolver},
+        meta::EunomiaObjectMeta,
+        tests::get_assets_dir,
+    };
 
     use super::check_export_types_btf;
 
@@ -179,7 +195,7 @@ mod tests {
         .unwrap();
         let struct_meta = &eunomia_meta.export_types[0];
         let btf = btf_container.borrow_btf();
-        let checked_types = check_export_types_btf(struct_meta, btf).unwrap();
+        let checked_types = check_export_types_btf(struct_meta, BtfResolver::new(btf)).unwrap();
         println!("{:#?}", checked_types);
 
         let st = if let BtfType::Struct(st) = btf.type_by_id(2) {
@@ -8,16 +8,17 @@ use std::ffi::CStr;
 
 use anyhow::{anyhow, bail, Result};
 use btf::types::{
-    Btf, BtfArray, BtfComposite, BtfConst, BtfEnum, BtfFloat, BtfInt, BtfIntEncoding, BtfRestrict,
+    BtfArray, BtfComposite, BtfConst, BtfEnum, BtfFloat, BtfInt, BtfIntEncoding, BtfRestrict,
     BtfType, BtfTypedef, BtfVolatile,
 };
 use log::debug;
 use serde_json::{json, Value};
 
-use crate::export_event::CheckedExportedMember;
+use crate::{btf_container::BtfResolver, export_event::CheckedExportedMember};
 
 /// The caller is responsible to ensure data is large enough
-pub(crate) fn dump_to_json(btf: &Btf, type_id: u32, data: &[u8]) -> Result<Value> {
+pub(crate) fn dump_to_json(resolver: BtfResolver, type_id: u32, data: &[u8]) -> Result<Value> {
+    let btf = resolver.btf();
     let ty = btf
         .types()
         .get(type_id as usize)
@@ -33,17 +34,21 @@ pub(crate) fn dump_to_json(btf: &Btf, type_id: u32, data: &[u8]) -> Result<Value
     match ty {
         BtfType::Int(btf_int) => dump_int(btf_int, range),
         BtfType::Ptr(_) => dump_pointer(range),
-        BtfType::Array(arr) => dump_array(btf, arr, type_id, range),
-        BtfType::Struct(comp) | BtfType::Union(comp) => dump_composed_type(btf, comp, range),
+        BtfType::Array(arr) => dump_array(resolver, arr, type_id, range),
+        BtfType::Struct(comp) | BtfType::Union(comp) => dump_composed_type(resolver, comp, range),
         BtfType::Enum(btf_enum) => dump_enum(btf_enum, range),
         BtfType::Float(ft) => dump_float(ft, range),
         BtfType::Typedef(BtfTypedef { type_id, .. })
         | BtfType::Volatile(BtfVolatile { type_id })
         | BtfType::Const(BtfConst { type_id })
-        | BtfType::Restrict(BtfRestrict { type_id }) => dump_to_json(btf, *type_id, data),
+        | BtfType::Restrict(BtfRestrict { type_id }) => dump_to_json(resolver, *type_id, data),
+        // Definitions of forward declarations could be found in the kernel BTF
+        BtfType::Fwd(fwd) => {
+            let (resolver, type_id) = resolver.resolve_fwd(fwd)?;
+            dump_to_json(resolver, type_id, data)
+        }
 
         BtfType::Void => bail!("Void type is not supported in dumping"),
-        BtfType::Fwd(_) => bail!("Forawrd is not supported"),
 
         BtfType::Func(_) => bail!("Func is not supported"),
         BtfType::FuncProto(_) => bail!("FuncProto is not supported"),
@@ -55,7 +60,7 @@ pub(crate) fn dump_to_json(btf: &Btf, type_id: u32, data: &[u8]) -> Result<Value
 }
 
 pub(crate) fn dump_to_json_with_checked_types(
-    btf: &Btf,
+    resolver: BtfResolver,
     checked_export_value_member_types: &[CheckedExportedMember],
     data: &[u8],
 ) -> Result<Value> {
@@ -65,7 +70,7 @@ pub(crate) fn dump_to_json_with_checked_types(
         result.insert(
             member.field_name.clone(),
             dump_to_json(
-                btf,
+                resolver.with_source(member.btf_source)?,
                 member.type_id,
                 data.get(
                     (member.bit_offset / 8) as usize
@@ -138,7 +143,13 @@ pub(crate) fn dump_pointer(range: &[u8]) -> Result<Value> {
     })
 }
 
-pub(crate) fn dump_array(btf: &Btf, arr: &BtfArray, type_id: u32, range: &[u8]) -> Result<Value> {
+pub(crate) fn dump_array(
+    resolver: BtfResolver,
+    arr: &BtfArray,
+    type_id: u32,
+    range: &[u8],
+) -> Result<Value> {
+    let btf = resolver.btf();
     // For c-strings, return a string; For arrays in other types, return a json array
     let elem_ty = btf.types().get(arr.val_type_id as usize).ok_or_else(|| {
         anyhow!(
@@ -159,10 +170,10 @@ pub(crate) fn dump_array(btf: &Btf, arr: &BtfArray, type_id: u32, range: &[u8])
     } else {
         // For non-strings, just create a json array and recursively to fill it
         let mut result: Vec<Value> = vec![];
-        let elem_size = btf.get_size_of(arr.val_type_id) as usize;
+        let elem_size = resolver.size_of(arr.val_type_id)? as usize;
         for i in 0..arr.nelems as usize {
             result.push(dump_to_json(
-                btf,
+                resolver,
                 arr.val_type_id,
                 range
                     .get(i * elem_size..(i + 1) * elem_size)
@@ -173,7 +184,11 @@ pub(crate) fn dump_array(btf: &Btf, arr: &BtfArray, type_id: u32, range: &[u8])
     }
 }
 
-pub(crate) fn dump_composed_type(btf: &Btf, comp: &BtfComposite, range: &[u8]) -> Result<Value> {
+pub(crate) fn dump_composed_type(
+    resolver: BtfResolver,
+    comp: &BtfComposite,
+    range: &[u8],
+) -> Result<Value> {
     // For structs or unions, construct a json object and fill elements into that
     let mut result = serde_json::Map::new();
     result.insert(
@@ -209,11 +224,11 @@ pub(crate) fn dump_composed_type(btf: &Btf, comp: &BtfComposite, range: &[u8]) -
             "Current member: name=`{}`, bit_offset={}, bit_size={}",
             elem.name, elem.bit_offset, elem.bit_size
         );
-        let elem_size = btf.get_size_of(elem.type_id);
+        let elem_size = resolver.size_of(elem.type_id)?;
         result.insert(
             elem.name.into(),
             dump_to_json(
-                btf,
+                resolver,
                 elem.type_id,
                 range
                     .get((elem.bit_offset / 8) as usize..(elem.bit_offset / 8 + elem_size) as usize)
@@ -260,7 +275,10 @@ pub(crate) fn dump_float(ft: &BtfFloat, range: &[u8]) -> Result<Value> {
 
 #[cfg(test)]
 mod tests {
-    use crate::tests::{get_assets_dir, ExampleTestStruct};
+    use crate::{
+        btf_container::BtfResolver,
+        tests::{get_assets_dir, ExampleTestStruct},
+    };
     use btf::types::Btf;
     use object::ElfFile;
     use serde::Deserialize;
@@ -275,7 +293,7 @@ mod tests {
         let elf: ElfFile = ElfFile::parse(&elf[..]).unwrap();
         let btf = Btf::load(&elf).unwrap();
         // type_id = 2 is the struct we want
-        let out_json = dump_to_json(&btf, 2, &bin[..]).unwrap();
+        let out_json = dump_to_json(BtfResolver::new(&btf), 2, &bin[..]).unwrap();
         let d: ExampleTestStruct = serde_json::from_value(out_json).unwrap();
         d.test_with_example_data();
     }
@@ -290,7 +308,7 @@ mod tests {
         let elf: ElfFile = ElfFile::parse(&elf[..]).unwrap();
         let btf = Btf::load(&elf).unwrap();
         // type_id = 4 is the struct we want
-        let out_json = dump_to_json(&btf, 4, &bin[..]).unwrap();
+        let out_json = dump_to_json(BtfResolver::new(&btf), 4, &bin[..]).unwrap();
         #[derive(Deserialize)]
         struct S {
             a: String,
@@ -4,13 +4,19 @@
 //! All rights reserved.
 //!
 
-use anyhow::{bail, Result};
-use btf::types::Btf;
+use crate::{
+    btf_container::BtfResolver,
+    export_event::{data_dumper::json::dump_to_json, CheckedExportedMember},
+};
+use anyhow::{anyhow, bail, Result};
 
-use crate::export_event::{data_dumper::json::dump_to_json, CheckedExportedMember};
-
-pub(crate) fn dump_to_string(btf: &Btf, type_id: u32, data: &[u8], out: &mut String) -> Result<()> {
-    out.push_str(&match dump_to_json(btf, type_id, data)? {
+pub(crate) fn dump_to_string(
+    resolver: BtfResolver,
+    type_id: u32,
+    data: &[u8],
+    out: &mut String,
+) -> Result<()> {
+    out.push_str(&match dump_to_json(resolver, type_id, data)? {
         // Remove semicolons..
         serde_json::Value::String(s) => s,
         serde_json::Value::Number(v) => v.to_string(),
@@ -20,7 +26,7 @@ pub(crate) fn dump_to_string(btf: &Btf, type_id: u32, data: &[u8], out: &mut Str
 }
 
 pub(crate) fn dump_to_string_with_checked_types(
-    btf: &Btf,
+    resolver: BtfResolver,
     checked_types: &[CheckedExportedMember],
     data: &[u8],
     out: &mut String,
@@ -39,9 +45,17 @@ pub(crate) fn dump_to_string_with_checked_types(
             );
         }
         dump_to_string(
-            btf,
+            resolver.with_source(member.btf_source)?,
             member.type_id,
-            &data[offset..offset + member.size],
+            data.get(offset..offset + member.size).ok_or_else(|| {
+                anyhow!(
+                    "Input buffer is too small when trying to slice bytes for field {}. \
+                 Required {}..{} to be valid",
+                    member.field_name,
+                    offset,
+                    offset + member.size
+                )
+            })?,
             out,
         )?;
     }
@@ -0,0 +1,494 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Event enrichment
+//!
+//! Programs usually export raw ids, e.g `pid`, `uid` and `cgroup_id`. Enrichers resolve them into readable metadata in userspace, and append it to the events in json and plain text output.
+//!
+//! Resolved metadata is cached for a while, since events of the same process tend to come in bursts.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+
+use crate::meta::{EnrichConfig, EnricherKind};
+
+use super::type_descriptor::CheckedExportedMember;
+
+const PASSWD_PATH: &str = "/etc/passwd";
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// Expired entries are only purged if the cache grows beyond this
+const CACHE_PURGE_THRESHOLD: usize = 4096;
+/// The cgroup hierarchy is walked on the polling thread, so don't walk it more often than this even if the cache TTL is shorter
+const CGROUP_INDEX_MIN_TTL: Duration = Duration::from_secs(1);
+
+/// Adds fields to exported events, derived from the fields the program exports
+pub trait EventEnricher {
+    /// Names of the added fields
+    fn field_names(&self) -> &[&'static str];
+    /// Values of the added fields, in the order of `field_names`. Unresolvable values should be `null`.
+    ///
+    /// `field` reads an integer field of the event by its name
+    fn enrich(&self, field: &dyn Fn(&str) -> Option<u64>) -> Vec<Value>;
+}
+
+/// Create the built-in enrichers in the config
+pub fn build_enrichers(config: &EnrichConfig) -> Vec<Box<dyn EventEnricher>> {
+    let ttl = Duration::from_millis(config.cache_ttl_ms);
+    config
+        .enrichers
+        .iter()
+        .map(|kind| -> Box<dyn EventEnricher> {
+            match kind {
+                EnricherKind::Process => Box::new(ProcessEnricher {
+                    pid_field: config.pid_field.clone(),
+                    cache: TtlCache::new(ttl),
+                }),
+                EnricherKind::User => Box::new(UserEnricher {
+                    uid_field: config.uid_field.clone(),
+                    cache: TtlCache::new(ttl),
+                }),
+                EnricherKind::Container => Box::new(ContainerEnricher {
+                    pid_field: config.pid_field.clone(),
+                    cgroup_id_field: config.cgroup_id_field.clone(),
+                    cache: TtlCache::new(ttl),
+                    cgroups: CgroupIndex::new(Path::new(CGROUP_ROOT), ttl),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Enrichers of an exporter
+#[derive(Default)]
+pub(crate) struct Enrichment {
+    enrichers: Vec<Box<dyn EventEnricher>>,
+}
+
+impl Enrichment {
+    pub(crate) fn new(enrichers: Vec<Box<dyn EventEnricher>>) -> Self {
+        Self { enrichers }
+    }
+    fn field_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.enrichers
+            .iter()
+            .flat_map(|v| v.field_names().iter().copied())
+    }
+    /// Append columns of the added fields to the plain text header
+    pub(crate) fn append_header(&self, header: &mut String) {
+        for name in self.field_names() {
+            let width = Self::column_width(name);
+            let name = name.to_ascii_uppercase();
+            header.push_str(&format!("{name:<width$} "));
+        }
+    }
+    /// Width of the column of a field. Like the other fields, short names are padded to 6 characters
+    fn column_width(name: &str) -> usize {
+        name.len().max(6)
+    }
+    fn values(&self, checked_types: &[CheckedExportedMember], data: &[u8]) -> Vec<Value> {
+        let field = |name: &str| read_integer_field(checked_types, data, name);
+        self.enrichers
+            .iter()
+            .flat_map(|v| v.enrich(&field))
+            .collect()
+    }
+    /// Add the fields to a json object of the event
+    pub(crate) fn enrich_json(
+        &self,
+        checked_types: &[CheckedExportedMember],
+        data: &[u8],
+        event: &mut Value,
+    ) {
+        if self.enrichers.is_empty() {
+            return;
+        }
+        let Value::Object(event) = event else {
+            return;
+        };
+        for (name, value) in self.field_names().zip(self.values(checked_types, data)) {
+            event.insert(name.to_string(), value);
+        }
+    }
+    /// Append the fields to a plain text line of the event, separated by spaces and padded like the header
+    pub(crate) fn enrich_plain_text(
+        &self,
+        checked_types: &[CheckedExportedMember],
+        data: &[u8],
+        out: &mut String,
+    ) {
+        for (name, value) in self.field_names().zip(self.values(checked_types, data)) {
+            let value = match value {
+                Value::Null => "-".to_string(),
+                Value::String(s) => s,
+                v => v.to_string(),
+            };
+            let width = Self::column_width(name);
+            out.push_str(&format!(" {value:<width$}"));
+        }
+    }
+}
+
+/// Read an integer member of the event in native endianness
+fn read_integer_field(
+    checked_types: &[CheckedExportedMember],
+    data: &[u8],
+    name: &str,
+) -> Option<u64> {
+    let member = checked_types.iter().find(|v| v.field_name == name)?;
+    if member.bit_offset % 8 != 0 {
+        return None;
+    }
+    let offset = (member.bit_offset / 8) as usize;
+    let bytes = data.get(offset..offset + member.size)?;
+    Some(match member.size {
+        1 => bytes[0] as u64,
+        2 => u16::from_ne_bytes(bytes.try_into().ok()?) as u64,
+        4 => u32::from_ne_bytes(bytes.try_into().ok()?) as u64,
+        8 => u64::from_ne_bytes(bytes.try_into().ok()?),
+        _ => return None,
+    })
+}
+
+/// A cache whose entries expire after a fixed time
+struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> TtlCache<K, V> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+    fn get_or_insert_with(&self, key: K, resolve: impl FnOnce(&K) -> V) -> V {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if let Some((time, value)) = entries.get(&key) {
+            if now.duration_since(*time) < self.ttl {
+                return value.clone();
+            }
+        }
+        let value = resolve(&key);
+        if entries.len() >= CACHE_PURGE_THRESHOLD {
+            entries.retain(|_, (time, _)| now.duration_since(*time) < self.ttl);
+        }
+        entries.insert(key, (now, value.clone()));
+        value
+    }
+}
+
+/// Adds `exe` and `cmdline` of the process
+struct ProcessEnricher {
+    pid_field: String,
+    cache: TtlCache<u64, (Value, Value)>,
+}
+
+impl EventEnricher for ProcessEnricher {
+    fn field_names(&self) -> &[&'static str] {
+        &["exe", "cmdline"]
+    }
+    fn enrich(&self, field: &dyn Fn(&str) -> Option<u64>) -> Vec<Value> {
+        let Some(pid) = field(&self.pid_field) else {
+            return vec![Value::Null, Value::Null];
+        };
+        let (exe, cmdline) = self.cache.get_or_insert_with(pid, |pid| {
+            let proc_dir = PathBuf::from(format!("/proc/{pid}"));
+            let exe = std::fs::read_link(proc_dir.join("exe"))
+                .map(|v| json!(v.to_string_lossy()))
+                .unwrap_or(Value::Null);
+            let cmdline = std::fs::read(proc_dir.join("cmdline"))
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(|v| {
+                    let args = v
+                        .split(|c| *c == 0)
+                        .filter(|v| !v.is_empty())
+                        .map(String::from_utf8_lossy)
+                        .collect::<Vec<_>>();
+                    json!(args.join(" "))
+                })
+                .unwrap_or(Value::Null);
+            (exe, cmdline)
+        });
+        vec![exe, cmdline]
+    }
+}
+
+/// Adds `username` of the uid
+struct UserEnricher {
+    uid_field: String,
+    cache: TtlCache<u64, Value>,
+}
+
+impl EventEnricher for UserEnricher {
+    fn field_names(&self) -> &[&'static str] {
+        &["username"]
+    }
+    fn enrich(&self, field: &dyn Fn(&str) -> Option<u64>) -> Vec<Value> {
+        let Some(uid) = field(&self.uid_field) else {
+            return vec![Value::Null];
+        };
+        vec![self.cache.get_or_insert_with(uid, |uid| {
+            std::fs::read_to_string(PASSWD_PATH)
+                .ok()
+                .and_then(|v| find_username(&v, *uid))
+                .map(|v| json!(v))
+                .unwrap_or(Value::Null)
+        })]
+    }
+}
+
+/// Find the name of a uid in the content of `/etc/passwd`
+fn find_username(passwd: &str, uid: u64) -> Option<String> {
+    passwd.lines().find_map(|line| {
+        let mut parts = line.split(':');
+        let name = parts.next()?;
+        let entry_uid = parts.nth(1)?.parse::<u64>().ok()?;
+        (entry_uid == uid).then(|| name.to_string())
+    })
+}
+
+#[derive(Hash, PartialEq, Eq, Clone)]
+enum ContainerKey {
+    Pid(u64),
+    CgroupId(u64),
+}
+
+/// Adds `container_id` and `container_runtime` of the process, or of the cgroup if the event has no pid
+struct ContainerEnricher {
+    pid_field: String,
+    cgroup_id_field: String,
+    cache: TtlCache<ContainerKey, Option<(String, &'static str)>>,
+    cgroups: CgroupIndex,
+}
+
+impl EventEnricher for ContainerEnricher {
+    fn field_names(&self) -> &[&'static str] {
+        &["container_id", "container_runtime"]
+    }
+    fn enrich(&self, field: &dyn Fn(&str) -> Option<u64>) -> Vec<Value> {
+        let key = match (field(&self.pid_field), field(&self.cgroup_id_field)) {
+            (Some(pid), _) => ContainerKey::Pid(pid),
+            (None, Some(id)) => ContainerKey::CgroupId(id),
+            (None, None) => return vec![Value::Null, Value::Null],
+        };
+        let container = self.cache.get_or_insert_with(key, |key| {
+            let cgroup_path = match key {
+                ContainerKey::Pid(pid) => std::fs::read_to_string(format!("/proc/{pid}/cgroup"))
+                    .ok()
+                    .and_then(|v| {
+                        // The unified hierarchy is `0::<PATH>`. With cgroup v1, any hierarchy with the container in its path works
+                        v.lines()
+                            .filter_map(|line| line.splitn(3, ':').nth(2).map(str::to_string))
+                            .find(|path| parse_container(path).is_some())
+                    }),
+                ContainerKey::CgroupId(id) => self.cgroups.path_of(*id),
+            };
+            cgroup_path.and_then(|v| parse_container(&v))
+        });
+        match container {
+            Some((id, runtime)) => vec![json!(id), json!(runtime)],
+            None => vec![Value::Null, Value::Null],
+        }
+    }
+}
+
+/// Paths of cgroups by their ids. Walking the hierarchy is slow, so it's walked at most once per TTL instead of once per id
+struct CgroupIndex {
+    root: PathBuf,
+    ttl: Duration,
+    paths: Mutex<Option<(Instant, HashMap<u64, String>)>>,
+}
+
+impl CgroupIndex {
+    fn new(root: &Path, ttl: Duration) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            ttl: ttl.max(CGROUP_INDEX_MIN_TTL),
+            paths: Mutex::new(None),
+        }
+    }
+    fn path_of(&self, id: u64) -> Option<String> {
+        let now = Instant::now();
+        let mut paths = self.paths.lock().unwrap();
+        let expired = paths
+            .as_ref()
+            .map_or(true, |(time, _)| now.duration_since(*time) >= self.ttl);
+        if expired {
+            *paths = Some((now, index_cgroups(&self.root)));
+        }
+        paths.as_ref()?.1.get(&id).cloned()
+    }
+}
+
+/// With cgroup v2, the id of a cgroup is the inode number of its directory
+fn index_cgroups(root: &Path) -> HashMap<u64, String> {
+    let mut paths = HashMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_dir() {
+                continue;
+            }
+            let path = entry.path();
+            if let Ok(relative) = path.strip_prefix(root) {
+                paths.insert(metadata.ino(), format!("/{}", relative.display()));
+            }
+            dirs.push(path);
+        }
+    }
+    paths
+}
+
+/// Extract the container id and the runtime from a cgroup path, e.g
+/// - `/system.slice/docker-<ID>.scope` or `/docker/<ID>` for docker
+/// - `/kubepods.slice/.../cri-containerd-<ID>.scope` for containerd
+/// - `/kubepods.slice/.../crio-<ID>.scope` for cri-o
+/// - `/machine.slice/libpod-<ID>.scope` for podman
+fn parse_container(cgroup_path: &str) -> Option<(String, &'static str)> {
+    const PREFIXES: [(&str, &str); 6] = [
+        ("docker-", "docker"),
+        ("cri-containerd-", "containerd"),
+        ("crio-", "cri-o"),
+        ("libpod-", "podman"),
+        ("docker/", "docker"),
+        ("containerd/", "containerd"),
+    ];
+    let components = cgroup_path.split('/').collect::<Vec<_>>();
+    // The innermost container wins
+    for (idx, component) in components.iter().enumerate().rev() {
+        let component = component.trim_end_matches(".scope");
+        for (prefix, runtime) in PREFIXES.iter() {
+            let id = match prefix.strip_suffix('/') {
+                // The id is the component after a directory named by the runtime
+                Some(dir) if idx > 0 && components[idx - 1] == dir => component,
+                Some(_) => continue,
+                None => match component.strip_prefix(prefix) {
+                    Some(id) => id,
+                    None => continue,
+                },
+            };
+            if id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Some((id.to_string(), runtime));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::MetadataExt, time::Duration};
+
+    use serde_json::{json, Value};
+
+    use crate::meta::{EnrichConfig, EnricherKind};
+
+    use super::{
+        build_enrichers, find_username, parse_container, CgroupIndex, CGROUP_INDEX_MIN_TTL,
+    };
+
+    const ID: &str = "3f1c2a8e9b7d6c5e4f3a2b1c0d9e8f7a6b5c4d3e2f1a0b9c8d7e6f5a4b3c2d1e";
+
+    #[test]
+    fn test_parse_container() {
+        for (path, runtime) in [
+            (format!("/system.slice/docker-{ID}.scope"), "docker"),
+            (format!("/docker/{ID}"), "docker"),
+            (
+                format!("/kubepods.slice/kubepods-pod1.slice/cri-containerd-{ID}.scope"),
+                "containerd",
+            ),
+            (
+                format!("/kubepods/besteffort/pod1/crio-{ID}.scope"),
+                "cri-o",
+            ),
+            (
+                format!("/machine.slice/libpod-{ID}.scope/container"),
+                "podman",
+            ),
+        ] {
+            assert_eq!(
+                parse_container(&path),
+                Some((ID.to_string(), runtime)),
+                "{path}"
+            );
+        }
+        assert_eq!(parse_container("/user.slice/user-1000.slice"), None);
+        assert_eq!(parse_container("/docker/not-an-id"), None);
+    }
+
+    #[test]
+    fn test_find_username() {
+        let passwd = "root:x:0:0:root:/root:/bin/bash\n\nnobody:x:65534:65534::/:/sbin/nologin";
+        assert_eq!(find_username(passwd, 0), Some("root".into()));
+        assert_eq!(find_username(passwd, 65534), Some("nobody".into()));
+        assert_eq!(find_username(passwd, 1000), None);
+    }
+
+    #[test]
+    fn test_process_enricher() {
+        let enrichers = build_enrichers(&EnrichConfig {
+            enrichers: vec![EnricherKind::Process],
+            pid_field: "pid".into(),
+            uid_field: "uid".into(),
+            cgroup_id_field: "cgroup_id".into(),
+            cache_ttl_ms: 1000,
+        });
+        let pid = std::process::id() as u64;
+        let values = enrichers[0].enrich(&|name| (name == "pid").then_some(pid));
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(values[0], json!(exe.to_string_lossy()));
+        assert!(values[1].as_str().unwrap().contains("bpf_loader_lib"));
+        // Served from the cache
+        assert_eq!(enrichers[0].enrich(&|_| Some(pid)), values);
+        assert_eq!(
+            enrichers[0].enrich(&|_| None),
+            vec![Value::Null, Value::Null]
+        );
+    }
+
+    #[test]
+    fn test_cgroup_index() {
+        let root = std::env::temp_dir().join(format!("cgroup-index-test-{}", std::process::id()));
+        let nested = root.join(format!("docker/{ID}"));
+        std::fs::create_dir_all(&nested).unwrap();
+        let id = std::fs::metadata(&nested).unwrap().ino();
+        let index = CgroupIndex::new(&root, Duration::from_secs(60));
+        assert_eq!(index.path_of(id), Some(format!("/docker/{ID}")));
+        // Directories created after indexing are found once the index expires
+        let later = root.join("later");
+        std::fs::create_dir(&later).unwrap();
+        let later_id = std::fs::metadata(&later).unwrap().ino();
+        assert_eq!(index.path_of(later_id), None);
+        if let Some((time, _)) = index.paths.lock().unwrap().as_mut() {
+            *time = time.checked_sub(Duration::from_secs(60)).unwrap();
+        }
+        assert_eq!(index.path_of(later_id), Some("/later".into()));
+        // A zero cache TTL doesn't make it walk the hierarchy on every lookup
+        assert_eq!(
+            CgroupIndex::new(&root, Duration::ZERO).ttl,
+            CGROUP_INDEX_MIN_TTL
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
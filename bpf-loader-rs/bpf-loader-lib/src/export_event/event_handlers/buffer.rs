@@ -41,20 +41,15 @@ impl InternalBufferValueEventProcessor for JsonExportEventHandler {
             _ => bail!("Unexpected"),
         };
 
-        let result = dump_to_json_with_checked_types(
-            exporter.btf_container.borrow_btf(),
+        let mut result = dump_to_json_with_checked_types(
+            exporter.btf_container.resolver(),
             checked_export_value_member_types,
             data,
         )?;
-        let str_out = serde_json::to_string(&json!(result))?;
-        if let Some(v) = exporter.user_export_event_handler.as_ref() {
-            v.handle_event(
-                exporter.user_ctx.clone(),
-                ReceivedEventData::JsonText(&str_out),
-            );
-        } else {
-            println!("{str_out}");
-        }
+        exporter
+            .enrichment
+            .enrich_json(checked_export_value_member_types, data, &mut result);
+        exporter.dump_json_to_user_callback_or_stdout(&json!(result));
         Ok(())
     }
 }
@@ -93,11 +88,14 @@ impl InternalBufferValueEventProcessor for PlainStringExportEventHandler {
         };
 
         dump_to_string_with_checked_types(
-            exporter.btf_container.borrow_btf(),
+            exporter.btf_container.resolver(),
             checked_export_value_member_types,
             data,
             &mut outbuf,
         )?;
+        exporter
+            .enrichment
+            .enrich_plain_text(checked_export_value_member_types, data, &mut outbuf);
         exporter
             .dump_data_to_user_callback_or_stdout(ReceivedEventData::PlainText(outbuf.as_str()));
 
@@ -158,7 +156,7 @@ impl InternalBufferValueEventProcessor for PlainTextStackTraceExportEventHandler
         };
 
         let result = dump_to_json_with_checked_types(
-            exporter.btf_container.borrow_btf(),
+            exporter.btf_container.resolver(),
             checked_export_value_member_types,
             data,
         )?;
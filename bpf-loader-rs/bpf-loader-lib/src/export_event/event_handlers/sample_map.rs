@@ -31,7 +31,7 @@ pub(crate) struct JsonExportEventHandler {
 impl InternalSampleMapProcessor for JsonExportEventHandler {
     fn handle_event(&self, key_buffer: &[u8], value_buffer: &[u8]) -> Result<()> {
         let exporter = self.exporter.upgrade().unwrap();
-        let btf = exporter.btf_container.borrow_btf();
+        let resolver = exporter.btf_container.resolver();
         let (checked_key_types, checked_value_types) =
             if let ExporterInternalImplementation::KeyValueMapProcessor {
                 ref checked_key_types,
@@ -43,17 +43,16 @@ impl InternalSampleMapProcessor for JsonExportEventHandler {
             } else {
                 bail!("Unexpected internal implementation");
             };
-        let key_out = dump_to_json_with_checked_types(btf, checked_key_types, key_buffer)
+        let key_out = dump_to_json_with_checked_types(resolver, checked_key_types, key_buffer)
             .with_context(|| anyhow!("Failed to dump key type to json"))?;
-        let value_out = dump_to_json_with_checked_types(btf, checked_value_types, value_buffer)
-            .with_context(|| anyhow!("Failed to dump value type to json"))?;
+        let value_out =
+            dump_to_json_with_checked_types(resolver, checked_value_types, value_buffer)
+                .with_context(|| anyhow!("Failed to dump value type to json"))?;
         let final_json = json!({
             "key":key_out,
             "value":value_out
         });
-        let out_str = serde_json::to_string(&final_json)
-            .with_context(|| anyhow!("Failed to serialize json"))?;
-        exporter.dump_data_to_user_callback_or_stdout(ReceivedEventData::JsonText(&out_str));
+        exporter.dump_json_to_user_callback_or_stdout(&final_json);
         Ok(())
     }
 }
@@ -87,7 +86,7 @@ pub(crate) struct DefaultKVStringExportEventHandler {
 impl InternalSampleMapProcessor for DefaultKVStringExportEventHandler {
     fn handle_event(&self, key_buffer: &[u8], value_buffer: &[u8]) -> Result<()> {
         let exporter = self.exporter.upgrade().unwrap();
-        let btf = exporter.btf_container.borrow_btf();
+        let resolver = exporter.btf_container.resolver();
         let (checked_key_types, checked_value_types) =
             if let ExporterInternalImplementation::KeyValueMapProcessor {
                 ref checked_key_types,
@@ -105,8 +104,8 @@ impl InternalSampleMapProcessor for DefaultKVStringExportEventHandler {
         write!(
             outbuf,
             "{} {}",
-            dump_to_json_with_checked_types(btf, checked_key_types, key_buffer)?,
-            dump_to_json_with_checked_types(btf, checked_value_types, value_buffer)?
+            dump_to_json_with_checked_types(resolver, checked_key_types, key_buffer)?,
+            dump_to_json_with_checked_types(resolver, checked_value_types, value_buffer)?
         )
         .unwrap();
         exporter
@@ -122,7 +121,7 @@ pub(crate) struct Log2HistExportEventHandler {
 impl InternalSampleMapProcessor for Log2HistExportEventHandler {
     fn handle_event(&self, key_buffer: &[u8], value_buffer: &[u8]) -> Result<()> {
         let exporter = self.exporter.upgrade().unwrap();
-        let btf = exporter.btf_container.borrow_btf();
+        let resolver = exporter.btf_container.resolver();
         let (checked_key_types, checked_value_types, sample_map_config) =
             if let ExporterInternalImplementation::KeyValueMapProcessor {
                 ref checked_key_types,
@@ -137,7 +136,7 @@ impl InternalSampleMapProcessor for Log2HistExportEventHandler {
             };
         let mut outbuf = String::default();
         write!(outbuf, "key = ").unwrap();
-        dump_to_string_with_checked_types(btf, checked_key_types, key_buffer, &mut outbuf)?;
+        dump_to_string_with_checked_types(resolver, checked_key_types, key_buffer, &mut outbuf)?;
         writeln!(outbuf).unwrap();
         struct SlotsDef {
             offset: u32,
@@ -158,7 +157,7 @@ impl InternalSampleMapProcessor for Log2HistExportEventHandler {
             } else {
                 write!(outbuf, "{} = ", member.field_name).unwrap();
                 dump_to_string(
-                    btf,
+                    resolver.with_source(member.btf_source)?,
                     member.type_id,
                     &value_buffer[offset as usize..offset as usize + member.size],
                     &mut outbuf,
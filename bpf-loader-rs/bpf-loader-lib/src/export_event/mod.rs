@@ -39,33 +39,59 @@ use crate::{
 };
 use anyhow::{anyhow, bail, Context, Result};
 use log::debug;
-use std::{any::Any, fmt::Display, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{any::Any, fmt::Display, str::FromStr, sync::Arc};
 
 use self::{
     checker::check_export_types_btf,
+    enricher::{Enrichment, EventEnricher},
     event_handlers::{buffer, get_plain_text_checked_types_header, sample_map},
     type_descriptor::{CheckedExportedMember, TypeDescriptor},
 };
 
 pub(crate) mod checker;
 pub(crate) mod data_dumper;
+/// Add metadata resolved in userspace to the events
+pub mod enricher;
 pub(crate) mod event_handlers;
 #[cfg(test)]
 mod tests;
 /// Contains utilities to describe where to obtain the export type of a map
 pub mod type_descriptor;
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 /// Describe the export format type
 pub enum ExportFormatType {
     /// Use human-readable texts to output
+    #[serde(rename = "plain_text", alias = "plain")]
     PlainText,
     /// Use machine-readable json to output
+    #[serde(rename = "json")]
     Json,
     /// Only call the callback with raw buffer
+    #[serde(rename = "raw_event", alias = "raw")]
     RawEvent,
 }
+
+impl FromStr for ExportFormatType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "plain_text" | "plain" => Ok(Self::PlainText),
+            "json" => Ok(Self::Json),
+            "raw_event" | "raw" => Ok(Self::RawEvent),
+            s => bail!(
+                "Invalid export format `{}`, expected one of plain_text, json and raw_event",
+                s
+            ),
+        }
+    }
+}
 #[derive(Debug)]
 /// Represents a sample data that the user will receive
+///
+/// More kinds of data may be added, so matches on it outside this crate need a wildcard arm
+#[non_exhaustive]
 pub enum ReceivedEventData<'a> {
     /// Raw buffer. will be used on simple value sampling pairing with `ExportFormatType::RawEvent`
     Buffer(&'a [u8]),
@@ -78,6 +104,8 @@ pub enum ReceivedEventData<'a> {
     PlainText(&'a str),
     // Json string. Will be used on `ExportFormatType::Json`
     JsonText(&'a str),
+    /// A line printed by `bpf_printk`, read from trace_pipe. Will be used if `RunnerConfig::print_kernel_debug` is set, regardless of the export format
+    KernelDebugText(&'a str),
 }
 impl<'a> Display for ReceivedEventData<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -88,7 +116,9 @@ impl<'a> Display for ReceivedEventData<'a> {
             ReceivedEventData::KeyValueBuffer { key, value } => {
                 write!(f, "key: {key:?} value: {value:?}")?;
             }
-            ReceivedEventData::PlainText(s) | ReceivedEventData::JsonText(s) => {
+            ReceivedEventData::PlainText(s)
+            | ReceivedEventData::JsonText(s)
+            | ReceivedEventData::KernelDebugText(s) => {
                 write!(f, "{s}")?;
             }
         }
@@ -104,6 +134,7 @@ impl<'a> ReceivedEventData<'a> {
             ReceivedEventData::KeyValueBuffer { value, .. } => value,
             ReceivedEventData::PlainText(txt) => txt.as_bytes(),
             ReceivedEventData::JsonText(txt) => txt.as_bytes(),
+            ReceivedEventData::KernelDebugText(txt) => txt.as_bytes(),
         }
     }
 }
@@ -111,6 +142,16 @@ impl<'a> ReceivedEventData<'a> {
 /// A handler to receive events provided by ebpf kernel program
 pub trait EventHandler {
     fn handle_event(&self, context: Option<Arc<dyn Any>>, data: ReceivedEventData);
+    /// Receive the header line of `ExportFormatType::PlainText`, which is sent once before the events
+    /// By default, it's passed to `handle_event` as `ReceivedEventData::PlainText`
+    fn handle_header(&self, context: Option<Arc<dyn Any>>, header: &str) {
+        self.handle_event(context, ReceivedEventData::PlainText(header));
+    }
+    /// Receive an event of `ExportFormatType::Json` before it's serialized, so bindings could convert the decoded value directly
+    /// By default, it's serialized and passed to `handle_event` as `ReceivedEventData::JsonText`
+    fn handle_json_event(&self, context: Option<Arc<dyn Any>>, value: &serde_json::Value) {
+        self.handle_event(context, ReceivedEventData::JsonText(&value.to_string()));
+    }
 }
 
 pub(crate) enum ExporterInternalImplementation {
@@ -140,6 +181,8 @@ pub struct EventExporter {
     /// user-defined context
     pub(crate) user_ctx: Option<Arc<dyn Any>>,
     pub(crate) btf_container: Arc<BtfContainer>,
+    /// Metadata added to json and plain text events
+    pub(crate) enrichment: Enrichment,
 }
 
 impl EventExporter {
@@ -150,6 +193,13 @@ impl EventExporter {
             data,
         );
     }
+    pub(crate) fn dump_json_to_user_callback_or_stdout(&self, value: &serde_json::Value) {
+        dump_json_to_user_callback_or_stdout(
+            self.user_export_event_handler.clone(),
+            self.user_ctx.clone(),
+            value,
+        );
+    }
 }
 pub(crate) fn dump_data_to_user_callback_or_stdout(
     user_export_event_handler: Option<Arc<dyn EventHandler>>,
@@ -162,6 +212,28 @@ pub(crate) fn dump_data_to_user_callback_or_stdout(
         println!("{data}");
     }
 }
+pub(crate) fn dump_json_to_user_callback_or_stdout(
+    user_export_event_handler: Option<Arc<dyn EventHandler>>,
+    user_ctx: Option<Arc<dyn Any>>,
+    value: &serde_json::Value,
+) {
+    if let Some(callback) = user_export_event_handler.as_ref() {
+        callback.handle_json_event(user_ctx, value);
+    } else {
+        println!("{value}");
+    }
+}
+pub(crate) fn dump_header_to_user_callback_or_stdout(
+    user_export_event_handler: Option<Arc<dyn EventHandler>>,
+    user_ctx: Option<Arc<dyn Any>>,
+    header: &str,
+) {
+    if let Some(callback) = user_export_event_handler.as_ref() {
+        callback.handle_header(user_ctx, header);
+    } else {
+        println!("{header}");
+    }
+}
 pub(crate) trait InternalBufferValueEventProcessor {
     fn handle_event(&self, data: &[u8]) -> Result<()>;
 }
@@ -175,6 +247,7 @@ pub struct EventExporterBuilder {
     export_format: ExportFormatType,
     export_event_handler: Option<Arc<dyn EventHandler>>,
     user_ctx: Option<Arc<dyn Any>>,
+    enrichers: Vec<Box<dyn EventEnricher>>,
 }
 
 impl Default for EventExporterBuilder {
@@ -183,6 +256,7 @@ impl Default for EventExporterBuilder {
             export_format: ExportFormatType::PlainText,
             export_event_handler: None,
             user_ctx: None,
+            enrichers: vec![],
         }
     }
 }
@@ -213,20 +287,25 @@ impl EventExporterBuilder {
             ..self
         }
     }
+    /// Set enrichers that add fields to the events. They only apply to single values exported as json or plain text
+    pub fn set_enrichers(self, enrichers: Vec<Box<dyn EventEnricher>>) -> Self {
+        Self { enrichers, ..self }
+    }
     /// Build an exporter use TypeDescriptor. Which can easily specify the source to obtain the value type
     pub fn build_for_single_value_with_type_descriptor(
-        self,
+        mut self,
         export_type: TypeDescriptor,
         btf_container: Arc<BtfContainer>,
         intepreter: &BufferValueInterpreter,
     ) -> Result<Arc<EventExporter>> {
         let mut checked_exported_members =
-            export_type.build_checked_exported_members(btf_container.borrow_btf())?;
+            export_type.build_checked_exported_members(btf_container.resolver())?;
         if matches!(intepreter, BufferValueInterpreter::StackTrace { .. })
             && !matches!(self.export_format, ExportFormatType::PlainText)
         {
             bail!("Intepreter `stack_trace` could only be paired with plaintext export format");
         }
+        let enrichment = Enrichment::new(std::mem::take(&mut self.enrichers));
         Ok(Arc::new_cyclic(move |me| {
             let internal_event_processor: Box<dyn InternalBufferValueEventProcessor> =
                 match (self.export_format, intepreter) {
@@ -250,14 +329,15 @@ impl EventExporterBuilder {
                         })
                     }
                     (ExportFormatType::PlainText, BufferValueInterpreter::DefaultStruct) => {
-                        let header = get_plain_text_checked_types_header(
+                        let mut header = get_plain_text_checked_types_header(
                             &mut checked_exported_members,
                             "TIME     ",
                         );
-                        dump_data_to_user_callback_or_stdout(
+                        enrichment.append_header(&mut header);
+                        dump_header_to_user_callback_or_stdout(
                             self.export_event_handler.clone(),
                             self.user_ctx.clone(),
-                            ReceivedEventData::PlainText(header.as_str()),
+                            header.as_str(),
                         );
                         Box::new(buffer::PlainStringExportEventHandler {
                             exporter: me.clone(),
@@ -279,6 +359,7 @@ impl EventExporterBuilder {
                     event_processor: internal_event_processor,
                     checked_types: checked_exported_members,
                 },
+                enrichment,
             }
         }))
     }
@@ -291,7 +372,7 @@ impl EventExporterBuilder {
         btf_container: Arc<BtfContainer>,
         intepreter: &BufferValueInterpreter,
     ) -> Result<Arc<EventExporter>> {
-        let checked_members = check_export_types_btf(export_type, btf_container.borrow_btf())?;
+        let checked_members = check_export_types_btf(export_type, btf_container.resolver())?;
         Self::build_for_single_value_with_type_descriptor(
             self,
             TypeDescriptor::CheckedMembers(checked_members),
@@ -308,9 +389,9 @@ impl EventExporterBuilder {
         btf_container: Arc<BtfContainer>,
     ) -> Result<Arc<EventExporter>> {
         let mut checked_key_types =
-            key_export_type.build_checked_exported_members(btf_container.borrow_btf())?;
+            key_export_type.build_checked_exported_members(btf_container.resolver())?;
         let mut checked_value_types =
-            value_export_type.build_checked_exported_members(btf_container.borrow_btf())?;
+            value_export_type.build_checked_exported_members(btf_container.resolver())?;
 
         if matches!(self.export_format, ExportFormatType::PlainText)
             && matches!(sample_config.ty, SampleMapType::LinearHist)
@@ -331,10 +412,10 @@ impl EventExporterBuilder {
                             get_plain_text_checked_types_header(&mut checked_key_types, header);
                         let header =
                             get_plain_text_checked_types_header(&mut checked_value_types, header);
-                        dump_data_to_user_callback_or_stdout(
+                        dump_header_to_user_callback_or_stdout(
                             self.export_event_handler.clone(),
                             self.user_ctx.clone(),
-                            ReceivedEventData::PlainText(header.as_str()),
+                            header.as_str(),
                         );
                         Box::new(sample_map::DefaultKVStringExportEventHandler {
                             exporter: me.clone(),
@@ -359,6 +440,7 @@ impl EventExporterBuilder {
                 },
                 user_ctx: self.user_ctx,
                 btf_container,
+                enrichment: Enrichment::default(),
             }
         }))
     }
@@ -376,11 +458,11 @@ impl EventExporterBuilder {
         export_type: &ExportedTypesStructMeta,
         btf_container: Arc<BtfContainer>,
     ) -> Result<Arc<EventExporter>> {
-        let btf = btf_container.borrow_btf();
-        let checked_key_types = check_sample_types_btf(btf, key_type_id, None)
+        let resolver = btf_container.resolver();
+        let checked_key_types = check_sample_types_btf(resolver, key_type_id, None)
             .with_context(|| anyhow!("Failed to check key type"))?;
         let checked_value_types =
-            check_sample_types_btf(btf, value_type_id, Some(export_type.clone()))
+            check_sample_types_btf(resolver, value_type_id, Some(export_type.clone()))
                 .with_context(|| anyhow!("Failed to check value type"))?;
         self.build_for_key_value_with_type_desc(
             TypeDescriptor::CheckedMembers(checked_key_types),
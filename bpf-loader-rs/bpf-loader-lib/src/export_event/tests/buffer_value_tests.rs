@@ -9,6 +9,7 @@ use std::{cell::RefCell, rc::Rc, sync::Arc};
 use crate::{
     btf_container::BtfContainer,
     export_event::{
+        enricher::EventEnricher,
         tests::{load_triple, RRC},
         EventExporter, EventExporterBuilder, EventHandler, ExportFormatType,
         ExporterInternalImplementation,
@@ -253,3 +254,64 @@ fn test_stacktrace_exporter() {
     let inner_data = received_data.borrow()[0].clone();
     assert_eq!(inner_data, STACKTRACE_EXPECTED_OUTPUT);
 }
+
+/// Doubles `u8v` of the example struct
+struct DoubleU8Enricher;
+impl EventEnricher for DoubleU8Enricher {
+    fn field_names(&self) -> &[&'static str] {
+        &["double_u8v"]
+    }
+    fn enrich(&self, field: &dyn Fn(&str) -> Option<u64>) -> Vec<serde_json::Value> {
+        vec![serde_json::json!(field("u8v").map(|v| v * 2))]
+    }
+}
+
+#[test]
+fn test_enrichers() {
+    let (btf, bin_data, skel) = load_triple();
+    let received_data = Rc::new(RefCell::new(Vec::new()));
+
+    struct MyEventHandler {
+        data: RRC<Vec<String>>,
+    }
+    impl EventHandler for MyEventHandler {
+        fn handle_event(
+            &self,
+            _context: Option<std::sync::Arc<dyn std::any::Any>>,
+            data: crate::export_event::ReceivedEventData,
+        ) {
+            match data {
+                crate::export_event::ReceivedEventData::PlainText(s)
+                | crate::export_event::ReceivedEventData::JsonText(s) => {
+                    self.data.borrow_mut().push(s.to_string());
+                }
+                _ => panic!("Unexpected data type"),
+            }
+        }
+    }
+    for export_format in [ExportFormatType::Json, ExportFormatType::PlainText] {
+        let exporter = EventExporterBuilder::new()
+            .set_export_event_handler(Arc::new(MyEventHandler {
+                data: received_data.clone(),
+            }))
+            .set_export_format(export_format)
+            .set_enrichers(vec![Box::new(DoubleU8Enricher)])
+            .build_for_single_value(
+                &skel.export_types[0],
+                btf.clone(),
+                &BufferValueInterpreter::DefaultStruct,
+            )
+            .unwrap();
+        send_data(exporter, &bin_data[..]);
+    }
+    let inner_data = received_data.borrow();
+    let json_event: serde_json::Value = serde_json::from_str(&inner_data[0]).unwrap();
+    assert_eq!(json_event["double_u8v"], 36);
+    assert_eq!(json_event["u8v"], 18);
+    assert_eq!(
+        inner_data[1],
+        format!("{EXPECTED_PLAIN_TEXT_OUTPUT_LINE1}DOUBLE_U8V ")
+    );
+    // Padded to the width of `DOUBLE_U8V`
+    assert!(inner_data[2].ends_with(&format!("{EXPECTED_PLAIN_TEXT_OUTPUT_LINE2} 36        ")));
+}
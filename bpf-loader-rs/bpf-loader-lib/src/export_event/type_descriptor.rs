@@ -3,10 +3,13 @@
 //! Copyright (c) 2023, eunomia-bpf
 //! All rights reserved.
 //!
-use anyhow::{anyhow, bail, Result};
-use btf::types::{Btf, BtfType};
+use anyhow::{bail, Result};
+use btf::types::BtfType;
 
-use crate::{helper::btf::BtfHelper, meta::OverridedStructMember};
+use crate::{
+    btf_container::{BtfResolver, BtfSource},
+    meta::OverridedStructMember,
+};
 
 /// Indicates a checked (able to directly used) struct member of a map's export type
 #[derive(Debug, Clone)]
@@ -16,6 +19,8 @@ pub struct CheckedExportedMember {
     pub(crate) bit_offset: u32,
     pub(crate) size: usize,
     pub(crate) output_header_offset: usize,
+    /// Which BTF `type_id` belongs to. Members of structs found in the kernel BTF are in the kernel's
+    pub(crate) btf_source: BtfSource,
 }
 /// Describe the source to obtain `Vec<CheckedExportedStructMember>` of a certain map
 pub enum TypeDescriptor {
@@ -30,8 +35,9 @@ pub enum TypeDescriptor {
 impl TypeDescriptor {
     pub(crate) fn build_checked_exported_members(
         self,
-        btf: &Btf,
+        resolver: BtfResolver,
     ) -> Result<Vec<CheckedExportedMember>> {
+        let btf = resolver.btf();
         let ret = match self {
             Self::ManuallyOverride(mut override_mems) => {
                 let mut result = vec![];
@@ -48,23 +54,27 @@ impl TypeDescriptor {
                     if mem.offset < last_pos {
                         bail!("Field `{}` overflapped with other fields", mem.name);
                     }
-                    last_pos = mem.offset + btf.get_size_of(mem.btf_type_id) as usize;
+                    let size = resolver.size_of(mem.btf_type_id)? as usize;
+                    last_pos = mem.offset + size;
                     result.push(CheckedExportedMember {
                         field_name: mem.name,
                         type_id: mem.btf_type_id,
                         bit_offset: (mem.offset * 8) as u32,
-                        size: btf.get_size_of(mem.btf_type_id) as usize,
+                        size,
                         output_header_offset: 0,
+                        btf_source: resolver.source,
                     });
                 }
                 result
             }
             Self::BtfType { type_id } => {
-                let ty = btf
-                    .types()
-                    .get(type_id as usize)
-                    .ok_or_else(|| anyhow!("Invalid btf type id: {}", type_id))?;
-                if let BtfType::Struct(st) = ty {
+                if btf.types().get(type_id as usize).is_none() {
+                    bail!("Invalid btf type id: {}", type_id);
+                }
+                // The struct may be a forward declaration defined in the kernel BTF
+                let (struct_resolver, real_type_id) = resolver.resolve_real_type(type_id)?;
+                let real_ty = struct_resolver.btf().type_by_id(real_type_id);
+                if let BtfType::Struct(st) = real_ty {
                     let mut result = vec![];
                     for member in st.members.iter() {
                         if member.bit_offset % 8 != 0 {
@@ -74,13 +84,14 @@ impl TypeDescriptor {
                             bit_offset: member.bit_offset,
                             field_name: member.name.to_string(),
                             output_header_offset: 0,
-                            size: btf.get_size_of(member.type_id) as usize,
+                            size: struct_resolver.size_of(member.type_id)? as usize,
                             type_id: member.type_id,
+                            btf_source: struct_resolver.source,
                         });
                     }
                     result
                 } else if matches!(
-                    btf.type_by_id(btf.resolve_real_type(type_id)?),
+                    real_ty,
                     BtfType::Array(_) | BtfType::Int(_) | BtfType::Float(_) | BtfType::Ptr(_)
                 ) {
                     vec![CheckedExportedMember {
@@ -88,7 +99,8 @@ impl TypeDescriptor {
                         type_id,
                         field_name: "".to_string(),
                         output_header_offset: 0,
-                        size: btf.get_size_of(type_id) as usize,
+                        size: resolver.size_of(type_id)? as usize,
+                        btf_source: resolver.source,
                     }]
                 } else {
                     bail!("Unsupported type when building exporter: {}", type_id)
@@ -4,7 +4,7 @@
 //! All rights reserved.
 //!
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use btf::types::{BtfConst, BtfIntEncoding, BtfRestrict, BtfType, BtfTypedef, BtfVolatile};
 use faerie::{ArtifactBuilder, Decl, SectionKind};
 use std::str::FromStr;
@@ -25,6 +25,116 @@ pub(crate) fn create_elf_with_btf_section(btf_data: &[u8], is_64: bool) -> Resul
     Ok(obj.emit()?)
 }
 
+const BTF_MAGIC: u16 = 0xeb9f;
+const BTF_HEADER_LEN: usize = 24;
+const BTF_KIND_INT: u32 = 1;
+const BTF_KIND_ENUM64: u32 = 19;
+const BTF_INT_SIGNED: u32 = 1 << 24;
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        data.get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("Unexpected end of BTF at offset {}", offset))?
+            .try_into()?,
+    ))
+}
+
+/// Split raw BTF into the type section and the string section
+fn split_raw_btf(raw: &[u8]) -> Result<(&[u8], &[u8])> {
+    if raw.len() < BTF_HEADER_LEN || u16::from_le_bytes([raw[0], raw[1]]) != BTF_MAGIC {
+        bail!("Not a little-endian raw BTF");
+    }
+    let hdr_len = read_u32(raw, 4)? as usize;
+    let section = |off: u32, len: u32| {
+        let start = hdr_len + off as usize;
+        raw.get(start..start + len as usize).ok_or_else(|| {
+            anyhow!(
+                "BTF section {}..{} is out of the data",
+                start,
+                start + len as usize
+            )
+        })
+    };
+    Ok((
+        section(read_u32(raw, 8)?, read_u32(raw, 12)?)?,
+        section(read_u32(raw, 16)?, read_u32(raw, 20)?)?,
+    ))
+}
+
+fn build_raw_btf(types: &[u8], strs: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(BTF_HEADER_LEN + types.len() + strs.len());
+    out.extend_from_slice(&BTF_MAGIC.to_le_bytes());
+    // version 1, no flags
+    out.extend_from_slice(&[1, 0]);
+    for v in [
+        BTF_HEADER_LEN as u32,
+        0,
+        types.len() as u32,
+        types.len() as u32,
+        strs.len() as u32,
+    ] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.extend_from_slice(types);
+    out.extend_from_slice(strs);
+    out
+}
+
+/// btfdump doesn't know `BTF_KIND_ENUM64`, which newer kernels have. Rewrite them into integers with the same size, so the type ids are kept
+pub(crate) fn rewrite_enum64_in_raw_btf(raw: &[u8]) -> Result<Vec<u8>> {
+    let (types, strs) = split_raw_btf(raw)?;
+    let mut out_types = Vec::with_capacity(types.len());
+    let mut offset = 0;
+    while offset < types.len() {
+        let name_off = read_u32(types, offset)?;
+        let info = read_u32(types, offset + 4)?;
+        let size = read_u32(types, offset + 8)?;
+        let kind = (info >> 24) & 0x1f;
+        let vlen = (info & 0xffff) as usize;
+        let extra_len = match kind {
+            // int, var, decl_tag
+            1 | 14 | 17 => 4,
+            // array
+            3 => 12,
+            // struct, union, datasec, enum64
+            4 | 5 | 15 | 19 => 12 * vlen,
+            // enum, func_proto
+            6 | 13 => 8 * vlen,
+            // ptr, fwd, typedef, volatile, const, restrict, func, float, type_tag
+            2 | 7..=12 | 16 | 18 => 0,
+            k => bail!("Unknown BTF kind {} at offset {}", k, offset),
+        };
+        let end = offset + 12 + extra_len;
+        if end > types.len() {
+            bail!("Unexpected end of BTF types at offset {}", offset);
+        }
+        if kind == BTF_KIND_ENUM64 {
+            let signed = info & (1 << 31) != 0;
+            out_types.extend_from_slice(&name_off.to_le_bytes());
+            out_types.extend_from_slice(&(BTF_KIND_INT << 24).to_le_bytes());
+            out_types.extend_from_slice(&size.to_le_bytes());
+            let encoding = if signed { BTF_INT_SIGNED } else { 0 };
+            out_types.extend_from_slice(&(encoding | (size * 8)).to_le_bytes());
+        } else {
+            out_types.extend_from_slice(&types[offset..end]);
+        }
+        offset = end;
+    }
+    Ok(build_raw_btf(&out_types, strs))
+}
+
+/// Merge the split BTF of a kernel module with its base (vmlinux), so it could be loaded as a standalone BTF
+///
+/// Type ids and string offsets in split BTF continue from the base, so concatenating the sections is enough
+pub(crate) fn merge_split_btf(base: &[u8], split: &[u8]) -> Result<Vec<u8>> {
+    let (base_types, base_strs) = split_raw_btf(base)?;
+    let (split_types, split_strs) = split_raw_btf(split)?;
+    Ok(build_raw_btf(
+        &[base_types, split_types].concat(),
+        &[base_strs, split_strs].concat(),
+    ))
+}
+
 pub(crate) trait BtfHelper {
     fn resolve_real_type(&self, ty: u32) -> Result<u32>;
     fn is_char(&self, ty: u32) -> Result<bool>;
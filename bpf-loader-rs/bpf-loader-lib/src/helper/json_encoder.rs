@@ -0,0 +1,278 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! The inverse of `export_event::data_dumper::json`: encode a json value into the bytes of a BTF type
+//!
+//! Accepts what the dumper produces, so a dumped value could be modified and written back:
+//! - Integers: numbers, or strings for 128-bit ones. Bools for `bool`
+//! - Enums: `"NAME"`, `"NAME(value)"`, or a number
+//! - `char[N]`: strings, which will be zero-terminated
+//! - Other arrays: arrays with at most `N` elements
+//! - Structs and unions: objects keyed by member names. Members not provided are left untouched. `__EUNOMIA_TYPE` and `__EUNOMIA_TYPE_NAME` are ignored
+
+use anyhow::{anyhow, bail, Result};
+use btf::types::{
+    Btf, BtfArray, BtfComposite, BtfConst, BtfEnum, BtfFloat, BtfInt, BtfIntEncoding, BtfRestrict,
+    BtfType, BtfTypedef, BtfVolatile,
+};
+use serde_json::Value;
+
+use super::btf::BtfHelper;
+
+/// `out` should be exactly as large as the type
+pub(crate) fn encode_json(btf: &Btf, type_id: u32, value: &Value, out: &mut [u8]) -> Result<()> {
+    let ty = btf
+        .types()
+        .get(type_id as usize)
+        .ok_or_else(|| anyhow!("Invalid type id: {}", type_id))?;
+    match ty {
+        BtfType::Int(btf_int) => encode_int(btf_int, value, out),
+        BtfType::Ptr(_) => encode_unsigned(value, out),
+        BtfType::Array(arr) => encode_array(btf, arr, type_id, value, out),
+        BtfType::Struct(comp) | BtfType::Union(comp) => encode_composed_type(btf, comp, value, out),
+        BtfType::Enum(btf_enum) => encode_enum(btf_enum, value, out),
+        BtfType::Float(ft) => encode_float(ft, value, out),
+        BtfType::Typedef(BtfTypedef { type_id, .. })
+        | BtfType::Volatile(BtfVolatile { type_id })
+        | BtfType::Const(BtfConst { type_id })
+        | BtfType::Restrict(BtfRestrict { type_id }) => encode_json(btf, *type_id, value, out),
+        ty => bail!("Type `{}` is not supported in encoding", ty),
+    }
+}
+
+fn copy_le_bytes(bytes: &[u8], out: &mut [u8]) -> Result<()> {
+    if bytes.len() < out.len() {
+        bail!(
+            "Expected {} bytes, but only {} available",
+            out.len(),
+            bytes.len()
+        );
+    }
+    let len = out.len();
+    out.copy_from_slice(&bytes[..len]);
+    Ok(())
+}
+
+fn encode_unsigned(value: &Value, out: &mut [u8]) -> Result<()> {
+    let num = match value {
+        Value::Number(num) => num
+            .as_u64()
+            .ok_or_else(|| anyhow!("Expected an unsigned integer, found {}", num))?
+            as u128,
+        Value::String(s) => s
+            .parse::<u128>()
+            .map_err(|e| anyhow!("Invalid integer `{}`: {}", s, e))?,
+        v => bail!("Expected an unsigned integer, found {}", v),
+    };
+    if out.len() < 16 && num >> (out.len() * 8) != 0 {
+        bail!("{} overflows {} bytes", num, out.len());
+    }
+    copy_le_bytes(&num.to_le_bytes(), out)
+}
+
+fn encode_signed(value: &Value, out: &mut [u8]) -> Result<()> {
+    let num = match value {
+        Value::Number(num) => {
+            num.as_i64()
+                .ok_or_else(|| anyhow!("Expected an integer, found {}", num))? as i128
+        }
+        Value::String(s) => s
+            .parse::<i128>()
+            .map_err(|e| anyhow!("Invalid integer `{}`: {}", s, e))?,
+        v => bail!("Expected an integer, found {}", v),
+    };
+    if out.len() < 16 {
+        let bits = out.len() * 8;
+        let (min, max) = (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1);
+        if num < min || num > max {
+            bail!("{} overflows {} bytes", num, out.len());
+        }
+    }
+    copy_le_bytes(&num.to_le_bytes(), out)
+}
+
+fn encode_int(btf_int: &BtfInt, value: &Value, out: &mut [u8]) -> Result<()> {
+    if btf_int.bits as usize != out.len() * 8 {
+        bail!(
+            "Unsupported integer `{}` with {} bits in {} bytes",
+            btf_int.name,
+            btf_int.bits,
+            out.len()
+        );
+    }
+    match (btf_int.encoding, value) {
+        (BtfIntEncoding::Bool, Value::Bool(b)) => {
+            out.fill(0);
+            out[0] = *b as u8;
+            Ok(())
+        }
+        (BtfIntEncoding::Bool, v) => bail!("Expected a bool, found {}", v),
+        (BtfIntEncoding::Signed, v) => encode_signed(v, out),
+        // A negative number for an unsigned type is likely a mistake, so they are not accepted
+        (_, v) => encode_unsigned(v, out),
+    }
+}
+
+fn encode_float(ft: &BtfFloat, value: &Value, out: &mut [u8]) -> Result<()> {
+    let num = value
+        .as_f64()
+        .ok_or_else(|| anyhow!("Expected a float, found {}", value))?;
+    match ft.sz {
+        4 => copy_le_bytes(&(num as f32).to_le_bytes(), out),
+        8 => copy_le_bytes(&num.to_le_bytes(), out),
+        s => bail!("Unsupported float size: {}", s),
+    }
+}
+
+fn encode_enum(btf_enum: &BtfEnum, value: &Value, out: &mut [u8]) -> Result<()> {
+    let val = match value {
+        Value::Number(_) => {
+            return encode_signed(value, out);
+        }
+        Value::String(s) => {
+            // Strip the `(value)` suffix that the dumper appends
+            let name = s.split_once('(').map(|v| v.0).unwrap_or(s);
+            btf_enum
+                .values
+                .iter()
+                .find(|v| v.name == name)
+                .ok_or_else(|| anyhow!("`{}` is not a variant of enum `{}`", name, btf_enum.name))?
+                .value
+        }
+        v => bail!("Expected a variant name or a number for enum, found {}", v),
+    };
+    copy_le_bytes(&(val as i64).to_le_bytes(), out)
+}
+
+fn encode_array(
+    btf: &Btf,
+    arr: &BtfArray,
+    type_id: u32,
+    value: &Value,
+    out: &mut [u8],
+) -> Result<()> {
+    if btf.is_char_array(type_id)? {
+        let s = value
+            .as_str()
+            .ok_or_else(|| anyhow!("Expected a string for char array, found {}", value))?;
+        if s.len() >= out.len() {
+            bail!(
+                "String is too long. Received a string with {} bytes, but only {} bytes is allowed, including the trailing zero",
+                s.len(),
+                out.len()
+            );
+        }
+        out.fill(0);
+        out[..s.len()].copy_from_slice(s.as_bytes());
+        return Ok(());
+    }
+    let elems = value
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected an array, found {}", value))?;
+    if elems.len() > arr.nelems as usize {
+        bail!(
+            "Too many elements: {} provided, but the array only has {}",
+            elems.len(),
+            arr.nelems
+        );
+    }
+    let elem_size = btf.get_size_of(arr.val_type_id) as usize;
+    for (i, elem) in elems.iter().enumerate() {
+        let range = out
+            .get_mut(i * elem_size..(i + 1) * elem_size)
+            .ok_or_else(|| anyhow!("Failed to slice {}-th element for array", i))?;
+        encode_json(btf, arr.val_type_id, elem, range)
+            .map_err(|e| anyhow!("At index {}: {}", i, e))?;
+    }
+    Ok(())
+}
+
+fn encode_composed_type(
+    btf: &Btf,
+    comp: &BtfComposite,
+    value: &Value,
+    out: &mut [u8],
+) -> Result<()> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| anyhow!("Expected an object for `{}`, found {}", comp.name, value))?;
+    for (key, val) in obj.iter() {
+        if key == "__EUNOMIA_TYPE" || key == "__EUNOMIA_TYPE_NAME" {
+            continue;
+        }
+        let member = comp
+            .members
+            .iter()
+            .find(|v| v.name == key)
+            .ok_or_else(|| anyhow!("`{}` has no member named `{}`", comp.name, key))?;
+        if member.bit_offset % 8 != 0 || member.bit_size % 8 != 0 {
+            bail!("Bit fields are not supported: {}::{}", comp.name, key);
+        }
+        let offset = (member.bit_offset / 8) as usize;
+        let size = btf.get_size_of(member.type_id) as usize;
+        let range = out
+            .get_mut(offset..offset + size)
+            .ok_or_else(|| anyhow!("Failed to slice member {} of {}", key, comp.name))?;
+        encode_json(btf, member.type_id, val, range)
+            .map_err(|e| anyhow!("At {}::{}: {}", comp.name, key, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use btf::types::Btf;
+    use object::ElfFile;
+    use serde_json::json;
+
+    use crate::{
+        btf_container::BtfResolver,
+        export_event::data_dumper::json::dump_to_json,
+        tests::{get_assets_dir, ExampleTestStruct},
+    };
+
+    use super::encode_json;
+
+    #[test]
+    fn test_encode_dumped_json() {
+        let assets_dir = get_assets_dir();
+        let elf = std::fs::read(assets_dir.join("simple_prog").join("simple_prog.bpf.o")).unwrap();
+        let bin = std::fs::read(assets_dir.join("simple_prog").join("dumper_test.bin")).unwrap();
+        let elf: ElfFile = ElfFile::parse(&elf[..]).unwrap();
+        let btf = Btf::load(&elf).unwrap();
+        // type_id = 2 is the struct we want
+        let dumped = dump_to_json(BtfResolver::new(&btf), 2, &bin[..]).unwrap();
+        let mut buf = vec![0u8; bin.len()];
+        encode_json(&btf, 2, &dumped, &mut buf).unwrap();
+        let d: ExampleTestStruct =
+            serde_json::from_value(dump_to_json(BtfResolver::new(&btf), 2, &buf).unwrap()).unwrap();
+        d.test_with_example_data();
+
+        assert!(encode_json(&btf, 2, &json!({"no_such_member": 1}), &mut buf).is_err());
+        assert!(encode_json(&btf, 2, &json!({"u8v": 256}), &mut buf).is_err());
+        assert!(encode_json(&btf, 2, &json!({"i8v": -129}), &mut buf).is_err());
+        assert!(encode_json(&btf, 2, &json!({"e": "NO_SUCH_VARIANT"}), &mut buf).is_err());
+    }
+
+    #[test]
+    fn test_encode_i128() {
+        let assets_dir = get_assets_dir();
+        let elf = std::fs::read(assets_dir.join("int128_test").join("prog.bpf.o")).unwrap();
+        let elf: ElfFile = ElfFile::parse(&elf[..]).unwrap();
+        let btf = Btf::load(&elf).unwrap();
+        let mut buf = vec![0u8; 32];
+        // type_id = 4 is the struct we want
+        encode_json(
+            &btf,
+            4,
+            &json!({"a": "-1237940039285380274899124224", "b": "170141183460469231731687303715884105738"}),
+            &mut buf,
+        )
+        .unwrap();
+        assert_eq!(buf[..16], (-(1i128 << 90)).to_le_bytes());
+        assert_eq!(buf[16..], ((1u128 << 127) + 10).to_le_bytes());
+    }
+}
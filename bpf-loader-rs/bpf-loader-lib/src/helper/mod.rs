@@ -5,4 +5,5 @@
 //!
 
 pub(crate) mod btf;
+pub(crate) mod json_encoder;
 pub(crate) mod log2_hist;
@@ -0,0 +1,470 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Package inspection
+//!
+//! Decode a package and describe what's inside, without loading anything into the kernel. So root is not required.
+//!
+//! The ELF is only opened by libbpf (which doesn't touch the kernel) to get the definitions of maps and programs, and BTF is read from the `.BTF` section of the ELF.
+
+use std::ffi::CStr;
+
+use anyhow::{anyhow, Context, Result};
+use btf::types::{Btf, BtfType};
+use libbpf_rs::{
+    libbpf_sys::{
+        bpf_map__btf_key_type_id, bpf_map__btf_value_type_id, bpf_map__key_size,
+        bpf_map__max_entries, bpf_map__name, bpf_map__type, bpf_map__value_size, bpf_object__close,
+        bpf_object__next_map, bpf_object__next_program, bpf_program__name, bpf_program__type,
+        libbpf_bpf_map_type_str, libbpf_bpf_prog_type_str,
+    },
+    ObjectBuilder,
+};
+use log::warn;
+use object::{Object, ObjectSection};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    btf_container::BtfContainer,
+    elf_container::ElfContainer,
+    meta::{BpfSkelDoc, ComposedObject, MapExportConfig, MapSampleMeta},
+};
+
+/// What's inside a package
+#[derive(Serialize, Debug, Clone)]
+pub struct PackageInspection {
+    /// Name of the bpf object
+    pub obj_name: String,
+    /// Docs of the program
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<BpfSkelDoc>,
+    /// bpf programs
+    pub programs: Vec<ProgramInspection>,
+    /// Maps
+    pub maps: Vec<MapInspection>,
+    /// Global variables in .rodata and .bss
+    pub variables: Vec<VariableInspection>,
+    /// Layouts of `export_types`
+    pub export_types: Vec<StructLayout>,
+    /// Sections of the ELF
+    pub sections: Vec<SectionInspection>,
+}
+
+/// Describe a bpf program in the package
+#[derive(Serialize, Debug, Clone)]
+pub struct ProgramInspection {
+    /// Name of the program
+    pub name: String,
+    /// The attach point, a.k.a the section name
+    pub attach: String,
+    /// Program type told by libbpf, e.g `kprobe`
+    pub prog_type: String,
+    /// Whether attaching it produces a bpf_link
+    pub link: bool,
+    /// Extra attach options, such as `ifindex` of xdp programs
+    #[serde(skip_serializing_if = "is_empty_object")]
+    pub extra: Value,
+}
+
+/// Describe a map in the package
+#[derive(Serialize, Debug, Clone)]
+pub struct MapInspection {
+    /// Name of the map
+    pub name: String,
+    /// Ident of the map
+    pub ident: String,
+    /// Map type told by libbpf, e.g `ringbuf`
+    pub map_type: String,
+    /// Size of the key, in bytes
+    pub key_size: u32,
+    /// Size of the value, in bytes
+    pub value_size: u32,
+    /// Max entries of the map
+    pub max_entries: u32,
+    /// Whether the map backs a data section
+    pub mmaped: bool,
+    /// How the map will be exported
+    pub export_config: MapExportConfig,
+    /// Sampling config, if it's a sample map
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample: Option<MapSampleMeta>,
+    /// Layout of the exported value, if it could be resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub export_layout: Option<StructLayout>,
+}
+
+/// Describe a global variable
+#[derive(Serialize, Debug, Clone)]
+pub struct VariableInspection {
+    /// The section it lives in
+    pub section: String,
+    /// Name of the variable
+    pub name: String,
+    /// C type of the variable
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// Default value, from the command line argument config or the package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// Description of the variable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// Layout of a struct, resolved from BTF
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StructLayout {
+    /// Name of the struct
+    pub name: String,
+    /// Size of the struct, in bytes
+    pub size: u32,
+    /// Members of the struct
+    pub members: Vec<MemberLayout>,
+}
+
+/// Layout of a struct member
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemberLayout {
+    /// Name of the member
+    pub name: String,
+    /// C type of the member
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// Offset of the member, in bytes
+    pub offset: u32,
+    /// Size of the member, in bytes
+    pub size: u32,
+}
+
+/// Describe an ELF section
+#[derive(Serialize, Debug, Clone)]
+pub struct SectionInspection {
+    /// Name of the section
+    pub name: String,
+    /// Size of the section, in bytes
+    pub size: u64,
+}
+
+fn is_empty_object(v: &Value) -> bool {
+    match v {
+        Value::Object(m) => m.is_empty(),
+        Value::Null => true,
+        _ => false,
+    }
+}
+
+/// A map, as libbpf sees it
+pub(crate) struct OpenedMapInfo {
+    pub(crate) name: String,
+    pub(crate) map_type: String,
+    pub(crate) key_size: u32,
+    pub(crate) value_size: u32,
+    pub(crate) max_entries: u32,
+    pub(crate) btf_key_type_id: u32,
+    pub(crate) btf_value_type_id: u32,
+}
+
+/// Maps and programs, as libbpf sees them
+pub(crate) struct OpenedObjectInfo {
+    pub(crate) maps: Vec<OpenedMapInfo>,
+    /// name, type
+    pub(crate) progs: Vec<(String, String)>,
+}
+
+impl OpenedObjectInfo {
+    pub(crate) fn map(&self, name: &str) -> Option<&OpenedMapInfo> {
+        self.maps.iter().find(|v| v.name == name)
+    }
+}
+
+pub(crate) fn c_str_to_string(s: *const std::ffi::c_char) -> String {
+    if s.is_null() {
+        return "unknown".into();
+    }
+    // SAFETY: libbpf returns nul-terminated strings
+    unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned()
+}
+
+/// Open the object with libbpf without loading it, and read definitions of maps and programs
+pub(crate) fn read_object_with_libbpf(name: &str, bpf_object: &[u8]) -> Result<OpenedObjectInfo> {
+    let obj = ObjectBuilder::default()
+        .open_memory(name, bpf_object)
+        .with_context(|| anyhow!("Failed to open the bpf object"))?
+        .take_ptr()
+        .as_ptr();
+    let mut maps = vec![];
+    let mut progs = vec![];
+    // SAFETY: obj is a valid opened object, and we only read from it before closing it
+    unsafe {
+        let mut map = bpf_object__next_map(obj, std::ptr::null());
+        while !map.is_null() {
+            maps.push(OpenedMapInfo {
+                name: c_str_to_string(bpf_map__name(map)),
+                map_type: c_str_to_string(libbpf_bpf_map_type_str(bpf_map__type(map))),
+                key_size: bpf_map__key_size(map),
+                value_size: bpf_map__value_size(map),
+                max_entries: bpf_map__max_entries(map),
+                btf_key_type_id: bpf_map__btf_key_type_id(map),
+                btf_value_type_id: bpf_map__btf_value_type_id(map),
+            });
+            map = bpf_object__next_map(obj, map);
+        }
+        let mut prog = bpf_object__next_program(obj, std::ptr::null_mut());
+        while !prog.is_null() {
+            progs.push((
+                c_str_to_string(bpf_program__name(prog)),
+                c_str_to_string(libbpf_bpf_prog_type_str(bpf_program__type(prog))),
+            ));
+            prog = bpf_object__next_program(obj, prog);
+        }
+        bpf_object__close(obj);
+    }
+    Ok(OpenedObjectInfo { maps, progs })
+}
+
+/// Get a C-like name of a BTF type, e.g `unsigned int`, `char[16]`, `struct event *`
+pub(crate) fn btf_type_name(btf: &Btf, type_id: u32) -> String {
+    let Some(ty) = btf.types().get(type_id as usize) else {
+        return format!("<invalid type {type_id}>");
+    };
+    match ty {
+        BtfType::Void => "void".into(),
+        BtfType::Ptr(p) => format!("{} *", btf_type_name(btf, p.type_id)),
+        BtfType::Array(arr) => format!("{}[{}]", btf_type_name(btf, arr.val_type_id), arr.nelems),
+        BtfType::Const(c) => format!("const {}", btf_type_name(btf, c.type_id)),
+        BtfType::Volatile(v) => format!("volatile {}", btf_type_name(btf, v.type_id)),
+        BtfType::Restrict(r) => btf_type_name(btf, r.type_id),
+        BtfType::TypeTag(t) => btf_type_name(btf, t.type_id),
+        BtfType::Struct(s) if s.name.is_empty() => "struct <anon>".into(),
+        BtfType::Struct(s) => format!("struct {}", s.name),
+        BtfType::Union(u) if u.name.is_empty() => "union <anon>".into(),
+        BtfType::Union(u) => format!("union {}", u.name),
+        BtfType::Enum(e) if e.name.is_empty() => "enum <anon>".into(),
+        BtfType::Enum(e) => format!("enum {}", e.name),
+        BtfType::FuncProto(_) => "<func proto>".into(),
+        ty => ty.name().to_string(),
+    }
+}
+
+/// Resolve the layout of the struct with the given type id
+pub(crate) fn resolve_struct_layout(btf: &Btf, type_id: u32) -> Result<StructLayout> {
+    let real_id = btf.skip_mods_and_typedefs(type_id);
+    let ty = btf
+        .types()
+        .get(real_id as usize)
+        .ok_or_else(|| anyhow!("Invalid type id {}", type_id))?;
+    let members = match ty {
+        BtfType::Struct(st) | BtfType::Union(st) => st
+            .members
+            .iter()
+            .map(|m| MemberLayout {
+                name: m.name.to_string(),
+                ty: btf_type_name(btf, m.type_id),
+                offset: m.bit_offset / 8,
+                size: btf.get_size_of(m.type_id),
+            })
+            .collect(),
+        // Sample maps may use plain types as values
+        _ => vec![],
+    };
+    Ok(StructLayout {
+        name: btf_type_name(btf, type_id),
+        size: btf.get_size_of(type_id),
+        members,
+    })
+}
+
+impl ComposedObject {
+    /// Describe what's inside this package, without loading it
+    pub fn inspect(&self) -> Result<PackageInspection> {
+        let meta = &self.meta;
+        let elf = ElfContainer::new_from_binary(&self.bpf_object)?;
+        let btf = BtfContainer::new_from_binary(&self.bpf_object)
+            .with_context(|| anyhow!("Failed to read BTF from the bpf object"))?;
+        let btf = btf.borrow_btf();
+        let opened = read_object_with_libbpf(&meta.bpf_skel.obj_name, &self.bpf_object)?;
+
+        let programs = meta
+            .bpf_skel
+            .progs
+            .iter()
+            .map(|prog| ProgramInspection {
+                name: prog.name.clone(),
+                attach: prog.attach.clone(),
+                prog_type: opened
+                    .progs
+                    .iter()
+                    .find(|(name, _)| name == &prog.name)
+                    .map(|(_, ty)| ty.clone())
+                    .unwrap_or_else(|| "unknown".into()),
+                link: prog.link,
+                extra: prog.others.clone(),
+            })
+            .collect();
+        let mut maps = vec![];
+        for map in meta.bpf_skel.maps.iter() {
+            let (map_type, key_size, value_size, max_entries, value_type_id) =
+                match opened.map(&map.name) {
+                    Some(v) => (
+                        v.map_type.clone(),
+                        v.key_size,
+                        v.value_size,
+                        v.max_entries,
+                        v.btf_value_type_id,
+                    ),
+                    None => ("unknown".into(), 0, 0, 0, 0),
+                };
+            let export_layout = match &map.export_config {
+                MapExportConfig::ExportUseBtf(id) => Some(resolve_struct_layout(btf, *id)),
+                MapExportConfig::Default if value_type_id != 0 => {
+                    Some(resolve_struct_layout(btf, value_type_id))
+                }
+                // Ringbufs and perf event arrays have no value type, their events are of the export type
+                MapExportConfig::Default
+                    if matches!(map_type.as_str(), "ringbuf" | "perf_event_array") =>
+                {
+                    meta.export_types
+                        .first()
+                        .map(|ty| resolve_struct_layout(btf, ty.type_id))
+                }
+                MapExportConfig::ExportUseCustomMembers(mems) => Some(Ok(StructLayout {
+                    name: "<custom>".into(),
+                    size: value_size,
+                    members: mems
+                        .iter()
+                        .map(|m| MemberLayout {
+                            name: m.name.clone(),
+                            ty: btf_type_name(btf, m.btf_type_id),
+                            offset: m.offset as u32,
+                            size: btf.get_size_of(m.btf_type_id),
+                        })
+                        .collect(),
+                })),
+                _ => None,
+            };
+            let export_layout = match export_layout {
+                Some(Ok(v)) => Some(v),
+                Some(Err(e)) => {
+                    warn!(
+                        "Failed to resolve export layout of map `{}`: {}",
+                        map.name, e
+                    );
+                    None
+                }
+                None => None,
+            };
+            maps.push(MapInspection {
+                name: map.name.clone(),
+                ident: map.ident.clone(),
+                map_type,
+                key_size,
+                value_size,
+                max_entries,
+                mmaped: map.mmaped,
+                export_config: map.export_config.clone(),
+                sample: map.sample.clone(),
+                export_layout,
+            });
+        }
+        let variables = meta
+            .bpf_skel
+            .data_sections
+            .iter()
+            .flat_map(|section| {
+                section
+                    .variables
+                    .iter()
+                    .filter(|v| !v.name.starts_with("__eunomia_dummy"))
+                    .map(|v| VariableInspection {
+                        section: section.name.clone(),
+                        name: v.name.clone(),
+                        ty: v.ty.clone(),
+                        default: v.cmdarg.default.clone().or_else(|| v.value.clone()),
+                        description: v.description.clone(),
+                    })
+            })
+            .collect();
+        let export_types = meta
+            .export_types
+            .iter()
+            .map(|ty| resolve_struct_layout(btf, ty.type_id))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| anyhow!("Failed to resolve export types"))?;
+        let sections = elf
+            .borrow_elf()
+            .sections()
+            .filter_map(|s| {
+                let name = s.name()?;
+                (!name.is_empty()).then(|| SectionInspection {
+                    name: name.to_string(),
+                    size: s.size(),
+                })
+            })
+            .collect();
+        Ok(PackageInspection {
+            obj_name: meta.bpf_skel.obj_name.clone(),
+            doc: meta.bpf_skel.doc.clone(),
+            programs,
+            maps,
+            variables,
+            export_types,
+            sections,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        meta::{ComposedObject, MapExportConfig},
+        tests::get_assets_dir,
+    };
+
+    #[test]
+    fn test_inspect_package() {
+        let package = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap();
+        let result = package.inspect().unwrap();
+        assert_eq!(result.obj_name, package.meta.bpf_skel.obj_name);
+        assert_eq!(result.programs.len(), package.meta.bpf_skel.progs.len());
+        let rb = result.maps.iter().find(|m| m.name == "rb").unwrap();
+        assert_eq!(rb.map_type, "ringbuf");
+        assert_eq!(result.export_types.len(), 1);
+        let event = &result.export_types[0];
+        assert_eq!(event.name, "struct event");
+        assert_eq!(event.members[0].name, "pid");
+        assert_eq!(event.members[0].ty, "int");
+        assert_eq!(event.members[0].offset, 0);
+        assert!(result.sections.iter().any(|s| s.name == ".BTF"));
+        assert!(result
+            .variables
+            .iter()
+            .any(|v| v.name == "min_duration_ns" && v.section == ".rodata"));
+    }
+    #[test]
+    fn test_inspect_ringbuf_export_layout() {
+        let mut package = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap();
+        let rb = package
+            .meta
+            .bpf_skel
+            .maps
+            .iter_mut()
+            .find(|m| m.name == "rb")
+            .unwrap();
+        rb.export_config = MapExportConfig::Default;
+        let result = package.inspect().unwrap();
+        let rb = result.maps.iter().find(|m| m.name == "rb").unwrap();
+        let layout = rb.export_layout.as_ref().unwrap();
+        assert_eq!(layout.name, "struct event");
+        assert_eq!(layout.members[0].name, "pid");
+    }
+}
@@ -12,10 +12,18 @@ pub mod elf_container;
 pub mod export_event;
 /// Some helper functions
 pub mod helper;
+/// Inspect packages without loading them
+pub mod inspect;
 /// Skeleton data types
 pub mod meta;
+/// Probe features of the running kernel
+pub mod probe;
+/// Sign packages and verify them before loading
+pub mod signature;
 /// The skeleton itself
 pub mod skeleton;
+/// Validate packages without loading them
+pub mod validate;
 
 /// Re-export clap
 pub use clap;
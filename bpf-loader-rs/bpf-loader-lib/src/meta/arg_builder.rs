@@ -14,6 +14,16 @@ const DEFAULT_DESCRIPTION: &str = "A simple eBPF program";
 const DEFAULT_VERSION: &str = "0.1.0";
 const DEFAULT_EPILOG: &str = "Built with eunomia-bpf framework.\nSee https://github.com/eunomia-bpf/eunomia-bpf for more information.";
 
+/// Id of the argument adding keys to a map. Variables use their names as ids, so maps are prefixed
+pub(crate) fn map_arg_id(map_name: &str) -> String {
+    format!("map:{map_name}")
+}
+
+/// Id of the argument adding entries to a map from files
+pub(crate) fn map_file_arg_id(map_name: &str) -> String {
+    format!("map:{map_name}-file")
+}
+
 impl EunomiaObjectMeta {
     /// Build an argument parser use the `cmdarg` sections in .rodata/.bss variables.
     ///
@@ -29,6 +39,14 @@ impl EunomiaObjectMeta {
     /// The first will be used to set the value of the variable to `true`, second one will be used to set `false`
     ///
     /// Variables with other types will accept values. But values will be checked in `parse_arguments_and_fill_skeleton_variables`, so here the values input in the command line parser will be regarded as strings.
+    ///
+    /// Maps with `cmdarg` will have a repeatable `--<NAME> KEY` to add keys, and a repeatable `--<NAME>-file PATH` to add entries from files. See `MapCommandArgument`
+    ///
+    /// Programs attached to cgroups will have a `--<NAME>-cgroup PATH` to override the cgroup they are attached to
+    ///
+    /// Kprobes and uprobes attached by patterns will have a repeatable `--<NAME>-functions PATTERN` to override the patterns
+    ///
+    /// A repeatable `--attach PROG=KIND:TARGET` is added to attach programs to other targets. See `AttachTarget`
     pub fn build_argument_parser(&self) -> Result<Command> {
         let cmd = Command::new(self.bpf_skel.obj_name.to_string());
 
@@ -161,6 +179,84 @@ impl EunomiaObjectMeta {
                 }
             }
         }
+        // Add arguments for maps that could be filled from the command line
+        for map in self.bpf_skel.maps.iter() {
+            let Some(cmdarg) = map.cmdarg.as_ref() else {
+                continue;
+            };
+            let long = cmdarg
+                .long
+                .to_owned()
+                .unwrap_or_else(|| map.name.to_string());
+            let help = cmdarg
+                .help
+                .to_owned()
+                .unwrap_or_else(|| format!("Add a key to map {}", map.name));
+            let arg = Arg::new(map_arg_id(&map.name))
+                .action(ArgAction::Append)
+                .value_name("KEY")
+                .help(help)
+                .long(long.clone());
+            let arg = if let Some(s) = cmdarg.short.as_ref() {
+                let chars = s.chars().collect::<Vec<char>>();
+                if chars.len() != 1 {
+                    bail!(
+                        "Short name for map `{}` is expected to be just in 1 character",
+                        map.name
+                    );
+                }
+                arg.short(chars[0])
+            } else {
+                arg
+            };
+            cmd = cmd.arg(arg).arg(
+                Arg::new(map_file_arg_id(&map.name))
+                    .action(ArgAction::Append)
+                    .value_name("PATH")
+                    .help(format!(
+                        "Add entries to map {} from a json or csv file",
+                        map.name
+                    ))
+                    .long(format!("{long}-file")),
+            );
+        }
+        if !self.bpf_skel.progs.is_empty() {
+            cmd = cmd.arg(
+                Arg::new("attach")
+                    .long("attach")
+                    .action(ArgAction::Append)
+                    .value_name("PROG=KIND:TARGET")
+                    .help("Attach a program to another target, e.g `handle_exec=tp:sched/sched_process_exec`"),
+            );
+        }
+        // Add arguments to override cgroups and function patterns of programs
+        for prog in self.bpf_skel.progs.iter() {
+            if prog.is_multi_probe() {
+                cmd = cmd.arg(
+                    Arg::new(format!("{}-functions", prog.name))
+                        .action(ArgAction::Append)
+                        .value_name("PATTERN")
+                        .help(format!(
+                            "Glob pattern of functions to attach program {} to",
+                            prog.name
+                        ))
+                        .long(format!("{}-functions", prog.name)),
+                );
+            }
+            if !prog.attaches_to_cgroup() {
+                continue;
+            }
+            cmd = cmd.arg(
+                Arg::new(format!("{}-cgroup", prog.name))
+                    .action(ArgAction::Set)
+                    .value_name("PATH")
+                    .help(format!(
+                        "Path of the cgroup to attach program {} to",
+                        prog.name
+                    ))
+                    .long(format!("{}-cgroup", prog.name)),
+            );
+        }
         Ok(cmd)
     }
 }
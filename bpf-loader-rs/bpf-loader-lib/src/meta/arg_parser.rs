@@ -4,11 +4,16 @@
 //! All rights reserved.
 //!
 
+use std::path::Path;
+
 use anyhow::{anyhow, bail, Context, Result};
 use clap::ArgMatches;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 
-use super::EunomiaObjectMeta;
+use super::{
+    arg_builder::{map_arg_id, map_file_arg_id},
+    EunomiaObjectMeta, MapEntry,
+};
 
 /// What to do if we met a variable which neither has the default value or has been supplied from command argument
 pub enum UnpresentVariableAction {
@@ -28,6 +33,12 @@ impl EunomiaObjectMeta {
     /// If the `on_unpresent` behavior is `FillWithZero`, in this way if we find a command line argument with no values provided (this situation may only happen if the command line argument was created with no default values, a.k.a the `value` field is `None` in `DataSectionVariableMeta`), the `value` field will still be leaved `None`. In this way, the section_loader will fill zeros in the corresponding memory areas.
     ///
     /// If the `on_unpresent` behavior is `ReportError`, in this way if we find a command line argument with no values, we'll report an error.
+    ///
+    /// Entries provided for maps with `cmdarg` are appended to their `initial_entries`
+    ///
+    /// Cgroups and function patterns provided for programs replace the `cgroup_path` and `functions` in their extra meta
+    ///
+    /// Targets provided with `--attach` are set as `attach_target` of the programs, and checked against their sections
     pub fn parse_arguments_and_fill_skeleton_variables(
         &mut self,
         args: &ArgMatches,
@@ -65,9 +76,181 @@ impl EunomiaObjectMeta {
                 }
             }
         }
+        for map in self.bpf_skel.maps.iter_mut() {
+            let Some(cmdarg) = map.cmdarg.as_ref() else {
+                continue;
+            };
+            if let Some(keys) = args.get_many::<String>(&map_arg_id(&map.name)) {
+                map.initial_entries.extend(keys.map(|v| MapEntry {
+                    key: parse_map_value(v),
+                    value: cmdarg.value.clone(),
+                }));
+            }
+            if let Some(paths) = args.get_many::<String>(&map_file_arg_id(&map.name)) {
+                for path in paths {
+                    let entries = read_map_entries_from_file(Path::new(path), &cmdarg.value)
+                        .with_context(|| {
+                            anyhow!("Failed to read entries of map `{}` from {}", map.name, path)
+                        })?;
+                    map.initial_entries.extend(entries);
+                }
+            }
+        }
+        for prog in self.bpf_skel.progs.iter_mut() {
+            if prog.attaches_to_cgroup() {
+                if let Some(path) = args.get_one::<String>(&format!("{}-cgroup", prog.name)) {
+                    set_extra_meta_field(&mut prog.others, "cgroup_path", json!(path));
+                }
+            }
+            if prog.is_multi_probe() {
+                if let Some(patterns) = args.get_many::<String>(&format!("{}-functions", prog.name))
+                {
+                    let patterns = patterns.collect::<Vec<_>>();
+                    set_extra_meta_field(&mut prog.others, "functions", json!(patterns));
+                }
+            }
+        }
+        if let Some(targets) = args.get_many::<String>("attach") {
+            for arg in targets {
+                let Some((prog_name, target)) = arg.split_once('=') else {
+                    bail!("Expected `PROG=KIND:TARGET` for `--attach`, got `{}`", arg);
+                };
+                let prog = self
+                    .bpf_skel
+                    .progs
+                    .iter_mut()
+                    .find(|v| v.name == prog_name)
+                    .ok_or_else(|| anyhow!("No program named `{}`", prog_name))?;
+                prog.attach_target = Some(target.to_string());
+                prog.resolve_attach_target()
+                    .with_context(|| anyhow!("Invalid attach target of `{}`", prog_name))?;
+            }
+        }
         self.debug_verbose = args.get_flag("verbose");
         Ok(())
     }
+    /// Fill the variables with values in a json object, keyed by the variable names
+    ///
+    /// Strings are parsed in the same way as command line arguments, so both `{"pid": 1}` and `{"pid": "1"}` work. Other values are used as they are, and will be checked when the skeleton is loaded
+    ///
+    /// Variables not in `values` keep their default values. If they don't have one, `on_unpresent` applies
+    pub fn fill_skeleton_variables_from_json(
+        &mut self,
+        values: &Map<String, Value>,
+        on_unpresent: UnpresentVariableAction,
+    ) -> Result<()> {
+        for name in values.keys() {
+            if !self
+                .bpf_skel
+                .data_sections
+                .iter()
+                .flat_map(|v| v.variables.iter())
+                .any(|v| &v.name == name && !v.name.starts_with("__eunomia_dummy"))
+            {
+                bail!("No variable named `{}`", name);
+            }
+        }
+        for section in self.bpf_skel.data_sections.iter_mut() {
+            for variable in section.variables.iter_mut() {
+                if variable.name.starts_with("__eunomia_dummy") {
+                    continue;
+                }
+                match values.get(&variable.name) {
+                    Some(Value::String(s)) => {
+                        variable.value = Some(parse_value(&variable.ty, s).with_context(|| {
+                            anyhow!("Failed to parse the value of `{}`", variable.name)
+                        })?);
+                    }
+                    Some(v) => variable.value = Some(v.clone()),
+                    None => {
+                        if variable.value.is_none()
+                            && matches!(on_unpresent, UnpresentVariableAction::ReportError)
+                        {
+                            bail!(
+                                "Variable `{}` has neither default values nor provided values",
+                                variable.name
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Set a field in the extra meta of a program
+fn set_extra_meta_field(others: &mut Value, key: &str, value: Value) {
+    match others {
+        Value::Object(others) => {
+            others.insert(key.into(), value);
+        }
+        others => *others = json!({ key: value }),
+    }
+}
+
+/// Keys and values of maps in the command line or csv files are json, or strings if they aren't valid json
+fn parse_map_value(s: &str) -> Value {
+    serde_json::from_str(s).unwrap_or_else(|_| json!(s))
+}
+
+/// Split a csv line into the key and the value. The key could be json containing commas, like `{"pid": 1, "tid": 2}, 3`
+fn split_csv_line(line: &str) -> (&str, Option<&str>) {
+    let mut values = serde_json::Deserializer::from_str(line).into_iter::<Value>();
+    if let Some(Ok(_)) = values.next() {
+        let (key, rest) = line.split_at(values.byte_offset());
+        let rest = rest.trim_start();
+        if rest.is_empty() {
+            return (key, None);
+        }
+        if let Some(value) = rest.strip_prefix(',') {
+            return (key, Some(value));
+        }
+    }
+    // Not json, e.g. a plain string
+    match line.split_once(',') {
+        Some((key, value)) => (key, Some(value)),
+        None => (line, None),
+    }
+}
+
+/// Read entries from a file. See `MapCommandArgument` for the format
+fn read_map_entries_from_file(path: &Path, default_value: &Option<Value>) -> Result<Vec<MapEntry>> {
+    let content = std::fs::read_to_string(path)?;
+    let is_csv = path
+        .extension()
+        .map(|v| v.eq_ignore_ascii_case("csv"))
+        .unwrap_or(false);
+    if is_csv {
+        Ok(content
+            .lines()
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty() && !v.starts_with('#'))
+            .map(|line| match split_csv_line(line) {
+                (key, Some(value)) => MapEntry {
+                    key: parse_map_value(key.trim()),
+                    value: Some(parse_map_value(value.trim())),
+                },
+                (key, None) => MapEntry {
+                    key: parse_map_value(key.trim()),
+                    value: default_value.clone(),
+                },
+            })
+            .collect())
+    } else {
+        let elements: Vec<Value> =
+            serde_json::from_str(&content).with_context(|| anyhow!("Expected a json array"))?;
+        elements
+            .into_iter()
+            .map(|v| match v {
+                Value::Object(ref obj) if obj.contains_key("key") => Ok(serde_json::from_value(v)?),
+                v => Ok(MapEntry {
+                    key: v,
+                    value: default_value.clone(),
+                }),
+            })
+            .collect()
+    }
 }
 
 macro_rules! parse_value_decl {
@@ -118,7 +301,10 @@ mod tests {
     use serde_json::json;
 
     use crate::{
-        meta::{arg_parser::UnpresentVariableAction, EunomiaObjectMeta},
+        meta::{
+            arg_parser::UnpresentVariableAction, AttachTarget, CgroupProgExtraMeta, ComposedObject,
+            EunomiaObjectMeta, MapCommandArgument, MultiProbeProgExtraMeta,
+        },
         tests::get_assets_dir,
     };
 
@@ -212,4 +398,242 @@ mod tests {
             Some(json!(true))
         );
     }
+    #[test]
+    fn test_fill_variables_from_json() {
+        let mut skel = serde_json::from_str::<EunomiaObjectMeta>(
+            &std::fs::read_to_string(get_assets_dir().join("arg_builder_test").join("skel.json"))
+                .unwrap(),
+        )
+        .unwrap();
+        let values = json!({
+            "const_val_1": 1234,
+            "const_val_2": "2345",
+            "const_val_3": "abcdefg",
+            "boolflag": true
+        });
+        skel.fill_skeleton_variables_from_json(
+            values.as_object().unwrap(),
+            UnpresentVariableAction::FillWithZero,
+        )
+        .unwrap();
+        let sections = &skel.bpf_skel.data_sections;
+        assert_eq!(sections[0].variables[0].value, Some(json!(1234)));
+        assert_eq!(sections[0].variables[1].value, Some(json!(2345)));
+        assert_eq!(sections[0].variables[2].value, Some(json!("abcdefg")));
+        assert_eq!(sections[0].variables[3].value, Some(json!(true)));
+
+        assert!(skel
+            .fill_skeleton_variables_from_json(
+                json!({"no_such_variable": 1}).as_object().unwrap(),
+                UnpresentVariableAction::FillWithZero,
+            )
+            .is_err());
+        assert!(skel
+            .fill_skeleton_variables_from_json(
+                json!({"const_val_1": "abc"}).as_object().unwrap(),
+                UnpresentVariableAction::FillWithZero,
+            )
+            .is_err());
+    }
+    #[test]
+    fn test_fill_map_entries() {
+        let mut skel = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap()
+        .meta;
+        skel.bpf_skel.maps[0].cmdarg = Some(MapCommandArgument {
+            long: Some("pids".into()),
+            value: Some(json!(1)),
+            ..Default::default()
+        });
+        let dir = std::env::temp_dir();
+        let csv = dir.join(format!("map-entries-test-{}.csv", std::process::id()));
+        let json_file = dir.join(format!("map-entries-test-{}.json", std::process::id()));
+        std::fs::write(
+            &csv,
+            "# pid,value\n56\n\n78, 2\n{\"a\": 1, \"b\": [2, 3]}, {\"c\": 4, \"d\": 5}\n[6,7]\nbash,8\n",
+        )
+        .unwrap();
+        std::fs::write(&json_file, r#"[90, {"key": 91, "value": 3}]"#).unwrap();
+        let cmd = skel.build_argument_parser().unwrap();
+        let matches = cmd
+            .try_get_matches_from([
+                "myprog",
+                "--pids",
+                "12",
+                "--pids",
+                "34",
+                "--pids-file",
+                csv.to_str().unwrap(),
+                "--pids-file",
+                json_file.to_str().unwrap(),
+            ])
+            .unwrap();
+        skel.parse_arguments_and_fill_skeleton_variables(
+            &matches,
+            UnpresentVariableAction::FillWithZero,
+        )
+        .unwrap();
+        std::fs::remove_file(csv).unwrap();
+        std::fs::remove_file(json_file).unwrap();
+        let entries = skel.bpf_skel.maps[0]
+            .initial_entries
+            .iter()
+            .map(|v| (v.key.clone(), v.value.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (json!(12), Some(json!(1))),
+                (json!(34), Some(json!(1))),
+                (json!(56), Some(json!(1))),
+                (json!(78), Some(json!(2))),
+                (json!({"a": 1, "b": [2, 3]}), Some(json!({"c": 4, "d": 5}))),
+                (json!([6, 7]), Some(json!(1))),
+                (json!("bash"), Some(json!(8))),
+                (json!(90), Some(json!(1))),
+                (json!(91), Some(json!(3))),
+            ]
+        );
+    }
+    #[test]
+    fn test_map_named_as_variable() {
+        let mut skel = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap()
+        .meta;
+        skel.bpf_skel.maps[0].name = "min_duration_ns".into();
+        skel.bpf_skel.maps[0].cmdarg = Some(MapCommandArgument {
+            long: Some("pids".into()),
+            ..Default::default()
+        });
+        let cmd = skel.build_argument_parser().unwrap();
+        let matches = cmd
+            .try_get_matches_from(["myprog", "--min_duration_ns", "5", "--pids", "12"])
+            .unwrap();
+        skel.parse_arguments_and_fill_skeleton_variables(
+            &matches,
+            UnpresentVariableAction::FillWithZero,
+        )
+        .unwrap();
+        assert_eq!(
+            skel.bpf_skel.data_sections[0].variables[0].value,
+            Some(json!(5))
+        );
+        assert_eq!(skel.bpf_skel.maps[0].initial_entries[0].key, json!(12));
+    }
+    #[test]
+    fn test_override_cgroup() {
+        let mut skel = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap()
+        .meta;
+        skel.bpf_skel.progs[0].attach = "cgroup_skb/ingress".into();
+        skel.bpf_skel.progs[1].attach = "cgroup/sysctl".into();
+        let cmd = skel.build_argument_parser().unwrap();
+        let prog_name = skel.bpf_skel.progs[0].name.clone();
+        let matches = cmd
+            .try_get_matches_from([
+                "myprog".to_string(),
+                format!("--{}-cgroup", prog_name),
+                "/sys/fs/cgroup/test".to_string(),
+            ])
+            .unwrap();
+        skel.parse_arguments_and_fill_skeleton_variables(
+            &matches,
+            UnpresentVariableAction::FillWithZero,
+        )
+        .unwrap();
+        let cgroup_path_of = |idx: usize| {
+            serde_json::from_value::<CgroupProgExtraMeta>(skel.bpf_skel.progs[idx].others.clone())
+                .unwrap()
+                .cgroup_path
+        };
+        assert_eq!(cgroup_path_of(0), "/sys/fs/cgroup/test");
+        assert_eq!(cgroup_path_of(1), "/sys/fs/cgroup");
+    }
+    #[test]
+    fn test_override_functions() {
+        let mut skel = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap()
+        .meta;
+        skel.bpf_skel.progs[0].attach = "kprobe".into();
+        skel.bpf_skel.progs[0].others = json!({ "functions": ["tcp_*"] });
+        let cmd = skel.build_argument_parser().unwrap();
+        let prog_name = skel.bpf_skel.progs[0].name.clone();
+        let matches = cmd
+            .try_get_matches_from([
+                "myprog".to_string(),
+                format!("--{}-functions", prog_name),
+                "udp_*".to_string(),
+                format!("--{}-functions", prog_name),
+                "inet_*".to_string(),
+            ])
+            .unwrap();
+        skel.parse_arguments_and_fill_skeleton_variables(
+            &matches,
+            UnpresentVariableAction::FillWithZero,
+        )
+        .unwrap();
+        let extra_meta = serde_json::from_value::<MultiProbeProgExtraMeta>(
+            skel.bpf_skel.progs[0].others.clone(),
+        )
+        .unwrap();
+        assert_eq!(extra_meta.functions, vec!["udp_*", "inet_*"]);
+    }
+    #[test]
+    fn test_override_attach_target() {
+        let skel = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap()
+        .meta;
+        let cmd = skel.build_argument_parser().unwrap();
+        let matches = cmd
+            .clone()
+            .try_get_matches_from([
+                "myprog",
+                "--attach",
+                "handle_exec=tracepoint:sched/sched_process_fork",
+            ])
+            .unwrap();
+        let mut retargeted = skel.clone();
+        retargeted
+            .parse_arguments_and_fill_skeleton_variables(
+                &matches,
+                UnpresentVariableAction::FillWithZero,
+            )
+            .unwrap();
+        assert_eq!(
+            retargeted.bpf_skel.progs[0]
+                .resolve_attach_target()
+                .unwrap(),
+            Some(AttachTarget::Tracepoint {
+                category: "sched".into(),
+                name: "sched_process_fork".into()
+            })
+        );
+        for arg in [
+            "handle_exit=kprobe:do_unlinkat",
+            "handle_exit=tp:sched",
+            "no_such_prog=tp:sched/sched_process_fork",
+        ] {
+            let matches = cmd
+                .clone()
+                .try_get_matches_from(["myprog", "--attach", arg])
+                .unwrap();
+            assert!(skel
+                .clone()
+                .parse_arguments_and_fill_skeleton_variables(
+                    &matches,
+                    UnpresentVariableAction::FillWithZero,
+                )
+                .is_err());
+        }
+    }
 }
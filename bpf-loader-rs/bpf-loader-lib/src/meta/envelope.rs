@@ -0,0 +1,151 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Package envelope
+//!
+//! A package (`ComposedObject`) is a json object like:
+//! ```json
+//! {
+//!    "version": 1,
+//!    "content_type": "zstd",
+//!    "bpf_object": "",
+//!    "bpf_object_size": 0,
+//!    "meta": {},
+//!    "signature": {}
+//! }
+//! ```
+//! - `version`: The version of the envelope. Packages without it are the ones produced before the envelope was versioned, and are treated as version 1
+//! - `content_type`: How the ELF is compressed before being base64 encoded. One of `zlib`, `zstd` and `uncompressed`. Defaults to `zlib`, which is what the old packages use
+//!
+//! The ELF is decoded from the base64 string in a streaming way, so no intermediate buffer of the compressed data is needed
+
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, read::DecoderReader, Engine};
+use deflate::deflate_bytes_zlib;
+use serde::{Deserialize, Serialize};
+
+/// The newest envelope version that this library understands
+pub const PACKAGE_ENVELOPE_VERSION: u32 = 1;
+
+const ZSTD_COMPRESSION_LEVEL: i32 = 19;
+/// `bpf_object_size` comes from the package, so don't trust it when preallocating the buffer
+const MAX_PREALLOCATED_SIZE: usize = 16 << 20;
+
+/// How the ELF in a package is compressed
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageContentType {
+    /// zlib deflate. Readable by all versions of loaders
+    #[default]
+    Zlib,
+    /// zstd. Faster and smaller for objects with big BTF
+    Zstd,
+    /// Not compressed, only base64 encoded
+    Uncompressed,
+}
+
+impl PackageContentType {
+    /// Compress the ELF and encode it into base64
+    pub fn encode(self, bpf_object: &[u8]) -> Result<String> {
+        let compressed = match self {
+            PackageContentType::Zlib => deflate_bytes_zlib(bpf_object),
+            PackageContentType::Zstd => zstd::encode_all(bpf_object, ZSTD_COMPRESSION_LEVEL)
+                .with_context(|| anyhow!("Failed to compress with zstd"))?,
+            PackageContentType::Uncompressed => return Ok(STANDARD.encode(bpf_object)),
+        };
+        Ok(STANDARD.encode(compressed))
+    }
+    /// Decode the base64 string and decompress it into the ELF, which should be `size` bytes
+    pub fn decode(self, encoded: &str, size: usize) -> Result<Vec<u8>> {
+        let base64_reader = DecoderReader::new(encoded.as_bytes(), &STANDARD);
+        let reader: Box<dyn Read> = match self {
+            PackageContentType::Zlib => Box::new(inflate::DeflateDecoder::from_zlib(base64_reader)),
+            PackageContentType::Zstd => Box::new(
+                zstd::stream::read::Decoder::new(base64_reader)
+                    .with_context(|| anyhow!("Failed to create zstd decoder"))?,
+            ),
+            PackageContentType::Uncompressed => Box::new(base64_reader),
+        };
+        let mut buf = Vec::with_capacity(size.min(MAX_PREALLOCATED_SIZE));
+        // Read one more byte than expected, so an oversized payload is detected without inflating all of it
+        reader
+            .take((size as u64).saturating_add(1))
+            .read_to_end(&mut buf)
+            .with_context(|| anyhow!("Malformed {:?} payload", self))?;
+        if buf.len() != size {
+            bail!(
+                "Unmatched size: {} in the json, but {}{} in the decompressed file",
+                size,
+                if buf.len() > size { "more than " } else { "" },
+                buf.len().min(size)
+            );
+        }
+        Ok(buf)
+    }
+}
+
+/// Check that the envelope version is supported
+pub(crate) fn check_version(version: Option<u32>) -> Result<()> {
+    match version {
+        Some(v) if v > PACKAGE_ENVELOPE_VERSION => bail!(
+            "Unsupported package version {}, the newest supported one is {}",
+            v,
+            PACKAGE_ENVELOPE_VERSION
+        ),
+        Some(0) => bail!("Invalid package version 0"),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_version, PackageContentType};
+
+    #[test]
+    fn test_encode_and_decode() {
+        let data = (0..4096u32)
+            .flat_map(|v| (v % 7).to_le_bytes())
+            .collect::<Vec<_>>();
+        for ty in [
+            PackageContentType::Zlib,
+            PackageContentType::Zstd,
+            PackageContentType::Uncompressed,
+        ] {
+            let encoded = ty.encode(&data).unwrap();
+            assert_eq!(ty.decode(&encoded, data.len()).unwrap(), data);
+            assert!(ty.decode(&encoded, data.len() - 1).is_err());
+            assert!(ty.decode(&encoded, data.len() + 1).is_err());
+        }
+        assert!(PackageContentType::Zstd
+            .decode(&PackageContentType::Zlib.encode(&data).unwrap(), data.len())
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_with_oversized_size() {
+        let data = vec![0x7f; 1024];
+        for ty in [
+            PackageContentType::Zlib,
+            PackageContentType::Zstd,
+            PackageContentType::Uncompressed,
+        ] {
+            let encoded = ty.encode(&data).unwrap();
+            // Neither allocates nor reads that much, the payload just turns out to be too short
+            assert!(ty.decode(&encoded, usize::MAX).is_err());
+            assert!(ty.decode(&encoded, 1 << 40).is_err());
+        }
+    }
+
+    #[test]
+    fn test_check_version() {
+        assert!(check_version(None).is_ok());
+        assert!(check_version(Some(1)).is_ok());
+        assert!(check_version(Some(0)).is_err());
+        assert!(check_version(Some(2)).is_err());
+    }
+}
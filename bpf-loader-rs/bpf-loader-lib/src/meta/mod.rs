@@ -64,12 +64,24 @@
 //!
 //! Describes an eBPF program
 
-use base64::Engine;
-use deflate::deflate_bytes_zlib;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Context};
 use libbpf_rs::libbpf_sys::{BPF_TC_CUSTOM, BPF_TC_EGRESS, BPF_TC_INGRESS};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::DefaultOnNull;
+
+use crate::{
+    export_event::ExportFormatType,
+    probe::KernelFeature,
+    signature::{self, PackageSignature},
+};
+
+use self::envelope::{PackageContentType, PACKAGE_ENVELOPE_VERSION};
 /// Describe a struct member in an exported type
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct ExportedTypesStructMemberMeta {
@@ -216,6 +228,105 @@ pub struct MapMeta {
     /// How to intepreter the buffer value of this map. Only applies if this map if a buffer value map (perf event or ringbuf)
     #[serde(default)]
     pub intepreter: BufferValueInterpreter,
+    /// What to turn this map into if the running kernel doesn't support its type
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<MapFallback>,
+    /// Programs to put into this map after loading, keyed by index. Only applies if this map is a prog array.
+    ///
+    /// Declared with `/// @tail_calls {"0": "handle_a", "1": "handle_b"}` on the map in the source. Programs listed here are only reached through tail calls, so they won't be attached
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tail_calls: BTreeMap<u32, String>,
+    /// Entries put into this map after loading, before programs are attached. Keys and values are encoded with BTF, in the same way as `BpfSkeleton::update_map`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub initial_entries: Vec<MapEntry>,
+    /// The command line argument to add entries to this map. No arguments are generated if not provided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmdarg: Option<MapCommandArgument>,
+    /// Inner maps of this map. Only applies if this map is an array of maps or a hash of maps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inner_map: Option<InnerMapMeta>,
+}
+
+/// Describe the inner maps of a map-in-map
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct InnerMapMeta {
+    /// What inner maps look like. Not needed if the map is declared with `__array(values, ...)`, which is already a template.
+    /// If provided, it overrides the one in the declaration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<InnerMapTemplate>,
+    /// Inner maps created and put into this map after loading
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub initial: Vec<InitialInnerMap>,
+}
+
+/// The template of inner maps
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InnerMapTemplate {
+    /// Map type, in the name that libbpf uses, e.g `hash`, `array`
+    pub map_type: String,
+    /// Size of keys, in bytes
+    pub key_size: u32,
+    /// Size of values, in bytes
+    pub value_size: u32,
+    /// Max entries of each inner map
+    pub max_entries: u32,
+    /// Flags to create inner maps with
+    #[serde(default)]
+    pub map_flags: u32,
+    /// Name of the key type in the BTF. Entries of inner maps can't be accessed with json without it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_type: Option<String>,
+    /// Name of the value type in the BTF
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_type: Option<String>,
+}
+
+/// An inner map put into the outer map after loading
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InitialInnerMap {
+    /// Key in the outer map
+    pub key: Value,
+    /// Entries of the inner map
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<MapEntry>,
+}
+
+/// An entry of a map
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MapEntry {
+    /// The key
+    pub key: Value,
+    /// The value. The value will be zero if not provided
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+/// The command line argument that adds entries to a map, e.g a set of pids to trace
+///
+/// `--<LONG> KEY` could be repeated, each adds a key with `value`. Keys are json, or strings if they aren't valid json.
+/// `--<LONG>-file PATH` adds entries in a file:
+/// - `.csv` files have one entry per line, as `KEY` or `KEY,VALUE`. Empty lines and lines starting with `#` are ignored. Keys could be json containing commas, like `{"pid": 1, "tid": 2},3`
+/// - Other files are json arrays. Elements with a `key` field are entries, other elements are keys
+pub struct MapCommandArgument {
+    /// The long name of this. If not provided, will use the map name
+    pub long: Option<String>,
+    /// The short name of this
+    pub short: Option<String>,
+    /// The help string of this option
+    pub help: Option<String>,
+    /// The value of keys provided in the command line. If not provided, values will be zero
+    #[serde(default)]
+    pub value: Option<Value>,
+}
+
+/// Fallbacks of a map, applied before loading if the map type isn't supported
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapFallback {
+    /// Turn a ringbuf into a perf event array. The poller will follow the new type.
+    /// The program should pick `bpf_perf_event_output` when ringbuf isn't available, e.g with `bpf_core_type_exists(struct bpf_ringbuf)`
+    #[serde(rename = "perf_event_array")]
+    PerfEventArray,
 }
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 /// Describe the meta of a bpf program
@@ -226,11 +337,192 @@ pub struct ProgMeta {
     pub attach: String,
     /// Whether the attaching of this program will generate a bpf_link
     pub link: bool,
+    /// Kernel features this program needs, besides the ones implied by its section (e.g `fentry/` needs `fentry`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<KernelFeature>,
+    /// Name of another program in the object, loaded instead of this one if the running kernel lacks the features it needs.
+    /// The fallback program is not loaded if this one could be
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// Attach the program to this target instead of the one in its section, in the form of `<KIND>:<TARGET>`. See `AttachTarget`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attach_target: Option<String>,
     #[serde(flatten)]
     /// Other fields
     pub others: Value,
 }
 
+impl ProgMeta {
+    /// Whether this program is attached to a cgroup, so `CgroupProgExtraMeta` applies
+    pub fn attaches_to_cgroup(&self) -> bool {
+        self.attach.starts_with("cgroup_skb/")
+            || self.attach.starts_with("cgroup/")
+            || self.attach == "sockops"
+    }
+    /// Whether this program is attached to a sockmap or sockhash, so `SockmapProgExtraMeta` applies
+    pub fn attaches_to_sockmap(&self) -> bool {
+        self.attach == "sk_msg" || self.attach == "sk_skb" || self.attach.starts_with("sk_skb/")
+    }
+    /// Whether this program is in a bare `kprobe`, `kretprobe`, `uprobe` or `uretprobe` section, so `MultiProbeProgExtraMeta` applies
+    pub fn is_multi_probe(&self) -> bool {
+        matches!(
+            self.attach.as_str(),
+            "kprobe" | "kretprobe" | "uprobe" | "uretprobe"
+        )
+    }
+    /// Whether this program is in a bare `usdt` section, so `UsdtProgExtraMeta` applies.
+    /// Programs in `usdt/<PATH>:<PROVIDER>:<PROBE>` sections are attached by libbpf on its own
+    pub fn is_usdt(&self) -> bool {
+        self.attach == "usdt"
+    }
+    /// Whether this program is a BPF iterator, so `IterProgExtraMeta` applies
+    pub fn is_iter(&self) -> bool {
+        self.attach.starts_with("iter/") || self.attach.starts_with("iter.s/")
+    }
+    /// Whether this program is attached to a network namespace, so `NetnsProgExtraMeta` applies
+    pub fn attaches_to_netns(&self) -> bool {
+        self.attach == "sk_lookup" || self.attach == "flow_dissector"
+    }
+    /// Parse `attach_target`, and check that the program could be attached to it
+    pub fn resolve_attach_target(&self) -> anyhow::Result<Option<AttachTarget>> {
+        let Some(target) = self.attach_target.as_ref() else {
+            return Ok(None);
+        };
+        let target = target.parse::<AttachTarget>()?;
+        let section_kind = self.attach.split('/').next().unwrap_or_default();
+        let compatible = match &target {
+            AttachTarget::Kprobe { .. } | AttachTarget::Uprobe { .. } => matches!(
+                section_kind,
+                "kprobe" | "kretprobe" | "uprobe" | "uretprobe" | "ksyscall" | "kretsyscall"
+            ),
+            AttachTarget::Tracepoint { .. } => matches!(section_kind, "tracepoint" | "tp"),
+            AttachTarget::RawTracepoint(_) => matches!(section_kind, "raw_tracepoint" | "raw_tp"),
+            // The attach type is decided by the section, only the function could be changed
+            AttachTarget::Btf { kind, .. } => section_kind.trim_end_matches(".s") == kind,
+        };
+        if !compatible {
+            bail!(
+                "Program `{}` in section `{}` can't be attached to `{}`",
+                self.name,
+                self.attach,
+                self.attach_target.as_ref().unwrap()
+            );
+        }
+        Ok(Some(target))
+    }
+}
+
+/// Where to attach a program instead of the target in its section
+///
+/// Only programs of a compatible type could be retargeted:
+/// - `kprobe:<FUNC>`, `kretprobe:<FUNC>`, `uprobe:<PATH>:<FUNC>[:<PID>]` and `uretprobe:<PATH>:<FUNC>[:<PID>]` for kprobes and uprobes
+/// - `tracepoint:<CATEGORY>/<NAME>` or `tp:<CATEGORY>/<NAME>` for tracepoints
+/// - `raw_tp:<NAME>` or `raw_tracepoint:<NAME>` for raw tracepoints
+/// - `fentry:<FUNC>`, `fexit:<FUNC>`, `fmod_ret:<FUNC>`, `lsm:<HOOK>` and `tp_btf:<NAME>` for programs of the same kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttachTarget {
+    /// A kernel function
+    Kprobe {
+        /// Name of the function
+        function: String,
+        /// Whether to attach to the return of the function
+        retprobe: bool,
+    },
+    /// A function in a binary or a shared library
+    Uprobe {
+        /// Path of the binary
+        binary_path: String,
+        /// Name of the function, resolved against symbols of the binary
+        function: String,
+        /// Whether to attach to the return of the function
+        retprobe: bool,
+        /// Only trace this process. `-1` for all processes, if it's not given
+        pid: i32,
+    },
+    /// A tracepoint
+    Tracepoint {
+        /// Category of the tracepoint, e.g `syscalls`
+        category: String,
+        /// Name of the tracepoint, e.g `sys_enter_openat`
+        name: String,
+    },
+    /// A raw tracepoint
+    RawTracepoint(String),
+    /// A BTF-enabled target, set before the program is loaded
+    Btf {
+        /// `fentry`, `fexit`, `fmod_ret`, `lsm` or `tp_btf`
+        kind: String,
+        /// Name of the function, the hook or the tracepoint
+        function: String,
+    },
+}
+
+impl FromStr for AttachTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let Some((kind, target)) = s.split_once(':') else {
+            bail!(
+                "Attach target `{}` should be in the form of `<KIND>:<TARGET>`",
+                s
+            );
+        };
+        if target.is_empty() {
+            bail!("Attach target `{}` has an empty target", s);
+        }
+        Ok(match kind {
+            "kprobe" | "kretprobe" => AttachTarget::Kprobe {
+                function: target.to_string(),
+                retprobe: kind == "kretprobe",
+            },
+            "uprobe" | "uretprobe" => {
+                // Names of functions never start with a digit, so a number at the end is the pid
+                let (target, pid) = match target.rsplit_once(':') {
+                    Some((rest, pid))
+                        if !pid.is_empty() && pid.bytes().all(|v| v.is_ascii_digit()) =>
+                    {
+                        let pid = pid
+                            .parse()
+                            .with_context(|| anyhow!("Invalid pid `{}` of `{}`", pid, s))?;
+                        (rest, pid)
+                    }
+                    _ => (target, -1),
+                };
+                let Some((binary_path, function)) = target.rsplit_once(':') else {
+                    bail!(
+                        "Uprobe target `{}` should be `<PATH>:<FUNC>[:<PID>]`",
+                        target
+                    );
+                };
+                AttachTarget::Uprobe {
+                    binary_path: binary_path.to_string(),
+                    function: function.to_string(),
+                    retprobe: kind == "uretprobe",
+                    pid,
+                }
+            }
+            "tracepoint" | "tp" => {
+                let Some((category, name)) = target.split_once('/') else {
+                    bail!(
+                        "Tracepoint target `{}` should be `<CATEGORY>/<NAME>`",
+                        target
+                    );
+                };
+                AttachTarget::Tracepoint {
+                    category: category.to_string(),
+                    name: name.to_string(),
+                }
+            }
+            "raw_tp" | "raw_tracepoint" => AttachTarget::RawTracepoint(target.to_string()),
+            "fentry" | "fexit" | "fmod_ret" | "lsm" | "tp_btf" => AttachTarget::Btf {
+                kind: kind.to_string(),
+                function: target.to_string(),
+            },
+            _ => bail!("Unsupported kind `{}` of attach target `{}`", kind, s),
+        })
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 /// Extra fields in prog meta for XDP programs
 pub struct XDPProgExtraMeta {
@@ -254,6 +546,75 @@ pub struct XDPOpts {
     pub old_prog_fd: i32,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+/// Extra fields in prog meta for programs attached to a cgroup, e.g `cgroup_skb/`, `cgroup/` and `sockops`
+pub struct CgroupProgExtraMeta {
+    #[serde(default = "default_helpers::default_cgroup_path")]
+    /// Path of the cgroup to attach to. The root of the cgroup v2 hierarchy by default
+    pub cgroup_path: String,
+    #[serde(default = "default_helpers::default_u32::<0>")]
+    /// Attach flags, e.g `BPF_F_ALLOW_MULTI`. With no flags, the program is attached through a bpf link
+    pub attach_flags: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+/// Extra fields in prog meta for `sk_msg` and `sk_skb` programs
+pub struct SockmapProgExtraMeta {
+    /// Name of the sockmap or sockhash in the object to attach to
+    pub sockmap: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+/// Extra fields in prog meta for `sk_lookup` and `flow_dissector` programs
+pub struct NetnsProgExtraMeta {
+    #[serde(default = "default_helpers::default_netns_path")]
+    /// Path of the network namespace to attach to. The one of the loader by default
+    pub netns_path: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+/// Extra fields in prog meta for kprobes and uprobes attached to every function matching glob patterns
+pub struct MultiProbeProgExtraMeta {
+    /// Glob patterns of the functions, e.g `tcp_*`. `*` and `?` are supported.
+    /// Kernel functions are resolved against `/proc/kallsyms`, and functions of uprobes are resolved against symbols of `binary_path`
+    pub functions: Vec<String>,
+    #[serde(default)]
+    /// Path of the binary or the shared library, required by uprobes
+    pub binary_path: Option<String>,
+    #[serde(default = "default_helpers::default_i32::<-1>")]
+    /// Only trace this process with uprobes. All processes are traced by default
+    pub pid: i32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+/// Extra fields in prog meta for USDT programs. The program should use `usdt.bpf.h` from libbpf
+pub struct UsdtProgExtraMeta {
+    /// Path of the binary or the shared library that defines the probe
+    pub binary_path: String,
+    /// Provider of the probe
+    pub provider: String,
+    /// Name of the probe
+    pub probe: String,
+    #[serde(default = "default_helpers::default_i32::<-1>")]
+    /// Only trace this process. All processes are traced by default
+    pub pid: i32,
+    #[serde(default)]
+    /// The value `bpf_usdt_cookie` returns in the program
+    pub cookie: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+/// Extra fields in prog meta for BPF iterators, e.g `iter/task`
+pub struct IterProgExtraMeta {
+    #[serde(default)]
+    /// Read the iterator every `interval_ms` milliseconds. If not set, it's read only once
+    pub interval_ms: Option<u64>,
+    #[serde(default)]
+    /// Name of the struct the program writes with `bpf_seq_write`. The records are exported like events of ringbufs.
+    /// If not set, the output is passed through as text, line by line
+    pub record_type: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
 /// Extra fields in prog meta for TC programs
 pub struct TCProgExtraMeta {
@@ -448,11 +809,15 @@ pub struct EunomiaObjectMeta {
     #[serde(default = "default_helpers::default_bool::<false>")]
     pub enable_multiple_export_types: bool,
 }
-#[derive(Deserialize, Serialize, PartialEq, Eq)]
-pub(crate) struct ComposedObjectInner {
-    pub(crate) bpf_object: String,
-    pub(crate) bpf_object_size: usize,
-    pub(crate) meta: EunomiaObjectMeta,
+#[derive(Serialize)]
+struct ComposedObjectInner<'a> {
+    version: u32,
+    content_type: PackageContentType,
+    bpf_object: String,
+    bpf_object_size: usize,
+    meta: &'a EunomiaObjectMeta,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<&'a PackageSignature>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -460,16 +825,47 @@ pub(crate) struct ComposedObjectInner {
 /// The original json should be like:
 /// ```json
 /// {
-///    "bpf_object": "", // An base64-encoded, zlib deflate-compressed object file
+///    "version": 1, /// Optional, the version of the envelope
+///    "content_type": "zlib", /// Optional, how the object file is compressed. See `envelope`
+///    "bpf_object": "", // An base64-encoded, compressed object file
 ///    "bpf_object_size" : 0 , /// The uncompressed size of the object file, in bytes
-///    "meta": {} /// The meta object
+///    "meta": {}, /// The meta object
+///    "signature": {} /// Optional, see `crate::signature`
 /// }
 /// ```
+///
+/// It can't be built with a struct literal outside this crate, since it also keeps the digest of the meta json it was
+/// deserialized from. Use `ComposedObject::new`, or deserialize it
+#[non_exhaustive]
 pub struct ComposedObject {
     /// The object binary
     pub bpf_object: Vec<u8>,
     /// The meta info
     pub meta: EunomiaObjectMeta,
+    /// The embedded signature. Could be replaced with a detached one before verifying
+    pub signature: Option<PackageSignature>,
+    /// How the object binary will be compressed when serializing. Kept from the json when deserializing
+    pub content_type: PackageContentType,
+    /// Digest of the meta as it was in the json, which is what the signature covers
+    /// None if the package was not deserialized from json
+    pub(crate) meta_digest: Option<[u8; 32]>,
+}
+
+impl ComposedObject {
+    /// Build a package from an object file and its meta. It's not signed, and will be serialized with the default compression
+    pub fn new(bpf_object: Vec<u8>, meta: EunomiaObjectMeta) -> Self {
+        Self {
+            bpf_object,
+            meta,
+            signature: None,
+            content_type: PackageContentType::default(),
+            meta_digest: None,
+        }
+    }
+    /// Deserialize a package from a reader, without reading the whole json into memory first
+    pub fn from_reader(reader: impl std::io::Read) -> anyhow::Result<Self> {
+        Ok(serde_json::from_reader(std::io::BufReader::new(reader))?)
+    }
 }
 
 impl Serialize for ComposedObject {
@@ -478,55 +874,192 @@ impl Serialize for ComposedObject {
         S: serde::Serializer,
     {
         use serde::ser::Error;
-        let bpf_object_size = self.bpf_object.len();
-        let compressed = deflate_bytes_zlib(&self.bpf_object);
-        let bpf_object_base64 = base64::engine::general_purpose::STANDARD.encode(compressed);
-
-        let json_val = serde_json::to_value(ComposedObjectInner {
-            bpf_object: bpf_object_base64,
-            bpf_object_size,
-            meta: self.meta.clone(),
-        })
-        .map_err(|e| Error::custom(format!("Failed to serialize: {e}")))?;
-        json_val.serialize(serializer)
+        ComposedObjectInner {
+            version: PACKAGE_ENVELOPE_VERSION,
+            content_type: self.content_type,
+            bpf_object: self
+                .content_type
+                .encode(&self.bpf_object)
+                .map_err(|e| Error::custom(format!("Failed to encode the object: {e}")))?,
+            bpf_object_size: self.bpf_object.len(),
+            meta: &self.meta,
+            signature: self.signature.as_ref(),
+        }
+        .serialize(serializer)
     }
 }
+
 impl<'de> Deserialize<'de> for ComposedObject {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ComposedObjectVisitor)
+    }
+}
+
+/// Visit the fields one by one, so the (possibly large) `bpf_object` won't go through a `Value`
+/// Only `meta` does, since the signature covers it as it was in the json
+struct ComposedObjectVisitor;
+
+impl<'de> serde::de::Visitor<'de> for ComposedObjectVisitor {
+    type Value = ComposedObject;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a package object")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
     {
         use serde::de::Error;
-        let json_val: ComposedObjectInner =
-            serde_json::from_value(Value::deserialize(deserializer)?)
-                .map_err(|e| Error::custom(format!("Malformed json provided: {e}")))?;
-        let base64_decoded = base64::engine::general_purpose::STANDARD
-            .decode(&json_val.bpf_object)
-            .map_err(|e| Error::custom(format!("Malformed base64: {e}")))?;
-        let uncompressed = inflate::inflate_bytes_zlib(&base64_decoded)
-            .map_err(|e| Error::custom(format!("Malformed compressed data: {e}")))?;
-        if uncompressed.len() != json_val.bpf_object_size {
-            return Err(Error::custom(format!(
-                "Unmatched size: {} in the json, but {} in the decompressed file",
-                json_val.bpf_object_size,
-                uncompressed.len()
-            )));
+        let mut version = None;
+        let mut content_type = None;
+        let mut bpf_object = None;
+        let mut bpf_object_size = None;
+        let mut meta = None;
+        let mut signature = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value::<u32>()?),
+                "content_type" => content_type = Some(map.next_value::<PackageContentType>()?),
+                "bpf_object" => bpf_object = Some(map.next_value::<String>()?),
+                "bpf_object_size" => bpf_object_size = Some(map.next_value::<usize>()?),
+                "meta" => meta = Some(map.next_value::<Value>()?),
+                "signature" => signature = map.next_value::<Option<PackageSignature>>()?,
+                _ => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
         }
-        Ok(Self {
-            bpf_object: uncompressed,
-            meta: json_val.meta,
+        envelope::check_version(version).map_err(|e| Error::custom(format!("{e}")))?;
+        let bpf_object = bpf_object.ok_or_else(|| Error::missing_field("bpf_object"))?;
+        let bpf_object_size =
+            bpf_object_size.ok_or_else(|| Error::missing_field("bpf_object_size"))?;
+        let meta_value = meta.ok_or_else(|| Error::missing_field("meta"))?;
+        let meta_digest = signature::meta_digest(&meta_value);
+        let meta = EunomiaObjectMeta::deserialize(&meta_value)
+            .map_err(|e| Error::custom(format!("Malformed json provided: {e}")))?;
+        let content_type = content_type.unwrap_or_default();
+        let bpf_object = content_type
+            .decode(&bpf_object, bpf_object_size)
+            .map_err(|e| Error::custom(format!("{e:#}")))?;
+        Ok(ComposedObject {
+            bpf_object,
+            meta,
+            signature,
+            content_type,
+            meta_digest: Some(meta_digest),
         })
     }
 }
 
 /// Global config to control the behavior of eunomia-bpf
-/// TODO: load config from json or config files
-#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+///
+/// The config is layered: values from the package (`EunomiaObjectMeta`) < config file < environment variables < command line flags.
+/// Fields left `None` won't override the lower layers. See `runner_config` for how the layers are loaded.
+/// `print_kernel_debug` is a plain bool, but a config file or env var setting it to false still turns off the lower layers.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
 pub struct RunnerConfig {
     /// whether we should print the bpf_printk
-    /// from /sys/kernel/debug/tracing/trace_pipe
+    /// from /sys/kernel/tracing/trace_pipe (or /sys/kernel/debug/tracing/trace_pipe)
+    /// The lines will be passed to the event handler as `ReceivedEventData::KernelDebugText`
+    /// Note that trace_pipe is shared by the whole system, lines read by us won't be seen by other readers
     #[serde(default = "default_helpers::default_bool::<false>")]
     pub print_kernel_debug: bool,
+    /// Only forward bpf_printk lines printed in the context of these pids
+    /// The processes that uprobe and USDT programs are restricted to (with `pid`) are added to them.
+    /// If both are empty, or some programs aren't restricted to a process, all lines will be forwarded
+    #[serde(default)]
+    pub kernel_debug_pids: Vec<u32>,
+    /// Override `perf_buffer_pages` of the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub perf_buffer_pages: Option<usize>,
+    /// Override `poll_timeout_ms` of the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_timeout_ms: Option<i32>,
+    /// Override `debug_verbose` of the package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug_verbose: Option<bool>,
+    /// Path to a custom BTF file of the running kernel. Takes precedence over env `BTF_FILE_PATH`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub btf_path: Option<String>,
+    /// Log level (or a `RUST_LOG` style spec) for the frontends that set up the logger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    /// The export format that the frontends should use if the user doesn't ask for one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_format: Option<ExportFormatType>,
+    /// Path to a PEM public key, or a directory of them. If set, only packages signed by these keys will be loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trust_store: Option<String>,
+    /// Load the kernel BTF, so forward declarations and kernel-only types could be resolved when exporting events.
+    /// It's loaded from `btf_path` if provided, or from `/sys/kernel/btf/vmlinux`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolve_kernel_types: Option<bool>,
+    /// Also load the split BTF of these kernel modules, e.g `nf_conntrack`. Implies `resolve_kernel_types`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kernel_btf_modules: Vec<String>,
+    /// Enable `BPF_STATS_RUN_TIME` while the skeleton is alive, so `run_cnt` and `run_time_ns` of the programs are counted.
+    /// It's system-wide and adds a little overhead to every bpf program
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_stats: Option<bool>,
+    /// Log level of the verifier. With 0 (the default), the log is only captured when a program is rejected.
+    /// With 1 or 2, logs of the accepted programs are also printed at debug level. 2 is much more verbose
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifier_log_level: Option<u32>,
+    /// Per-map exporter settings, keyed by map name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub maps: HashMap<String, MapRunnerConfig>,
+}
+
+/// Exporter settings of a single map
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapRunnerConfig {
+    /// Export this map in the given format, regardless of what the caller asked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_format: Option<ExportFormatType>,
+    /// Override the sampling interval of this map, in milliseconds. Only applies to sample maps
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample_interval: Option<usize>,
+    /// Don't export this map. Only applies if `enable_multiple_export_types` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub no_export: Option<bool>,
+    /// Add process, user or container metadata to the events. Only applies to ringbufs and perf event arrays exported as json or plain text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enrich: Option<EnrichConfig>,
+}
+
+/// Which metadata to add to the events of a map, and where to find the ids they are resolved from
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EnrichConfig {
+    /// Enrichers to run. Their fields are appended in this order
+    pub enrichers: Vec<EnricherKind>,
+    /// Name of the field holding the pid
+    #[serde(default = "default_helpers::default_pid_field")]
+    pub pid_field: String,
+    /// Name of the field holding the uid
+    #[serde(default = "default_helpers::default_uid_field")]
+    pub uid_field: String,
+    /// Name of the field holding the cgroup id, used by `container` if the event has no pid
+    #[serde(default = "default_helpers::default_cgroup_id_field")]
+    pub cgroup_id_field: String,
+    /// How long resolved metadata is cached, in milliseconds
+    #[serde(default = "default_helpers::default_u64::<5000>")]
+    pub cache_ttl_ms: u64,
+}
+
+/// Built-in enrichers
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnricherKind {
+    /// Add `exe` and `cmdline` of the process, read from `/proc/<pid>`
+    Process,
+    /// Add `username` of the uid, read from `/etc/passwd`
+    User,
+    /// Add `container_id` and `container_runtime`, derived from the cgroup path of the process
+    Container,
 }
 
 pub(crate) mod default_helpers {
@@ -542,6 +1075,29 @@ pub(crate) mod default_helpers {
     pub(crate) fn default_u32<const V: u32>() -> u32 {
         V
     }
+    pub(crate) fn default_u64<const V: u64>() -> u64 {
+        V
+    }
+
+    pub(crate) fn default_cgroup_path() -> String {
+        "/sys/fs/cgroup".into()
+    }
+
+    pub(crate) fn default_pid_field() -> String {
+        "pid".into()
+    }
+
+    pub(crate) fn default_uid_field() -> String {
+        "uid".into()
+    }
+
+    pub(crate) fn default_cgroup_id_field() -> String {
+        "cgroup_id".into()
+    }
+
+    pub(crate) fn default_netns_path() -> String {
+        "/proc/self/ns/net".into()
+    }
 
     pub(crate) fn map_unit_default() -> String {
         "(unit)".into()
@@ -552,5 +1108,9 @@ pub(crate) mod default_helpers {
 pub mod arg_builder;
 /// A parser that can parse values from command line
 pub mod arg_parser;
+/// The versioned envelope of packages, and how the ELF is compressed in it
+pub mod envelope;
+/// Loading and merging of the layered runner config
+pub mod runner_config;
 #[cfg(test)]
 mod tests;
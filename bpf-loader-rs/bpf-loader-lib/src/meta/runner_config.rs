@@ -0,0 +1,426 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, warn};
+
+use crate::skeleton::BTF_PATH_ENV_NAME;
+
+use super::{EunomiaObjectMeta, MapExportConfig, RunnerConfig};
+
+/// Path of the config file to load, if not provided explicitly
+pub const CONFIG_PATH_ENV_NAME: &str = "EUNOMIA_CONFIG";
+/// Overrides `RunnerConfig::print_kernel_debug`
+pub const PRINT_KERNEL_DEBUG_ENV_NAME: &str = "EUNOMIA_PRINT_KERNEL_DEBUG";
+/// Overrides `RunnerConfig::kernel_debug_pids`, separated by commas
+pub const KERNEL_DEBUG_PIDS_ENV_NAME: &str = "EUNOMIA_KERNEL_DEBUG_PIDS";
+/// Overrides `RunnerConfig::perf_buffer_pages`
+pub const PERF_BUFFER_PAGES_ENV_NAME: &str = "EUNOMIA_PERF_BUFFER_PAGES";
+/// Overrides `RunnerConfig::poll_timeout_ms`
+pub const POLL_TIMEOUT_MS_ENV_NAME: &str = "EUNOMIA_POLL_TIMEOUT_MS";
+/// Overrides `RunnerConfig::debug_verbose`
+pub const DEBUG_VERBOSE_ENV_NAME: &str = "EUNOMIA_DEBUG_VERBOSE";
+/// Overrides `RunnerConfig::log_level`
+pub const LOG_LEVEL_ENV_NAME: &str = "EUNOMIA_LOG_LEVEL";
+/// Overrides `RunnerConfig::export_format`
+pub const EXPORT_FORMAT_ENV_NAME: &str = "EUNOMIA_EXPORT_FORMAT";
+/// Overrides `RunnerConfig::trust_store`
+pub const TRUST_STORE_ENV_NAME: &str = "EUNOMIA_TRUST_STORE";
+/// Overrides `RunnerConfig::resolve_kernel_types`
+pub const RESOLVE_KERNEL_TYPES_ENV_NAME: &str = "EUNOMIA_RESOLVE_KERNEL_TYPES";
+/// Overrides `RunnerConfig::kernel_btf_modules`, separated by commas
+pub const KERNEL_BTF_MODULES_ENV_NAME: &str = "EUNOMIA_KERNEL_BTF_MODULES";
+/// Overrides `RunnerConfig::enable_stats`
+pub const ENABLE_STATS_ENV_NAME: &str = "EUNOMIA_ENABLE_STATS";
+/// Overrides `RunnerConfig::verifier_log_level`
+pub const VERIFIER_LOG_LEVEL_ENV_NAME: &str = "EUNOMIA_VERIFIER_LOG_LEVEL";
+
+/// A layer of config to be merged
+///
+/// `RunnerConfig::print_kernel_debug` can't tell whether it was set, so it's tracked here,
+/// letting a layer turn it off for the lower ones
+#[derive(Default)]
+struct ConfigLayer {
+    config: RunnerConfig,
+    print_kernel_debug: Option<bool>,
+}
+
+impl ConfigLayer {
+    fn merge(self, upper: ConfigLayer) -> ConfigLayer {
+        ConfigLayer {
+            config: self.config.merge(upper.config),
+            print_kernel_debug: upper.print_kernel_debug.or(self.print_kernel_debug),
+        }
+    }
+    fn into_config(self) -> RunnerConfig {
+        RunnerConfig {
+            print_kernel_debug: self.print_kernel_debug.unwrap_or(false),
+            ..self.config
+        }
+    }
+}
+
+impl RunnerConfig {
+    /// Load a config from a json file
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::layer_from_config_file(path.as_ref()).map(ConfigLayer::into_config)
+    }
+    fn layer_from_config_file(path: &Path) -> Result<ConfigLayer> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read config file {}", path.display()))?;
+        let value: Value = serde_json::from_str(&content)
+            .with_context(|| anyhow!("Failed to parse config file {}", path.display()))?;
+        Self::layer_from_value(value)
+            .with_context(|| anyhow!("Failed to parse config file {}", path.display()))
+    }
+    fn layer_from_value(value: Value) -> Result<ConfigLayer> {
+        let print_kernel_debug = value.get("print_kernel_debug").and_then(Value::as_bool);
+        Ok(ConfigLayer {
+            config: serde_json::from_value(value)?,
+            print_kernel_debug,
+        })
+    }
+    /// Load a config from the `EUNOMIA_*` environment variables, and `BTF_FILE_PATH`
+    ///
+    /// Per-map settings can only be provided by the config file
+    pub fn from_env() -> Result<Self> {
+        Self::layer_from_env_source(|name| std::env::var(name).ok()).map(ConfigLayer::into_config)
+    }
+    fn layer_from_env_source(get: impl Fn(&str) -> Option<String>) -> Result<ConfigLayer> {
+        fn parse<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<Option<T>>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value
+                .map(|v| {
+                    v.trim()
+                        .parse::<T>()
+                        .map_err(|e| anyhow!("Invalid value `{}` of env `{}`: {}", v, name, e))
+                })
+                .transpose()
+        }
+        fn parse_bool(name: &str, value: Option<String>) -> Result<Option<bool>> {
+            value
+                .map(|v| match v.trim() {
+                    "1" | "true" | "yes" | "on" => Ok(true),
+                    "0" | "false" | "no" | "off" => Ok(false),
+                    s => bail!(
+                        "Invalid value `{}` of env `{}`, expected a boolean",
+                        s,
+                        name
+                    ),
+                })
+                .transpose()
+        }
+        let kernel_debug_pids = match get(KERNEL_DEBUG_PIDS_ENV_NAME) {
+            Some(v) => v
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| parse(KERNEL_DEBUG_PIDS_ENV_NAME, Some(s.to_string())))
+                .collect::<Result<Vec<Option<u32>>>>()?
+                .into_iter()
+                .flatten()
+                .collect(),
+            None => vec![],
+        };
+        let kernel_btf_modules = get(KERNEL_BTF_MODULES_ENV_NAME)
+            .map(|v| {
+                v.split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let config = Self {
+            print_kernel_debug: false,
+            kernel_debug_pids,
+            perf_buffer_pages: parse(PERF_BUFFER_PAGES_ENV_NAME, get(PERF_BUFFER_PAGES_ENV_NAME))?,
+            poll_timeout_ms: parse(POLL_TIMEOUT_MS_ENV_NAME, get(POLL_TIMEOUT_MS_ENV_NAME))?,
+            debug_verbose: parse_bool(DEBUG_VERBOSE_ENV_NAME, get(DEBUG_VERBOSE_ENV_NAME))?,
+            btf_path: get(BTF_PATH_ENV_NAME),
+            log_level: get(LOG_LEVEL_ENV_NAME),
+            export_format: parse(EXPORT_FORMAT_ENV_NAME, get(EXPORT_FORMAT_ENV_NAME))?,
+            trust_store: get(TRUST_STORE_ENV_NAME),
+            resolve_kernel_types: parse_bool(
+                RESOLVE_KERNEL_TYPES_ENV_NAME,
+                get(RESOLVE_KERNEL_TYPES_ENV_NAME),
+            )?,
+            kernel_btf_modules,
+            enable_stats: parse_bool(ENABLE_STATS_ENV_NAME, get(ENABLE_STATS_ENV_NAME))?,
+            verifier_log_level: parse(
+                VERIFIER_LOG_LEVEL_ENV_NAME,
+                get(VERIFIER_LOG_LEVEL_ENV_NAME),
+            )?,
+            maps: Default::default(),
+        };
+        Ok(ConfigLayer {
+            config,
+            print_kernel_debug: parse_bool(
+                PRINT_KERNEL_DEBUG_ENV_NAME,
+                get(PRINT_KERNEL_DEBUG_ENV_NAME),
+            )?,
+        })
+    }
+    /// Merge two layers of config. Values set in `upper` take precedence
+    ///
+    /// `print_kernel_debug` is enabled if any of the layers enables it
+    pub fn merge(self, upper: RunnerConfig) -> RunnerConfig {
+        let mut maps = self.maps;
+        for (name, upper_map) in upper.maps.into_iter() {
+            let map = maps.entry(name).or_default();
+            map.export_format = upper_map.export_format.or(map.export_format);
+            map.sample_interval = upper_map.sample_interval.or(map.sample_interval);
+            map.no_export = upper_map.no_export.or(map.no_export);
+            map.enrich = upper_map.enrich.or(map.enrich.take());
+        }
+        RunnerConfig {
+            print_kernel_debug: self.print_kernel_debug || upper.print_kernel_debug,
+            kernel_debug_pids: if upper.kernel_debug_pids.is_empty() {
+                self.kernel_debug_pids
+            } else {
+                upper.kernel_debug_pids
+            },
+            perf_buffer_pages: upper.perf_buffer_pages.or(self.perf_buffer_pages),
+            poll_timeout_ms: upper.poll_timeout_ms.or(self.poll_timeout_ms),
+            debug_verbose: upper.debug_verbose.or(self.debug_verbose),
+            btf_path: upper.btf_path.or(self.btf_path),
+            log_level: upper.log_level.or(self.log_level),
+            export_format: upper.export_format.or(self.export_format),
+            trust_store: upper.trust_store.or(self.trust_store),
+            resolve_kernel_types: upper.resolve_kernel_types.or(self.resolve_kernel_types),
+            kernel_btf_modules: if upper.kernel_btf_modules.is_empty() {
+                self.kernel_btf_modules
+            } else {
+                upper.kernel_btf_modules
+            },
+            enable_stats: upper.enable_stats.or(self.enable_stats),
+            verifier_log_level: upper.verifier_log_level.or(self.verifier_log_level),
+            maps,
+        }
+    }
+    /// Whether the kernel BTF should be loaded to resolve types when exporting
+    pub fn should_resolve_kernel_types(&self) -> bool {
+        self.resolve_kernel_types.unwrap_or(false) || !self.kernel_btf_modules.is_empty()
+    }
+    /// Load the layers of config, and merge them
+    ///
+    /// The layers, from lowest to highest, are:
+    /// - The config file. `config_file` if provided, or the one pointed by env `EUNOMIA_CONFIG`, or nothing
+    /// - Environment variables, see `from_env`
+    /// - `cli`, usually built from command line flags
+    ///
+    /// The config file and env vars may turn `print_kernel_debug` off by setting it to false.
+    /// `cli` can only turn it on, set the field of the result to force it off
+    ///
+    /// Values of the package are the lowest layer, they will be overridden when building the skeleton
+    pub fn load_layered(config_file: Option<&Path>, cli: RunnerConfig) -> Result<Self> {
+        let config_file = config_file
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os(CONFIG_PATH_ENV_NAME).map(PathBuf::from));
+        let file_layer = if let Some(path) = config_file {
+            debug!("Loading runner config from {}", path.display());
+            Self::layer_from_config_file(&path)?
+        } else {
+            ConfigLayer::default()
+        };
+        let env_layer = Self::layer_from_env_source(|name| std::env::var(name).ok())
+            .with_context(|| anyhow!("Failed to load config from env"))?;
+        let cli_layer = ConfigLayer {
+            print_kernel_debug: cli.print_kernel_debug.then_some(true),
+            config: cli,
+        };
+        Ok(file_layer.merge(env_layer).merge(cli_layer).into_config())
+    }
+    /// Override values in the package with this config
+    pub(crate) fn apply_to_meta(&self, meta: &mut EunomiaObjectMeta) {
+        if let Some(v) = self.perf_buffer_pages {
+            meta.perf_buffer_pages = v;
+        }
+        if let Some(v) = self.poll_timeout_ms {
+            meta.poll_timeout_ms = v;
+        }
+        if let Some(v) = self.debug_verbose {
+            meta.debug_verbose = v;
+        }
+        for (name, map_config) in self.maps.iter() {
+            let Some(map_meta) = meta.bpf_skel.maps.iter_mut().find(|m| &m.name == name) else {
+                warn!("Map `{}` in runner config not found in the package", name);
+                continue;
+            };
+            if let Some(interval) = map_config.sample_interval {
+                match map_meta.sample.as_mut() {
+                    Some(sample) => sample.interval = interval,
+                    None => warn!(
+                        "Map `{}` is not a sample map, `sample_interval` ignored",
+                        name
+                    ),
+                }
+            }
+            if map_config.no_export == Some(true) {
+                map_meta.export_config = MapExportConfig::NoExport;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use crate::{
+        export_event::ExportFormatType,
+        meta::{ComposedObject, EnricherKind, MapExportConfig, MapRunnerConfig, RunnerConfig},
+        tests::get_assets_dir,
+    };
+
+    #[test]
+    fn test_merge_layers() {
+        let file_layer: RunnerConfig = serde_json::from_value(json!({
+            "poll_timeout_ms": 10,
+            "perf_buffer_pages": 8,
+            "export_format": "json",
+            "maps": {
+                "events": {
+                    "export_format": "plain",
+                    "sample_interval": 100,
+                    "enrich": { "enrichers": ["process", "container"] }
+                }
+            }
+        }))
+        .unwrap();
+        let env_layer = RunnerConfig::layer_from_env_source(|name| match name {
+            "EUNOMIA_POLL_TIMEOUT_MS" => Some("20".into()),
+            "EUNOMIA_KERNEL_DEBUG_PIDS" => Some("1, 2".into()),
+            "EUNOMIA_DEBUG_VERBOSE" => Some("on".into()),
+            "EUNOMIA_KERNEL_BTF_MODULES" => Some("nf_conntrack, ,ext4".into()),
+            "EUNOMIA_ENABLE_STATS" => Some("yes".into()),
+            "EUNOMIA_VERIFIER_LOG_LEVEL" => Some("2".into()),
+            _ => None,
+        })
+        .unwrap()
+        .into_config();
+        let cli_layer = RunnerConfig {
+            export_format: Some(ExportFormatType::RawEvent),
+            maps: HashMap::from([(
+                "events".to_string(),
+                MapRunnerConfig {
+                    sample_interval: Some(200),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let merged = file_layer.merge(env_layer).merge(cli_layer);
+        assert_eq!(merged.poll_timeout_ms, Some(20));
+        assert_eq!(merged.perf_buffer_pages, Some(8));
+        assert_eq!(merged.debug_verbose, Some(true));
+        assert_eq!(merged.kernel_debug_pids, vec![1, 2]);
+        assert_eq!(merged.export_format, Some(ExportFormatType::RawEvent));
+        assert_eq!(merged.kernel_btf_modules, vec!["nf_conntrack", "ext4"]);
+        assert!(merged.should_resolve_kernel_types());
+        assert_eq!(merged.enable_stats, Some(true));
+        assert_eq!(merged.verifier_log_level, Some(2));
+        let events = &merged.maps["events"];
+        assert_eq!(events.export_format, Some(ExportFormatType::PlainText));
+        assert_eq!(events.sample_interval, Some(200));
+        let enrich = events.enrich.as_ref().unwrap();
+        assert_eq!(
+            enrich.enrichers,
+            vec![EnricherKind::Process, EnricherKind::Container]
+        );
+        assert_eq!(enrich.pid_field, "pid");
+        assert_eq!(enrich.cache_ttl_ms, 5000);
+    }
+    #[test]
+    fn test_merge_turns_flags_off() {
+        let file_layer = RunnerConfig::layer_from_value(json!({
+            "print_kernel_debug": true,
+            "maps": { "events": { "no_export": true } }
+        }))
+        .unwrap();
+        let merged = file_layer
+            .merge(RunnerConfig::layer_from_env_source(|_| None).unwrap())
+            .into_config();
+        assert!(merged.print_kernel_debug);
+        assert_eq!(merged.maps["events"].no_export, Some(true));
+        let file_layer = RunnerConfig::layer_from_value(json!({
+            "print_kernel_debug": true,
+        }))
+        .unwrap();
+        let env_layer = RunnerConfig::layer_from_env_source(|name| {
+            (name == "EUNOMIA_PRINT_KERNEL_DEBUG").then(|| "0".into())
+        })
+        .unwrap();
+        assert!(!file_layer.merge(env_layer).into_config().print_kernel_debug);
+        let cli_layer = RunnerConfig {
+            maps: HashMap::from([(
+                "events".to_string(),
+                MapRunnerConfig {
+                    no_export: Some(false),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let merged = merged.merge(cli_layer);
+        assert!(merged.print_kernel_debug);
+        assert_eq!(merged.maps["events"].no_export, Some(false));
+    }
+    #[test]
+    fn test_invalid_env() {
+        assert!(RunnerConfig::layer_from_env_source(|name| match name {
+            "EUNOMIA_EXPORT_FORMAT" => Some("xml".into()),
+            _ => None,
+        })
+        .is_err());
+    }
+    #[test]
+    fn test_apply_to_meta() {
+        let mut package = serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("runqlat.json")).unwrap(),
+        )
+        .unwrap();
+        let sample_map = package
+            .meta
+            .bpf_skel
+            .maps
+            .iter()
+            .find(|m| m.sample.is_some())
+            .unwrap()
+            .name
+            .clone();
+        let config = RunnerConfig {
+            poll_timeout_ms: Some(1234),
+            maps: HashMap::from([(
+                sample_map.clone(),
+                MapRunnerConfig {
+                    sample_interval: Some(4321),
+                    no_export: Some(true),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        config.apply_to_meta(&mut package.meta);
+        assert_eq!(package.meta.poll_timeout_ms, 1234);
+        let map = package
+            .meta
+            .bpf_skel
+            .maps
+            .iter()
+            .find(|m| m.name == sample_map)
+            .unwrap();
+        assert_eq!(map.sample.as_ref().unwrap().interval, 4321);
+        assert_eq!(map.export_config, MapExportConfig::NoExport);
+    }
+}
@@ -8,8 +8,8 @@ use serde_json::{json, Value};
 
 use crate::{
     meta::{
-        DataSectionMeta, DataSectionVariableMeta, ExportedTypesStructMemberMeta,
-        ExportedTypesStructMeta, MapExportConfig, MapMeta, ProgMeta,
+        envelope::PackageContentType, AttachTarget, DataSectionMeta, DataSectionVariableMeta,
+        ExportedTypesStructMemberMeta, ExportedTypesStructMeta, MapExportConfig, MapMeta, ProgMeta,
     },
     tests::get_assets_dir,
 };
@@ -55,7 +55,12 @@ fn test_deserialize_meta() {
         mmaped: false,
         sample: None,
         export_config: MapExportConfig::NoExport,
-        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct
+        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct,
+        fallback: None,
+        tail_calls: Default::default(),
+        initial_entries: vec![],
+        cmdarg: None,
+        inner_map: None,
     }));
     assert!(maps.contains(&MapMeta {
         ident: "rb".into(),
@@ -63,7 +68,12 @@ fn test_deserialize_meta() {
         mmaped: false,
         sample: None,
         export_config: MapExportConfig::NoExport,
-        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct
+        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct,
+        fallback: None,
+        tail_calls: Default::default(),
+        initial_entries: vec![],
+        cmdarg: None,
+        inner_map: None,
     }));
     assert!(maps.contains(&MapMeta {
         ident: "rodata".into(),
@@ -71,7 +81,12 @@ fn test_deserialize_meta() {
         mmaped: true,
         sample: None,
         export_config: MapExportConfig::NoExport,
-        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct
+        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct,
+        fallback: None,
+        tail_calls: Default::default(),
+        initial_entries: vec![],
+        cmdarg: None,
+        inner_map: None,
     }));
     assert!(maps.contains(&MapMeta {
         ident: "bss".into(),
@@ -79,7 +94,12 @@ fn test_deserialize_meta() {
         mmaped: true,
         sample: None,
         export_config: MapExportConfig::NoExport,
-        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct
+        intepreter: crate::meta::BufferValueInterpreter::DefaultStruct,
+        fallback: None,
+        tail_calls: Default::default(),
+        initial_entries: vec![],
+        cmdarg: None,
+        inner_map: None,
     }));
     assert_eq!(bpf_skel.obj_name, "client_bpf");
     let progs = &bpf_skel.progs;
@@ -88,12 +108,18 @@ fn test_deserialize_meta() {
         name: "handle_exec".into(),
         link: true,
         attach: "tp/sched/sched_process_exec".into(),
+        requires: vec![],
+        fallback: None,
+        attach_target: None,
         others: json!({})
     }));
     assert!(progs.contains(&ProgMeta {
         name: "handle_exit".into(),
         link: true,
         attach: "tp/sched/sched_process_exit".into(),
+        requires: vec![],
+        fallback: None,
+        attach_target: None,
         others: json!({})
     }));
     let export_types = &decoded.meta.export_types;
@@ -138,3 +164,64 @@ fn test_deserialize_meta() {
         type_id: 613
     }));
 }
+
+#[test]
+fn test_package_envelope() {
+    let json_str = std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap();
+    // Packages without an envelope version are zlib ones
+    let legacy: ComposedObject = serde_json::from_str(&json_str).unwrap();
+    assert_eq!(legacy.content_type, PackageContentType::Zlib);
+    for content_type in [
+        PackageContentType::Zlib,
+        PackageContentType::Zstd,
+        PackageContentType::Uncompressed,
+    ] {
+        let mut package = legacy.clone();
+        package.content_type = content_type;
+        let encoded = serde_json::to_value(&package).unwrap();
+        assert_eq!(encoded["version"], json!(1));
+        assert_eq!(
+            encoded["content_type"],
+            serde_json::to_value(content_type).unwrap()
+        );
+        let decoded = ComposedObject::from_reader(encoded.to_string().as_bytes()).unwrap();
+        assert_eq!(decoded.bpf_object, legacy.bpf_object);
+        assert_eq!(decoded.meta, legacy.meta);
+        assert_eq!(decoded.content_type, content_type);
+    }
+    let mut future: Value = serde_json::from_str(&json_str).unwrap();
+    future["version"] = json!(2);
+    assert!(serde_json::from_value::<ComposedObject>(future).is_err());
+}
+
+#[test]
+fn test_parse_uprobe_attach_target() {
+    let uprobe =
+        |binary_path: &str, function: &str, retprobe: bool, pid: i32| AttachTarget::Uprobe {
+            binary_path: binary_path.into(),
+            function: function.into(),
+            retprobe,
+            pid,
+        };
+    assert_eq!(
+        "uprobe:/bin/bash:readline".parse::<AttachTarget>().unwrap(),
+        uprobe("/bin/bash", "readline", false, -1)
+    );
+    assert_eq!(
+        "uretprobe:/bin/bash:readline:1234"
+            .parse::<AttachTarget>()
+            .unwrap(),
+        uprobe("/bin/bash", "readline", true, 1234)
+    );
+    // Paths could contain colons
+    assert_eq!(
+        "uprobe:/opt/a:b/app:main:42"
+            .parse::<AttachTarget>()
+            .unwrap(),
+        uprobe("/opt/a:b/app", "main", false, 42)
+    );
+    assert!("uprobe:readline:1234".parse::<AttachTarget>().is_err());
+    assert!("uprobe:/bin/bash:readline:99999999999"
+        .parse::<AttachTarget>()
+        .is_err());
+}
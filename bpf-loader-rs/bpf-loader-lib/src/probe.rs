@@ -0,0 +1,381 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Kernel feature probing
+//!
+//! Detect what the running kernel supports, by loading tiny programs or creating maps, in the same way that libbpf probes its own features.
+//!
+//! Probing needs the same privileges as loading a bpf program. Failures of a probe are treated as "not supported"
+
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    fmt::Display,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+use libbpf_rs::libbpf_sys::{
+    self, bpf_insn, bpf_link_create, bpf_link_create_opts, bpf_prog_load, bpf_prog_load_opts,
+    bpf_raw_tracepoint_open, btf, btf__find_by_name_kind, btf__free, btf__load_vmlinux_btf,
+    btf__name_by_offset, btf__type_by_id, btf_enum, btf_type, libbpf_probe_bpf_helper,
+    libbpf_probe_bpf_map_type, BPF_ALU64, BPF_EXIT, BPF_JMP, BPF_K, BPF_MOV,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// A kernel feature that a package may depend on
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum KernelFeature {
+    /// `BPF_MAP_TYPE_RINGBUF`, since 5.8
+    Ringbuf,
+    /// fentry, fexit and fmod_ret programs. Needs BPF trampolines, which came to arm64 much later than x86
+    Fentry,
+    /// BTF-enabled raw tracepoints (`tp_btf/`), since 5.5
+    BtfTracepoint,
+    /// Attaching kprobes, uprobes and tracepoints through bpf_link, since 5.15. Older kernels fall back to perf event ioctls
+    PerfEventLink,
+    /// `kprobe.multi` links, since 5.18
+    KprobeMultiLink,
+    /// A bpf helper, like `bpf_loop` or `bpf_get_func_ip`. Availability depends on the program type
+    Helper(String),
+}
+
+impl Display for KernelFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KernelFeature::Ringbuf => write!(f, "ringbuf maps"),
+            KernelFeature::Fentry => write!(f, "fentry/fexit programs"),
+            KernelFeature::BtfTracepoint => write!(f, "BTF-enabled tracepoints"),
+            KernelFeature::PerfEventLink => write!(f, "bpf_link for perf events"),
+            KernelFeature::KprobeMultiLink => write!(f, "kprobe.multi links"),
+            KernelFeature::Helper(name) => write!(f, "helper `{}`", name),
+        }
+    }
+}
+
+impl KernelFeature {
+    /// Features that don't depend on a program type, in the order of `probe_all`
+    pub const GENERAL: [KernelFeature; 5] = [
+        KernelFeature::Ringbuf,
+        KernelFeature::Fentry,
+        KernelFeature::BtfTracepoint,
+        KernelFeature::PerfEventLink,
+        KernelFeature::KprobeMultiLink,
+    ];
+    /// Features implied by the section name of a program
+    pub fn required_by_section(section: &str) -> Vec<KernelFeature> {
+        let prefix = section.split('/').next().unwrap_or(section);
+        match prefix {
+            "fentry" | "fexit" | "fmod_ret" | "fentry.s" | "fexit.s" | "fmod_ret.s" => {
+                vec![KernelFeature::Fentry]
+            }
+            "tp_btf" => vec![KernelFeature::BtfTracepoint],
+            "kprobe.multi" | "kretprobe.multi" => vec![KernelFeature::KprobeMultiLink],
+            _ => vec![],
+        }
+    }
+}
+
+/// Probes features of the running kernel, and caches the results
+#[derive(Default)]
+pub struct FeatureProber {
+    results: HashMap<(KernelFeature, u32), bool>,
+    /// The vmlinux BTF used by probes, loaded once on first use. `Some(None)` if it failed to load
+    vmlinux: Option<Option<VmlinuxBtf>>,
+}
+
+impl FeatureProber {
+    /// Create a prober with nothing cached
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Check if a feature is supported. Helpers are probed for kprobe programs
+    pub fn is_supported(&mut self, feature: &KernelFeature) -> bool {
+        self.is_supported_by(feature, libbpf_sys::BPF_PROG_TYPE_KPROBE)
+    }
+    /// Check if a feature is supported. `prog_type` is the `BPF_PROG_TYPE_*` that helpers are probed for
+    pub fn is_supported_by(&mut self, feature: &KernelFeature, prog_type: u32) -> bool {
+        let prog_type = match feature {
+            KernelFeature::Helper(_) => prog_type,
+            _ => 0,
+        };
+        let key = (feature.clone(), prog_type);
+        if let Some(result) = self.results.get(&key) {
+            return *result;
+        }
+        let result = self.probe_feature(feature, prog_type);
+        debug!("Probed {}: {}", feature, result);
+        self.results.insert(key, result);
+        result
+    }
+    /// Probe all features in `KernelFeature::GENERAL`
+    pub fn probe_all(&mut self) -> Vec<(KernelFeature, bool)> {
+        KernelFeature::GENERAL
+            .into_iter()
+            .map(|feature| {
+                let supported = self.is_supported(&feature);
+                (feature, supported)
+            })
+            .collect()
+    }
+    fn vmlinux(&mut self) -> Option<&VmlinuxBtf> {
+        self.vmlinux.get_or_insert_with(VmlinuxBtf::load).as_ref()
+    }
+    fn probe_feature(&mut self, feature: &KernelFeature, prog_type: u32) -> bool {
+        match feature {
+            // SAFETY: FFI call without pointers
+            KernelFeature::Ringbuf => unsafe {
+                libbpf_probe_bpf_map_type(libbpf_sys::BPF_MAP_TYPE_RINGBUF, std::ptr::null()) == 1
+            },
+            KernelFeature::Fentry => self.vmlinux().is_some_and(probe_fentry),
+            KernelFeature::BtfTracepoint => self.vmlinux().is_some_and(probe_btf_tracepoint),
+            KernelFeature::PerfEventLink => probe_perf_event_link(),
+            KernelFeature::KprobeMultiLink => self.vmlinux().is_some_and(probe_kprobe_multi_link),
+            KernelFeature::Helper(name) => self
+                .vmlinux()
+                .is_some_and(|vmlinux| probe_helper(vmlinux, prog_type, name)),
+        }
+    }
+}
+
+/// The vmlinux BTF, loaded by libbpf
+struct VmlinuxBtf(*mut btf);
+
+impl VmlinuxBtf {
+    fn load() -> Option<Self> {
+        // SAFETY: FFI call without arguments. The result is checked
+        let ptr = unsafe { btf__load_vmlinux_btf() };
+        if ptr.is_null() {
+            debug!("Failed to load vmlinux BTF: {}", errno::errno());
+            return None;
+        }
+        Some(Self(ptr))
+    }
+    fn find(&self, name: &str, kind: u32) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        // SAFETY: self.0 is valid, and name is nul-terminated
+        let id = unsafe { btf__find_by_name_kind(self.0, name.as_ptr(), kind) };
+        (id > 0).then_some(id as u32)
+    }
+    /// Value of an enumerator of a 32-bit enum
+    fn enum_value(&self, enum_name: &str, value_name: &str) -> Option<i32> {
+        let id = self.find(enum_name, libbpf_sys::BTF_KIND_ENUM)?;
+        // SAFETY: id is a valid enum type. Enumerators are laid out right after the btf_type, and vlen is the count of them
+        unsafe {
+            let ty = btf__type_by_id(self.0, id);
+            let vlen = ((*ty).info & 0xffff) as usize;
+            let values = std::slice::from_raw_parts(
+                (ty as *const u8).add(std::mem::size_of::<btf_type>()) as *const btf_enum,
+                vlen,
+            );
+            values.iter().find_map(|v| {
+                let name = btf__name_by_offset(self.0, v.name_off);
+                (!name.is_null() && CStr::from_ptr(name).to_bytes() == value_name.as_bytes())
+                    .then_some(v.val)
+            })
+        }
+    }
+}
+
+// SAFETY: The btf struct is owned exclusively by `VmlinuxBtf`, and libbpf has no thread affinity for it
+unsafe impl Send for VmlinuxBtf {}
+
+impl Drop for VmlinuxBtf {
+    fn drop(&mut self) {
+        // SAFETY: self.0 was created by btf__load_vmlinux_btf
+        unsafe { btf__free(self.0) };
+    }
+}
+
+/// Load `r0 = 0; exit`. Returns None if the kernel rejects it
+fn load_trivial_prog(prog_type: u32, mut opts: bpf_prog_load_opts) -> Option<OwnedFd> {
+    let insn = |code: u32| bpf_insn {
+        code: code as u8,
+        _bitfield_1: bpf_insn::new_bitfield_1(0, 0),
+        ..Default::default()
+    };
+    let insns = [insn(BPF_ALU64 | BPF_MOV | BPF_K), insn(BPF_JMP | BPF_EXIT)];
+    opts.sz = std::mem::size_of::<bpf_prog_load_opts>() as _;
+    // SAFETY: All pointers live during the call
+    let fd = unsafe {
+        bpf_prog_load(
+            prog_type,
+            std::ptr::null(),
+            c"GPL".as_ptr(),
+            insns.as_ptr(),
+            insns.len() as _,
+            &mut opts,
+        )
+    };
+    if fd < 0 {
+        debug!("Probe program of type {} rejected: {}", prog_type, -fd);
+        return None;
+    }
+    // SAFETY: fd was just created
+    Some(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Kernel functions that are safe to trace for probing. `bpf_fentry_test1` exists just for testing trampolines
+const PROBE_TARGET_FUNCTIONS: [&str; 2] = ["bpf_fentry_test1", "do_nanosleep"];
+
+fn find_probe_target(vmlinux: &VmlinuxBtf) -> Option<(&'static str, u32)> {
+    PROBE_TARGET_FUNCTIONS
+        .into_iter()
+        .find_map(|name| Some((name, vmlinux.find(name, libbpf_sys::BTF_KIND_FUNC)?)))
+}
+
+fn probe_fentry(vmlinux: &VmlinuxBtf) -> bool {
+    let Some((_, func_id)) = find_probe_target(vmlinux) else {
+        return false;
+    };
+    let Some(prog) = load_trivial_prog(
+        libbpf_sys::BPF_PROG_TYPE_TRACING,
+        bpf_prog_load_opts {
+            expected_attach_type: libbpf_sys::BPF_TRACE_FENTRY,
+            attach_btf_id: func_id,
+            ..Default::default()
+        },
+    ) else {
+        return false;
+    };
+    // Some architectures load fentry programs but fail to attach them, so really attach it
+    // SAFETY: The fd is valid
+    let link = unsafe { bpf_raw_tracepoint_open(std::ptr::null(), prog.as_raw_fd()) };
+    if link < 0 {
+        debug!("Failed to attach the fentry probe: {}", -link);
+        return false;
+    }
+    // SAFETY: link was just created
+    drop(unsafe { OwnedFd::from_raw_fd(link) });
+    true
+}
+
+fn probe_btf_tracepoint(vmlinux: &VmlinuxBtf) -> bool {
+    let Some(tp_id) = vmlinux.find("btf_trace_sched_switch", libbpf_sys::BTF_KIND_TYPEDEF) else {
+        return false;
+    };
+    load_trivial_prog(
+        libbpf_sys::BPF_PROG_TYPE_TRACING,
+        bpf_prog_load_opts {
+            expected_attach_type: libbpf_sys::BPF_TRACE_RAW_TP,
+            attach_btf_id: tp_id,
+            ..Default::default()
+        },
+    )
+    .is_some()
+}
+
+fn probe_perf_event_link() -> bool {
+    let Some(prog) = load_trivial_prog(libbpf_sys::BPF_PROG_TYPE_TRACEPOINT, Default::default())
+    else {
+        return false;
+    };
+    // Same as libbpf: kernels supporting it complain about the bad perf event fd, others reject the attach type
+    // SAFETY: The fd is valid, and opts could be null
+    let ret = unsafe {
+        bpf_link_create(
+            prog.as_raw_fd(),
+            -1,
+            libbpf_sys::BPF_PERF_EVENT,
+            std::ptr::null(),
+        )
+    };
+    if ret >= 0 {
+        // SAFETY: ret is a newly created fd
+        drop(unsafe { OwnedFd::from_raw_fd(ret) });
+        return true;
+    }
+    ret == -libc::EBADF
+}
+
+fn probe_kprobe_multi_link(vmlinux: &VmlinuxBtf) -> bool {
+    let Some((target, _)) = find_probe_target(vmlinux) else {
+        return false;
+    };
+    let Some(prog) = load_trivial_prog(
+        libbpf_sys::BPF_PROG_TYPE_KPROBE,
+        bpf_prog_load_opts {
+            expected_attach_type: libbpf_sys::BPF_TRACE_KPROBE_MULTI,
+            ..Default::default()
+        },
+    ) else {
+        return false;
+    };
+    let target = CString::new(target).unwrap();
+    let mut syms = [target.as_ptr()];
+    let mut opts = bpf_link_create_opts {
+        sz: std::mem::size_of::<bpf_link_create_opts>() as _,
+        ..Default::default()
+    };
+    opts.__bindgen_anon_1.kprobe_multi.cnt = 1;
+    opts.__bindgen_anon_1.kprobe_multi.syms = syms.as_mut_ptr();
+    // SAFETY: The fd is valid, and pointers in opts live during the call
+    let link = unsafe {
+        bpf_link_create(
+            prog.as_raw_fd(),
+            0,
+            libbpf_sys::BPF_TRACE_KPROBE_MULTI,
+            &opts,
+        )
+    };
+    if link < 0 {
+        debug!("Failed to attach the kprobe.multi probe: {}", -link);
+        return false;
+    }
+    // SAFETY: link was just created
+    drop(unsafe { OwnedFd::from_raw_fd(link) });
+    true
+}
+
+fn probe_helper(vmlinux: &VmlinuxBtf, prog_type: u32, name: &str) -> bool {
+    // Helpers are named `bpf_xxx`, and their ids are `BPF_FUNC_xxx`
+    let enumerator = format!("BPF_FUNC_{}", name.strip_prefix("bpf_").unwrap_or(name));
+    let Some(id) = vmlinux.enum_value("bpf_func_id", &enumerator) else {
+        debug!("Helper `{}` is unknown to the running kernel", name);
+        return false;
+    };
+    // SAFETY: FFI call, opts could be null
+    unsafe { libbpf_probe_bpf_helper(prog_type, id as _, std::ptr::null()) == 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "no-load-bpf-tests"))]
+    use super::FeatureProber;
+    use super::KernelFeature;
+
+    #[test]
+    #[cfg(not(feature = "no-load-bpf-tests"))]
+    fn test_probe_features() {
+        let mut prober = FeatureProber::new();
+        let results = prober.probe_all();
+        assert_eq!(
+            results.iter().map(|(f, _)| f.clone()).collect::<Vec<_>>(),
+            KernelFeature::GENERAL
+        );
+        // Results are cached, so probing again gives the same answers
+        for (feature, supported) in results {
+            assert_eq!(prober.is_supported(&feature), supported);
+        }
+        // The machines running these tests are new enough
+        assert!(prober.is_supported(&KernelFeature::Ringbuf));
+        assert!(prober.is_supported(&KernelFeature::Helper("bpf_get_current_pid_tgid".into())));
+        assert!(!prober.is_supported(&KernelFeature::Helper("bpf_no_such_helper".into())));
+    }
+    #[test]
+    fn test_required_by_section() {
+        assert_eq!(
+            KernelFeature::required_by_section("fentry/do_unlinkat"),
+            vec![KernelFeature::Fentry]
+        );
+        assert_eq!(
+            KernelFeature::required_by_section("tp_btf/sched_switch"),
+            vec![KernelFeature::BtfTracepoint]
+        );
+        assert!(KernelFeature::required_by_section("tp/sched/sched_process_exec").is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Package signatures
+//!
+//! A package could be signed with an ed25519 key. The signature covers the ELF and the meta, and could be embedded in the package json (the `signature` field), or be put in a detached file with the same content.
+//!
+//! The signed message is
+//! ```text
+//! "eunomia-bpf package signature v1\0" + sha256(ELF) + sha256(canonical meta json)
+//! ```
+//! The canonical meta json is the `meta` field, as it was in the package, serialized compactly with object keys sorted. So values filled by the argument parser at runtime won't affect the signature.
+//!
+//! If a `TrustStore` is provided to `BpfSkeletonBuilder` (or set through `RunnerConfig::trust_store`), packages must be signed by one of the keys in it, or they won't be loaded.
+//!
+//! Keys are the ones produced by openssl:
+//! ```text
+//! openssl genpkey -algorithm ed25519 -out private.pem
+//! openssl pkey -in private.pem -pubout -out public.pem
+//! ```
+
+use std::{io::Write, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    Signature, Signer, SigningKey, Verifier, VerifyingKey,
+};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::meta::ComposedObject;
+
+const SIGNATURE_DOMAIN: &[u8] = b"eunomia-bpf package signature v1\0";
+
+/// Signature of a package
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackageSignature {
+    /// The ed25519 public key of the signer, base64 encoded
+    pub public_key: String,
+    /// The ed25519 signature, base64 encoded
+    pub signature: String,
+}
+
+impl PackageSignature {
+    /// Load a detached signature from a json file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read signature file {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| anyhow!("Failed to parse signature file {}", path.display()))
+    }
+}
+
+/// Public keys that packages are allowed to be signed with
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    keys: Vec<VerifyingKey>,
+}
+
+impl TrustStore {
+    /// Load a trust store from a PEM public key file, or a directory of them (`*.pem`)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut store = Self::default();
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)
+                .with_context(|| anyhow!("Failed to read trust store {}", path.display()))?
+                .map(|v| v.map(|v| v.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            entries.sort();
+            for entry in entries {
+                if entry.extension().is_some_and(|v| v == "pem") {
+                    store.add_pem_file(&entry)?;
+                }
+            }
+        } else {
+            store.add_pem_file(path)?;
+        }
+        if store.keys.is_empty() {
+            bail!("No public keys found in trust store {}", path.display());
+        }
+        Ok(store)
+    }
+    fn add_pem_file(&mut self, path: &Path) -> Result<()> {
+        let pem = std::fs::read_to_string(path)
+            .with_context(|| anyhow!("Failed to read public key {}", path.display()))?;
+        let key = VerifyingKey::from_public_key_pem(&pem)
+            .map_err(|e| anyhow!("Invalid ed25519 public key {}: {}", path.display(), e))?;
+        debug!("Trusting public key from {}", path.display());
+        self.add_key(key);
+        Ok(())
+    }
+    /// Trust the given key
+    pub fn add_key(&mut self, key: VerifyingKey) {
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+        }
+    }
+    /// Trust the given key, base64 encoded, in the form of `PackageSignature::public_key`
+    pub fn add_key_base64(&mut self, key: &str) -> Result<()> {
+        self.add_key(decode_public_key(key)?);
+        Ok(())
+    }
+    /// Check that the signature is valid, and is made by a trusted key
+    pub fn verify(
+        &self,
+        bpf_object: &[u8],
+        meta_digest: &[u8; 32],
+        signature: Option<&PackageSignature>,
+    ) -> Result<()> {
+        let signature = signature
+            .ok_or_else(|| anyhow!("The package is not signed, but a trust store is provided"))?;
+        let key = decode_public_key(&signature.public_key)?;
+        if !self.keys.contains(&key) {
+            bail!(
+                "The package is signed by an untrusted key `{}`",
+                signature.public_key
+            );
+        }
+        let sig_bytes = base64::engine::general_purpose::STANDARD
+            .decode(&signature.signature)
+            .map_err(|e| anyhow!("Malformed base64 in signature: {}", e))?;
+        let sig =
+            Signature::from_slice(&sig_bytes).map_err(|e| anyhow!("Malformed signature: {}", e))?;
+        key.verify(&signing_payload(bpf_object, meta_digest), &sig)
+            .map_err(|_| anyhow!("Bad signature, the package may have been tampered with"))
+    }
+}
+
+fn decode_public_key(key: &str) -> Result<VerifyingKey> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(key)
+        .map_err(|e| anyhow!("Malformed base64 in public key: {}", e))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("Public key is expected to be 32 bytes"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| anyhow!("Invalid public key: {}", e))
+}
+
+/// Load a PEM (PKCS#8) ed25519 private key
+pub fn load_signing_key(path: impl AsRef<Path>) -> Result<SigningKey> {
+    let path = path.as_ref();
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| anyhow!("Failed to read private key {}", path.display()))?;
+    SigningKey::from_pkcs8_pem(&pem)
+        .map_err(|e| anyhow!("Invalid ed25519 private key {}: {}", path.display(), e))
+}
+
+/// Sign the ELF and the meta digest (see `meta_digest`)
+pub fn sign(bpf_object: &[u8], meta_digest: &[u8; 32], key: &SigningKey) -> PackageSignature {
+    let sig = key.sign(&signing_payload(bpf_object, meta_digest));
+    PackageSignature {
+        public_key: base64::engine::general_purpose::STANDARD
+            .encode(key.verifying_key().as_bytes()),
+        signature: base64::engine::general_purpose::STANDARD.encode(sig.to_bytes()),
+    }
+}
+
+fn signing_payload(bpf_object: &[u8], meta_digest: &[u8; 32]) -> Vec<u8> {
+    let mut payload = SIGNATURE_DOMAIN.to_vec();
+    payload.extend_from_slice(&Sha256::digest(bpf_object));
+    payload.extend_from_slice(meta_digest);
+    payload
+}
+
+/// sha256 of the canonical form of the meta json: compact, with object keys sorted
+pub fn meta_digest(meta: &Value) -> [u8; 32] {
+    let mut buf = vec![];
+    write_canonical_json(meta, &mut buf).expect("Writing to a vec never fails");
+    Sha256::digest(&buf).into()
+}
+
+fn write_canonical_json(value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    match value {
+        Value::Array(arr) => {
+            out.push(b'[');
+            for (i, v) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_canonical_json(v, out)?;
+            }
+            out.push(b']');
+        }
+        Value::Object(obj) => {
+            let mut keys = obj.keys().collect::<Vec<_>>();
+            keys.sort();
+            out.push(b'{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                serde_json::to_writer(&mut *out, key)?;
+                out.push(b':');
+                write_canonical_json(&obj[key], out)?;
+            }
+            out.push(b'}');
+        }
+        v => out.write_all(&serde_json::to_vec(v)?)?,
+    }
+    Ok(())
+}
+
+impl ComposedObject {
+    /// Check that the package is signed by a key in the trust store
+    ///
+    /// Only works on packages deserialized from json, since the signature covers the meta as it was in the json
+    pub fn verify_signature(&self, trust_store: &TrustStore) -> Result<()> {
+        let meta_digest = self.meta_digest.as_ref().ok_or_else(|| {
+            anyhow!("The package wasn't loaded from json, so it can't be verified")
+        })?;
+        trust_store.verify(&self.bpf_object, meta_digest, self.signature.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use serde_json::{json, Value};
+
+    use crate::{meta::ComposedObject, tests::get_assets_dir};
+
+    use super::{meta_digest, sign, TrustStore};
+
+    fn signed_package(key: &SigningKey) -> Value {
+        let mut package: Value = serde_json::from_str(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap();
+        let parsed = serde_json::from_value::<ComposedObject>(package.clone()).unwrap();
+        let signature = sign(&parsed.bpf_object, &meta_digest(&package["meta"]), key);
+        package["signature"] = serde_json::to_value(signature).unwrap();
+        package
+    }
+
+    #[test]
+    fn test_canonical_meta_digest() {
+        assert_eq!(
+            meta_digest(&json!({"b": [1, {"d": 1, "c": 2}], "a": "x"})),
+            meta_digest(&json!({"a": "x", "b": [1, {"c": 2, "d": 1}]}))
+        );
+        assert_ne!(meta_digest(&json!({"a": 1})), meta_digest(&json!({"a": 2})));
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let mut store = TrustStore::default();
+        store.add_key(key.verifying_key());
+        let package = signed_package(&key);
+        serde_json::from_value::<ComposedObject>(package.clone())
+            .unwrap()
+            .verify_signature(&store)
+            .unwrap();
+
+        // Tampered meta
+        let mut tampered = package.clone();
+        tampered["meta"]["bpf_skel"]["progs"][0]["attach"] = json!("tp/sched/sched_switch");
+        let err = serde_json::from_value::<ComposedObject>(tampered)
+            .unwrap()
+            .verify_signature(&store)
+            .unwrap_err();
+        assert!(err.to_string().contains("Bad signature"));
+
+        // Untrusted key
+        let other = SigningKey::from_bytes(&[8; 32]);
+        let err = serde_json::from_value::<ComposedObject>(signed_package(&other))
+            .unwrap()
+            .verify_signature(&store)
+            .unwrap_err();
+        assert!(err.to_string().contains("untrusted key"));
+
+        // Unsigned
+        let mut unsigned = package.clone();
+        unsigned.as_object_mut().unwrap().remove("signature");
+        assert!(serde_json::from_value::<ComposedObject>(unsigned)
+            .unwrap()
+            .verify_signature(&store)
+            .is_err());
+        // Built in memory, so there's no json meta to check against, even with the signature copied over
+        let parsed = serde_json::from_value::<ComposedObject>(package).unwrap();
+        let mut built = ComposedObject::new(parsed.bpf_object, parsed.meta);
+        built.signature = parsed.signature;
+        let err = built.verify_signature(&store).unwrap_err();
+        assert!(err.to_string().contains("wasn't loaded from json"));
+    }
+}
@@ -0,0 +1,298 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! Access global variables and maps of a loaded skeleton, with values in json
+//!
+//! Values are converted using the BTF of the program, in the same format that the json exporter uses
+
+use anyhow::{anyhow, bail, Context, Result};
+use btf::types::{Btf, BtfType};
+use libbpf_rs::{
+    libbpf_sys::{libbpf_bpf_map_type_str, libbpf_bpf_prog_type_str, BPF_F_MMAPABLE},
+    Map, MapFlags,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    export_event::data_dumper::json::dump_to_json, helper::json_encoder::encode_json,
+    inspect::c_str_to_string, meta::MapEntry,
+};
+
+use super::BpfSkeleton;
+
+/// A program of the loaded skeleton
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoadedProgramInfo {
+    /// Name of the program
+    pub name: String,
+    /// Section of the program, e.g `tp/sched/sched_process_exec`
+    pub section: String,
+    /// Program type, in the name that libbpf uses
+    pub prog_type: String,
+    /// The fd of the program
+    pub fd: i32,
+}
+
+/// A map of the loaded skeleton
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LoadedMapInfo {
+    /// Name of the map
+    pub name: String,
+    /// Map type, in the name that libbpf uses
+    pub map_type: String,
+    /// Size of keys, in bytes
+    pub key_size: u32,
+    /// Size of values, in bytes
+    pub value_size: u32,
+    /// Max entries of the map
+    pub max_entries: u32,
+    /// The fd of the map
+    pub fd: i32,
+}
+
+/// Where a global variable lives
+struct VariableLocation<'a> {
+    section: &'a str,
+    map: &'a Map,
+    type_id: u32,
+    offset: usize,
+    size: usize,
+}
+
+impl BpfSkeleton {
+    /// List programs in the skeleton
+    pub fn list_programs(&self) -> Vec<LoadedProgramInfo> {
+        self.prog
+            .progs_iter()
+            .map(|prog| LoadedProgramInfo {
+                name: prog.name().to_string(),
+                section: prog.section().to_string(),
+                prog_type: c_str_to_string(unsafe {
+                    libbpf_bpf_prog_type_str(prog.prog_type() as u32)
+                }),
+                fd: prog.fd(),
+            })
+            .collect()
+    }
+    /// List maps in the skeleton, including the ones for data sections
+    pub fn list_maps(&self) -> Vec<LoadedMapInfo> {
+        self.prog
+            .maps_iter()
+            .map(|map| LoadedMapInfo {
+                name: map.name().to_string(),
+                map_type: c_str_to_string(unsafe {
+                    libbpf_bpf_map_type_str(map.map_type() as u32)
+                }),
+                key_size: map.key_size(),
+                value_size: map.value_size(),
+                max_entries: map.info().map(|v| v.info.max_entries).unwrap_or(0),
+                fd: map.fd(),
+            })
+            .collect()
+    }
+
+    fn locate_variable(&self, name: &str) -> Result<VariableLocation<'_>> {
+        let btf = self.btf.borrow_btf();
+        for section in self.meta.bpf_skel.data_sections.iter() {
+            if !section.variables.iter().any(|v| v.name == name) {
+                continue;
+            }
+            let ident = match section.name.as_str() {
+                ".rodata" => "rodata",
+                ".bss" => "bss",
+                s => bail!("Unsupported section: {}", s),
+            };
+            let map_meta = self
+                .meta
+                .bpf_skel
+                .find_map_by_ident(ident)
+                .ok_or_else(|| anyhow!("Failed to find map with ident `{}`", ident))?;
+            let map = self
+                .prog
+                .map(&map_meta.name)
+                .ok_or_else(|| anyhow!("Map `{}` not found in bpf program", map_meta.name))?;
+            let (type_id, offset, size) = find_variable_in_datasec(btf, &section.name, name)?;
+            return Ok(VariableLocation {
+                section: &section.name,
+                map,
+                type_id,
+                offset,
+                size,
+            });
+        }
+        bail!("Variable `{}` not found in data sections", name)
+    }
+
+    /// Read the current value of a global variable in `.rodata` or `.bss`
+    pub fn get_variable(&self, name: &str) -> Result<Value> {
+        let loc = self.locate_variable(name)?;
+        let data = loc
+            .map
+            .lookup(&0u32.to_ne_bytes(), MapFlags::ANY)
+            .with_context(|| anyhow!("Failed to read map `{}`", loc.map.name()))?
+            .ok_or_else(|| anyhow!("Map `{}` has no value", loc.map.name()))?;
+        let range = data
+            .get(loc.offset..loc.offset + loc.size)
+            .ok_or_else(|| anyhow!("Variable `{}` is out of the map value", name))?;
+        dump_to_json(self.btf.resolver(), loc.type_id, range)
+    }
+
+    /// Set the value of a global variable in `.bss`. Variables in `.rodata` are frozen after loading
+    ///
+    /// If the map is mmaped, only bytes of the variable are written, so the program won't see a partially updated section
+    pub fn set_variable(&self, name: &str, value: &Value) -> Result<()> {
+        let loc = self.locate_variable(name)?;
+        if loc.section == ".rodata" {
+            bail!(
+                "Variable `{}` is in .rodata, which is read-only after loading",
+                name
+            );
+        }
+        let key = 0u32.to_ne_bytes();
+        let mut data = loc
+            .map
+            .lookup(&key, MapFlags::ANY)
+            .with_context(|| anyhow!("Failed to read map `{}`", loc.map.name()))?
+            .ok_or_else(|| anyhow!("Map `{}` has no value", loc.map.name()))?;
+        let range = data
+            .get_mut(loc.offset..loc.offset + loc.size)
+            .ok_or_else(|| anyhow!("Variable `{}` is out of the map value", name))?;
+        encode_json(self.btf.borrow_btf(), loc.type_id, value, range)
+            .with_context(|| anyhow!("Failed to encode value of `{}`", name))?;
+        if write_mmaped_value(loc.map, loc.offset, range)? {
+            return Ok(());
+        }
+        loc.map
+            .update(&key, &data, MapFlags::ANY)
+            .with_context(|| anyhow!("Failed to update map `{}`", loc.map.name()))
+    }
+
+    fn outer_map(&self, name: &str) -> Result<&Map> {
+        self.prog
+            .map(name)
+            .ok_or_else(|| anyhow!("Map `{}` not found in bpf program", name))
+    }
+
+    fn map_with_btf(&self, name: &str) -> Result<(&Map, u32, u32)> {
+        let map = self
+            .prog
+            .map(name)
+            .ok_or_else(|| anyhow!("Map `{}` not found in bpf program", name))?;
+        if map.map_type().is_percpu() {
+            bail!("Per-cpu map `{}` is not supported", name);
+        }
+        let info = map
+            .info()
+            .with_context(|| anyhow!("Failed to get map info for `{}`", name))?;
+        if info.info.btf_key_type_id == 0 || info.info.btf_value_type_id == 0 {
+            bail!("Map `{}` doesn't have BTF info of keys and values", name);
+        }
+        Ok((map, info.info.btf_key_type_id, info.info.btf_value_type_id))
+    }
+
+    /// Look up a key in a map. Returns None if the key doesn't exist
+    ///
+    /// For map-in-maps, the inner map is returned as an array of `{"key": ..., "value": ...}`
+    pub fn lookup_map(&self, map_name: &str, key: &Value) -> Result<Option<Value>> {
+        if let Some(nested) = self.nested_maps.get(map_name) {
+            return nested.dump_inner_map(self.btf.resolver(), self.outer_map(map_name)?, key);
+        }
+        let (map, key_type, value_type) = self.map_with_btf(map_name)?;
+        let btf = self.btf.borrow_btf();
+        let mut key_buf = vec![0u8; map.key_size() as usize];
+        encode_json(btf, key_type, key, &mut key_buf)
+            .with_context(|| anyhow!("Failed to encode the key"))?;
+        map.lookup(&key_buf, MapFlags::ANY)
+            .with_context(|| anyhow!("Failed to look up map `{}`", map_name))?
+            .map(|v| dump_to_json(self.btf.resolver(), value_type, &v))
+            .transpose()
+    }
+
+    /// Insert or update a key in a map
+    ///
+    /// Members of structs not provided in `value` are zero
+    ///
+    /// For map-in-maps, `value` is an array of `{"key": ..., "value": ...}`. A new inner map with these entries is created, and replaces the old one
+    pub fn update_map(&self, map_name: &str, key: &Value, value: &Value) -> Result<()> {
+        if let Some(nested) = self.nested_maps.get(map_name) {
+            let entries: Vec<MapEntry> = serde_json::from_value(value.clone())
+                .with_context(|| anyhow!("Expected an array of entries of the inner map"))?;
+            return nested.put_inner_map(
+                self.btf.borrow_btf(),
+                self.outer_map(map_name)?,
+                key,
+                &entries,
+            );
+        }
+        let (map, key_type, value_type) = self.map_with_btf(map_name)?;
+        let btf = self.btf.borrow_btf();
+        let mut key_buf = vec![0u8; map.key_size() as usize];
+        encode_json(btf, key_type, key, &mut key_buf)
+            .with_context(|| anyhow!("Failed to encode the key"))?;
+        let mut value_buf = vec![0u8; map.value_size() as usize];
+        encode_json(btf, value_type, value, &mut value_buf)
+            .with_context(|| anyhow!("Failed to encode the value"))?;
+        map.update(&key_buf, &value_buf, MapFlags::ANY)
+            .with_context(|| anyhow!("Failed to update map `{}`", map_name))
+    }
+}
+
+/// Returns (type id, offset, size) of the variable
+fn find_variable_in_datasec(btf: &Btf, section: &str, name: &str) -> Result<(u32, usize, usize)> {
+    let sec = match btf.types().iter().find(|ty| ty.name() == section) {
+        Some(BtfType::Datasec(sec)) => sec,
+        _ => bail!("Cannot find datasec `{}` in the BTF", section),
+    };
+    for var in sec.vars.iter() {
+        if let BtfType::Var(v) = btf.type_by_id(var.type_id) {
+            if v.name == name {
+                return Ok((v.type_id, var.offset as usize, var.sz as usize));
+            }
+        }
+    }
+    bail!("Variable `{}` not found in datasec `{}`", name, section)
+}
+
+/// Write the bytes into the map value through mmap, so other bytes of the value are untouched
+/// Returns false if the map isn't mmapable
+fn write_mmaped_value(map: &Map, offset: usize, bytes: &[u8]) -> Result<bool> {
+    let info = map
+        .info()
+        .with_context(|| anyhow!("Failed to get map info for `{}`", map.name()))?;
+    if info.info.map_flags & BPF_F_MMAPABLE == 0 {
+        return Ok(false);
+    }
+    let size = map.value_size() as usize;
+    if size < offset + bytes.len() {
+        bail!(
+            "Range {}..{} is out of the map value",
+            offset,
+            offset + bytes.len()
+        );
+    }
+    // SAFETY: The array map with BPF_F_MMAPABLE could be mmaped, and the range is checked
+    unsafe {
+        let data = libc::mmap(
+            std::ptr::null_mut(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_SHARED,
+            map.fd(),
+            0,
+        );
+        if data == libc::MAP_FAILED {
+            bail!(
+                "Failed to mmap map `{}`: {}",
+                map.name(),
+                std::io::Error::last_os_error()
+            );
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), (data as *mut u8).add(offset), bytes.len());
+        libc::munmap(data, size);
+    }
+    Ok(true)
+}
@@ -6,33 +6,45 @@
 
 use std::{
     collections::HashMap,
-    ffi::{c_void, CStr},
+    ffi::{c_void, CStr, OsString},
     os::{
+        fd::AsRawFd,
         raw::c_char,
         unix::prelude::{OsStrExt, PermissionsExt},
     },
     path::PathBuf,
     ptr::NonNull,
+    sync::Arc,
 };
 
 use crate::{
-    btf_container::BtfContainer,
+    btf_container::{BtfContainer, KernelBtf},
     elf_container::ElfContainer,
     helper::btf::create_elf_with_btf_section,
     meta::{ComposedObject, EunomiaObjectMeta, RunnerConfig},
+    probe::FeatureProber,
+    signature::TrustStore,
     skeleton::{BTF_PATH_ENV_NAME, VMLINUX_BTF_PATH},
 };
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use bpf_compatible_rs::get_current_system_btf_file;
 use libbpf_rs::{
     libbpf_sys::{
-        self, bpf_map__name, bpf_map__value_size, bpf_object__btf, bpf_object__next_map,
-        btf__get_raw_data,
+        self, bpf_map__name, bpf_map__reuse_fd, bpf_map__value_size, bpf_object, bpf_object__btf,
+        bpf_object__close, bpf_object__next_map, btf__get_raw_data,
     },
-    ObjectBuilder, OpenObject,
+    set_print, ObjectBuilder, OpenObject, PrintLevel,
 };
 
-use super::preload::PreLoadBpfSkeleton;
+use super::{
+    handover::{MapLayout, SkeletonHandover},
+    map_in_map::NestedMap,
+    preload::{
+        attach::{multi_probe::prefer_kprobe_multi, target::set_btf_attach_targets},
+        PreLoadBpfSkeleton,
+    },
+    verifier::VerifierLogBuffers,
+};
 
 /// Builder of BpfSkeleton
 pub struct BpfSkeletonBuilder<'a> {
@@ -40,6 +52,9 @@ pub struct BpfSkeletonBuilder<'a> {
     object_meta: &'a EunomiaObjectMeta,
     bpf_object: &'a [u8],
     runner_config: Option<RunnerConfig>,
+    package: Option<&'a ComposedObject>,
+    trust_store: Option<Arc<TrustStore>>,
+    handover: Option<SkeletonHandover>,
 }
 
 impl<'a> BpfSkeletonBuilder<'a> {
@@ -55,6 +70,9 @@ impl<'a> BpfSkeletonBuilder<'a> {
             object_meta: meta,
             bpf_object,
             runner_config: None,
+            package: None,
+            trust_store: None,
+            handover: None,
         }
     }
     /// Create a builder from the json package
@@ -63,21 +81,63 @@ impl<'a> BpfSkeletonBuilder<'a> {
         package: &'a ComposedObject,
         btf_archive_path: Option<&'a str>,
     ) -> Self {
-        Self::from_object_meta_and_object_buffer(
-            &package.meta,
-            &package.bpf_object,
-            btf_archive_path,
-        )
+        Self {
+            package: Some(package),
+            ..Self::from_object_meta_and_object_buffer(
+                &package.meta,
+                &package.bpf_object,
+                btf_archive_path,
+            )
+        }
     }
     /// Set the runner_config of this bpf program
+    /// Values set in it will override the ones in the package. See `RunnerConfig::load_layered` to load it from config files and env vars
     pub fn set_runner_config(self, cfg: RunnerConfig) -> Self {
         Self {
             runner_config: Some(cfg),
             ..self
         }
     }
+    /// Only load packages signed by keys in this trust store. Takes precedence over `RunnerConfig::trust_store`
+    /// Signatures can only be verified if the builder is created by `from_json_package`
+    pub fn set_trust_store(self, store: Arc<TrustStore>) -> Self {
+        Self {
+            trust_store: Some(store),
+            ..self
+        }
+    }
+    /// Take over maps and links of a running skeleton, usually an older version of this package. See `HandoverHandle`
+    pub fn set_handover(self, handover: SkeletonHandover) -> Self {
+        Self {
+            handover: Some(handover),
+            ..self
+        }
+    }
     /// Build (open) the skeleton
-    pub fn build(self) -> Result<PreLoadBpfSkeleton> {
+    pub fn build(mut self) -> Result<PreLoadBpfSkeleton> {
+        let runner_config = self.runner_config.unwrap_or_default();
+        let trust_store = match (self.trust_store, runner_config.trust_store.as_ref()) {
+            (Some(v), _) => Some(v),
+            (None, Some(path)) => {
+                Some(Arc::new(TrustStore::load(path).with_context(|| {
+                    anyhow!("Failed to load trust store {}", path)
+                })?))
+            }
+            (None, None) => None,
+        };
+        if let Some(store) = trust_store {
+            let package = self.package.ok_or_else(|| {
+                anyhow!("A trust store is provided, but signatures can only be verified when building from a json package")
+            })?;
+            package
+                .verify_signature(&store)
+                .with_context(|| anyhow!("Failed to verify the package signature"))?;
+        }
+        let mut meta = self.object_meta.clone();
+        runner_config.apply_to_meta(&mut meta);
+        if meta.debug_verbose {
+            set_print(Some((PrintLevel::Debug, forward_libbpf_log)));
+        }
         let mut open_bpts = ObjectBuilder::default()
             .opts(self.object_meta.bpf_skel.obj_name.as_bytes().as_ptr() as *const c_char);
         // Why we put path_holder here? to keep its iveness until this function returns, so that we can safely use the pointers to the underlying data in bpf_object_openopts
@@ -90,8 +150,17 @@ impl<'a> BpfSkeletonBuilder<'a> {
         } else {
             None
         };
-        // Ditto
-        let env_btf_file_path = std::env::var_os(BTF_PATH_ENV_NAME);
+        // Ditto. The runner config takes precedence over the env var
+        let custom_btf_file_path = runner_config
+            .btf_path
+            .as_ref()
+            .map(|v| OsString::from(format!("{v}\0")))
+            .or_else(|| {
+                std::env::var_os(BTF_PATH_ENV_NAME).map(|mut v| {
+                    v.push("\0");
+                    v
+                })
+            });
 
         let vmlinux_btf_exists = if PathBuf::from(VMLINUX_BTF_PATH).exists() {
             match std::fs::metadata(VMLINUX_BTF_PATH) {
@@ -117,12 +186,29 @@ impl<'a> BpfSkeletonBuilder<'a> {
                 .as_os_str()
                 .as_bytes()
                 .as_ptr() as *const _;
-        } else if let Some(env_btf) = env_btf_file_path.as_ref() {
-            // SAFETY: env_btf_file_path will live until this function returns
-            open_bpts.btf_custom_path = env_btf.as_bytes().as_ptr() as *const _;
+        } else if let Some(custom_btf) = custom_btf_file_path.as_ref() {
+            // SAFETY: custom_btf_file_path will live until this function returns, and it's nul-terminated
+            open_bpts.btf_custom_path = custom_btf.as_bytes().as_ptr() as *const _;
         } else if !vmlinux_btf_exists {
             bail!("All ways tried to find vmlinux BTF, but not found. Please provide the vmlinux btf using env `BTF_FILE_PATH`. (Tried parameter `btf_archive_path`, {}, and {})",BTF_PATH_ENV_NAME,VMLINUX_BTF_PATH);
         };
+        // Load it before opening the object, so nothing leaks if it fails. Use the same BTF that libbpf relocates against
+        let kernel_btf = if runner_config.should_resolve_kernel_types() {
+            let path = match (path_holder.as_ref(), vmlinux_btf_exists) {
+                (Some(path), false) => path.clone(),
+                _ => custom_btf_file_path
+                    .as_ref()
+                    .map(|v| PathBuf::from(v.to_string_lossy().trim_end_matches('\0')))
+                    .unwrap_or_else(|| PathBuf::from(VMLINUX_BTF_PATH)),
+            };
+            Some(Arc::new(
+                KernelBtf::load(&path, &runner_config.kernel_btf_modules).with_context(|| {
+                    anyhow!("Failed to load kernel BTF from {}", path.display())
+                })?,
+            ))
+        } else {
+            None
+        };
         // SAFETY: FFI call. Pointers passed in will live during the call
         let open_result = unsafe {
             libbpf_sys::bpf_object__open_mem(
@@ -131,17 +217,17 @@ impl<'a> BpfSkeletonBuilder<'a> {
                 &open_bpts,
             )
         };
-        if open_result.is_null() {
+        let Some(open_result) = NonNull::new(open_result).map(OpenedObjectGuard) else {
             bail!(
                 "Failed to open bpf object: bpf_object__open_mem returned NULL with errno={}",
                 errno::errno()
             );
-        }
+        };
 
         // Retrieve the btf archive from the loaded bpf_object
         let btf = {
             // SAFETY: This function will always succeed
-            let btf = unsafe { bpf_object__btf(open_result) };
+            let btf = unsafe { bpf_object__btf(open_result.as_ptr()) };
             if btf.is_null() {
                 bail!("Failed to get btf* from the bpf_object: {}", errno::errno());
             }
@@ -159,15 +245,20 @@ impl<'a> BpfSkeletonBuilder<'a> {
             // The slice will never be used once this block exits, it will be cloned in BtfContainer
             let data =
                 unsafe { std::slice::from_raw_parts(raw_data as *const u8, dumped_size as usize) };
-            BtfContainer::new_from_binary(&create_elf_with_btf_section(data, true)?)?
+            let mut btf = BtfContainer::new_from_binary(&create_elf_with_btf_section(data, true)?)?;
+            if let Some(kernel_btf) = kernel_btf {
+                btf.set_kernel_btf(kernel_btf);
+            }
+            btf
         };
 
+        let mut nested_maps = HashMap::default();
         let map_value_sizes = {
             let mut sizes = HashMap::default();
-            let mut curr_map = std::ptr::null();
+            let mut curr_map = std::ptr::null_mut();
             loop {
                 // SAFETY: it's always to call this, since open_result and curr_map are all valid
-                curr_map = unsafe { bpf_object__next_map(open_result, curr_map) };
+                curr_map = unsafe { bpf_object__next_map(open_result.as_ptr(), curr_map) };
                 if curr_map.is_null() {
                     break;
                 }
@@ -178,23 +269,98 @@ impl<'a> BpfSkeletonBuilder<'a> {
                 // SAFETY: curr_map is valid
                 let value_size = unsafe { bpf_map__value_size(curr_map) };
                 sizes.insert(map_name.into(), value_size);
+                let inner_meta = meta
+                    .bpf_skel
+                    .maps
+                    .iter()
+                    .find(|v| v.name == map_name)
+                    .and_then(|v| v.inner_map.as_ref());
+                // SAFETY: curr_map is valid
+                if let Some(nested) =
+                    unsafe { NestedMap::record(curr_map, inner_meta, btf.borrow_btf()) }
+                        .with_context(|| anyhow!("Invalid inner map of `{}`", map_name))?
+                {
+                    nested_maps.insert(map_name.to_string(), nested);
+                }
+                if let Some(handover) = self.handover.as_mut() {
+                    // SAFETY: curr_map is valid
+                    let layout = unsafe { MapLayout::from_map_ptr(curr_map, btf.borrow_btf()) };
+                    if !layout.is_reusable(map_name) {
+                        continue;
+                    }
+                    if let Some(fd) = handover.take_map(map_name, &layout) {
+                        // SAFETY: curr_map and fd are valid. libbpf duplicates the fd
+                        let err = unsafe { bpf_map__reuse_fd(curr_map, fd.as_raw_fd()) };
+                        if err < 0 {
+                            bail!("Failed to reuse map `{}`: errno={}", map_name, -err);
+                        }
+                        log::info!("Reusing map `{}` of the running skeleton", map_name);
+                    }
+                }
             }
             sizes
         };
-        // SAFETY: The pointer won't be used by us anymore, and we also checked if it's null
-        let open_object = unsafe { OpenObject::from_ptr(NonNull::new_unchecked(open_result)) }?;
+        // SAFETY: open_result is valid
+        let kprobe_multi_progs =
+            unsafe { prefer_kprobe_multi(open_result.as_ptr(), &meta, &mut FeatureProber::new()) }?;
+        // SAFETY: open_result is valid and not loaded
+        unsafe { set_btf_attach_targets(open_result.as_ptr(), &meta) }?;
+        // SAFETY: open_result is valid, and the buffers are kept in PreLoadBpfSkeleton until the object is loaded
+        let verifier_logs = unsafe {
+            VerifierLogBuffers::install(
+                open_result.as_ptr(),
+                runner_config.verifier_log_level.unwrap_or(0),
+            )
+        }?;
+        let open_object = open_result.into_open_object()?;
 
         Ok(PreLoadBpfSkeleton {
             bpf_object: open_object,
-            config_data: self.runner_config.unwrap_or_default(),
+            config_data: runner_config,
             btf,
-            meta: self.object_meta.clone(),
+            meta,
             map_value_sizes,
+            nested_maps,
+            kprobe_multi_progs,
             raw_elf: ElfContainer::new_from_binary(self.bpf_object)?,
+            verifier_logs,
+            handover: self.handover,
         })
     }
 }
 
+/// Owns the object opened by `bpf_object__open_mem` until it's handed to `OpenObject`, so it's closed if building fails halfway
+struct OpenedObjectGuard(NonNull<bpf_object>);
+
+impl OpenedObjectGuard {
+    fn as_ptr(&self) -> *mut bpf_object {
+        self.0.as_ptr()
+    }
+    fn into_open_object(self) -> Result<OpenObject> {
+        let ptr = self.0;
+        std::mem::forget(self);
+        // SAFETY: The pointer is valid and owned by us. OpenObject closes it even if it fails
+        Ok(unsafe { OpenObject::from_ptr(ptr) }?)
+    }
+}
+
+impl Drop for OpenedObjectGuard {
+    fn drop(&mut self) {
+        // SAFETY: The object was opened by bpf_object__open_mem, and nobody else owns it
+        unsafe { bpf_object__close(self.0.as_ptr()) };
+    }
+}
+
+/// Forward libbpf's output to our logger, used if `debug_verbose` is set
+fn forward_libbpf_log(level: PrintLevel, msg: String) {
+    let msg = msg.trim_end();
+    match level {
+        PrintLevel::Warn => log::warn!("libbpf: {}", msg),
+        PrintLevel::Info => log::info!("libbpf: {}", msg),
+        PrintLevel::Debug => log::debug!("libbpf: {}", msg),
+    }
+}
+
 #[cfg(test)]
 #[cfg(not(feature = "no-load-bpf-tests"))]
 mod tests {
@@ -49,3 +49,10 @@ impl PollingHandle {
         self.state.fetch_or(TERMINATING_BIT, Ordering::Relaxed);
     }
 }
+
+/// A handle that isn't shared with any skeleton. Useful for bindings that drive their own pollers
+impl Default for PollingHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,209 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Hot reload
+//!
+//! A new version of a package could take over a running skeleton, without losing the contents of maps:
+//! - Create a `HandoverHandle` from the running `BpfSkeleton`, it can be sent to other threads
+//! - Call `HandoverHandle::acquire` while the old skeleton is still running, and pass the result to `BpfSkeletonBuilder::set_handover`
+//! - Maps with the same name and the same layout are reused by the new skeleton. Maps that export events (ringbuf, perf event array) and maps of data sections are never reused.
+//!   struct_ops maps hold the programs of the old version, so they are never reused and are registered again by the new skeleton
+//! - Links of programs with the same name are updated to the new programs with `bpf_link_update`, if the kernel supports it for the link type. Other programs are attached normally, so both versions may run for a short while
+//! - Drop the old skeleton once the new one is running
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use anyhow::{anyhow, bail, Result};
+use btf::types::Btf;
+use libbpf_rs::libbpf_sys::{
+    bpf_link_get_fd_by_id, bpf_link_get_info_by_fd, bpf_link_info, bpf_link_update, bpf_map,
+    bpf_map__btf_key_type_id, bpf_map__btf_value_type_id, bpf_map__key_size, bpf_map__map_flags,
+    bpf_map__max_entries, bpf_map__type, bpf_map__value_size, bpf_map_get_fd_by_id,
+    bpf_map_get_info_by_fd, bpf_map_info, BPF_MAP_TYPE_PERF_EVENT_ARRAY, BPF_MAP_TYPE_RINGBUF,
+    BPF_MAP_TYPE_STRUCT_OPS,
+};
+use log::info;
+
+use crate::inspect::{resolve_struct_layout, StructLayout};
+
+use super::{preload::attach::AttachLink, BpfSkeleton};
+
+/// What a map looks like. Maps are only reused if their layouts are the same
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MapLayout {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+    key: Option<StructLayout>,
+    value: Option<StructLayout>,
+}
+
+impl MapLayout {
+    /// Read the layout of a map, opened or loaded. `btf` should be the BTF of the object containing it
+    ///
+    /// # Safety
+    /// `map` must be a valid pointer
+    pub(crate) unsafe fn from_map_ptr(map: *const bpf_map, btf: &Btf) -> Self {
+        let layout_of = |type_id: u32| {
+            (type_id != 0)
+                .then(|| resolve_struct_layout(btf, type_id).ok())
+                .flatten()
+        };
+        Self {
+            map_type: bpf_map__type(map),
+            key_size: bpf_map__key_size(map),
+            value_size: bpf_map__value_size(map),
+            max_entries: bpf_map__max_entries(map),
+            map_flags: bpf_map__map_flags(map),
+            key: layout_of(bpf_map__btf_key_type_id(map)),
+            value: layout_of(bpf_map__btf_value_type_id(map)),
+        }
+    }
+    /// Whether maps of this layout carry state that is worth keeping
+    pub(crate) fn is_reusable(&self, name: &str) -> bool {
+        !name.contains('.')
+            && self.map_type != BPF_MAP_TYPE_RINGBUF
+            && self.map_type != BPF_MAP_TYPE_PERF_EVENT_ARRAY
+            && self.map_type != BPF_MAP_TYPE_STRUCT_OPS
+    }
+}
+
+/// Records ids of the maps and links of a running skeleton, so they could be handed over from another thread
+///
+/// It won't keep anything alive. Acquiring fails if the skeleton is gone
+#[derive(Debug, Clone)]
+pub struct HandoverHandle {
+    maps: Vec<(String, MapLayout, u32)>,
+    links: Vec<(String, u32)>,
+}
+
+/// Fds of the maps and links of a running skeleton, see `BpfSkeletonBuilder::set_handover`
+#[derive(Debug)]
+pub struct SkeletonHandover {
+    pub(crate) maps: Vec<(String, MapLayout, OwnedFd)>,
+    pub(crate) links: Vec<(String, OwnedFd)>,
+    /// Names of maps taken by the new skeleton. Their initial entries won't be put again
+    pub(crate) reused_maps: Vec<String>,
+}
+
+fn fd_from_id(name: &str, id: u32, get: unsafe extern "C" fn(u32) -> i32) -> Result<OwnedFd> {
+    let fd = unsafe { get(id) };
+    if fd < 0 {
+        bail!("Failed to get fd of `{}` (id {}), errno={}", name, id, -fd);
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+impl HandoverHandle {
+    /// Get fds of the maps and links. The skeleton must still be alive
+    pub fn acquire(&self) -> Result<SkeletonHandover> {
+        Ok(SkeletonHandover {
+            maps: self
+                .maps
+                .iter()
+                .map(|(name, layout, id)| {
+                    Ok((
+                        name.clone(),
+                        layout.clone(),
+                        fd_from_id(name, *id, bpf_map_get_fd_by_id)?,
+                    ))
+                })
+                .collect::<Result<_>>()?,
+            links: self
+                .links
+                .iter()
+                .map(|(name, id)| Ok((name.clone(), fd_from_id(name, *id, bpf_link_get_fd_by_id)?)))
+                .collect::<Result<_>>()?,
+            reused_maps: vec![],
+        })
+    }
+}
+
+impl SkeletonHandover {
+    /// Take the map to reuse, if its layout is the same as `layout`
+    pub(crate) fn take_map(&mut self, name: &str, layout: &MapLayout) -> Option<OwnedFd> {
+        let idx = self.maps.iter().position(|(n, _, _)| n == name)?;
+        let (_, old_layout, fd) = self.maps.remove(idx);
+        if &old_layout != layout {
+            info!(
+                "Layout of map `{}` changed, it won't be reused: {:?} -> {:?}",
+                name, old_layout, layout
+            );
+            return None;
+        }
+        self.reused_maps.push(name.to_string());
+        Some(fd)
+    }
+    /// Try to update the link of the program with the same name to the new program
+    ///
+    /// Returns None if there is no such link, or the kernel can't update it
+    pub(crate) fn update_link(&mut self, name: &str, prog_fd: i32) -> Option<AttachLink> {
+        let idx = self.links.iter().position(|(n, _)| n == name)?;
+        let (_, link_fd) = self.links.remove(idx);
+        let err = unsafe { bpf_link_update(link_fd.as_raw_fd(), prog_fd, std::ptr::null()) };
+        if err < 0 {
+            info!(
+                "Unable to update the link of program `{}` (errno={}), attaching it again",
+                name, -err
+            );
+            return None;
+        }
+        info!("Link of program `{}` updated", name);
+        Some(AttachLink::HandedOver(link_fd))
+    }
+}
+
+fn map_id(fd: i32) -> Result<u32> {
+    let mut info = bpf_map_info::default();
+    let mut len = std::mem::size_of::<bpf_map_info>() as u32;
+    let err = unsafe { bpf_map_get_info_by_fd(fd, &mut info, &mut len) };
+    if err < 0 {
+        bail!("Failed to get map info, errno={}", -err);
+    }
+    Ok(info.id)
+}
+
+fn link_id(fd: i32) -> Result<u32> {
+    let mut info = bpf_link_info::default();
+    let mut len = std::mem::size_of::<bpf_link_info>() as u32;
+    let err = unsafe { bpf_link_get_info_by_fd(fd, &mut info, &mut len) };
+    if err < 0 {
+        bail!("Failed to get link info, errno={}", -err);
+    }
+    Ok(info.id)
+}
+
+impl BpfSkeleton {
+    /// Create a handle to hand the maps and links of this skeleton over to a new version of it
+    pub fn create_handover_handle(&self) -> Result<HandoverHandle> {
+        let btf = self.btf.borrow_btf();
+        let mut maps = vec![];
+        for map in self.prog.maps_iter() {
+            let ptr = map
+                .as_libbpf_bpf_map_ptr()
+                .ok_or_else(|| anyhow!("Failed to get pointer of map `{}`", map.name()))?;
+            // SAFETY: the map is owned by the loaded object
+            let layout = unsafe { MapLayout::from_map_ptr(ptr.as_ptr(), btf) };
+            if layout.is_reusable(map.name()) {
+                maps.push((map.name().to_string(), layout, map_id(map.fd())?));
+            }
+        }
+        let mut links = vec![];
+        for (name, link) in self.links.iter() {
+            let Some(fd) = link.link_fd() else {
+                continue;
+            };
+            // Perf events attached by ioctl on kernels before 5.15 aren't bpf_links. They'll be attached again
+            match link_id(fd) {
+                Ok(id) => links.push((name.clone(), id)),
+                Err(e) => info!("Link of program `{}` can't be handed over: {}", name, e),
+            }
+        }
+        Ok(HandoverHandle { maps, links })
+    }
+}
@@ -0,0 +1,397 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Map-in-map
+//!
+//! libbpf drops the BTF of an outer map when creating it, and frees the template of its inner maps after loading.
+//! So both are recorded before loading, and inner maps could be created and accessed with json afterwards
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use btf::types::{Btf, BtfType};
+use libbpf_rs::{
+    libbpf_sys::{
+        bpf_map, bpf_map__btf_key_type_id, bpf_map__btf_value_type_id, bpf_map__inner_map,
+        bpf_map__key_size, bpf_map__map_flags, bpf_map__max_entries, bpf_map__type,
+        bpf_map__value_size, bpf_map_create_opts, libbpf_bpf_map_type_str,
+        BPF_MAP_TYPE_ARRAY_OF_MAPS, BPF_MAP_TYPE_HASH_OF_MAPS,
+    },
+    Map, MapFlags, MapType, Object, OpenObject,
+};
+use log::debug;
+use serde_json::{json, Value};
+
+use crate::{
+    btf_container::BtfResolver,
+    export_event::{
+        data_dumper::json::dump_to_json,
+        type_descriptor::{CheckedExportedMember, TypeDescriptor},
+    },
+    helper::json_encoder::encode_json,
+    inspect::c_str_to_string,
+    meta::{EunomiaObjectMeta, InnerMapMeta, InnerMapTemplate, MapEntry},
+};
+
+use super::preload::map_entries::MapEntryLayout;
+
+/// What inner maps of a map-in-map look like
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InnerMapLayout {
+    pub(crate) map_type: u32,
+    pub(crate) key_size: u32,
+    pub(crate) value_size: u32,
+    pub(crate) max_entries: u32,
+    pub(crate) map_flags: u32,
+    /// 0 if unknown
+    pub(crate) btf_key_type_id: u32,
+    /// 0 if unknown
+    pub(crate) btf_value_type_id: u32,
+}
+
+/// A map-in-map, recorded before loading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NestedMap {
+    /// 0 if the outer map has no BTF info of keys
+    pub(crate) outer_key_type_id: u32,
+    /// None if neither the declaration nor the meta provides a template
+    pub(crate) inner: Option<InnerMapLayout>,
+}
+
+/// Find a map type by the name that libbpf uses
+pub(crate) fn map_type_from_name(name: &str) -> Option<u32> {
+    (0..)
+        // SAFETY: FFI call without pointers. It returns null for unknown types, or a static string
+        .map(|i| (i, unsafe { libbpf_bpf_map_type_str(i) }))
+        .take_while(|(_, s)| !s.is_null())
+        .find(|(_, s)| c_str_to_string(*s) == name)
+        .map(|(i, _)| i)
+}
+
+pub(crate) fn find_type_by_name(btf: &Btf, name: &str) -> Result<u32> {
+    btf.types()
+        .iter()
+        .position(|ty| {
+            ty.name() == name
+                && !matches!(
+                    ty,
+                    BtfType::Var(_) | BtfType::Func(_) | BtfType::Datasec(_) | BtfType::Fwd(_)
+                )
+        })
+        .map(|v| v as u32)
+        .ok_or_else(|| anyhow!("Type named `{}` not found in the BTF", name))
+}
+
+fn is_map_in_map(map_type: u32) -> bool {
+    map_type == BPF_MAP_TYPE_ARRAY_OF_MAPS || map_type == BPF_MAP_TYPE_HASH_OF_MAPS
+}
+
+impl InnerMapLayout {
+    pub(crate) fn from_template(template: &InnerMapTemplate, btf: &Btf) -> Result<Self> {
+        let type_id_of = |name: &Option<String>| {
+            name.as_deref()
+                .map(|v| find_type_by_name(btf, v))
+                .transpose()
+                .map(|v| v.unwrap_or(0))
+        };
+        Ok(Self {
+            map_type: map_type_from_name(&template.map_type)
+                .ok_or_else(|| anyhow!("Unknown map type `{}`", template.map_type))?,
+            key_size: template.key_size,
+            value_size: template.value_size,
+            max_entries: template.max_entries,
+            map_flags: template.map_flags,
+            btf_key_type_id: type_id_of(&template.key_type)?,
+            btf_value_type_id: type_id_of(&template.value_type)?,
+        })
+    }
+    /// Create an inner map
+    pub(crate) fn create(&self, name: &str) -> Result<Map> {
+        let map_type = MapType::try_from(self.map_type)
+            .map_err(|_| anyhow!("Unsupported map type {}", self.map_type))?;
+        let opts = bpf_map_create_opts {
+            sz: std::mem::size_of::<bpf_map_create_opts>() as _,
+            map_flags: self.map_flags,
+            ..Default::default()
+        };
+        Map::create(
+            map_type,
+            Some(name),
+            self.key_size,
+            self.value_size,
+            self.max_entries,
+            &opts,
+        )
+        .with_context(|| anyhow!("Failed to create inner map `{}`", name))
+    }
+    fn entry_layout(&self) -> MapEntryLayout {
+        MapEntryLayout {
+            key_size: self.key_size,
+            value_size: self.value_size,
+            btf_key_type_id: self.btf_key_type_id,
+            btf_value_type_id: self.btf_value_type_id,
+        }
+    }
+}
+
+impl NestedMap {
+    /// Record a map before loading. Returns None if it's neither a map-in-map, nor described as one in the meta
+    ///
+    /// # Safety
+    /// `map` must be a valid pointer of an opened map
+    pub(crate) unsafe fn record(
+        map: *mut bpf_map,
+        inner_meta: Option<&InnerMapMeta>,
+        btf: &Btf,
+    ) -> Result<Option<Self>> {
+        if inner_meta.is_none() && !is_map_in_map(bpf_map__type(map)) {
+            return Ok(None);
+        }
+        let inner = match inner_meta.and_then(|v| v.template.as_ref()) {
+            Some(template) => Some(InnerMapLayout::from_template(template, btf)?),
+            None => {
+                let inner = bpf_map__inner_map(map);
+                (!inner.is_null()).then(|| InnerMapLayout {
+                    map_type: bpf_map__type(inner),
+                    key_size: bpf_map__key_size(inner),
+                    value_size: bpf_map__value_size(inner),
+                    max_entries: bpf_map__max_entries(inner),
+                    map_flags: bpf_map__map_flags(inner),
+                    btf_key_type_id: bpf_map__btf_key_type_id(inner),
+                    btf_value_type_id: bpf_map__btf_value_type_id(inner),
+                })
+            }
+        };
+        Ok(Some(Self {
+            outer_key_type_id: bpf_map__btf_key_type_id(map),
+            inner,
+        }))
+    }
+    fn inner(&self, name: &str) -> Result<&InnerMapLayout> {
+        self.inner.as_ref().ok_or_else(|| {
+            anyhow!(
+                "Map `{}` has no template of inner maps. Declare it with `__array(values, ...)`, or provide `inner_map.template` in the meta",
+                name
+            )
+        })
+    }
+    fn encode_outer_key(&self, name: &str, btf: &Btf, outer: &Map, key: &Value) -> Result<Vec<u8>> {
+        if self.outer_key_type_id == 0 {
+            bail!("Map `{}` doesn't have BTF info of keys", name);
+        }
+        let mut buf = vec![0u8; outer.key_size() as usize];
+        encode_json(btf, self.outer_key_type_id, key, &mut buf)
+            .with_context(|| anyhow!("Failed to encode the key"))?;
+        Ok(buf)
+    }
+    /// Create an inner map with `entries`, and put it into `outer` at `key`. The old inner map at `key` is replaced
+    pub(crate) fn put_inner_map(
+        &self,
+        btf: &Btf,
+        outer: &Map,
+        key: &Value,
+        entries: &[MapEntry],
+    ) -> Result<()> {
+        let name = outer.name();
+        let layout = self.inner(name)?;
+        let key = self.encode_outer_key(name, btf, outer, key)?;
+        let inner = layout.create(name)?;
+        let entry_layout = layout.entry_layout();
+        for entry in entries.iter() {
+            let (k, v) = entry_layout
+                .encode(btf, entry)
+                .with_context(|| anyhow!("Invalid entry of inner map of `{}`", name))?;
+            inner
+                .update(&k, &v, MapFlags::ANY)
+                .with_context(|| anyhow!("Failed to put key {} into inner map", entry.key))?;
+        }
+        // The outer map holds a reference to the inner map, so ours could be closed
+        outer
+            .update(&key, &(inner.fd() as u32).to_ne_bytes(), MapFlags::ANY)
+            .with_context(|| anyhow!("Failed to put the inner map into `{}`", name))
+    }
+    /// Dump the inner map at `key` as a json array of `{"key": ..., "value": ...}`
+    pub(crate) fn dump_inner_map(
+        &self,
+        resolver: BtfResolver,
+        outer: &Map,
+        key: &Value,
+    ) -> Result<Option<Value>> {
+        let name = outer.name();
+        let layout = self.inner(name)?;
+        if layout.btf_key_type_id == 0 || layout.btf_value_type_id == 0 {
+            bail!(
+                "Inner maps of `{}` don't have BTF info of keys and values",
+                name
+            );
+        }
+        let key = self.encode_outer_key(name, resolver.btf(), outer, key)?;
+        let Some(inner) = open_inner_map(outer, &key)? else {
+            return Ok(None);
+        };
+        let mut entries = vec![];
+        for_each_entry(&inner, |k, v| {
+            entries.push(json!({
+                "key": dump_to_json(resolver, layout.btf_key_type_id, k)?,
+                "value": dump_to_json(resolver, layout.btf_value_type_id, v)?,
+            }));
+            Ok(())
+        })?;
+        Ok(Some(Value::Array(entries)))
+    }
+    /// Members of keys when sampling the map. Entries of all inner maps are reported together, so keys are the outer key followed by the inner key
+    pub(crate) fn sample_key_members(
+        &self,
+        name: &str,
+        resolver: BtfResolver,
+    ) -> Result<Vec<CheckedExportedMember>> {
+        let layout = self.inner(name)?;
+        if self.outer_key_type_id == 0 || layout.btf_key_type_id == 0 {
+            bail!(
+                "Map `{}` or its inner maps don't have BTF info of keys",
+                name
+            );
+        }
+        let outer_size = resolver.size_of(self.outer_key_type_id)?;
+        let mut members = TypeDescriptor::BtfType {
+            type_id: self.outer_key_type_id,
+        }
+        .build_checked_exported_members(resolver)?;
+        for member in members.iter_mut().filter(|v| v.field_name.is_empty()) {
+            member.field_name = "outer_key".into();
+        }
+        let inner = TypeDescriptor::BtfType {
+            type_id: layout.btf_key_type_id,
+        }
+        .build_checked_exported_members(resolver)?;
+        members.extend(inner.into_iter().map(|mut v| {
+            v.bit_offset += outer_size * 8;
+            if v.field_name.is_empty() {
+                v.field_name = "key".into();
+            }
+            v
+        }));
+        Ok(members)
+    }
+    pub(crate) fn inner_value_type_id(&self, name: &str) -> Result<u32> {
+        Ok(self.inner(name)?.btf_value_type_id)
+    }
+}
+
+/// Open the inner map at `key`
+pub(crate) fn open_inner_map(outer: &Map, key: &[u8]) -> Result<Option<Map>> {
+    let Some(id) = outer
+        .lookup(key, MapFlags::ANY)
+        .with_context(|| anyhow!("Failed to look up map `{}`", outer.name()))?
+    else {
+        return Ok(None);
+    };
+    let id = u32::from_ne_bytes(
+        id.get(..4)
+            .and_then(|v| v.try_into().ok())
+            .ok_or_else(|| anyhow!("Invalid inner map id"))?,
+    );
+    Ok(Some(Map::from_map_id(id).with_context(|| {
+        anyhow!("Failed to open inner map {} of `{}`", id, outer.name())
+    })?))
+}
+
+/// Call `f` with every key and value in the map
+pub(crate) fn for_each_entry(
+    map: &Map,
+    mut f: impl FnMut(&[u8], &[u8]) -> Result<()>,
+) -> Result<()> {
+    for key in map.keys() {
+        // The key may be deleted by the program meanwhile
+        if let Some(value) = map
+            .lookup(&key, MapFlags::ANY)
+            .with_context(|| anyhow!("Failed to look up value of the key `{:?}`", key))?
+        {
+            f(&key, &value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Set inner map fds of maps with templates in the meta. The returned maps should be kept until the object is loaded
+pub(crate) fn set_inner_map_templates(
+    meta: &EunomiaObjectMeta,
+    nested_maps: &HashMap<String, NestedMap>,
+    object: &mut OpenObject,
+) -> Result<Vec<Map>> {
+    let mut templates = vec![];
+    for map_meta in meta.bpf_skel.maps.iter() {
+        if map_meta
+            .inner_map
+            .as_ref()
+            .and_then(|v| v.template.as_ref())
+            .is_none()
+        {
+            continue;
+        }
+        let Some(layout) = nested_maps
+            .get(&map_meta.name)
+            .and_then(|v| v.inner.as_ref())
+        else {
+            continue;
+        };
+        let map = object
+            .map_mut(&map_meta.name)
+            .ok_or_else(|| anyhow!("Map named `{}` not found in libbpf", map_meta.name))?;
+        if !matches!(map.map_type(), MapType::ArrayOfMaps | MapType::HashOfMaps) {
+            bail!(
+                "Map `{}` has a template of inner maps, but it's a {:?}",
+                map_meta.name,
+                map.map_type()
+            );
+        }
+        let template = layout.create(&map_meta.name)?;
+        map.set_inner_map_fd(&template);
+        templates.push(template);
+    }
+    Ok(templates)
+}
+
+/// Create the initial inner maps and put them into the outer maps. Maps in `skipped` (e.g maps reused from a running skeleton) are untouched
+pub(crate) fn fill_initial_inner_maps(
+    meta: &EunomiaObjectMeta,
+    nested_maps: &HashMap<String, NestedMap>,
+    btf: &Btf,
+    object: &Object,
+    skipped: &[String],
+) -> Result<()> {
+    for map_meta in meta.bpf_skel.maps.iter() {
+        let Some(inner_meta) = map_meta.inner_map.as_ref() else {
+            continue;
+        };
+        if inner_meta.initial.is_empty() || skipped.contains(&map_meta.name) {
+            continue;
+        }
+        let nested = nested_maps
+            .get(&map_meta.name)
+            .ok_or_else(|| anyhow!("Map `{}` is not recorded as a map-in-map", map_meta.name))?;
+        let outer = object
+            .map(&map_meta.name)
+            .ok_or_else(|| anyhow!("Map named `{}` not found in libbpf", map_meta.name))?;
+        for inner in inner_meta.initial.iter() {
+            nested
+                .put_inner_map(btf, outer, &inner.key, &inner.entries)
+                .with_context(|| {
+                    anyhow!(
+                        "Failed to put the initial inner map at {} into `{}`",
+                        inner.key,
+                        map_meta.name
+                    )
+                })?;
+        }
+        debug!(
+            "Put {} initial inner maps into map `{}`",
+            inner_meta.initial.len(),
+            map_meta.name
+        );
+    }
+    Ok(())
+}
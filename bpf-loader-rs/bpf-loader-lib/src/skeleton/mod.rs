@@ -35,17 +35,24 @@
 //! It provide abilities to polling data from the bpf program (through ringbuf, perfevent, or maps) in a unified interface. See `wait_and_poll_to_handler` for details.
 //!
 //! Besides, it provide ability to control the polling progress in another thread. You can get a handle using `create_poll_handle`, then pause/resume/terminate the polling function in another thread.
-use std::{any::Any, sync::Arc};
+use std::{any::Any, collections::HashMap, os::fd::OwnedFd, sync::Arc};
 
 use libbpf_rs::{Map, MapType, Object};
 use log::{debug, warn};
 
-use self::{handle::PollingHandle, poller::Poller, preload::attach::AttachLink};
+use self::{
+    handle::PollingHandle,
+    map_in_map::NestedMap,
+    poller::Poller,
+    preload::attach::AttachLink,
+    stats::{query_prog_info, query_prog_stats, ProgStats, StatsHandle},
+};
 use crate::{
     btf_container::BtfContainer,
     export_event::{
-        type_descriptor::TypeDescriptor, EventExporter, EventExporterBuilder, EventHandler,
-        ExportFormatType,
+        enricher::{build_enrichers, EventEnricher},
+        type_descriptor::TypeDescriptor,
+        EventExporter, EventExporterBuilder, EventHandler, ExportFormatType,
     },
     meta::{EunomiaObjectMeta, MapExportConfig, MapMeta, MapSampleMeta, RunnerConfig},
     program_poll_loop,
@@ -53,15 +60,27 @@ use crate::{
 use anyhow::{anyhow, bail, Context, Result};
 
 const VMLINUX_BTF_PATH: &str = "/sys/kernel/btf/vmlinux";
-const BTF_PATH_ENV_NAME: &str = "BTF_FILE_PATH";
+pub(crate) const BTF_PATH_ENV_NAME: &str = "BTF_FILE_PATH";
+/// The name that `exporter_provider` of `wait_and_poll_to_handler_with_multiple_exporter` will receive when asking for the handler of bpf_printk output
+/// It can't conflict with map names, since brackets are not allowed in C identifiers
+pub const KERNEL_DEBUG_EXPORTER_NAME: &str = "[trace_pipe]";
 
+/// Access to global variables and maps of the loaded skeleton
+pub mod access;
 /// The builder of the skeleton
 pub mod builder;
 /// controlling handles
 pub mod handle;
+/// Hot reload of running skeletons
+pub mod handover;
+pub(crate) mod map_in_map;
 pub(crate) mod poller;
 /// The preloaded skeleton
 pub mod preload;
+/// Runtime statistics of the loaded programs
+pub mod stats;
+/// Capturing and parsing of the verifier logs
+pub mod verifier;
 
 #[cfg(test)]
 #[cfg(not(feature = "no-load-bpf-tests"))]
@@ -76,17 +95,19 @@ pub struct BpfSkeleton {
     /// config of eunomia itself,
     /// for how we creating, loading and interacting with the eBPF program
     /// eg. poll maps timeout in ms
-    /// Note: this field seems to be never used
-    #[allow(unused)]
     pub(crate) config_data: RunnerConfig,
 
     // exporter: EventExporter,
     /// the btf info of the loaded program
     pub(crate) btf: Arc<BtfContainer>,
-    /// the links
-    #[allow(unused)]
-    pub(crate) links: Vec<AttachLink>,
+    /// the links, with names of the programs they belong to
+    pub(crate) links: Vec<(String, AttachLink)>,
     pub(crate) prog: Object,
+    /// Map-in-maps, keyed by names of the outer maps
+    pub(crate) nested_maps: HashMap<String, NestedMap>,
+    /// Keeps run time stats enabled while the skeleton is alive
+    #[allow(unused)]
+    pub(crate) stats_fd: Option<OwnedFd>,
 }
 
 impl BpfSkeleton {
@@ -109,7 +130,39 @@ impl BpfSkeleton {
     pub fn get_prog_fd(&self, name: impl AsRef<str>) -> Option<i32> {
         self.prog.prog(name).map(|p| p.fd())
     }
+    /// Get runtime statistics of the loaded programs. Programs replaced by their fallbacks are skipped
+    pub fn prog_stats(&self) -> Result<Vec<ProgStats>> {
+        self.loaded_progs()
+            .map(|(name, fd)| query_prog_stats(name, fd))
+            .collect()
+    }
+    /// Create a handle to query runtime statistics of the programs in another thread
+    pub fn create_stats_handle(&self) -> Result<StatsHandle> {
+        let progs = self
+            .loaded_progs()
+            .map(|(name, fd)| Ok((name.to_string(), query_prog_info(name, fd)?.id)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(StatsHandle { progs })
+    }
+    fn loaded_progs(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.meta.bpf_skel.progs.iter().filter_map(|prog_meta| {
+            self.prog
+                .prog(&prog_meta.name)
+                .map(|p| p.fd())
+                .filter(|fd| *fd >= 0)
+                .map(|fd| (prog_meta.name.as_str(), fd))
+        })
+    }
 
+    /// Enrichers of the map set in the runner config
+    fn map_enrichers(&self, map_name: &str) -> Vec<Box<dyn EventEnricher>> {
+        self.config_data
+            .maps
+            .get(map_name)
+            .and_then(|v| v.enrich.as_ref())
+            .map(build_enrichers)
+            .unwrap_or_default()
+    }
     fn build_poller_from_exporter<'a>(
         &self,
         exporter: Arc<EventExporter>,
@@ -126,8 +179,13 @@ impl BpfSkeleton {
                     .with_context(|| anyhow!("Failed to builf perfevent poller"))?,
             ),
             ExportMapType::Sample(sp) => Poller::SampleMap(
-                self.build_sample_map_poller(bpf_map, exporter, sp)
-                    .with_context(|| anyhow!("Failed to build sample map poller"))?,
+                self.build_sample_map_poller(
+                    bpf_map,
+                    exporter,
+                    sp,
+                    self.nested_maps.contains_key(bpf_map.name()),
+                )
+                .with_context(|| anyhow!("Failed to build sample map poller"))?,
             ),
         };
         Ok(ret)
@@ -166,8 +224,16 @@ impl BpfSkeleton {
                 .prog
                 .map(&map_meta.name)
                 .ok_or_else(|| anyhow!("Invalid map name: {}", map_meta.name))?;
-            let exporter_builder =
-                create_exporter_builder(export_format_type, export_event_handler, user_context);
+            let exporter_builder = create_exporter_builder(
+                self.config_data
+                    .maps
+                    .get(&map_meta.name)
+                    .and_then(|v| v.export_format)
+                    .unwrap_or(export_format_type),
+                export_event_handler.clone(),
+                user_context.clone(),
+            )
+            .set_enrichers(self.map_enrichers(&map_meta.name));
             if self.meta.export_types.is_empty() {
                 bail!(
                     "Export map named `{}` found, but no export type is provided",
@@ -189,28 +255,70 @@ impl BpfSkeleton {
                     let map_info = bpf_map.info().with_context(|| {
                         anyhow!("Failed to get map info for `{}`", bpf_map.name())
                     })?;
-                    exporter_builder.build_for_key_value(
-                        map_info.info.btf_key_type_id,
-                        map_info.info.btf_value_type_id,
-                        sp,
-                        &self.meta.export_types[0],
-                        self.btf.clone(),
-                    )?
+                    if let Some(nested) = self.nested_maps.get(&map_meta.name) {
+                        exporter_builder.build_for_key_value_with_type_desc(
+                            TypeDescriptor::CheckedMembers(
+                                nested.sample_key_members(&map_meta.name, self.btf.resolver())?,
+                            ),
+                            TypeDescriptor::BtfType {
+                                type_id: nested.inner_value_type_id(&map_meta.name)?,
+                            },
+                            sp,
+                            self.btf.clone(),
+                        )?
+                    } else {
+                        exporter_builder.build_for_key_value(
+                            map_info.info.btf_key_type_id,
+                            map_info.info.btf_value_type_id,
+                            sp,
+                            &self.meta.export_types[0],
+                            self.btf.clone(),
+                        )?
+                    }
                 }
             };
             let poller = self.build_poller_from_exporter(exporter, export_type, bpf_map)?;
+            let mut pollers = self.build_iter_pollers(|_| {
+                (
+                    export_format_type,
+                    export_event_handler.clone(),
+                    user_context.clone(),
+                )
+            })?;
+            pollers.extend(self.build_trace_pipe_poller(
+                export_event_handler,
+                user_context,
+                false,
+            )?);
             self.handle.reset();
             program_poll_loop!(&self.handle, {
                 poller.poll()?;
+                for poller in pollers.iter() {
+                    poller.poll()?;
+                }
             });
         } else {
-            self.wait_for_no_export_program()
+            let mut pollers = self.build_iter_pollers(|_| {
+                (
+                    export_format_type,
+                    export_event_handler.clone(),
+                    user_context.clone(),
+                )
+            })?;
+            pollers.extend(self.build_trace_pipe_poller(
+                export_event_handler,
+                user_context,
+                true,
+            )?);
+            self.wait_for_no_export_program_with_pollers(&pollers)
                 .with_context(|| anyhow!("Failed to wait for program"))?;
         }
         Ok(())
     }
     /// Start poll with each map corresponding to a different exporter
     /// The function `exporter_provider` should return the ExportFormatType, EventHandler, and UserContext(if applies) for the given map name (If you want to set the exporter)
+    /// If `print_kernel_debug` is set, it will also be called with `KERNEL_DEBUG_EXPORTER_NAME` to get the handler of bpf_printk output
+    /// It will also be called with names of iterator programs, to get the handlers of their output
     pub fn wait_and_poll_to_handler_with_multiple_exporter(
         &self,
         exporter_provider: impl Fn(
@@ -254,14 +362,29 @@ impl BpfSkeleton {
             }
         }
         debug!("Export maps: {:#?}", export_maps);
+        let trace_pipe = if self.config_data.print_kernel_debug {
+            let (handler, ctx) = exporter_provider(KERNEL_DEBUG_EXPORTER_NAME)
+                .map(|(_, handler, ctx)| (Some(handler), ctx))
+                .unwrap_or_default();
+            self.build_trace_pipe_poller(handler, ctx, export_maps.is_empty())?
+        } else {
+            None
+        };
+
+        let iters = self.build_iter_pollers(|name| {
+            exporter_provider(name)
+                .map(|(ty, handler, ctx)| (ty, Some(handler), ctx))
+                .unwrap_or((ExportFormatType::PlainText, None, None))
+        })?;
 
         // Before polling, we should reset the control flags
         self.handle.reset();
         if export_maps.is_empty() {
-            self.wait_for_no_export_program()
+            let pollers = iters.into_iter().chain(trace_pipe).collect::<Vec<_>>();
+            self.wait_for_no_export_program_with_pollers(&pollers)
                 .with_context(|| anyhow!("Failed to wait for a non-export program"))?;
         } else {
-            let mut pollers = vec![];
+            let mut pollers = iters;
             for (map_meta, export_map_type) in export_maps.into_iter() {
                 let bpf_map = self
                     .prog
@@ -282,7 +405,10 @@ impl BpfSkeleton {
                     MapExportConfig::Default => {
                         if is_sample_map {
                             TypeDescriptor::BtfType {
-                                type_id: map_info.info.btf_value_type_id,
+                                type_id: match self.nested_maps.get(&map_meta.name) {
+                                    Some(v) => v.inner_value_type_id(&map_meta.name)?,
+                                    None => map_info.info.btf_value_type_id,
+                                },
                             }
                         } else {
                             bail!("MapExportConfig::Default only applies to sample map");
@@ -299,6 +425,17 @@ impl BpfSkeleton {
                 } else {
                     builder
                 };
+                // Format set in the runner config wins over the one from the provider
+                let builder = match self
+                    .config_data
+                    .maps
+                    .get(&map_meta.name)
+                    .and_then(|v| v.export_format)
+                {
+                    Some(fmt) => builder.set_export_format(fmt),
+                    None => builder,
+                };
+                let builder = builder.set_enrichers(self.map_enrichers(&map_meta.name));
                 match export_map_type {
                     ExportMapType::RingBuffer => {
                         let exporter = builder
@@ -325,11 +462,19 @@ impl BpfSkeleton {
                         ));
                     }
                     ExportMapType::Sample(cfg) => {
+                        let nested = self.nested_maps.get(&map_meta.name);
+                        let key_desc = match nested {
+                            // Keys of entries in inner maps are prefixed with the outer keys
+                            Some(v) => TypeDescriptor::CheckedMembers(
+                                v.sample_key_members(&map_meta.name, self.btf.resolver())?,
+                            ),
+                            None => TypeDescriptor::BtfType {
+                                type_id: map_info.info.btf_key_type_id,
+                            },
+                        };
                         let exporter = builder
                             .build_for_key_value_with_type_desc(
-                                TypeDescriptor::BtfType {
-                                    type_id: map_info.info.btf_key_type_id,
-                                },
+                                key_desc,
                                 type_desc,
                                 cfg,
                                 self.btf.clone(),
@@ -340,12 +485,16 @@ impl BpfSkeleton {
                                     bpf_map.name()
                                 )
                             })?;
-                        pollers.push(Poller::SampleMap(
-                            self.build_sample_map_poller(bpf_map, exporter, cfg)?,
-                        ));
+                        pollers.push(Poller::SampleMap(self.build_sample_map_poller(
+                            bpf_map,
+                            exporter,
+                            cfg,
+                            nested.is_some(),
+                        )?));
                     }
                 }
             }
+            pollers.extend(trace_pipe);
             program_poll_loop!(&self.handle, {
                 for poller in pollers.iter() {
                     poller.poll()?;
@@ -0,0 +1,254 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{
+    any::Any,
+    cell::Cell,
+    fs::File,
+    io::Read,
+    os::fd::FromRawFd,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libbpf_rs::libbpf_sys::bpf_iter_create;
+use log::{debug, warn};
+
+use serde_json::Value;
+
+use crate::export_event::{
+    dump_data_to_user_callback_or_stdout, dump_json_to_user_callback_or_stdout, EventExporter,
+    EventHandler, ExportFormatType, ExporterInternalImplementation, ReceivedEventData,
+};
+
+/// Where the output of an iterator goes
+pub(crate) enum IterOutput {
+    /// Forward the output line by line: as plain text, as json strings, or as raw buffers
+    Text {
+        export_format: ExportFormatType,
+        event_handler: Option<Arc<dyn EventHandler>>,
+        user_ctx: Option<Arc<dyn Any>>,
+    },
+    /// Split the output into records of `record_size` bytes, and export them like ringbuf events
+    Records {
+        exporter: Arc<EventExporter>,
+        record_size: usize,
+    },
+}
+
+impl IterOutput {
+    /// Export what was read from the iterator named `name`
+    fn export(&self, name: &str, buf: &[u8]) -> Result<()> {
+        match self {
+            IterOutput::Text {
+                export_format,
+                event_handler,
+                user_ctx,
+            } => {
+                for line in String::from_utf8_lossy(buf).lines() {
+                    match export_format {
+                        ExportFormatType::PlainText => dump_data_to_user_callback_or_stdout(
+                            event_handler.clone(),
+                            user_ctx.clone(),
+                            ReceivedEventData::PlainText(line),
+                        ),
+                        ExportFormatType::Json => dump_json_to_user_callback_or_stdout(
+                            event_handler.clone(),
+                            user_ctx.clone(),
+                            &Value::String(line.to_string()),
+                        ),
+                        ExportFormatType::RawEvent => dump_data_to_user_callback_or_stdout(
+                            event_handler.clone(),
+                            user_ctx.clone(),
+                            ReceivedEventData::Buffer(line.as_bytes()),
+                        ),
+                    }
+                }
+            }
+            IterOutput::Records {
+                exporter,
+                record_size,
+            } => {
+                let event_processor = match &exporter.internal_impl {
+                    ExporterInternalImplementation::BufferValueProcessor {
+                        event_processor,
+                        ..
+                    } => event_processor,
+                    _ => bail!("Expected the exporter uses buffer value processor"),
+                };
+                if buf.len() % *record_size != 0 {
+                    warn!(
+                        "Output of iterator `{}` is {} bytes, not a multiple of records of {} bytes",
+                        name,
+                        buf.len(),
+                        record_size
+                    );
+                }
+                for record in buf.chunks_exact(*record_size) {
+                    event_processor.handle_event(record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a BPF iterator once, or every `interval`. It never blocks
+pub(crate) struct IterPoller {
+    name: String,
+    link_fd: i32,
+    interval: Option<Duration>,
+    // None if the iterator is only read once, and it has been read
+    next_read: Cell<Option<Instant>>,
+    output: IterOutput,
+}
+
+impl IterPoller {
+    /// link_fd - The iterator link, it must outlive the poller
+    pub(crate) fn new(
+        name: String,
+        link_fd: i32,
+        interval: Option<Duration>,
+        output: IterOutput,
+    ) -> Self {
+        Self {
+            name,
+            link_fd,
+            interval,
+            next_read: Cell::new(Some(Instant::now())),
+            output,
+        }
+    }
+
+    pub(crate) fn poll(&self) -> Result<()> {
+        let now = Instant::now();
+        match self.next_read.get() {
+            Some(v) if v <= now => {}
+            _ => return Ok(()),
+        }
+        self.next_read.set(self.interval.map(|v| now + v));
+        let buf = self.read()?;
+        debug!("Read {} bytes from iterator `{}`", buf.len(), self.name);
+        self.output.export(&self.name, &buf)
+    }
+
+    /// Create an iterator from the link, and read it to the end
+    fn read(&self) -> Result<Vec<u8>> {
+        // SAFETY: it only takes an fd
+        let fd = unsafe { bpf_iter_create(self.link_fd) };
+        if fd < 0 {
+            bail!(
+                "Failed to create iterator `{}`: {}",
+                self.name,
+                errno::errno()
+            );
+        }
+        // SAFETY: the fd was just created and is owned by nobody else
+        let mut iter = unsafe { File::from_raw_fd(fd) };
+        let mut buf = vec![];
+        iter.read_to_end(&mut buf)
+            .with_context(|| anyhow!("Failed to read iterator `{}`", self.name))?;
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+    use serde_json::{json, Value};
+
+    use super::IterOutput;
+    use crate::{
+        btf_container::BtfContainer,
+        export_event::{
+            type_descriptor::TypeDescriptor, EventExporterBuilder, EventHandler, ExportFormatType,
+            ReceivedEventData,
+        },
+        meta::BufferValueInterpreter,
+        skeleton::map_in_map::find_type_by_name,
+        tests::{get_assets_dir, ExampleTestStruct},
+    };
+
+    /// Collects json events, and plain texts or buffers as json strings
+    struct CollectingHandler {
+        data: Rc<RefCell<Vec<Value>>>,
+    }
+    impl EventHandler for CollectingHandler {
+        fn handle_event(&self, _context: Option<Arc<dyn std::any::Any>>, data: ReceivedEventData) {
+            let value = match data {
+                ReceivedEventData::JsonText(s) => serde_json::from_str(s).unwrap(),
+                ReceivedEventData::PlainText(s) => json!(s),
+                ReceivedEventData::Buffer(buf) => json!(String::from_utf8(buf.to_vec()).unwrap()),
+                _ => panic!("Unexpected data type"),
+            };
+            self.data.borrow_mut().push(value);
+        }
+    }
+
+    #[test]
+    fn test_export_text() {
+        for export_format in [
+            ExportFormatType::PlainText,
+            ExportFormatType::Json,
+            ExportFormatType::RawEvent,
+        ] {
+            let data = Rc::new(RefCell::new(vec![]));
+            let output = IterOutput::Text {
+                export_format,
+                event_handler: Some(Arc::new(CollectingHandler { data: data.clone() })),
+                user_ctx: None,
+            };
+            output
+                .export("dump_task", b"1 systemd\n2 \"kthreadd\"\n")
+                .unwrap();
+            assert_eq!(
+                *data.borrow(),
+                vec![json!("1 systemd"), json!("2 \"kthreadd\"")]
+            );
+        }
+    }
+
+    #[test]
+    fn test_export_records() {
+        let assets = get_assets_dir().join("simple_prog");
+        let btf = Arc::new(
+            BtfContainer::new_from_binary(
+                &std::fs::read(assets.join("simple_prog.bpf.o")).unwrap(),
+            )
+            .unwrap(),
+        );
+        let record = std::fs::read(assets.join("dumper_test.bin")).unwrap();
+        let type_id = find_type_by_name(btf.borrow_btf(), "S").unwrap();
+        assert_eq!(btf.borrow_btf().get_size_of(type_id) as usize, record.len());
+        let data = Rc::new(RefCell::new(vec![]));
+        let exporter = EventExporterBuilder::new()
+            .set_export_format(ExportFormatType::Json)
+            .set_export_event_handler(Arc::new(CollectingHandler { data: data.clone() }))
+            .build_for_single_value_with_type_descriptor(
+                TypeDescriptor::BtfType { type_id },
+                btf,
+                &BufferValueInterpreter::DefaultStruct,
+            )
+            .unwrap();
+        let output = IterOutput::Records {
+            exporter,
+            record_size: record.len(),
+        };
+        // Two records, with an incomplete one at the end which is dropped
+        let mut buf = [record.clone(), record.clone()].concat();
+        buf.extend_from_slice(&record[..4]);
+        output.export("dump_task", &buf).unwrap();
+        let data = data.borrow();
+        assert_eq!(data.len(), 2);
+        for event in data.iter() {
+            serde_json::from_value::<ExampleTestStruct>(event.clone())
+                .unwrap()
+                .test_with_example_data();
+        }
+    }
+}
@@ -5,6 +5,7 @@
 //!
 
 use std::{
+    any::Any,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -14,17 +15,31 @@ use std::{
 
 use crate::{
     export_event::{
-        EventExporter, ExporterInternalImplementation, InternalBufferValueEventProcessor,
+        type_descriptor::TypeDescriptor, EventExporter, EventHandler, ExportFormatType,
+        ExporterInternalImplementation, InternalBufferValueEventProcessor,
         InternalSampleMapProcessor,
     },
-    meta::MapSampleMeta,
+    meta::{BufferValueInterpreter, IterProgExtraMeta, MapSampleMeta},
 };
 use anyhow::anyhow;
 use anyhow::{bail, Context, Result};
 use libbpf_rs::{Map, MapFlags, PerfBuffer, PerfBufferBuilder, RingBuffer, RingBufferBuilder};
-use log::error;
+use log::{debug, error};
+
+use self::{
+    iter::{IterOutput, IterPoller},
+    trace_pipe::TracePipePoller,
+};
+
+use super::{
+    create_exporter_builder,
+    map_in_map::{find_type_by_name, for_each_entry, open_inner_map},
+    BpfSkeleton,
+};
+
+pub(crate) mod iter;
+pub(crate) mod trace_pipe;
 
-use super::BpfSkeleton;
 #[macro_export]
 macro_rules! program_poll_loop {
     ($handle: expr, $blk: block) => {{
@@ -74,28 +89,46 @@ pub(crate) struct SampleMapPollerContext<'a> {
     sample_config: &'a MapSampleMeta,
     #[borrows(exporter)]
     event_processor: &'this dyn InternalSampleMapProcessor,
+    /// Whether the map is a map-in-map. If so, entries of the inner maps are sampled
+    nested: bool,
 }
 
 pub(crate) enum Poller<'a> {
     RingBuf(RingBufPollerContext),
     PerfEvent(PerfEventPollerContext),
     SampleMap(SampleMapPollerContext<'a>),
+    TracePipe(TracePipePoller),
+    Iter(IterPoller),
 }
 
 impl<'a> Drop for Poller<'a> {
     fn drop(&mut self) {
         if let Poller::SampleMap(ctx) = self {
             if ctx.borrow_sample_config().clear_map {
-                // Clean up the map
-                let keys = ctx.borrow_map().keys().collect::<Vec<_>>();
-                for key in keys.into_iter() {
-                    ctx.borrow_map().delete(&key).ok();
+                let map = *ctx.borrow_map();
+                if *ctx.borrow_nested() {
+                    // Clean up the inner maps, which the program looks up
+                    for key in map.keys() {
+                        if let Ok(Some(inner)) = open_inner_map(map, &key) {
+                            clear_map(&inner);
+                        }
+                    }
+                } else {
+                    // Clean up the map
+                    clear_map(map);
                 }
             }
         }
     }
 }
 
+fn clear_map(map: &Map) {
+    let keys = map.keys().collect::<Vec<_>>();
+    for key in keys.into_iter() {
+        map.delete(&key).ok();
+    }
+}
+
 impl<'a> Poller<'a> {
     pub(crate) fn poll(&self) -> Result<()> {
         match self {
@@ -112,6 +145,21 @@ impl<'a> Poller<'a> {
                     bail!("Failed to poll perf event. See log for details");
                 }
             }
+            Poller::SampleMap(ctx) if *ctx.borrow_nested() => {
+                for key in ctx.borrow_map().keys() {
+                    let Some(inner) = open_inner_map(ctx.borrow_map(), &key)? else {
+                        continue;
+                    };
+                    for_each_entry(&inner, |inner_key, value| {
+                        ctx.borrow_event_processor()
+                            .handle_event(&[&key[..], inner_key].concat(), value)
+                            .with_context(|| anyhow!("Failed to handle event"))
+                    })?;
+                }
+                std::thread::sleep(Duration::from_millis(
+                    ctx.borrow_sample_config().interval as u64,
+                ));
+            }
             Poller::SampleMap(ctx) => {
                 for key in ctx.borrow_map().keys() {
                     let value = ctx
@@ -129,20 +177,121 @@ impl<'a> Poller<'a> {
                     ctx.borrow_sample_config().interval as u64,
                 ));
             }
+            Poller::TracePipe(tp) => tp.poll()?,
+            Poller::Iter(iter) => iter.poll()?,
         };
         Ok(())
     }
 }
 
 impl BpfSkeleton {
-    #[inline]
+    #[cfg(all(test, not(feature = "no-load-bpf-tests")))]
     pub(crate) fn wait_for_no_export_program(&self) -> Result<()> {
+        self.wait_for_no_export_program_with_pollers(&[])
+    }
+    /// Wait for a program without export maps, while polling the given non-map pollers (trace_pipe, iterators)
+    pub(crate) fn wait_for_no_export_program_with_pollers(&self, pollers: &[Poller]) -> Result<()> {
+        // Only the trace_pipe poller blocks when it's the only export
+        let blocking = pollers.iter().any(|v| matches!(v, Poller::TracePipe(_)));
         program_poll_loop!(self.handle, {
-            std::hint::spin_loop();
-            std::thread::sleep(Duration::from_millis(1));
+            for poller in pollers.iter() {
+                poller.poll()?;
+            }
+            if !blocking {
+                std::hint::spin_loop();
+                std::thread::sleep(Duration::from_millis(1));
+            }
         });
         Ok(())
     }
+    /// Build a poller forwarding bpf_printk output, if `print_kernel_debug` is set
+    /// exclusive - Whether it's the only poller. If not, it won't block when polling
+    pub(crate) fn build_trace_pipe_poller(
+        &self,
+        event_handler: Option<Arc<dyn EventHandler>>,
+        user_ctx: Option<Arc<dyn Any>>,
+        exclusive: bool,
+    ) -> Result<Option<Poller<'static>>> {
+        if !self.config_data.print_kernel_debug {
+            return Ok(None);
+        }
+        let poller = TracePipePoller::open(
+            trace_pipe::kernel_debug_pid_filter(&self.meta, &self.config_data.kernel_debug_pids),
+            if exclusive {
+                self.meta.poll_timeout_ms
+            } else {
+                0
+            },
+            event_handler,
+            user_ctx,
+        )
+        .with_context(|| anyhow!("Failed to build trace_pipe poller"))?;
+        Ok(Some(Poller::TracePipe(poller)))
+    }
+
+    /// Build pollers reading the iterators attached by this skeleton
+    /// provider - Returns the export format, event handler and user context for the given program name
+    pub(crate) fn build_iter_pollers(
+        &self,
+        provider: impl Fn(
+            &str,
+        ) -> (
+            ExportFormatType,
+            Option<Arc<dyn EventHandler>>,
+            Option<Arc<dyn Any>>,
+        ),
+    ) -> Result<Vec<Poller<'static>>> {
+        let mut pollers = vec![];
+        for prog_meta in self.meta.bpf_skel.progs.iter().filter(|v| v.is_iter()) {
+            let Some(link_fd) = self
+                .links
+                .iter()
+                .find(|(name, _)| name == &prog_meta.name)
+                .and_then(|(_, link)| link.link_fd())
+            else {
+                debug!("Iterator `{}` is not attached, skipping", prog_meta.name);
+                continue;
+            };
+            let iter_extra_meta =
+                serde_json::from_value::<IterProgExtraMeta>(prog_meta.others.clone())
+                    .with_context(|| anyhow!("Failed to deserialize iter extra meta"))?;
+            let (export_format, event_handler, user_ctx) = provider(&prog_meta.name);
+            let output = match iter_extra_meta.record_type.as_ref() {
+                Some(record_type) => {
+                    let type_id = find_type_by_name(self.btf.borrow_btf(), record_type)?;
+                    let record_size = self.btf.borrow_btf().get_size_of(type_id) as usize;
+                    if record_size == 0 {
+                        bail!("Record type `{}` has no size", record_type);
+                    }
+                    let exporter = create_exporter_builder(export_format, event_handler, user_ctx)
+                        .build_for_single_value_with_type_descriptor(
+                            TypeDescriptor::BtfType { type_id },
+                            self.btf.clone(),
+                            &BufferValueInterpreter::DefaultStruct,
+                        )
+                        .with_context(|| {
+                            anyhow!("Failed to build exporter for iterator `{}`", prog_meta.name)
+                        })?;
+                    IterOutput::Records {
+                        exporter,
+                        record_size,
+                    }
+                }
+                None => IterOutput::Text {
+                    export_format,
+                    event_handler,
+                    user_ctx,
+                },
+            };
+            pollers.push(Poller::Iter(IterPoller::new(
+                prog_meta.name.clone(),
+                link_fd,
+                iter_extra_meta.interval_ms.map(Duration::from_millis),
+                output,
+            )));
+        }
+        Ok(pollers)
+    }
 
     pub(crate) fn build_ringbuf_poller(
         &self,
@@ -206,6 +355,7 @@ impl BpfSkeleton {
             },
             perf_builder: |processor, error_flag: &AtomicBool| {
                 let perf = PerfBufferBuilder::new(map)
+                    .pages(self.meta.perf_buffer_pages)
                     .sample_cb(|_cpu: i32, data: &[u8]| {
                         if let Err(e) = processor.handle_event(data) {
                             error!("Failed to handle event for perf array: \n{:?}", e);
@@ -227,6 +377,7 @@ impl BpfSkeleton {
         map: &'a Map,
         exporter: Arc<EventExporter>,
         sample_config: &'a MapSampleMeta,
+        nested: bool,
     ) -> Result<SampleMapPollerContext<'a>> {
         let ctx = SampleMapPollerContextTryBuilder {
             exporter,
@@ -242,6 +393,7 @@ impl BpfSkeleton {
             },
             map,
             sample_config,
+            nested,
         }
         .try_build()?;
         Ok(ctx)
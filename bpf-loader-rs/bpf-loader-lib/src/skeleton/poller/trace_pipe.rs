@@ -0,0 +1,322 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Read},
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+
+use crate::{
+    export_event::{dump_data_to_user_callback_or_stdout, EventHandler, ReceivedEventData},
+    meta::{AttachTarget, EunomiaObjectMeta, MultiProbeProgExtraMeta, UsdtProgExtraMeta},
+};
+
+/// Path of trace_pipe if tracefs is mounted at its own place
+pub(crate) const TRACEFS_TRACE_PIPE_PATH: &str = "/sys/kernel/tracing/trace_pipe";
+/// Path of trace_pipe if only debugfs is mounted
+pub(crate) const DEBUGFS_TRACE_PIPE_PATH: &str = "/sys/kernel/debug/tracing/trace_pipe";
+
+const BPF_TRACE_PRINTK_MARKER: &str = ": bpf_trace_printk: ";
+
+/// A line produced by `bpf_printk`, split into its fields
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct TracePipeLine<'a> {
+    pub(crate) comm: &'a str,
+    /// The thread that printed the line
+    pub(crate) tid: u32,
+    /// The process of the thread, if the kernel prints it
+    pub(crate) tgid: Option<u32>,
+    pub(crate) cpu: u32,
+    pub(crate) message: &'a str,
+}
+
+impl TracePipeLine<'_> {
+    /// The process that printed the line. Without the tgid, only lines of main threads get the right one
+    pub(crate) fn pid(&self) -> u32 {
+        self.tgid.unwrap_or(self.tid)
+    }
+}
+
+/// Parse a line of trace_pipe. Lines look like
+/// ```text
+///            <...>-1234    [002] d..31  1234.567890: bpf_trace_printk: hello
+///  bash-1234    (   1234) [002] d..31  1234.567890: bpf_trace_printk: hello
+/// ```
+/// Returns None if the line is not produced by `bpf_printk`
+pub(crate) fn parse_trace_pipe_line(line: &str) -> Option<TracePipeLine<'_>> {
+    let (head, message) = line.split_once(BPF_TRACE_PRINTK_MARKER)?;
+    // Flags and timestamps never contain `[`, so the last one starts the cpu field
+    let cpu_start = head.rfind('[')?;
+    let cpu_end = cpu_start + head[cpu_start..].find(']')?;
+    let cpu = head[cpu_start + 1..cpu_end].trim().parse().ok()?;
+    let mut task = head[..cpu_start].trim();
+    let mut tgid = None;
+    // Some kernels print the tgid as `(  1234)` after the task, or `(-------)` if it's unknown
+    if task.ends_with(')') {
+        let tgid_start = task.rfind('(')?;
+        tgid = task[tgid_start + 1..task.len() - 1].trim().parse().ok();
+        task = task[..tgid_start].trim_end();
+    }
+    // comm may contain `-`, but the thread id never does
+    let (comm, tid) = task.rsplit_once('-')?;
+    Some(TracePipeLine {
+        comm,
+        tid: tid.parse().ok()?,
+        tgid,
+        cpu,
+        message,
+    })
+}
+
+/// Get the process a program only runs in, if it's a uprobe or a USDT program restricted to a pid
+fn program_pid(prog: &crate::meta::ProgMeta) -> Option<u32> {
+    let pid = if let Ok(Some(target)) = prog.resolve_attach_target() {
+        // The attach target replaces the one in the extra meta
+        match target {
+            AttachTarget::Uprobe { pid, .. } => pid,
+            _ => return None,
+        }
+    } else if prog.is_usdt() {
+        serde_json::from_value::<UsdtProgExtraMeta>(prog.others.clone())
+            .ok()?
+            .pid
+    } else if prog.is_multi_probe() {
+        serde_json::from_value::<MultiProbeProgExtraMeta>(prog.others.clone())
+            .ok()?
+            .pid
+    } else {
+        return None;
+    };
+    u32::try_from(pid).ok().filter(|v| *v > 0)
+}
+
+/// Get the pids whose bpf_printk lines should be forwarded
+///
+/// The pids given by the user are always kept. The processes the programs are restricted to are added to them,
+/// but if the user gives none, they are only used when every program is restricted to a process, since lines of
+/// the other programs could come from any process. An empty result means no filtering
+pub(crate) fn kernel_debug_pid_filter(meta: &EunomiaObjectMeta, user_pids: &[u32]) -> Vec<u32> {
+    let program_pids = meta
+        .bpf_skel
+        .progs
+        .iter()
+        .map(program_pid)
+        .collect::<Vec<_>>();
+    if user_pids.is_empty() && program_pids.iter().any(|v| v.is_none()) {
+        return vec![];
+    }
+    let mut pids = user_pids.to_vec();
+    for pid in program_pids.into_iter().flatten() {
+        if !pids.contains(&pid) {
+            pids.push(pid);
+        }
+    }
+    pids
+}
+
+/// Forwards lines emitted by `bpf_printk` to the event handler
+pub(crate) struct TracePipePoller {
+    pipe: File,
+    pending: RefCell<Vec<u8>>,
+    pid_filter: Vec<u32>,
+    poll_timeout_ms: i32,
+    event_handler: Option<Arc<dyn EventHandler>>,
+    user_ctx: Option<Arc<dyn Any>>,
+}
+
+impl TracePipePoller {
+    /// Open trace_pipe from tracefs, or from debugfs if tracefs is not mounted
+    /// poll_timeout_ms - How long a poll waits for new lines. Use 0 if other pollers will block
+    pub(crate) fn open(
+        pid_filter: Vec<u32>,
+        poll_timeout_ms: i32,
+        event_handler: Option<Arc<dyn EventHandler>>,
+        user_ctx: Option<Arc<dyn Any>>,
+    ) -> Result<Self> {
+        let path = [TRACEFS_TRACE_PIPE_PATH, DEBUGFS_TRACE_PIPE_PATH]
+            .into_iter()
+            .find(|p| Path::new(p).exists())
+            .ok_or_else(|| {
+                anyhow!(
+                    "trace_pipe not found, is tracefs or debugfs mounted? (Tried {} and {})",
+                    TRACEFS_TRACE_PIPE_PATH,
+                    DEBUGFS_TRACE_PIPE_PATH
+                )
+            })?;
+        debug!("Forwarding bpf_printk output from {}", path);
+        let pipe = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+        Ok(Self {
+            pipe,
+            pending: RefCell::new(vec![]),
+            pid_filter,
+            poll_timeout_ms,
+            event_handler,
+            user_ctx,
+        })
+    }
+
+    pub(crate) fn poll(&self) -> Result<()> {
+        let mut pfd = libc::pollfd {
+            fd: self.pipe.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: pfd is valid during the call
+        let ret = unsafe { libc::poll(&mut pfd, 1, self.poll_timeout_ms) };
+        if ret < 0 {
+            let err = errno::errno();
+            if err.0 == libc::EINTR {
+                return Ok(());
+            }
+            bail!("Failed to poll trace_pipe: {}", err);
+        }
+        if ret == 0 {
+            return Ok(());
+        }
+        let mut pending = self.pending.borrow_mut();
+        let mut buf = [0u8; 4096];
+        loop {
+            match (&self.pipe).read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => pending.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => bail!("Failed to read trace_pipe: {}", e),
+            }
+        }
+        // Only complete lines are forwarded, the rest waits for the next poll
+        while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+            let line = pending.drain(..=pos).collect::<Vec<_>>();
+            self.forward_line(String::from_utf8_lossy(&line).trim_end());
+        }
+        Ok(())
+    }
+
+    fn forward_line(&self, line: &str) {
+        let parsed = match parse_trace_pipe_line(line) {
+            Some(v) => v,
+            None => return,
+        };
+        // The filter holds process ids, not thread ids
+        if !self.pid_filter.is_empty() && !self.pid_filter.contains(&parsed.pid()) {
+            return;
+        }
+        dump_data_to_user_callback_or_stdout(
+            self.event_handler.clone(),
+            self.user_ctx.clone(),
+            ReceivedEventData::KernelDebugText(line),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{kernel_debug_pid_filter, parse_trace_pipe_line, TracePipeLine};
+    use crate::meta::EunomiaObjectMeta;
+
+    fn meta_with_progs(progs: serde_json::Value) -> EunomiaObjectMeta {
+        serde_json::from_value(json!({
+            "bpf_skel": {
+                "data_sections": [],
+                "maps": [],
+                "progs": progs,
+                "obj_name": "test"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_trace_pipe_line() {
+        assert_eq!(
+            parse_trace_pipe_line(
+                "           <...>-1234    [002] d..31  1234.567890: bpf_trace_printk: hello: world"
+            ),
+            Some(TracePipeLine {
+                comm: "<...>",
+                tid: 1234,
+                tgid: None,
+                cpu: 2,
+                message: "hello: world"
+            })
+        );
+        assert_eq!(
+            parse_trace_pipe_line(
+                " kworker/u8:1-abc-56 (     56) [010] .... 99.000001: bpf_trace_printk: x"
+            ),
+            Some(TracePipeLine {
+                comm: "kworker/u8:1-abc",
+                tid: 56,
+                tgid: Some(56),
+                cpu: 10,
+                message: "x"
+            })
+        );
+        // A thread other than the main one is filtered by its process
+        let thread_line = parse_trace_pipe_line(
+            "      python3-4321    (   4300) [001] d..31  5.000000: bpf_trace_printk: y",
+        )
+        .unwrap();
+        assert_eq!(
+            thread_line,
+            TracePipeLine {
+                comm: "python3",
+                tid: 4321,
+                tgid: Some(4300),
+                cpu: 1,
+                message: "y"
+            }
+        );
+        assert_eq!(thread_line.pid(), 4300);
+        let unknown_tgid =
+            parse_trace_pipe_line("  bash-77 (-------) [003] .... 1.0: bpf_trace_printk: z")
+                .unwrap();
+        assert_eq!((unknown_tgid.tid, unknown_tgid.tgid), (77, None));
+        assert_eq!(unknown_tgid.pid(), 77);
+        assert_eq!(
+            parse_trace_pipe_line("bash-1 [000] .... 1.0: sched_switch: prev_comm=bash"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_kernel_debug_pid_filter() {
+        let restricted = meta_with_progs(json!([
+            {"name": "a", "attach": "usdt", "link": true, "binary_path": "/bin/a", "provider": "p", "probe": "x", "pid": 10},
+            {"name": "b", "attach": "uprobe", "link": true, "functions": ["f"], "binary_path": "/bin/b", "pid": 20},
+        ]));
+        assert_eq!(kernel_debug_pid_filter(&restricted, &[]), vec![10, 20]);
+        assert_eq!(
+            kernel_debug_pid_filter(&restricted, &[20, 30]),
+            vec![20, 30, 10]
+        );
+        let retargeted = meta_with_progs(json!([
+            {"name": "a", "attach": "uprobe", "link": true, "functions": ["f"], "binary_path": "/bin/a", "pid": 10, "attach_target": "uprobe:/bin/b:g:20"},
+            {"name": "b", "attach": "uprobe/bin/c:h", "link": true, "attach_target": "uretprobe:/bin/c:h:30"},
+        ]));
+        assert_eq!(kernel_debug_pid_filter(&retargeted, &[]), vec![20, 30]);
+        let mixed = meta_with_progs(json!([
+            {"name": "a", "attach": "usdt", "link": true, "binary_path": "/bin/a", "provider": "p", "probe": "x", "pid": 10},
+            {"name": "c", "attach": "tp/sched/sched_process_exec", "link": true},
+        ]));
+        assert!(kernel_debug_pid_filter(&mixed, &[]).is_empty());
+        assert_eq!(kernel_debug_pid_filter(&mixed, &[30]), vec![30, 10]);
+    }
+}
@@ -0,0 +1,59 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{
+    fs::File,
+    os::fd::{AsRawFd, OwnedFd},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libbpf_rs::{libbpf_sys::bpf_prog_attach, Program};
+use log::debug;
+
+use crate::meta::{CgroupProgExtraMeta, ProgMeta};
+
+use super::AttachLink;
+
+pub(crate) fn attach_cgroup(program: &mut Program, meta: &ProgMeta) -> Result<AttachLink> {
+    let cgroup_extra_meta = serde_json::from_value::<CgroupProgExtraMeta>(meta.others.clone())
+        .with_context(|| anyhow!("Failed to deserialize cgroup extra meta"))?;
+    let cgroup = File::open(&cgroup_extra_meta.cgroup_path)
+        .with_context(|| anyhow!("Failed to open cgroup {}", cgroup_extra_meta.cgroup_path))?;
+    debug!(
+        "Attaching `{}` to cgroup {}, flags {}",
+        meta.name, cgroup_extra_meta.cgroup_path, cgroup_extra_meta.attach_flags
+    );
+    if cgroup_extra_meta.attach_flags == 0 {
+        // The link holds the cgroup by itself
+        let link = program
+            .attach_cgroup(cgroup.as_raw_fd())
+            .with_context(|| anyhow!("Failed to create cgroup link"))?;
+        return Ok(AttachLink::BpfLink(link));
+    }
+    let attach_type = program.attach_type() as u32;
+    let prog_fd = program.fd();
+    // SAFETY: both fds are valid during the call
+    let err = unsafe {
+        bpf_prog_attach(
+            prog_fd,
+            cgroup.as_raw_fd(),
+            attach_type,
+            cgroup_extra_meta.attach_flags,
+        )
+    };
+    if err < 0 {
+        bail!(
+            "Failed to attach to cgroup {}: {}",
+            cgroup_extra_meta.cgroup_path,
+            errno::errno()
+        );
+    }
+    Ok(AttachLink::ProgAttach(
+        OwnedFd::from(cgroup),
+        prog_fd,
+        attach_type,
+    ))
+}
@@ -4,20 +4,32 @@
 //! All rights reserved.
 //!
 
-use std::os::fd::{self, FromRawFd};
+use std::os::fd::{self, AsRawFd, FromRawFd, OwnedFd};
 
 use libbpf_rs::{
-    libbpf_sys::{bpf_tc_hook, bpf_tc_hook_destroy, bpf_xdp_attach_opts, bpf_xdp_detach},
+    libbpf_sys::{
+        bpf_prog_detach2, bpf_tc_hook, bpf_tc_hook_destroy, bpf_xdp_attach_opts, bpf_xdp_detach,
+    },
     Link,
 };
 use log::{debug, error};
 
+pub(crate) mod cgroup;
+pub(crate) mod multi_probe;
 pub(crate) mod perf;
+pub(crate) mod socket;
+pub(crate) mod target;
 pub(crate) mod tc;
+pub(crate) mod usdt;
 pub(crate) mod xdp;
 
+pub(crate) use cgroup::attach_cgroup;
+pub(crate) use multi_probe::attach_multi_probe;
 pub(crate) use perf::attach_perf_event;
+pub(crate) use socket::{attach_netns, attach_sockmap};
+pub(crate) use target::attach_target;
 pub(crate) use tc::attach_tc;
+pub(crate) use usdt::attach_usdt;
 pub(crate) use xdp::attach_xdp;
 
 pub(crate) enum AttachLink {
@@ -25,12 +37,43 @@ pub(crate) enum AttachLink {
     TCAttach(Box<bpf_tc_hook>),
     XDPAttach(i32, u32, Box<bpf_xdp_attach_opts>),
     PerfEventAttachWithFd(Link, i32),
+    /// A program attached with `bpf_prog_attach`: the target (a cgroup or a sockmap), the program fd and the attach type
+    ProgAttach(OwnedFd, i32, u32),
+    /// A kprobe.multi link, created without libbpf
+    MultiLink(OwnedFd),
+    /// A registered struct_ops map. Dropping the link unregisters it
+    StructOps(
+        // Never read, it's only held to keep the struct_ops map registered
+        #[allow(dead_code)] Link,
+    ),
+    /// A link of the previous skeleton, updated to the program of this one
+    HandedOver(OwnedFd),
+}
+
+impl AttachLink {
+    /// The fd of the bpf link, if the attachment is made through one
+    pub(crate) fn link_fd(&self) -> Option<i32> {
+        match self {
+            AttachLink::BpfLink(link) | AttachLink::PerfEventAttachWithFd(link, _) => {
+                Some(link.fd())
+            }
+            AttachLink::HandedOver(fd) | AttachLink::MultiLink(fd) => Some(fd.as_raw_fd()),
+            // Not a real bpf link for maps without BPF_F_LINK, it can't be handed over
+            AttachLink::TCAttach(_)
+            | AttachLink::XDPAttach(..)
+            | AttachLink::ProgAttach(..)
+            | AttachLink::StructOps(_) => None,
+        }
+    }
 }
 
 impl Drop for AttachLink {
     fn drop(&mut self) {
         match self {
-            AttachLink::BpfLink(_) => {}
+            AttachLink::BpfLink(_)
+            | AttachLink::StructOps(_)
+            | AttachLink::HandedOver(_)
+            | AttachLink::MultiLink(_) => {}
             AttachLink::TCAttach(hook) => {
                 let err = unsafe { bpf_tc_hook_destroy(&mut **hook) };
                 if err != 0 {
@@ -43,6 +86,12 @@ impl Drop for AttachLink {
                     error!("Failed to detach xdp: \n{:?}", err);
                 }
             }
+            AttachLink::ProgAttach(target, prog_fd, attach_type) => {
+                let err = unsafe { bpf_prog_detach2(*prog_fd, target.as_raw_fd(), *attach_type) };
+                if err != 0 {
+                    error!("Failed to detach program: \n{:?}", err);
+                }
+            }
             AttachLink::PerfEventAttachWithFd(_, fd) => {
                 debug!("Closing pefd {}", fd);
                 // SAFETY: fds are created by us, they are gurateended to be correct
@@ -0,0 +1,387 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    ffi::CString,
+    os::fd::{FromRawFd, OwnedFd},
+    path::Path,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libbpf_rs::{
+    libbpf_sys::{
+        bpf_link_create, bpf_link_create_opts, bpf_object, bpf_object__find_program_by_name,
+        bpf_program__set_expected_attach_type, BPF_F_KPROBE_MULTI_RETURN, BPF_TRACE_KPROBE_MULTI,
+    },
+    Program,
+};
+use libc::PT_LOAD;
+use log::{debug, info, warn};
+use object::{ElfFile, Object, SymbolKind};
+
+use crate::{
+    meta::{EunomiaObjectMeta, MultiProbeProgExtraMeta, ProgMeta},
+    probe::{FeatureProber, KernelFeature},
+};
+
+use super::AttachLink;
+
+const KALLSYMS_PATH: &str = "/proc/kallsyms";
+/// Functions that could be traced. Not every function in kallsyms could be, and a single one fails a whole kprobe.multi link
+const AVAILABLE_FILTER_FUNCTIONS_PATHS: [&str; 2] = [
+    "/sys/kernel/tracing/available_filter_functions",
+    "/sys/kernel/debug/tracing/available_filter_functions",
+];
+
+/// Match `name` against a glob pattern, supporting `*` and `?`
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let name = name.as_bytes();
+    let (mut p, mut n) = (0, 0);
+    // Where to retry if the characters after the last `*` don't match
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == b'?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|v| glob_match(v, name))
+}
+
+/// Kernel functions matching the patterns, resolved against kallsyms
+pub(crate) fn resolve_kernel_functions(patterns: &[String]) -> Result<Vec<String>> {
+    let kallsyms = std::fs::read_to_string(KALLSYMS_PATH)
+        .with_context(|| anyhow!("Failed to read {}", KALLSYMS_PATH))?;
+    let traceable = AVAILABLE_FILTER_FUNCTIONS_PATHS
+        .into_iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .map(|v| {
+            v.lines()
+                .filter_map(|line| line.split_whitespace().next().map(|v| v.to_string()))
+                .collect::<HashSet<_>>()
+        });
+    if traceable.is_none() {
+        debug!("available_filter_functions is not readable, all functions in kallsyms are used");
+    }
+    let functions = kallsyms
+        .lines()
+        .filter_map(|line| {
+            // Lines look like `ffffffff81000000 T _stext`, with a `[module]` at the end for modules
+            let mut fields = line.split_whitespace();
+            let (_, ty, name) = (fields.next()?, fields.next()?, fields.next()?);
+            matches!(ty, "t" | "T").then_some(name)
+        })
+        .filter(|name| matches_any(patterns, name))
+        .filter(|name| traceable.as_ref().map_or(true, |v| v.contains(*name)))
+        .map(|v| v.to_string())
+        .collect::<BTreeSet<_>>();
+    Ok(functions.into_iter().collect())
+}
+
+/// Functions in the ELF matching the patterns, with their offsets in the file
+pub(crate) fn resolve_elf_functions(
+    binary_path: &Path,
+    patterns: &[String],
+) -> Result<BTreeMap<String, usize>> {
+    let data = std::fs::read(binary_path)
+        .with_context(|| anyhow!("Failed to read {}", binary_path.display()))?;
+    let file = ElfFile::parse(&data)
+        .map_err(|e| anyhow!("Failed to parse {}: {}", binary_path.display(), e))?;
+    // (virtual address, size in the file, offset in the file) of loadable segments
+    let segments = file
+        .elf()
+        .program_headers
+        .iter()
+        .filter(|v| v.p_type == PT_LOAD)
+        .map(|v| (v.p_vaddr, v.p_filesz, v.p_offset))
+        .collect::<Vec<_>>();
+    let mut functions = BTreeMap::new();
+    for symbol in file.symbols().chain(file.dynamic_symbols()) {
+        if symbol.kind() != SymbolKind::Text || symbol.is_undefined() || symbol.address() == 0 {
+            continue;
+        }
+        let Some(name) = symbol.name() else {
+            continue;
+        };
+        if functions.contains_key(name) || !matches_any(patterns, name) {
+            continue;
+        }
+        let address = symbol.address();
+        let Some(offset) = segments
+            .iter()
+            .find(|(start, size, _)| (*start..*start + *size).contains(&address))
+            .map(|(start, _, offset)| address - start + offset)
+        else {
+            debug!("Function `{}` is not in any loadable segment", name);
+            continue;
+        };
+        functions.insert(name.to_string(), offset as usize);
+    }
+    Ok(functions)
+}
+
+/// Kprobes attached by patterns are loaded as kprobe.multi programs, if the running kernel supports it. It must be decided before loading
+///
+/// Returns names of the programs that will use kprobe.multi links
+///
+/// # Safety
+/// `object` must be a valid opened bpf object
+pub(crate) unsafe fn prefer_kprobe_multi(
+    object: *mut bpf_object,
+    meta: &EunomiaObjectMeta,
+    prober: &mut FeatureProber,
+) -> Result<HashSet<String>> {
+    let mut progs = HashSet::new();
+    for prog_meta in meta.bpf_skel.progs.iter() {
+        if !prog_meta.is_multi_probe()
+            || !prog_meta.attach.starts_with('k')
+            || prog_meta.attach_target.is_some()
+        {
+            continue;
+        }
+        if !prober.is_supported(&KernelFeature::KprobeMultiLink) {
+            info!(
+                "kprobe.multi links are not supported by the running kernel, program `{}` will be attached to each function",
+                prog_meta.name
+            );
+            continue;
+        }
+        let name = CString::new(prog_meta.name.as_str())?;
+        let prog = bpf_object__find_program_by_name(object, name.as_ptr());
+        if prog.is_null() {
+            bail!("Program named `{}` not found in libbpf", prog_meta.name);
+        }
+        let err = bpf_program__set_expected_attach_type(prog, BPF_TRACE_KPROBE_MULTI);
+        if err < 0 {
+            bail!(
+                "Failed to set attach type of program `{}`: errno={}",
+                prog_meta.name,
+                -err
+            );
+        }
+        progs.insert(prog_meta.name.clone());
+    }
+    Ok(progs)
+}
+
+/// Attach a kprobe or uprobe to every function matching the patterns in its meta
+/// kprobe_multi - Whether the program was loaded as a kprobe.multi program
+pub(crate) fn attach_multi_probe(
+    program: &mut Program,
+    meta: &ProgMeta,
+    kprobe_multi: bool,
+) -> Result<Vec<AttachLink>> {
+    let extra_meta = serde_json::from_value::<MultiProbeProgExtraMeta>(meta.others.clone())
+        .with_context(|| anyhow!("Failed to deserialize multi probe extra meta"))?;
+    let retprobe = meta.attach.ends_with("retprobe");
+    let links = if meta.attach.starts_with('k') {
+        let functions = resolve_kernel_functions(&extra_meta.functions)?;
+        if functions.is_empty() {
+            bail!("No kernel function matches {:?}", extra_meta.functions);
+        }
+        debug!(
+            "Attaching `{}` to {} kernel functions",
+            meta.name,
+            functions.len()
+        );
+        if kprobe_multi {
+            vec![attach_kprobe_multi(program, &functions, retprobe)?]
+        } else {
+            attach_each(&functions, |function| {
+                program.attach_kprobe(retprobe, function)
+            })?
+        }
+    } else {
+        let binary_path = extra_meta
+            .binary_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("`binary_path` is required for uprobes"))?;
+        let functions = resolve_elf_functions(Path::new(binary_path), &extra_meta.functions)?;
+        if functions.is_empty() {
+            bail!(
+                "No function in {} matches {:?}",
+                binary_path,
+                extra_meta.functions
+            );
+        }
+        debug!(
+            "Attaching `{}` to {} functions in {}",
+            meta.name,
+            functions.len(),
+            binary_path
+        );
+        // uprobe.multi links need a newer libbpf, so uprobes are always attached one by one
+        attach_each(functions.keys(), |function| {
+            program.attach_uprobe(retprobe, extra_meta.pid, binary_path, functions[function])
+        })?
+    };
+    Ok(links)
+}
+
+/// Attach to the functions one by one. Functions that can't be probed are skipped
+fn attach_each<'a>(
+    functions: impl IntoIterator<Item = &'a String>,
+    mut attach: impl FnMut(&str) -> libbpf_rs::Result<libbpf_rs::Link>,
+) -> Result<Vec<AttachLink>> {
+    let mut links = vec![];
+    for function in functions {
+        match attach(function) {
+            Ok(link) => links.push(AttachLink::BpfLink(link)),
+            Err(e) => warn!("Failed to attach to function `{}`: {}", function, e),
+        }
+    }
+    if links.is_empty() {
+        bail!("Failed to attach to any function");
+    }
+    Ok(links)
+}
+
+fn attach_kprobe_multi(
+    program: &Program,
+    functions: &[String],
+    retprobe: bool,
+) -> Result<AttachLink> {
+    let names = functions
+        .iter()
+        .map(|v| CString::new(v.as_str()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut syms = names.iter().map(|v| v.as_ptr()).collect::<Vec<_>>();
+    let mut opts = bpf_link_create_opts {
+        sz: std::mem::size_of::<bpf_link_create_opts>() as _,
+        ..Default::default()
+    };
+    opts.__bindgen_anon_1.kprobe_multi.flags = if retprobe {
+        BPF_F_KPROBE_MULTI_RETURN
+    } else {
+        0
+    };
+    opts.__bindgen_anon_1.kprobe_multi.cnt = syms.len() as u32;
+    opts.__bindgen_anon_1.kprobe_multi.syms = syms.as_mut_ptr();
+    // SAFETY: The fd is valid, and pointers in opts live during the call
+    let fd = unsafe { bpf_link_create(program.fd(), 0, BPF_TRACE_KPROBE_MULTI, &opts) };
+    if fd < 0 {
+        bail!("Failed to create kprobe.multi link: errno={}", -fd);
+    }
+    // SAFETY: fd was just created
+    Ok(AttachLink::MultiLink(unsafe { OwnedFd::from_raw_fd(fd) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{glob_match, resolve_elf_functions};
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("tcp_*", "tcp_sendmsg"));
+        assert!(glob_match("tcp_*", "tcp_"));
+        assert!(!glob_match("tcp_*", "udp_sendmsg"));
+        assert!(glob_match("*_sendmsg", "tcp_sendmsg"));
+        assert!(glob_match("*send*", "inet_sendmsg"));
+        assert!(glob_match("tcp_?endmsg", "tcp_sendmsg"));
+        assert!(!glob_match("tcp_?endmsg", "tcp_endmsg"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+        assert!(glob_match("do_nanosleep", "do_nanosleep"));
+        assert!(!glob_match("do_nanosleep", "do_nanosleep2"));
+    }
+    #[test]
+    fn test_resolve_elf_functions() {
+        let exe = std::fs::read_link("/proc/self/exe").unwrap();
+        let functions = resolve_elf_functions(&exe, &["main".into()]).unwrap();
+        let offset = functions["main"];
+        assert!(offset > 0 && (offset as u64) < std::fs::metadata(&exe).unwrap().len());
+        assert!(
+            resolve_elf_functions(Path::new(&exe), &["no_such_function*".into()])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[no_mangle]
+    #[inline(never)]
+    extern "C" fn bpf_loader_glob_test_1() -> i32 {
+        std::hint::black_box(1)
+    }
+    #[no_mangle]
+    #[inline(never)]
+    extern "C" fn bpf_loader_glob_test_2() -> i32 {
+        std::hint::black_box(2)
+    }
+
+    /// Offset in the file of an address in the mapped executable, according to `/proc/self/maps`
+    fn offset_in_exe(address: u64, exe: &Path) -> u64 {
+        std::fs::read_to_string("/proc/self/maps")
+            .unwrap()
+            .lines()
+            .find_map(|line| {
+                // Lines look like `55d0c0a00000-55d0c0a21000 r-xp 00002000 fd:01 1234 /path/to/exe`
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                let (start, end) = fields[0].split_once('-')?;
+                let (start, end) = (
+                    u64::from_str_radix(start, 16).ok()?,
+                    u64::from_str_radix(end, 16).ok()?,
+                );
+                let offset = u64::from_str_radix(fields[2], 16).ok()?;
+                (fields.get(5).map(Path::new) == Some(exe) && (start..end).contains(&address))
+                    .then_some(address - start + offset)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_elf_functions_by_glob() {
+        let exe = std::fs::read_link("/proc/self/exe").unwrap();
+        let functions = resolve_elf_functions(
+            &exe,
+            &["bpf_loader_glob_test_?".into(), "no_such_function*".into()],
+        )
+        .unwrap();
+        assert_eq!(
+            functions.keys().collect::<Vec<_>>(),
+            ["bpf_loader_glob_test_1", "bpf_loader_glob_test_2"]
+        );
+        let functions_with_addresses = [
+            (
+                "bpf_loader_glob_test_1",
+                bpf_loader_glob_test_1 as *const () as u64,
+            ),
+            (
+                "bpf_loader_glob_test_2",
+                bpf_loader_glob_test_2 as *const () as u64,
+            ),
+        ];
+        for (name, address) in functions_with_addresses {
+            assert_eq!(
+                functions[name] as u64,
+                offset_in_exe(address, &exe),
+                "Wrong offset of `{}`",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{
+    fs::File,
+    os::fd::{AsRawFd, BorrowedFd},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libbpf_rs::{libbpf_sys::bpf_prog_attach, MapType, Object, Program};
+use log::debug;
+
+use crate::meta::{NetnsProgExtraMeta, ProgMeta, SockmapProgExtraMeta};
+
+use super::AttachLink;
+
+/// Attach a `sk_msg` or `sk_skb` program to the sockmap named in its meta
+pub(crate) fn attach_sockmap(object: &Object, meta: &ProgMeta) -> Result<AttachLink> {
+    let sockmap_extra_meta = serde_json::from_value::<SockmapProgExtraMeta>(meta.others.clone())
+        .with_context(|| anyhow!("Failed to deserialize sockmap extra meta"))?;
+    let program = object
+        .prog(&meta.name)
+        .ok_or_else(|| anyhow!("Program named `{}` not found", meta.name))?;
+    let map = object
+        .map(&sockmap_extra_meta.sockmap)
+        .ok_or_else(|| anyhow!("Map named `{}` not found", sockmap_extra_meta.sockmap))?;
+    if !matches!(map.map_type(), MapType::Sockmap | MapType::Sockhash) {
+        bail!(
+            "Map `{}` is a {:?}, not a sockmap or sockhash",
+            sockmap_extra_meta.sockmap,
+            map.map_type()
+        );
+    }
+    debug!(
+        "Attaching `{}` to map `{}`",
+        meta.name, sockmap_extra_meta.sockmap
+    );
+    let attach_type = program.attach_type() as u32;
+    let prog_fd = program.fd();
+    // SAFETY: both fds are valid during the call
+    let err = unsafe { bpf_prog_attach(prog_fd, map.fd(), attach_type, 0) };
+    if err < 0 {
+        bail!(
+            "Failed to attach to map `{}`: {}",
+            sockmap_extra_meta.sockmap,
+            errno::errno()
+        );
+    }
+    // SAFETY: the map fd is valid while the object lives
+    let map_fd = unsafe { BorrowedFd::borrow_raw(map.fd()) }
+        .try_clone_to_owned()
+        .with_context(|| anyhow!("Failed to duplicate fd of map"))?;
+    Ok(AttachLink::ProgAttach(map_fd, prog_fd, attach_type))
+}
+
+/// Attach a `sk_lookup` or `flow_dissector` program to the network namespace in its meta
+pub(crate) fn attach_netns(program: &mut Program, meta: &ProgMeta) -> Result<AttachLink> {
+    let netns_extra_meta = serde_json::from_value::<NetnsProgExtraMeta>(meta.others.clone())
+        .with_context(|| anyhow!("Failed to deserialize netns extra meta"))?;
+    let netns = File::open(&netns_extra_meta.netns_path).with_context(|| {
+        anyhow!(
+            "Failed to open network namespace {}",
+            netns_extra_meta.netns_path
+        )
+    })?;
+    debug!(
+        "Attaching `{}` to network namespace {}",
+        meta.name, netns_extra_meta.netns_path
+    );
+    let link = program
+        .attach_netns(netns.as_raw_fd())
+        .with_context(|| anyhow!("Failed to create netns link"))?;
+    Ok(AttachLink::BpfLink(link))
+}
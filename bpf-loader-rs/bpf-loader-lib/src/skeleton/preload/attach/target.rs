@@ -0,0 +1,85 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{ffi::CString, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use libbpf_rs::{
+    libbpf_sys::{bpf_object, bpf_object__find_program_by_name, bpf_program__set_attach_target},
+    Program,
+};
+use log::debug;
+
+use crate::meta::{AttachTarget, EunomiaObjectMeta};
+
+use super::{multi_probe::resolve_elf_functions, AttachLink};
+
+/// Set the functions of BTF-enabled programs retargeted with `attach_target`. They are resolved when the object is loaded
+/// # Safety
+/// `object` must be a valid opened bpf object which is not loaded
+pub(crate) unsafe fn set_btf_attach_targets(
+    object: *mut bpf_object,
+    meta: &EunomiaObjectMeta,
+) -> Result<()> {
+    for prog_meta in meta.bpf_skel.progs.iter() {
+        let Some(AttachTarget::Btf { function, .. }) = prog_meta.resolve_attach_target()? else {
+            continue;
+        };
+        let name = CString::new(prog_meta.name.as_str())?;
+        let prog = bpf_object__find_program_by_name(object, name.as_ptr());
+        if prog.is_null() {
+            bail!("Program named `{}` not found in libbpf", prog_meta.name);
+        }
+        let function_name = CString::new(function.as_str())?;
+        let err = bpf_program__set_attach_target(prog, 0, function_name.as_ptr());
+        if err < 0 {
+            bail!(
+                "Failed to set attach target of program `{}` to `{}`: errno={}",
+                prog_meta.name,
+                function,
+                -err
+            );
+        }
+        debug!("Retargeted program `{}` to `{}`", prog_meta.name, function);
+    }
+    Ok(())
+}
+
+/// Attach a program to the target given in its meta, instead of the one in its section
+pub(crate) fn attach_target(program: &mut Program, target: &AttachTarget) -> Result<AttachLink> {
+    debug!("Attaching `{}` to {:?}", program.name(), target);
+    let link = match target {
+        AttachTarget::Kprobe { function, retprobe } => {
+            program
+                .attach_kprobe(*retprobe, function)
+                .with_context(|| anyhow!("Failed to attach to kernel function `{}`", function))?
+        }
+        AttachTarget::Uprobe {
+            binary_path,
+            function,
+            retprobe,
+            pid,
+        } => {
+            let functions =
+                resolve_elf_functions(Path::new(binary_path), std::slice::from_ref(function))?;
+            let offset = functions
+                .get(function)
+                .ok_or_else(|| anyhow!("Function `{}` not found in {}", function, binary_path))?;
+            program
+                .attach_uprobe(*retprobe, *pid, binary_path, *offset)
+                .with_context(|| anyhow!("Failed to attach to `{}` in {}", function, binary_path))?
+        }
+        AttachTarget::Tracepoint { category, name } => program
+            .attach_tracepoint(category, name)
+            .with_context(|| anyhow!("Failed to attach to tracepoint {}/{}", category, name))?,
+        AttachTarget::RawTracepoint(name) => program
+            .attach_raw_tracepoint(name)
+            .with_context(|| anyhow!("Failed to attach to raw tracepoint {}", name))?,
+        // The function was set before loading, libbpf attaches to it
+        AttachTarget::Btf { .. } => program.attach()?,
+    };
+    Ok(AttachLink::BpfLink(link))
+}
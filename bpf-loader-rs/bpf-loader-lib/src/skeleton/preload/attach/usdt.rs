@@ -0,0 +1,48 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use anyhow::{anyhow, Context, Result};
+use libbpf_rs::{Program, UsdtOpts};
+use log::debug;
+
+use crate::meta::{ProgMeta, UsdtProgExtraMeta};
+
+use super::AttachLink;
+
+/// Attach a USDT program to every site of the probe in its meta.
+/// libbpf parses `.note.stapsdt` of the binary, and fills the spec maps `usdt.bpf.h` reads arguments from
+pub(crate) fn attach_usdt(program: &mut Program, meta: &ProgMeta) -> Result<AttachLink> {
+    let usdt_extra_meta = serde_json::from_value::<UsdtProgExtraMeta>(meta.others.clone())
+        .with_context(|| anyhow!("Failed to deserialize usdt extra meta"))?;
+    debug!(
+        "Attaching `{}` to {}:{}:{}, pid {}",
+        meta.name,
+        usdt_extra_meta.binary_path,
+        usdt_extra_meta.provider,
+        usdt_extra_meta.probe,
+        usdt_extra_meta.pid
+    );
+    let link = program
+        .attach_usdt_with_opts(
+            usdt_extra_meta.pid,
+            &usdt_extra_meta.binary_path,
+            &usdt_extra_meta.provider,
+            &usdt_extra_meta.probe,
+            UsdtOpts {
+                cookie: usdt_extra_meta.cookie,
+                ..Default::default()
+            },
+        )
+        .with_context(|| {
+            anyhow!(
+                "Failed to attach to probe {}:{} in {}",
+                usdt_extra_meta.provider,
+                usdt_extra_meta.probe,
+                usdt_extra_meta.binary_path
+            )
+        })?;
+    Ok(AttachLink::BpfLink(link))
+}
@@ -0,0 +1,133 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Context, Result};
+use libbpf_rs::{MapType, OpenObject};
+use log::info;
+
+use crate::{
+    meta::{EunomiaObjectMeta, MapFallback, ProgMeta},
+    probe::{FeatureProber, KernelFeature},
+};
+
+/// Check the features that maps and programs need, and apply the fallbacks declared in the package if some are missing
+///
+/// Returns names of programs that won't be loaded, either replaced by their fallbacks, or being unused fallbacks
+pub(crate) fn apply_fallbacks(
+    meta: &EunomiaObjectMeta,
+    object: &mut OpenObject,
+    prober: &mut FeatureProber,
+) -> Result<HashSet<String>> {
+    for map_meta in meta.bpf_skel.maps.iter() {
+        let map = object
+            .map_mut(&map_meta.name)
+            .ok_or_else(|| anyhow!("Map named `{}` not found in libbpf", map_meta.name))?;
+        if map.map_type() != MapType::RingBuf || prober.is_supported(&KernelFeature::Ringbuf) {
+            continue;
+        }
+        match map_meta.fallback {
+            Some(MapFallback::PerfEventArray) => {
+                info!(
+                    "Ringbuf is not supported by the running kernel, map `{}` falls back to perf event array",
+                    map_meta.name
+                );
+                // Perf event arrays are keyed by cpu, and max_entries of 0 will be set to the count of cpus by libbpf
+                map.set_type(MapType::PerfEventArray)
+                    .and_then(|_| map.set_key_size(4))
+                    .and_then(|_| map.set_value_size(4))
+                    .and_then(|_| map.set_max_entries(0))
+                    .with_context(|| {
+                        anyhow!("Failed to turn map `{}` into perf event array", map_meta.name)
+                    })?;
+            }
+            None => bail!(
+                "Map `{}` is a ringbuf, which is not supported by the running kernel. Declare `\"fallback\": \"perf_event_array\"` for it to run on older kernels",
+                map_meta.name
+            ),
+        }
+    }
+
+    let progs = &meta.bpf_skel.progs;
+    let find_prog = |name: &str| {
+        progs
+            .iter()
+            .find(|v| v.name == name)
+            .ok_or_else(|| anyhow!("Fallback program `{}` is not in the package", name))
+    };
+    let fallbacks = progs
+        .iter()
+        .filter_map(|v| v.fallback.as_deref())
+        .collect::<HashSet<_>>();
+    let mut disabled = HashSet::new();
+    for prog_meta in progs
+        .iter()
+        .filter(|v| !fallbacks.contains(v.name.as_str()))
+    {
+        let mut current = prog_meta;
+        // Walk along the chain of fallbacks, until one could be loaded
+        for _ in 0..=progs.len() {
+            let missing = missing_features(object, current, prober)?;
+            if missing.is_empty() {
+                break;
+            }
+            let missing = missing
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            disabled.insert(current.name.clone());
+            let Some(fallback) = current.fallback.as_deref() else {
+                bail!(
+                    "Program `{}` needs {}, which is not supported by the running kernel",
+                    current.name,
+                    missing
+                );
+            };
+            info!(
+                "Program `{}` needs {}, which is not supported by the running kernel. Using `{}` instead",
+                current.name, missing, fallback
+            );
+            current = find_prog(fallback)?;
+        }
+        if disabled.contains(&current.name) {
+            bail!("Fallbacks of program `{}` form a cycle", prog_meta.name);
+        }
+        // The rest of the chain is unused
+        let mut next = current.fallback.as_deref();
+        while let Some(name) = next {
+            if !disabled.insert(name.to_string()) {
+                break;
+            }
+            next = find_prog(name)?.fallback.as_deref();
+        }
+    }
+    for name in disabled.iter() {
+        object
+            .prog_mut(name)
+            .ok_or_else(|| anyhow!("Program named `{}` not found in libbpf", name))?
+            .set_autoload(false)
+            .with_context(|| anyhow!("Failed to disable program `{}`", name))?;
+    }
+    Ok(disabled)
+}
+
+fn missing_features(
+    object: &OpenObject,
+    prog_meta: &ProgMeta,
+    prober: &mut FeatureProber,
+) -> Result<Vec<KernelFeature>> {
+    let prog = object
+        .prog(&prog_meta.name)
+        .ok_or_else(|| anyhow!("Program named `{}` not found in libbpf", prog_meta.name))?;
+    let prog_type = prog.prog_type() as u32;
+    Ok(KernelFeature::required_by_section(prog.section())
+        .into_iter()
+        .chain(prog_meta.requires.iter().cloned())
+        .filter(|v| !prober.is_supported_by(v, prog_type))
+        .collect())
+}
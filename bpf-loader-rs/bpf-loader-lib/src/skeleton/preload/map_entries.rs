@@ -0,0 +1,98 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use anyhow::{anyhow, bail, Context, Result};
+use btf::types::Btf;
+use libbpf_rs::{MapFlags, Object};
+use log::debug;
+
+use crate::{
+    helper::json_encoder::encode_json,
+    meta::{EunomiaObjectMeta, MapEntry},
+};
+
+/// Sizes and BTF types of the keys and values of a map
+pub(crate) struct MapEntryLayout {
+    pub(crate) key_size: u32,
+    pub(crate) value_size: u32,
+    pub(crate) btf_key_type_id: u32,
+    pub(crate) btf_value_type_id: u32,
+}
+
+impl MapEntryLayout {
+    /// Encode an entry into bytes of the key and the value
+    pub(crate) fn encode(&self, btf: &Btf, entry: &MapEntry) -> Result<(Vec<u8>, Vec<u8>)> {
+        if self.btf_key_type_id == 0 || self.btf_value_type_id == 0 {
+            bail!("The map doesn't have BTF info of keys and values");
+        }
+        let mut key = vec![0u8; self.key_size as usize];
+        encode_json(btf, self.btf_key_type_id, &entry.key, &mut key)
+            .with_context(|| anyhow!("Failed to encode key {}", entry.key))?;
+        let mut value = vec![0u8; self.value_size as usize];
+        if let Some(v) = entry.value.as_ref() {
+            encode_json(btf, self.btf_value_type_id, v, &mut value)
+                .with_context(|| anyhow!("Failed to encode value {}", v))?;
+        }
+        Ok((key, value))
+    }
+}
+
+/// Put `initial_entries` of maps into the loaded maps. Maps in `skipped` (e.g maps reused from a running skeleton) are untouched
+pub(crate) fn fill_initial_entries(
+    meta: &EunomiaObjectMeta,
+    btf: &Btf,
+    object: &mut Object,
+    skipped: &[String],
+) -> Result<()> {
+    for map_meta in meta.bpf_skel.maps.iter() {
+        if map_meta.initial_entries.is_empty() {
+            continue;
+        }
+        if skipped.contains(&map_meta.name) {
+            debug!(
+                "Map `{}` is reused, initial entries are not put",
+                map_meta.name
+            );
+            continue;
+        }
+        let map = object
+            .map_mut(&map_meta.name)
+            .ok_or_else(|| anyhow!("Map named `{}` not found in libbpf", map_meta.name))?;
+        if map.map_type().is_percpu() {
+            bail!(
+                "Map `{}` is a per-cpu map, initial entries are not supported",
+                map_meta.name
+            );
+        }
+        let info = map
+            .info()
+            .with_context(|| anyhow!("Failed to get map info for `{}`", map_meta.name))?;
+        let layout = MapEntryLayout {
+            key_size: map.key_size(),
+            value_size: map.value_size(),
+            btf_key_type_id: info.info.btf_key_type_id,
+            btf_value_type_id: info.info.btf_value_type_id,
+        };
+        for entry in map_meta.initial_entries.iter() {
+            let (key, value) = layout
+                .encode(btf, entry)
+                .with_context(|| anyhow!("Invalid initial entry of map `{}`", map_meta.name))?;
+            map.update(&key, &value, MapFlags::ANY).with_context(|| {
+                anyhow!(
+                    "Failed to put key {} into map `{}`",
+                    entry.key,
+                    map_meta.name
+                )
+            })?;
+        }
+        debug!(
+            "Put {} initial entries into map `{}`",
+            map_meta.initial_entries.len(),
+            map_meta.name
+        );
+    }
+    Ok(())
+}
@@ -4,25 +4,45 @@
 //! All rights reserved.
 //!
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{
     btf_container::BtfContainer,
     elf_container::ElfContainer,
     meta::{EunomiaObjectMeta, RunnerConfig},
+    probe::FeatureProber,
     skeleton::preload::{
-        attach::{attach_perf_event, attach_tc, attach_xdp, AttachLink},
+        attach::{
+            attach_cgroup, attach_multi_probe, attach_netns, attach_perf_event, attach_sockmap,
+            attach_target, attach_tc, attach_usdt, attach_xdp, AttachLink,
+        },
+        fallback::apply_fallbacks,
+        map_entries::fill_initial_entries,
         section_loader::load_section_data_with_skel_value,
+        tail_calls::{fill_tail_calls, tail_call_targets},
     },
 };
 use anyhow::{anyhow, bail, Context, Result};
-use libbpf_rs::OpenObject;
+use libbpf_rs::{MapType, OpenObject, ProgramType};
 use log::debug;
 use object::{Object, ObjectSection};
 
-use super::{handle::PollingHandle, BpfSkeleton};
+use super::{
+    handle::PollingHandle,
+    handover::SkeletonHandover,
+    map_in_map::{fill_initial_inner_maps, set_inner_map_templates, NestedMap},
+    stats::enable_run_time_stats,
+    verifier::VerifierLogBuffers,
+    BpfSkeleton,
+};
 pub(crate) mod attach;
+pub(crate) mod fallback;
+pub(crate) mod map_entries;
 pub(crate) mod section_loader;
+pub(crate) mod tail_calls;
 /// Represents an initialized bpf skeleton. It's waiting for the loading and attaching of bpf programs
 pub struct PreLoadBpfSkeleton {
     ///   data storage
@@ -43,7 +63,19 @@ pub struct PreLoadBpfSkeleton {
     // This is a workaround for libbpf-rs not exposing bpf_map* in OpenMap
     pub(crate) map_value_sizes: HashMap<String, u32>,
 
+    // Map-in-maps, recorded before libbpf drops their BTF info
+    pub(crate) nested_maps: HashMap<String, NestedMap>,
+
+    // Kprobes attached by patterns that were set to use kprobe.multi links
+    pub(crate) kprobe_multi_progs: HashSet<String>,
+
     pub(crate) raw_elf: ElfContainer,
+
+    // Buffers that libbpf writes verifier logs to. They must live until the object is loaded
+    pub(crate) verifier_logs: VerifierLogBuffers,
+
+    // Maps are reused when building, only links and names of the reused maps are left here
+    pub(crate) handover: Option<SkeletonHandover>,
 }
 
 impl PreLoadBpfSkeleton {
@@ -120,19 +152,110 @@ impl PreLoadBpfSkeleton {
                 .map_err(|e| anyhow!("Failed to set initial value of map `{}`: {}", map_name, e))?;
         }
 
-        let mut bpf_object = self
-            .bpf_object
-            .load()
-            .with_context(|| anyhow!("Failed to load bpf object"))?;
+        let disabled_progs =
+            apply_fallbacks(&self.meta, &mut self.bpf_object, &mut FeatureProber::new())
+                .with_context(|| anyhow!("The running kernel lacks features the package needs"))?;
+
+        let stats_fd = if self.config_data.enable_stats.unwrap_or(false) {
+            Some(enable_run_time_stats()?)
+        } else {
+            None
+        };
+
+        let inner_map_templates =
+            set_inner_map_templates(&self.meta, &self.nested_maps, &mut self.bpf_object)?;
+        let mut bpf_object = match self.bpf_object.load() {
+            Ok(v) => v,
+            Err(e) => {
+                let err = anyhow::Error::from(e);
+                let err = match self.verifier_logs.find_failure() {
+                    Some(verifier_error) => err.context(verifier_error),
+                    None => err,
+                };
+                return Err(err.context(anyhow!("Failed to load bpf object")));
+            }
+        };
+        // The kernel has copied what it needs from the templates
+        drop(inner_map_templates);
+        for (name, log) in self.verifier_logs.logs() {
+            debug!("Verifier log of program `{}`:\n{}", name, log);
+        }
+        let reused_maps = self
+            .handover
+            .as_ref()
+            .map(|v| v.reused_maps.clone())
+            .unwrap_or_default();
+        fill_initial_entries(
+            &self.meta,
+            self.btf.borrow_btf(),
+            &mut bpf_object,
+            &reused_maps,
+        )
+        .with_context(|| anyhow!("Failed to fill initial entries of maps"))?;
+        fill_initial_inner_maps(
+            &self.meta,
+            &self.nested_maps,
+            self.btf.borrow_btf(),
+            &bpf_object,
+            &reused_maps,
+        )?;
+        fill_tail_calls(&self.meta, &mut bpf_object)
+            .with_context(|| anyhow!("Failed to fill prog arrays for tail calls"))?;
+        let tail_call_targets = tail_call_targets(&self.meta);
         // Next steps are attaching...
         let mut not_attached = vec![];
         let mut links = vec![];
         for prog_meta in self.meta.bpf_skel.progs.iter() {
+            if disabled_progs.contains(&prog_meta.name) {
+                debug!("Skip attaching program `{}`", prog_meta.name);
+                continue;
+            }
+            if tail_call_targets.contains(&prog_meta.name) {
+                debug!(
+                    "Program `{}` is a tail call target, not attaching",
+                    prog_meta.name
+                );
+                continue;
+            }
             let bpf_prog = bpf_object
                 .prog_mut(&prog_meta.name)
                 .ok_or_else(|| anyhow!("Program named `{}` not found in libbpf", prog_meta.name))?;
+            if matches!(bpf_prog.prog_type(), ProgramType::StructOps) {
+                debug!(
+                    "Program `{}` is a struct_ops program, it's registered with its map",
+                    prog_meta.name
+                );
+                continue;
+            }
+            if let Some(link) = self
+                .handover
+                .as_mut()
+                .and_then(|v| v.update_link(&prog_meta.name, bpf_prog.fd()))
+            {
+                links.push((prog_meta.name.clone(), link));
+                continue;
+            }
+            if let Some(target) = prog_meta.resolve_attach_target()? {
+                links.push((
+                    prog_meta.name.clone(),
+                    attach_target(bpf_prog, &target).with_context(|| {
+                        anyhow!("Failed to attach program `{}`", prog_meta.name)
+                    })?,
+                ));
+                continue;
+            }
+            if prog_meta.is_multi_probe() {
+                let probe_links = attach_multi_probe(
+                    bpf_prog,
+                    prog_meta,
+                    self.kprobe_multi_progs.contains(&prog_meta.name),
+                )
+                .with_context(|| anyhow!("Failed to attach program `{}`", prog_meta.name))?;
+                links.extend(probe_links.into_iter().map(|v| (prog_meta.name.clone(), v)));
+                continue;
+            }
             match bpf_prog.attach() {
-                Ok(link) => links.push(AttachLink::BpfLink(link)),
+                Ok(link) => links.push((prog_meta.name.clone(), AttachLink::BpfLink(link))),
                 // EOPNOTSUPP 95 Operation not supported
                 Err(_) if errno::errno().0 == 95 => {
                     // Not supported for auto-attaching, needs manually operations
@@ -143,26 +266,70 @@ impl PreLoadBpfSkeleton {
             };
         }
         for prog_meta in not_attached.into_iter() {
+            // The program and the map are both borrowed from the object
+            if prog_meta.attaches_to_sockmap() {
+                links.push((
+                    prog_meta.name.clone(),
+                    attach_sockmap(&bpf_object, prog_meta).with_context(|| {
+                        anyhow!("Failed to attach sockmap program `{}`", prog_meta.name)
+                    })?,
+                ));
+                continue;
+            }
             let bpf_prog = bpf_object
                 .prog_mut(&prog_meta.name)
                 .ok_or_else(|| anyhow!("Program named `{}` not found", prog_meta.name))?;
             match bpf_prog.section() {
-                "tc" => links.push(attach_tc(bpf_prog, prog_meta).with_context(|| {
-                    anyhow!("Failed to attach tc program `{}`", prog_meta.name)
-                })?),
-                "xdp" => links.push(attach_xdp(bpf_prog, prog_meta).with_context(|| {
-                    anyhow!("Failed to attach xdp program `{}`", prog_meta.name)
-                })?),
+                "tc" => links.push((
+                    prog_meta.name.clone(),
+                    attach_tc(bpf_prog, prog_meta).with_context(|| {
+                        anyhow!("Failed to attach tc program `{}`", prog_meta.name)
+                    })?,
+                )),
+                "xdp" => links.push((
+                    prog_meta.name.clone(),
+                    attach_xdp(bpf_prog, prog_meta).with_context(|| {
+                        anyhow!("Failed to attach xdp program `{}`", prog_meta.name)
+                    })?,
+                )),
                 "perf_event" => {
-                    let mut perf_links =
-                        attach_perf_event(bpf_prog, prog_meta).with_context(|| {
-                            anyhow!("Failed to attach perf event program `{}`", prog_meta.name)
-                        })?;
-                    links.append(&mut perf_links);
+                    let perf_links = attach_perf_event(bpf_prog, prog_meta).with_context(|| {
+                        anyhow!("Failed to attach perf event program `{}`", prog_meta.name)
+                    })?;
+                    links.extend(perf_links.into_iter().map(|v| (prog_meta.name.clone(), v)));
                 }
+                _ if prog_meta.attaches_to_cgroup() => links.push((
+                    prog_meta.name.clone(),
+                    attach_cgroup(bpf_prog, prog_meta).with_context(|| {
+                        anyhow!("Failed to attach cgroup program `{}`", prog_meta.name)
+                    })?,
+                )),
+                "usdt" => links.push((
+                    prog_meta.name.clone(),
+                    attach_usdt(bpf_prog, prog_meta).with_context(|| {
+                        anyhow!("Failed to attach usdt program `{}`", prog_meta.name)
+                    })?,
+                )),
+                _ if prog_meta.attaches_to_netns() => links.push((
+                    prog_meta.name.clone(),
+                    attach_netns(bpf_prog, prog_meta).with_context(|| {
+                        anyhow!("Failed to attach netns program `{}`", prog_meta.name)
+                    })?,
+                )),
                 s => bail!("Unsupported attach type: {}", s),
             }
         }
+        // Register struct_ops maps after the other programs are attached
+        for map in bpf_object.maps_iter() {
+            if map.map_type() != MapType::StructOps {
+                continue;
+            }
+            let link = map
+                .attach_struct_ops()
+                .with_context(|| anyhow!("Failed to register struct_ops map `{}`", map.name()))?;
+            debug!("Registered struct_ops map `{}`", map.name());
+            links.push((map.name().to_string(), AttachLink::StructOps(link)));
+        }
         Ok(BpfSkeleton {
             handle: PollingHandle::new(),
             meta: self.meta,
@@ -170,6 +337,8 @@ impl PreLoadBpfSkeleton {
             btf: Arc::new(self.btf),
             links,
             prog: bpf_object,
+            nested_maps: self.nested_maps,
+            stats_fd,
         })
     }
 }
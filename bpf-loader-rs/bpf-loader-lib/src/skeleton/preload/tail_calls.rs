@@ -0,0 +1,76 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Context, Result};
+use libbpf_rs::{MapFlags, MapType, Object};
+use log::debug;
+
+use crate::meta::EunomiaObjectMeta;
+
+/// Names of programs that are only reached through tail calls. They are not attached
+pub(crate) fn tail_call_targets(meta: &EunomiaObjectMeta) -> HashSet<String> {
+    meta.bpf_skel
+        .maps
+        .iter()
+        .flat_map(|v| v.tail_calls.values().cloned())
+        .collect()
+}
+
+/// Put the programs declared in `tail_calls` of maps into the prog arrays. Must be called after loading and before attaching,
+/// so no program runs with an empty prog array
+pub(crate) fn fill_tail_calls(meta: &EunomiaObjectMeta, object: &mut Object) -> Result<()> {
+    for map_meta in meta.bpf_skel.maps.iter() {
+        if map_meta.tail_calls.is_empty() {
+            continue;
+        }
+        let mut entries = vec![];
+        for (idx, prog_name) in map_meta.tail_calls.iter() {
+            let prog = object.prog(prog_name).ok_or_else(|| {
+                anyhow!(
+                    "Program `{}` for tail call {} of map `{}` not found",
+                    prog_name,
+                    idx,
+                    map_meta.name
+                )
+            })?;
+            // Programs disabled by fallbacks have no fds
+            if prog.fd() < 0 {
+                bail!(
+                    "Program `{}` for tail call {} of map `{}` is not loaded",
+                    prog_name,
+                    idx,
+                    map_meta.name
+                );
+            }
+            entries.push((*idx, prog.fd()));
+        }
+        let map = object
+            .map_mut(&map_meta.name)
+            .ok_or_else(|| anyhow!("Map named `{}` not found in libbpf", map_meta.name))?;
+        if map.map_type() != MapType::ProgArray {
+            bail!(
+                "Map `{}` declares tail calls, but it is a {:?}, not a prog array",
+                map_meta.name,
+                map.map_type()
+            );
+        }
+        for (idx, fd) in entries.into_iter() {
+            debug!("Tail call {} of map `{}`: fd {}", idx, map_meta.name, fd);
+            map.update(&idx.to_ne_bytes(), &fd.to_ne_bytes(), MapFlags::ANY)
+                .with_context(|| {
+                    anyhow!(
+                        "Failed to put program `{}` into map `{}` at {}",
+                        map_meta.tail_calls[&idx],
+                        map_meta.name,
+                        idx
+                    )
+                })?;
+        }
+    }
+    Ok(())
+}
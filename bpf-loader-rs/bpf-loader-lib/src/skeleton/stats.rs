@@ -0,0 +1,96 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use anyhow::{bail, Result};
+use libbpf_rs::libbpf_sys::{
+    bpf_enable_stats, bpf_prog_get_fd_by_id, bpf_prog_get_info_by_fd, bpf_prog_info,
+    BPF_STATS_RUN_TIME,
+};
+use serde::{Deserialize, Serialize};
+
+/// Runtime statistics of a loaded program, read from `bpf_prog_info`
+///
+/// `run_cnt` and `run_time_ns` are only counted while run time stats are enabled, see `RunnerConfig::enable_stats`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgStats {
+    /// Name of the program
+    pub name: String,
+    /// How many times the program has run
+    pub run_cnt: u64,
+    /// Total time spent in the program, in nanoseconds
+    pub run_time_ns: u64,
+    /// How many times the program was skipped, because it would have recursed into itself
+    pub recursion_misses: u64,
+    /// Count of instructions processed by the verifier when loading the program
+    pub verified_insns: u32,
+}
+
+/// A handle to query statistics of the programs from another thread
+///
+/// It only records ids of the programs, so it won't keep them alive. Querying fails after the skeleton is dropped
+#[derive(Clone, Debug)]
+pub struct StatsHandle {
+    pub(crate) progs: Vec<(String, u32)>,
+}
+
+impl StatsHandle {
+    /// Get statistics of all loaded programs
+    pub fn prog_stats(&self) -> Result<Vec<ProgStats>> {
+        self.progs
+            .iter()
+            .map(|(name, id)| {
+                // SAFETY: FFI call without pointers. The result is checked
+                let fd = unsafe { bpf_prog_get_fd_by_id(*id) };
+                if fd < 0 {
+                    bail!(
+                        "Failed to get fd of program `{}` (id {}), errno={}",
+                        name,
+                        id,
+                        -fd
+                    );
+                }
+                // SAFETY: fd was just created, and nothing else owns it
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                query_prog_stats(name, fd.as_raw_fd())
+            })
+            .collect()
+    }
+}
+
+/// Enable `BPF_STATS_RUN_TIME` for the whole system. It stays enabled until the returned fd is closed
+pub(crate) fn enable_run_time_stats() -> Result<OwnedFd> {
+    // SAFETY: FFI call without pointers. The result is checked
+    let fd = unsafe { bpf_enable_stats(BPF_STATS_RUN_TIME) };
+    if fd < 0 {
+        bail!("Failed to enable bpf run time stats, errno={}", -fd);
+    }
+    // SAFETY: fd was just created, and nothing else owns it
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+pub(crate) fn query_prog_info(name: &str, fd: i32) -> Result<bpf_prog_info> {
+    let mut info = bpf_prog_info::default();
+    let mut len = std::mem::size_of::<bpf_prog_info>() as u32;
+    // SAFETY: info and len live during the call, and len is the size of info
+    let err = unsafe { bpf_prog_get_info_by_fd(fd, &mut info, &mut len) };
+    if err < 0 {
+        bail!("Failed to get info of program `{}`, errno={}", name, -err);
+    }
+    Ok(info)
+}
+
+pub(crate) fn query_prog_stats(name: &str, fd: i32) -> Result<ProgStats> {
+    let info = query_prog_info(name, fd)?;
+    Ok(ProgStats {
+        name: name.to_string(),
+        run_cnt: info.run_cnt,
+        run_time_ns: info.run_time_ns,
+        recursion_misses: info.recursion_misses,
+        verified_insns: info.verified_insns,
+    })
+}
@@ -7,6 +7,7 @@
 use std::{
     cell::RefCell,
     io::{BufRead, BufReader},
+    os::fd::{AsRawFd, BorrowedFd},
     rc::Rc,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -17,17 +18,31 @@ use std::{
 };
 
 use anyhow::Result;
+use libbpf_rs::{
+    libbpf_sys::{
+        bpf_prog_query, BPF_CGROUP_INET_EGRESS, BPF_F_ALLOW_MULTI, BPF_SK_LOOKUP,
+        BPF_SK_SKB_STREAM_VERDICT,
+    },
+    MapFlags, MapType,
+};
+use object::{Object, ObjectSection};
 use serde::Deserialize;
 use serde_json::json;
 
 use crate::{
     export_event::{EventHandler, ExportFormatType, ReceivedEventData},
-    meta::ComposedObject,
+    meta::{
+        ComposedObject, EunomiaObjectMeta, MapEntry, MapSampleMeta, RunnerConfig, SampleMapType,
+    },
+    probe::{FeatureProber, KernelFeature},
     skeleton::handle::PollingHandle,
     tests::get_assets_dir,
 };
 
-use super::builder::BpfSkeletonBuilder;
+use super::{
+    builder::BpfSkeletonBuilder, preload::attach::AttachLink, stats::query_prog_info,
+    verifier::VerifierError,
+};
 
 mod multiple_export_type;
 
@@ -130,6 +145,80 @@ fn test_load_rodata_and_bss() {
     assert_eq!(recv_data.val_6, "112233445566");
 }
 
+#[test]
+fn test_access_variables_and_maps() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("simple_prog_3").join("package.json"))
+            .unwrap(),
+    )
+    .unwrap();
+    skel.meta.bpf_skel.data_sections[0]
+        .variables
+        .iter_mut()
+        .filter(|s| s.name == "const_val_1")
+        .for_each(|s| s.value = Some(json!(0x12345678)));
+    let bpf_skel = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    assert_eq!(
+        bpf_skel.get_variable("const_val_1").unwrap(),
+        json!(0x12345678)
+    );
+    assert!(bpf_skel.set_variable("const_val_1", &json!(1)).is_err());
+    bpf_skel
+        .set_variable("bss_val_2", &json!(1u64 << 60))
+        .unwrap();
+    bpf_skel.set_variable("bss_val_3", &json!("abc")).unwrap();
+    assert_eq!(
+        bpf_skel.get_variable("bss_val_2").unwrap(),
+        json!(1u64 << 60)
+    );
+    // The dumper doesn't treat `volatile char[]` as strings
+    assert_eq!(
+        bpf_skel
+            .get_variable("bss_val_3")
+            .unwrap()
+            .as_array()
+            .unwrap()[..4],
+        [json!(97), json!(98), json!(99), json!(0)]
+    );
+    assert!(bpf_skel.get_variable("no_such_variable").is_err());
+
+    let progs = bpf_skel.list_programs();
+    assert_eq!(progs.len(), 1);
+    assert_eq!(progs[0].name, "handle_exec");
+    assert_eq!(progs[0].prog_type, "tracepoint");
+    assert!(bpf_skel
+        .list_maps()
+        .iter()
+        .any(|v| v.name == "rb" && v.map_type == "ringbuf"));
+
+    let skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    let bpf_skel = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    // Nobody else uses pid 0
+    bpf_skel
+        .update_map("exec_start", &json!(0), &json!(42))
+        .unwrap();
+    assert_eq!(
+        bpf_skel.lookup_map("exec_start", &json!(0)).unwrap(),
+        Some(json!(42))
+    );
+    assert!(bpf_skel
+        .lookup_map("exec_start", &json!(-1))
+        .unwrap()
+        .is_none());
+    assert!(bpf_skel.lookup_map("rb", &json!(0)).is_err());
+}
+
 #[test]
 fn test_pause_resume_terminate_1() {
     let skel = serde_json::from_str::<ComposedObject>(
@@ -320,3 +409,538 @@ fn test_tc_attach_and_detach() {
         panic!("Failed to attach tc program to lo");
     }
 }
+
+#[test]
+fn test_prog_fallback() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    skel.meta.bpf_skel.progs[0]
+        .requires
+        .push(KernelFeature::Helper("bpf_no_such_helper".to_string()));
+    // Without a fallback, loading should fail
+    let err = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .err()
+        .unwrap();
+    assert!(format!("{:?}", err).contains("bpf_no_such_helper"));
+
+    skel.meta.bpf_skel.progs[0].fallback = Some("handle_exit".to_string());
+    let loaded = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    assert_eq!(loaded.links.len(), 1);
+}
+
+#[test]
+fn test_prog_stats() {
+    let skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    let loaded = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .set_runner_config(RunnerConfig {
+            enable_stats: Some(true),
+            ..Default::default()
+        })
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    let handle = loaded.create_stats_handle().unwrap();
+    std::process::Command::new("sh").output().unwrap();
+    let handle_inner = handle.clone();
+    let stats = thread::spawn(move || handle_inner.prog_stats().unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(stats.len(), 2);
+    let exec = stats.iter().find(|v| v.name == "handle_exec").unwrap();
+    assert!(exec.run_cnt > 0);
+    assert!(exec.run_time_ns > 0);
+    assert!(exec.verified_insns > 0);
+    assert_eq!(loaded.prog_stats().unwrap().len(), 2);
+    drop(loaded);
+    // Programs are gone with the skeleton
+    assert!(handle.prog_stats().is_err());
+}
+
+#[test]
+fn test_verifier_error() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    let offset = {
+        let elf = object::File::parse(&skel.bpf_object).unwrap();
+        let data = elf
+            .section_by_name("tp/sched/sched_process_exec")
+            .unwrap()
+            .data();
+        data.as_ptr() as usize - skel.bpf_object.as_ptr() as usize
+    };
+    // Replace the first instruction with `r0 = r2`, reading an uninitialized register
+    skel.bpf_object[offset..offset + 8].copy_from_slice(&[0xbf, 0x20, 0, 0, 0, 0, 0, 0]);
+    let err = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .err()
+        .unwrap();
+    let verifier_error = err.downcast_ref::<VerifierError>().unwrap();
+    assert_eq!(verifier_error.program, "handle_exec");
+    assert_eq!(verifier_error.insn, Some(0));
+    assert_eq!(verifier_error.message, "R2 !read_ok");
+    assert!(verifier_error.source.is_some());
+}
+
+#[test]
+fn test_handover() {
+    let skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    let old = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    // Nobody else uses pid 0
+    old.update_map("exec_start", &json!(0), &json!(42)).unwrap();
+    let handle = old.create_handover_handle().unwrap();
+    let handover = thread::spawn(move || handle.acquire().unwrap())
+        .join()
+        .unwrap();
+    let new = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .set_handover(handover)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    let map_id = |skel: &super::BpfSkeleton, name: &str| {
+        skel.prog.map(name).unwrap().info().unwrap().info.id
+    };
+    assert_eq!(map_id(&old, "exec_start"), map_id(&new, "exec_start"));
+    // Maps exporting events are never shared
+    assert_ne!(map_id(&old, "rb"), map_id(&new, "rb"));
+    // Tracepoint links can't be updated, so the programs are attached again
+    assert_eq!(new.links.len(), 2);
+    drop(old);
+    assert_eq!(
+        new.lookup_map("exec_start", &json!(0)).unwrap(),
+        Some(json!(42))
+    );
+}
+
+#[test]
+fn test_tail_calls() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("runqlat.json")).unwrap(),
+    )
+    .unwrap();
+    let cgroup_map = skel
+        .meta
+        .bpf_skel
+        .maps
+        .iter_mut()
+        .find(|v| v.name == "cgroup_map")
+        .unwrap();
+    cgroup_map.tail_calls = [(0, "handle_sched_wakeup_new".to_string())].into();
+    // A cgroup array is not a prog array
+    let err = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .err()
+        .unwrap();
+    assert!(format!("{:?}", err).contains("not a prog array"));
+
+    let mut preload = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap();
+    // None of the assets has a prog array. `cgroup_map` is only used in a branch that the verifier prunes
+    // (`filter_cg` is false), so turn it into one
+    preload
+        .bpf_object
+        .map_mut("cgroup_map")
+        .unwrap()
+        .set_type(MapType::ProgArray)
+        .unwrap();
+    let loaded = preload.load_and_attach().unwrap();
+    // The tail call target is not attached
+    assert_eq!(loaded.links.len(), 2);
+    let prog_fd = loaded.prog.prog("handle_sched_wakeup_new").unwrap().fd();
+    let prog_id = query_prog_info("handle_sched_wakeup_new", prog_fd)
+        .unwrap()
+        .id;
+    let value = loaded
+        .prog
+        .map("cgroup_map")
+        .unwrap()
+        .lookup(&0u32.to_ne_bytes(), MapFlags::ANY)
+        .unwrap()
+        .unwrap();
+    assert_eq!(u32::from_ne_bytes(value.try_into().unwrap()), prog_id);
+}
+
+#[test]
+fn test_initial_map_entries() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    // Nobody else uses pid 0
+    skel.meta.bpf_skel.maps[0].initial_entries = vec![
+        MapEntry {
+            key: json!(0),
+            value: Some(json!(42)),
+        },
+        MapEntry {
+            key: json!(-1),
+            value: None,
+        },
+    ];
+    let loaded = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    assert_eq!(
+        loaded.lookup_map("exec_start", &json!(0)).unwrap(),
+        Some(json!(42))
+    );
+    assert_eq!(
+        loaded.lookup_map("exec_start", &json!(-1)).unwrap(),
+        Some(json!(0))
+    );
+
+    skel.meta.bpf_skel.maps[0].initial_entries[0].key = json!("abc");
+    let err = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .err()
+        .unwrap();
+    assert!(format!("{:?}", err).contains("exec_start"));
+}
+
+#[test]
+fn test_map_in_map() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("runqlat.json")).unwrap(),
+    )
+    .unwrap();
+    for map in skel.meta.bpf_skel.maps.iter_mut() {
+        map.sample = None;
+    }
+    let cgroup_map = skel
+        .meta
+        .bpf_skel
+        .maps
+        .iter_mut()
+        .find(|v| v.name == "cgroup_map")
+        .unwrap();
+    cgroup_map.inner_map = Some(
+        serde_json::from_value(json!({
+            "template": {
+                "map_type": "hash",
+                "key_size": 4,
+                "value_size": 8,
+                "max_entries": 16,
+                "key_type": "u32",
+                "value_type": "u64"
+            },
+            "initial": [{"key": 0, "entries": [{"key": 1, "value": 2}]}]
+        }))
+        .unwrap(),
+    );
+    cgroup_map.sample = Some(MapSampleMeta {
+        interval: 10,
+        ty: SampleMapType::DefaultKV,
+        unit: "".into(),
+        clear_map: false,
+    });
+    let mut preload = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap();
+    // None of the assets has a map-in-map. `cgroup_map` is only used in a branch that the verifier prunes
+    // (`filter_cg` is false), so turn it into one
+    preload
+        .bpf_object
+        .map_mut("cgroup_map")
+        .unwrap()
+        .set_type(MapType::ArrayOfMaps)
+        .unwrap();
+    let loaded = preload.load_and_attach().unwrap();
+    assert_eq!(
+        loaded.lookup_map("cgroup_map", &json!(0)).unwrap(),
+        Some(json!([{"key": 1, "value": 2}]))
+    );
+    loaded
+        .update_map("cgroup_map", &json!(0), &json!([{"key": 3, "value": 4}]))
+        .unwrap();
+    assert_eq!(
+        loaded.lookup_map("cgroup_map", &json!(0)).unwrap(),
+        Some(json!([{"key": 3, "value": 4}]))
+    );
+
+    // Sampling walks through the inner maps
+    struct MyEventHandler {
+        data: Arc<Mutex<Vec<String>>>,
+        handle: PollingHandle,
+    }
+    impl EventHandler for MyEventHandler {
+        fn handle_event(&self, _context: Option<Arc<dyn std::any::Any>>, data: ReceivedEventData) {
+            if let ReceivedEventData::JsonText(s) = data {
+                self.data.lock().unwrap().push(s.to_string());
+                self.handle.terminate();
+            }
+        }
+    }
+    let data = Arc::new(Mutex::new(vec![]));
+    let handler = Arc::new(MyEventHandler {
+        data: data.clone(),
+        handle: loaded.create_poll_handle(),
+    });
+    loaded
+        .wait_and_poll_to_handler(ExportFormatType::Json, Some(handler), None)
+        .unwrap();
+    let event: serde_json::Value = serde_json::from_str(&data.lock().unwrap()[0]).unwrap();
+    assert_eq!(event["key"], json!({"outer_key": 0, "key": 3}));
+}
+
+#[test]
+fn test_multi_probe() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    let prog = &mut skel.meta.bpf_skel.progs[1];
+    assert_eq!(prog.name, "handle_exit");
+    prog.attach = "kprobe".into();
+    prog.others = json!({ "functions": ["do_nanosleep", "hrtimer_nanoslee?"] });
+    let preload = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap();
+    let kprobe_multi = FeatureProber::new().is_supported(&KernelFeature::KprobeMultiLink);
+    assert_eq!(
+        preload.kprobe_multi_progs.contains("handle_exit"),
+        kprobe_multi
+    );
+}
+
+#[test]
+fn test_attach_target() {
+    let mut skel = serde_json::from_str::<ComposedObject>(
+        &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+    )
+    .unwrap();
+    let prog = &mut skel.meta.bpf_skel.progs[1];
+    assert_eq!(prog.name, "handle_exit");
+    prog.attach_target = Some("tp:sched/sched_process_fork".into());
+    let skel = BpfSkeletonBuilder::from_json_package(&skel, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    assert_eq!(skel.links.len(), 2);
+    assert!(skel.links.iter().any(|(name, _)| name == "handle_exit"));
+}
+
+#[test]
+fn test_struct_ops_registration() {
+    let dir = get_assets_dir().join("simple_prog_7");
+    let meta = serde_json::from_str::<EunomiaObjectMeta>(
+        &std::fs::read_to_string(dir.join("test.skel.json")).unwrap(),
+    )
+    .unwrap();
+    let bpf_object = std::fs::read(dir.join("test.bpf.o")).unwrap();
+    let available_ca =
+        || std::fs::read_to_string("/proc/sys/net/ipv4/tcp_available_congestion_control").unwrap();
+    let skel = BpfSkeletonBuilder::from_object_meta_and_object_buffer(&meta, &bpf_object, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    // Only the map is registered, the programs themselves aren't attached
+    assert_eq!(skel.links.len(), 1);
+    assert_eq!(skel.links[0].0, "ca_test");
+    assert!(matches!(skel.links[0].1, AttachLink::StructOps(_)));
+    assert!(available_ca()
+        .split_whitespace()
+        .any(|v| v == "bpf_loader_ca"));
+    drop(skel);
+    assert!(!available_ca()
+        .split_whitespace()
+        .any(|v| v == "bpf_loader_ca"));
+}
+
+/// Number of programs attached to the target with the attach type
+fn query_attached_progs(target: &impl AsRawFd, attach_type: u32) -> u32 {
+    let mut attach_flags = 0;
+    let mut prog_cnt = 0;
+    // SAFETY: prog_ids is null, so only the count is written
+    let err = unsafe {
+        bpf_prog_query(
+            target.as_raw_fd(),
+            attach_type,
+            0,
+            &mut attach_flags,
+            std::ptr::null_mut(),
+            &mut prog_cnt,
+        )
+    };
+    assert_eq!(err, 0, "Failed to query attached programs");
+    prog_cnt
+}
+
+#[test]
+fn test_cgroup_attach_and_detach() {
+    let dir = get_assets_dir().join("simple_prog_8");
+    let mut meta = serde_json::from_str::<EunomiaObjectMeta>(
+        &std::fs::read_to_string(dir.join("test.skel.json")).unwrap(),
+    )
+    .unwrap();
+    let bpf_object = std::fs::read(dir.join("test.bpf.o")).unwrap();
+    let cgroup2_root = std::fs::read_to_string("/proc/mounts")
+        .unwrap()
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields[2] == "cgroup2")
+        .map(|fields| fields[1].to_string())
+        .expect("cgroup v2 isn't mounted");
+    let cgroup_path = format!("{}/bpf_loader_test_cgroup", cgroup2_root);
+    std::fs::create_dir_all(&cgroup_path).unwrap();
+    let cgroup = std::fs::File::open(&cgroup_path).unwrap();
+
+    for attach_flags in [0, BPF_F_ALLOW_MULTI] {
+        meta.bpf_skel.progs[0].others =
+            json!({ "cgroup_path": cgroup_path, "attach_flags": attach_flags });
+        let skel = BpfSkeletonBuilder::from_object_meta_and_object_buffer(&meta, &bpf_object, None)
+            .build()
+            .unwrap()
+            .load_and_attach()
+            .unwrap();
+        assert_eq!(skel.links.len(), 1);
+        // Without flags the program is attached through a link
+        if attach_flags == 0 {
+            assert!(matches!(skel.links[0].1, AttachLink::BpfLink(_)));
+        } else {
+            assert!(matches!(skel.links[0].1, AttachLink::ProgAttach(..)));
+        }
+        assert_eq!(query_attached_progs(&cgroup, BPF_CGROUP_INET_EGRESS), 1);
+        drop(skel);
+        assert_eq!(query_attached_progs(&cgroup, BPF_CGROUP_INET_EGRESS), 0);
+    }
+    drop(cgroup);
+    std::fs::remove_dir(&cgroup_path).unwrap();
+}
+
+#[test]
+fn test_sockmap_and_netns_attach_and_detach() {
+    let dir = get_assets_dir().join("simple_prog_9");
+    let meta = serde_json::from_str::<EunomiaObjectMeta>(
+        &std::fs::read_to_string(dir.join("test.skel.json")).unwrap(),
+    )
+    .unwrap();
+    let bpf_object = std::fs::read(dir.join("test.bpf.o")).unwrap();
+    let netns = std::fs::File::open("/proc/self/ns/net").unwrap();
+    let skel = BpfSkeletonBuilder::from_object_meta_and_object_buffer(&meta, &bpf_object, None)
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    assert_eq!(skel.links.len(), 2);
+    assert!(matches!(
+        skel.links.iter().find(|(name, _)| name == "sk_verdict"),
+        Some((_, AttachLink::ProgAttach(..)))
+    ));
+    assert!(matches!(
+        skel.links.iter().find(|(name, _)| name == "sk_lookup_pass"),
+        Some((_, AttachLink::BpfLink(_)))
+    ));
+    // Keep the sockmap alive after the skeleton is dropped, to check that the program was detached
+    // SAFETY: the map fd is valid while the skeleton lives
+    let sockmap = unsafe { BorrowedFd::borrow_raw(skel.prog.map("sock_map").unwrap().fd()) }
+        .try_clone_to_owned()
+        .unwrap();
+    assert_eq!(query_attached_progs(&sockmap, BPF_SK_SKB_STREAM_VERDICT), 1);
+    assert_eq!(query_attached_progs(&netns, BPF_SK_LOOKUP), 1);
+    drop(skel);
+    assert_eq!(query_attached_progs(&sockmap, BPF_SK_SKB_STREAM_VERDICT), 0);
+    assert_eq!(query_attached_progs(&netns, BPF_SK_LOOKUP), 0);
+}
+
+/// A USDT probe `bpf_loader_test:test_probe` without arguments, described in `.note.stapsdt` like `sys/sdt.h` does
+#[inline(never)]
+fn usdt_test_probe() {
+    // SAFETY: it only emits a nop, and a note describing its address
+    unsafe {
+        std::arch::asm!(
+            "990: nop",
+            ".pushsection .note.stapsdt, \"?\", \"note\"",
+            ".balign 4",
+            ".4byte 992f-991f, 994f-993f, 3",
+            "991: .asciz \"stapsdt\"",
+            "992: .balign 4",
+            "993: .8byte 990b",
+            ".8byte _.stapsdt.base",
+            ".8byte 0",
+            ".asciz \"bpf_loader_test\"",
+            ".asciz \"test_probe\"",
+            ".asciz \"\"",
+            "994: .balign 4",
+            ".popsection",
+            ".ifndef _.stapsdt.base",
+            ".pushsection .stapsdt.base, \"aG\", \"progbits\", .stapsdt.base, comdat",
+            ".weak _.stapsdt.base",
+            ".hidden _.stapsdt.base",
+            "_.stapsdt.base: .space 1",
+            ".size _.stapsdt.base, 1",
+            ".popsection",
+            ".endif",
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
+#[test]
+fn test_usdt_attach() {
+    let dir = get_assets_dir().join("simple_prog_10");
+    let mut meta = serde_json::from_str::<EunomiaObjectMeta>(
+        &std::fs::read_to_string(dir.join("test.skel.json")).unwrap(),
+    )
+    .unwrap();
+    let bpf_object = std::fs::read(dir.join("test.bpf.o")).unwrap();
+    let binary_path = std::env::current_exe().unwrap();
+    for prog in meta.bpf_skel.progs.iter_mut() {
+        prog.others["binary_path"] = json!(binary_path.to_str().unwrap());
+    }
+    let skel = BpfSkeletonBuilder::from_object_meta_and_object_buffer(&meta, &bpf_object, None)
+        .set_runner_config(RunnerConfig {
+            enable_stats: Some(true),
+            ..Default::default()
+        })
+        .build()
+        .unwrap()
+        .load_and_attach()
+        .unwrap();
+    assert_eq!(skel.links.len(), 2);
+    for _ in 0..3 {
+        usdt_test_probe();
+    }
+    let stats = skel.prog_stats().unwrap();
+    for name in ["handle__usdt", "handle__usdt_with_cookie"] {
+        let prog_stats = stats.iter().find(|v| v.name == name).unwrap();
+        assert!(
+            prog_stats.run_cnt >= 3,
+            "`{}` ran {} times",
+            name,
+            prog_stats.run_cnt
+        );
+    }
+}
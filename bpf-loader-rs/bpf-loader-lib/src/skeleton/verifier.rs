@@ -0,0 +1,296 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+use std::{
+    ffi::{c_char, CStr},
+    fmt::Display,
+};
+
+use anyhow::{anyhow, bail, Result};
+use libbpf_rs::libbpf_sys::{
+    bpf_object, bpf_object__next_program, bpf_program__name, bpf_program__set_log_buf,
+    bpf_program__set_log_level,
+};
+use serde::Serialize;
+
+/// Size of the log buffer of each program. It's the same as the largest buffer libbpf would allocate.
+/// The memory is only touched if the verifier writes to it
+const VERIFIER_LOG_BUF_SIZE: usize = (u32::MAX >> 8) as usize;
+/// How many lines of the log `VerifierError::report` shows
+const REPORT_LOG_LINES: usize = 10;
+
+/// The source line that the verifier printed from the BTF line info
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceLine {
+    /// The source code
+    pub code: String,
+    /// The file containing the code. Only printed by newer kernels
+    pub file: Option<String>,
+    /// Line number in the file. Only printed by newer kernels
+    pub line: Option<u32>,
+}
+
+/// A program was rejected by the verifier
+///
+/// It's attached to the error returned by `PreLoadBpfSkeleton::load_and_attach`, use `anyhow::Error::downcast_ref` to get it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifierError {
+    /// Name of the rejected program
+    pub program: String,
+    /// Index of the instruction that the verifier stopped at
+    pub insn: Option<usize>,
+    /// What the verifier complained about
+    pub message: String,
+    /// The source line of the failing instruction, if the program has BTF line info
+    pub source: Option<SourceLine>,
+    /// The full verifier log
+    pub log: String,
+}
+
+impl Display for VerifierError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Program `{}` was rejected by the verifier", self.program)?;
+        if let Some(insn) = self.insn {
+            write!(f, " at instruction {}", insn)?;
+        }
+        if let Some(SourceLine {
+            file: Some(file),
+            line: Some(line),
+            ..
+        }) = &self.source
+        {
+            write!(f, " ({}:{})", file, line)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl VerifierError {
+    /// A human-readable report, with the source line and the tail of the verifier log
+    pub fn report(&self) -> String {
+        let mut out = format!("Program `{}` was rejected by the verifier\n", self.program);
+        match (self.insn, &self.source) {
+            (Some(insn), Some(source)) => {
+                out.push_str(&format!("  at instruction {}", insn));
+                if let (Some(file), Some(line)) = (&source.file, source.line) {
+                    out.push_str(&format!(", {}:{}", file, line));
+                }
+                out.push_str(&format!("\n    {}\n", source.code));
+            }
+            (Some(insn), None) => out.push_str(&format!("  at instruction {}\n", insn)),
+            _ => {}
+        }
+        for line in self.message.lines() {
+            out.push_str(&format!("  {}\n", line));
+        }
+        let lines = self.log.lines().collect::<Vec<_>>();
+        out.push_str("Last lines of the verifier log:\n");
+        for line in lines[lines.len().saturating_sub(REPORT_LOG_LINES)..].iter() {
+            out.push_str(&format!("  | {}\n", line));
+        }
+        out
+    }
+}
+
+/// Log buffers of the programs in an opened bpf_object
+pub(crate) struct VerifierLogBuffers {
+    buffers: Vec<(String, Vec<u8>)>,
+}
+
+impl VerifierLogBuffers {
+    /// Give every program of the object a log buffer
+    ///
+    /// With `level` 0, the verifier only writes the log if the program is rejected
+    ///
+    /// # Safety
+    /// `obj` must be a valid opened bpf_object, and the returned buffers must outlive the loading of it
+    pub(crate) unsafe fn install(obj: *const bpf_object, level: u32) -> Result<Self> {
+        let mut buffers = vec![];
+        let mut prog = std::ptr::null_mut();
+        loop {
+            prog = bpf_object__next_program(obj, prog);
+            if prog.is_null() {
+                break;
+            }
+            let name = CStr::from_ptr(bpf_program__name(prog))
+                .to_str()
+                .map_err(|e| anyhow!("Program name contains invalid character: {}", e))?
+                .to_string();
+            let mut buf = vec![0u8; VERIFIER_LOG_BUF_SIZE];
+            let err =
+                bpf_program__set_log_buf(prog, buf.as_mut_ptr() as *mut c_char, buf.len() as _);
+            if err < 0 {
+                bail!("Failed to set log buffer of program `{}`: {}", name, err);
+            }
+            let err = bpf_program__set_log_level(prog, level);
+            if err < 0 {
+                bail!("Failed to set log level of program `{}`: {}", name, err);
+            }
+            buffers.push((name, buf));
+        }
+        Ok(Self { buffers })
+    }
+    /// Names and logs of the programs that the verifier wrote something for
+    pub(crate) fn logs(&self) -> impl Iterator<Item = (&str, String)> {
+        self.buffers.iter().filter_map(|(name, buf)| {
+            let log = CStr::from_bytes_until_nul(buf)
+                .ok()?
+                .to_string_lossy()
+                .trim_end()
+                .to_string();
+            (!log.is_empty()).then_some((name.as_str(), log))
+        })
+    }
+    /// Find the program that failed the loading
+    ///
+    /// libbpf loads programs in order and stops at the first failure, so it's the last one with a log
+    pub(crate) fn find_failure(&self) -> Option<VerifierError> {
+        self.logs()
+            .last()
+            .map(|(name, log)| parse_verifier_log(name, log))
+    }
+}
+
+/// Parse the instruction index from lines like `12: (63) *(u32 *)(r0 +0) = r6`, or `12: <invalid CO-RE relocation>` rewritten by libbpf
+fn parse_insn_line(line: &str) -> Option<usize> {
+    let (idx, rest) = line.split_once(": ")?;
+    if !(rest.starts_with('(') || rest.starts_with('<')) {
+        return None;
+    }
+    idx.parse().ok()
+}
+
+/// Parse lines like `; e->pid = pid; @ bootstrap.bpf.c:60`. Kernels before 6.4 don't print the location
+fn parse_source_line(line: &str) -> Option<SourceLine> {
+    let line = line.strip_prefix(';')?.trim();
+    if let Some((code, location)) = line.rsplit_once(" @ ") {
+        if let Some((file, line_no)) = location.rsplit_once(':') {
+            if let Ok(line_no) = line_no.parse() {
+                return Some(SourceLine {
+                    code: code.trim().to_string(),
+                    file: Some(file.to_string()),
+                    line: Some(line_no),
+                });
+            }
+        }
+    }
+    Some(SourceLine {
+        code: line.to_string(),
+        file: None,
+        line: None,
+    })
+}
+
+/// Lines that describe the verifier states or the statistics, rather than the error
+fn is_noise_line(line: &str) -> bool {
+    const PREFIXES: [&str; 8] = [
+        "processed ",
+        "verification time",
+        "stack depth",
+        "from ",
+        "last_idx ",
+        "regs=",
+        "mark_precise",
+        "func#",
+    ];
+    if PREFIXES.iter().any(|p| line.starts_with(p)) {
+        return true;
+    }
+    // States of the registers, like `12: R0=map_value(...) R1=...`
+    matches!(line.split_once(": "), Some((idx, rest)) if idx.parse::<usize>().is_ok() && rest.starts_with('R'))
+}
+
+pub(crate) fn parse_verifier_log(program: &str, log: String) -> VerifierError {
+    let mut insn = None;
+    let mut source = None;
+    let mut pending_source = None;
+    let mut message = vec![];
+    for line in log.lines().map(|v| v.trim_end()).filter(|v| !v.is_empty()) {
+        if line.starts_with(';') {
+            pending_source = parse_source_line(line);
+        } else if let Some(idx) = parse_insn_line(line) {
+            insn = Some(idx);
+            // The source line is printed before the first instruction of it
+            if pending_source.is_some() {
+                source = pending_source.take();
+            }
+            message.clear();
+        } else if !is_noise_line(line) {
+            message.push(line);
+        }
+    }
+    let message = if message.is_empty() {
+        log.lines()
+            .rev()
+            .find(|v| !v.trim().is_empty() && !is_noise_line(v))
+            .unwrap_or("No message from the verifier")
+            .to_string()
+    } else {
+        message.join("\n")
+    };
+    VerifierError {
+        program: program.to_string(),
+        insn,
+        message,
+        source: insn.and(source),
+        log,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_verifier_log, SourceLine};
+
+    #[test]
+    fn test_parse_verifier_log() {
+        let log = r#"func#0 @0
+0: R1=ctx() R10=fp0
+; int BPF_PROG(handle_exec, struct task_struct *p) @ bootstrap.bpf.c:45
+0: (79) r6 = *(u64 *)(r1 +0)
+1: R1=ctx() R6_w=ptr_task_struct()
+; e = bpf_ringbuf_reserve(&rb, sizeof(*e), 0); @ bootstrap.bpf.c:58
+1: (18) r1 = 0xffff888003a4c000
+3: (b7) r2 = 168
+4: (85) call bpf_ringbuf_reserve#131
+5: R0_w=ringbuf_mem_or_null(id=2,ref_obj_id=2,sz=168)
+; e->pid = pid; @ bootstrap.bpf.c:60
+5: (63) *(u32 *)(r0 +0) = r6
+R0 invalid mem access 'ringbuf_mem_or_null'
+verification time 52 usec
+stack depth 0
+processed 6 insns (limit 1000000) max_states_per_insn 0 total_states 0 peak_states 0 mark_read 0
+"#;
+        let err = parse_verifier_log("handle_exec", log.to_string());
+        assert_eq!(err.insn, Some(5));
+        assert_eq!(err.message, "R0 invalid mem access 'ringbuf_mem_or_null'");
+        assert_eq!(
+            err.source,
+            Some(SourceLine {
+                code: "e->pid = pid;".to_string(),
+                file: Some("bootstrap.bpf.c".to_string()),
+                line: Some(60)
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "Program `handle_exec` was rejected by the verifier at instruction 5 (bootstrap.bpf.c:60): R0 invalid mem access 'ringbuf_mem_or_null'"
+        );
+        assert!(err.report().contains("    e->pid = pid;\n"));
+    }
+    #[test]
+    fn test_parse_verifier_log_without_insn() {
+        let log = "invalid func unknown#195896080\nprocessed 0 insns (limit 1000000)";
+        let err = parse_verifier_log("prog", log.to_string());
+        assert_eq!(err.insn, None);
+        assert_eq!(err.source, None);
+        assert_eq!(err.message, "invalid func unknown#195896080");
+        // Older kernels don't print the location of source lines
+        let log = "; int x = 1;\n0: (63) *(u32 *)(r10 -4) = r1\nR1 !read_ok";
+        let err = parse_verifier_log("prog", log.to_string());
+        assert_eq!(err.source.unwrap().file, None);
+        assert_eq!(err.message, "R1 !read_ok");
+    }
+}
@@ -0,0 +1,825 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Offline package validation
+//!
+//! Run the checks that would otherwise only fail when loading the package on the target host, purely in userspace.
+//!
+//! Nothing will be loaded into the kernel, so it can be used in CI to reject broken packages.
+
+use std::{collections::HashSet, fmt::Display};
+
+use btf::types::{Btf, BtfType};
+use clap::ArgMatches;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    btf_container::{BtfContainer, BtfResolver},
+    export_event::{
+        checker::{check_export_types_btf, check_sample_types_btf},
+        type_descriptor::TypeDescriptor,
+    },
+    inspect::{read_object_with_libbpf, OpenedObjectInfo},
+    meta::{
+        arg_parser::UnpresentVariableAction, ComposedObject, DataSectionMeta, EunomiaObjectMeta,
+        MapExportConfig, MultiProbeProgExtraMeta, ProgMeta, SockmapProgExtraMeta,
+        UsdtProgExtraMeta,
+    },
+    skeleton::{
+        map_in_map::InnerMapLayout,
+        preload::{map_entries::MapEntryLayout, section_loader::load_section_data_with_skel_value},
+    },
+};
+
+/// How serious a diagnostic is
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Loading the package will fail
+    Error,
+    /// The package can be loaded, but may not behave as expected
+    Warning,
+}
+
+/// Which check produced the diagnostic
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    /// The ELF, or the BTF in it, can't be read
+    Object,
+    /// Maps or programs in the meta don't match the ones in the ELF
+    Definition,
+    /// An export type doesn't match the BTF
+    ExportType,
+    /// A data section or a variable can't be resolved in the BTF
+    DataSection,
+    /// The command line argument parser can't be built
+    ArgParser,
+    /// A default value can't be parsed or encoded into its variable, or an initial entry into its map
+    DefaultValue,
+}
+
+/// A problem found in the package
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// How serious it is
+    pub severity: Severity,
+    /// Which check found it
+    pub kind: DiagnosticKind,
+    /// Where it is, e.g "map `rb`"
+    pub location: String,
+    /// What's wrong
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let kind = serde_json::to_value(self.kind).map_err(|_| std::fmt::Error)?;
+        write!(
+            f,
+            "{}[{}] {}: {}",
+            severity,
+            kind.as_str().unwrap_or_default(),
+            self.location,
+            self.message
+        )
+    }
+}
+
+/// Diagnostics found in a package
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ValidationReport {
+    /// All diagnostics, in the order they were found
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationReport {
+    /// Whether there is any diagnostic with `Severity::Error`
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|v| v.severity == Severity::Error)
+    }
+    fn push(
+        &mut self,
+        severity: Severity,
+        kind: DiagnosticKind,
+        location: impl Into<String>,
+        message: impl Display,
+    ) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            kind,
+            location: location.into(),
+            // `{:#}` prints the whole context chain of anyhow::Error in one line
+            message: format!("{message:#}"),
+        });
+    }
+    fn error(&mut self, kind: DiagnosticKind, location: impl Into<String>, message: impl Display) {
+        self.push(Severity::Error, kind, location, message)
+    }
+    fn warning(
+        &mut self,
+        kind: DiagnosticKind,
+        location: impl Into<String>,
+        message: impl Display,
+    ) {
+        self.push(Severity::Warning, kind, location, message)
+    }
+}
+
+impl ComposedObject {
+    /// Check the package without loading it. Returns all problems found
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let btf = match BtfContainer::new_from_binary(&self.bpf_object) {
+            Ok(v) => v,
+            Err(e) => {
+                report.error(DiagnosticKind::Object, "bpf object", e);
+                return report;
+            }
+        };
+        let opened = match read_object_with_libbpf(&self.meta.bpf_skel.obj_name, &self.bpf_object) {
+            Ok(v) => v,
+            Err(e) => {
+                report.error(DiagnosticKind::Object, "bpf object", e);
+                return report;
+            }
+        };
+        check_definitions(&self.meta, &opened, &mut report);
+        check_export_types(&self.meta, &opened, btf.resolver(), &mut report);
+        let btf = btf.borrow_btf();
+        check_data_sections(&self.meta, btf, &mut report);
+        check_arguments(&self.meta, &opened, btf, &mut report);
+        check_inner_maps(&self.meta, &opened, btf, &mut report);
+        report
+    }
+}
+
+fn check_definitions(
+    meta: &EunomiaObjectMeta,
+    opened: &OpenedObjectInfo,
+    report: &mut ValidationReport,
+) {
+    let skel = &meta.bpf_skel;
+    for map in skel.maps.iter() {
+        match opened.map(&map.name) {
+            None => report.error(
+                DiagnosticKind::Definition,
+                format!("map `{}`", map.name),
+                "Map not found in the bpf object",
+            ),
+            Some(info) if map.fallback.is_some() && info.map_type != "ringbuf" => report.warning(
+                DiagnosticKind::Definition,
+                format!("map `{}`", map.name),
+                format!(
+                    "Fallbacks only apply to ringbuf maps, but it's a `{}` map",
+                    info.map_type
+                ),
+            ),
+            _ => {}
+        }
+        if map.tail_calls.is_empty() {
+            continue;
+        }
+        if let Some(info) = opened.map(&map.name) {
+            if info.map_type != "prog_array" {
+                report.error(
+                    DiagnosticKind::Definition,
+                    format!("map `{}`", map.name),
+                    format!(
+                        "Tail calls only apply to prog arrays, but it's a `{}` map",
+                        info.map_type
+                    ),
+                );
+            }
+        }
+        for (idx, prog_name) in map.tail_calls.iter() {
+            if !skel.progs.iter().any(|v| &v.name == prog_name) {
+                report.error(
+                    DiagnosticKind::Definition,
+                    format!("map `{}`", map.name),
+                    format!("Program `{prog_name}` for tail call {idx} is not in the meta"),
+                );
+            }
+        }
+    }
+    for map in opened.maps.iter() {
+        if !skel.maps.iter().any(|v| v.name == map.name) {
+            report.warning(
+                DiagnosticKind::Definition,
+                format!("map `{}`", map.name),
+                "Map is not described in the meta, it won't be exported",
+            );
+        }
+    }
+    for prog in skel.progs.iter() {
+        if !opened.progs.iter().any(|(name, _)| name == &prog.name) {
+            report.error(
+                DiagnosticKind::Definition,
+                format!("program `{}`", prog.name),
+                "Program not found in the bpf object",
+            );
+        }
+        if let Some(fallback) = prog.fallback.as_ref() {
+            if fallback == &prog.name || !skel.progs.iter().any(|v| &v.name == fallback) {
+                report.error(
+                    DiagnosticKind::Definition,
+                    format!("program `{}`", prog.name),
+                    format!("Fallback program `{fallback}` is not another program in the meta"),
+                );
+            }
+        }
+        if let Err(e) = prog.resolve_attach_target() {
+            report.error(
+                DiagnosticKind::Definition,
+                format!("program `{}`", prog.name),
+                format!("Invalid attach target: {e}"),
+            );
+        }
+        if prog.attaches_to_sockmap() {
+            check_sockmap(prog, opened, report);
+        }
+        if prog.is_usdt() {
+            check_extra_meta::<UsdtProgExtraMeta>(prog, "usdt", report);
+        }
+        if prog.is_multi_probe() {
+            check_extra_meta::<MultiProbeProgExtraMeta>(prog, "multi probe", report);
+        }
+    }
+    for (name, _) in opened.progs.iter() {
+        if !skel.progs.iter().any(|v| &v.name == name) {
+            report.warning(
+                DiagnosticKind::Definition,
+                format!("program `{name}`"),
+                "Program is not described in the meta, it won't be attached",
+            );
+        }
+    }
+}
+
+/// Deserialize the extra meta of a program, reporting an error if it's invalid
+fn check_extra_meta<T: DeserializeOwned>(
+    prog: &ProgMeta,
+    kind: &str,
+    report: &mut ValidationReport,
+) -> Option<T> {
+    match serde_json::from_value::<T>(prog.others.clone()) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            report.error(
+                DiagnosticKind::Definition,
+                format!("program `{}`", prog.name),
+                format!("Invalid {kind} extra meta: {e}"),
+            );
+            None
+        }
+    }
+}
+
+fn check_sockmap(prog: &ProgMeta, opened: &OpenedObjectInfo, report: &mut ValidationReport) {
+    let Some(extra_meta) = check_extra_meta::<SockmapProgExtraMeta>(prog, "sockmap", report) else {
+        return;
+    };
+    let location = format!("program `{}`", prog.name);
+    match opened.map(&extra_meta.sockmap) {
+        None => report.error(
+            DiagnosticKind::Definition,
+            location,
+            format!(
+                "Map `{}` to attach to is not in the bpf object",
+                extra_meta.sockmap
+            ),
+        ),
+        Some(info) if !matches!(info.map_type.as_str(), "sockmap" | "sockhash") => report.error(
+            DiagnosticKind::Definition,
+            location,
+            format!(
+                "Programs could only be attached to sockmaps or sockhashes, but `{}` is a `{}` map",
+                extra_meta.sockmap, info.map_type
+            ),
+        ),
+        _ => {}
+    }
+}
+
+fn is_event_map_type(map_type: &str) -> bool {
+    matches!(map_type, "ringbuf" | "perf_event_array")
+}
+
+fn check_export_types(
+    meta: &EunomiaObjectMeta,
+    opened: &OpenedObjectInfo,
+    resolver: BtfResolver,
+    report: &mut ValidationReport,
+) {
+    for (i, ty) in meta.export_types.iter().enumerate() {
+        if let Err(e) = check_export_types_btf(ty, resolver) {
+            report.error(
+                DiagnosticKind::ExportType,
+                format!("export_types[{i}] `{}`", ty.name),
+                e,
+            );
+        }
+    }
+    if !meta.enable_multiple_export_types {
+        // Same as the single export mode of the skeleton: the last sample, ringbuf or perf event map wins
+        let export_maps = meta
+            .bpf_skel
+            .maps
+            .iter()
+            .filter_map(|map| {
+                let info = opened.map(&map.name)?;
+                (map.sample.is_some() || is_event_map_type(&info.map_type)).then_some((map, info))
+            })
+            .collect::<Vec<_>>();
+        if export_maps.len() > 1 {
+            report.warning(
+                DiagnosticKind::ExportType,
+                "maps",
+                format!(
+                    "Multiple export maps found, only `{}` will be exported",
+                    export_maps.last().unwrap().0.name
+                ),
+            );
+        }
+        let Some((map, info)) = export_maps.last() else {
+            return;
+        };
+        let location = format!("map `{}`", map.name);
+        let Some(export_type) = meta.export_types.first() else {
+            report.error(
+                DiagnosticKind::ExportType,
+                location,
+                "Export map found, but no export type is provided",
+            );
+            return;
+        };
+        if map.sample.is_some() {
+            if let Err(e) = check_sample_types_btf(resolver, info.btf_key_type_id, None) {
+                report.error(DiagnosticKind::ExportType, location.clone(), e);
+            }
+            if let Err(e) =
+                check_sample_types_btf(resolver, info.btf_value_type_id, Some(export_type.clone()))
+            {
+                report.error(DiagnosticKind::ExportType, location, e);
+            }
+        }
+        return;
+    }
+    for map in meta.bpf_skel.maps.iter() {
+        if matches!(map.export_config, MapExportConfig::NoExport) {
+            continue;
+        }
+        let Some(info) = opened.map(&map.name) else {
+            continue;
+        };
+        let location = format!("map `{}`", map.name);
+        let is_sample_map = map.sample.is_some();
+        if !is_sample_map && !is_event_map_type(&info.map_type) {
+            report.warning(
+                DiagnosticKind::ExportType,
+                location,
+                format!(
+                    "Map has an export config, but it's a `{}` map which is neither a sample map, ringbuf nor perf event array. It won't be exported",
+                    info.map_type
+                ),
+            );
+            continue;
+        }
+        let type_desc = match &map.export_config {
+            MapExportConfig::ExportUseBtf(type_id) => TypeDescriptor::BtfType { type_id: *type_id },
+            MapExportConfig::ExportUseCustomMembers(mems) => {
+                TypeDescriptor::ManuallyOverride(mems.clone())
+            }
+            MapExportConfig::Default if is_sample_map => TypeDescriptor::BtfType {
+                type_id: info.btf_value_type_id,
+            },
+            MapExportConfig::Default => {
+                report.error(
+                    DiagnosticKind::ExportType,
+                    location,
+                    "MapExportConfig::Default only applies to sample map",
+                );
+                continue;
+            }
+            MapExportConfig::NoExport => unreachable!(),
+        };
+        if let Err(e) = type_desc.build_checked_exported_members(resolver) {
+            report.error(DiagnosticKind::ExportType, location.clone(), e);
+        }
+        if is_sample_map {
+            if let Err(e) = (TypeDescriptor::BtfType {
+                type_id: info.btf_key_type_id,
+            })
+            .build_checked_exported_members(resolver)
+            {
+                report.error(DiagnosticKind::ExportType, location, e);
+            }
+        }
+    }
+}
+
+fn find_datasec<'a>(btf: &'a Btf, name: &str) -> Option<&'a btf::types::BtfDatasec<'a>> {
+    btf.types().iter().find_map(|ty| match ty {
+        BtfType::Datasec(sec) if sec.name == name => Some(sec),
+        _ => None,
+    })
+}
+
+fn check_data_sections(meta: &EunomiaObjectMeta, btf: &Btf, report: &mut ValidationReport) {
+    for section in meta.bpf_skel.data_sections.iter() {
+        let location = format!("section `{}`", section.name);
+        let ident = match section.name.as_str() {
+            ".rodata" => "rodata",
+            ".bss" => "bss",
+            s => {
+                report.error(
+                    DiagnosticKind::DataSection,
+                    location,
+                    format!("Unsupported section: {s}"),
+                );
+                continue;
+            }
+        };
+        if meta.bpf_skel.find_map_by_ident(ident).is_none() {
+            report.error(
+                DiagnosticKind::DataSection,
+                location.clone(),
+                format!("No map with ident `{ident}` to hold the section"),
+            );
+        }
+        let Some(sec) = find_datasec(btf, &section.name) else {
+            report.error(
+                DiagnosticKind::DataSection,
+                location,
+                "Cannot find the datasec in the BTF",
+            );
+            continue;
+        };
+        let var_names = sec
+            .vars
+            .iter()
+            .filter_map(|v| match btf.types().get(v.type_id as usize) {
+                Some(BtfType::Var(var)) => Some(var.name),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        for variable in section.variables.iter() {
+            if variable.name.starts_with("__eunomia_dummy") {
+                continue;
+            }
+            if !var_names.contains(variable.name.as_str()) {
+                report.warning(
+                    DiagnosticKind::DataSection,
+                    format!("variable `{}`", variable.name),
+                    format!(
+                        "Variable not found in the datasec `{}`, its value will be ignored",
+                        section.name
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn check_arguments(
+    meta: &EunomiaObjectMeta,
+    opened: &OpenedObjectInfo,
+    btf: &Btf,
+    report: &mut ValidationReport,
+) {
+    let cmd = match meta.build_argument_parser() {
+        Ok(v) => v,
+        Err(e) => {
+            report.error(DiagnosticKind::ArgParser, "arguments", e);
+            return;
+        }
+    };
+    // clap panics on conflicting arguments, so find them before parsing
+    let mut ids = HashSet::new();
+    let mut longs = HashSet::from(["help".to_string(), "version".to_string()]);
+    let mut shorts = HashSet::from(['h', 'V']);
+    let mut conflicted = false;
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        if !ids.insert(id.to_string()) {
+            report.error(
+                DiagnosticKind::ArgParser,
+                format!("argument `{id}`"),
+                "Duplicated argument name",
+            );
+            conflicted = true;
+        }
+        if let Some(long) = arg.get_long() {
+            if !longs.insert(long.to_string()) {
+                report.error(
+                    DiagnosticKind::ArgParser,
+                    format!("argument `{id}`"),
+                    format!("Long flag `--{long}` is used by another argument"),
+                );
+                conflicted = true;
+            }
+        }
+        if let Some(short) = arg.get_short() {
+            if !shorts.insert(short) {
+                report.error(
+                    DiagnosticKind::ArgParser,
+                    format!("argument `{id}`"),
+                    format!("Short flag `-{short}` is used by another argument"),
+                );
+                conflicted = true;
+            }
+        }
+    }
+    if conflicted {
+        return;
+    }
+    let matches: ArgMatches = match cmd.try_get_matches_from([meta.bpf_skel.obj_name.as_str()]) {
+        Ok(v) => v,
+        Err(e) => {
+            report.error(DiagnosticKind::ArgParser, "arguments", e.to_string().trim());
+            return;
+        }
+    };
+    // Fill the variables with their defaults, in the same way as running the package without arguments
+    let mut filled = meta.clone();
+    if let Err(e) = filled.parse_arguments_and_fill_skeleton_variables(
+        &matches,
+        UnpresentVariableAction::FillWithZero,
+    ) {
+        report.error(DiagnosticKind::DefaultValue, "arguments", e);
+        return;
+    }
+    for section in filled.bpf_skel.data_sections.iter() {
+        // Datasecs in the object file have no size, use the size of the map holding it, as the loader does
+        let ident = section.name.trim_start_matches('.');
+        let Some(buffer_size) = filled
+            .bpf_skel
+            .find_map_by_ident(ident)
+            .and_then(|map| opened.map(&map.name))
+            .map(|map| map.value_size as usize)
+        else {
+            continue;
+        };
+        for variable in section.variables.iter() {
+            if variable.value.is_none() || variable.name.starts_with("__eunomia_dummy") {
+                continue;
+            }
+            // Encode variables one by one, so that every bad default will be reported
+            let single = DataSectionMeta {
+                name: section.name.clone(),
+                variables: vec![variable.clone()],
+            };
+            let mut buffer = vec![0u8; buffer_size];
+            if let Err(e) = load_section_data_with_skel_value(btf, &single, &mut buffer) {
+                report.error(
+                    DiagnosticKind::DefaultValue,
+                    format!("variable `{}`", variable.name),
+                    e,
+                );
+            }
+        }
+    }
+    for map in filled.bpf_skel.maps.iter() {
+        let Some(info) = opened.map(&map.name) else {
+            continue;
+        };
+        let layout = MapEntryLayout {
+            key_size: info.key_size,
+            value_size: info.value_size,
+            btf_key_type_id: info.btf_key_type_id,
+            btf_value_type_id: info.btf_value_type_id,
+        };
+        for entry in map.initial_entries.iter() {
+            if let Err(e) = layout.encode(btf, entry) {
+                report.error(
+                    DiagnosticKind::DefaultValue,
+                    format!("map `{}`", map.name),
+                    format!("{e:#}"),
+                );
+            }
+        }
+    }
+}
+
+fn check_inner_maps(
+    meta: &EunomiaObjectMeta,
+    opened: &OpenedObjectInfo,
+    btf: &Btf,
+    report: &mut ValidationReport,
+) {
+    for map in meta.bpf_skel.maps.iter() {
+        let Some(inner_map) = map.inner_map.as_ref() else {
+            continue;
+        };
+        let location = format!("map `{}`", map.name);
+        if let Some(info) = opened.map(&map.name) {
+            if !matches!(info.map_type.as_str(), "array_of_maps" | "hash_of_maps") {
+                report.error(
+                    DiagnosticKind::Definition,
+                    &location,
+                    format!(
+                        "Inner maps only apply to map-in-maps, but it's a `{}` map",
+                        info.map_type
+                    ),
+                );
+            }
+        }
+        if let Some(template) = inner_map.template.as_ref() {
+            if let Err(e) = InnerMapLayout::from_template(template, btf) {
+                report.error(DiagnosticKind::Definition, &location, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        meta::{ComposedObject, MapEntry},
+        tests::get_assets_dir,
+    };
+
+    use super::{DiagnosticKind, Severity};
+
+    fn load_bootstrap() -> ComposedObject {
+        serde_json::from_str::<ComposedObject>(
+            &std::fs::read_to_string(get_assets_dir().join("bootstrap.json")).unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_valid_packages() {
+        for name in ["bootstrap.json", "runqlat.json"] {
+            let package = serde_json::from_str::<ComposedObject>(
+                &std::fs::read_to_string(get_assets_dir().join(name)).unwrap(),
+            )
+            .unwrap();
+            let report = package.validate();
+            assert!(!report.has_errors(), "{}: {:#?}", name, report);
+        }
+    }
+
+    #[test]
+    fn test_validate_broken_package() {
+        let mut package = load_bootstrap();
+        package.meta.export_types[0].name = "not_event".into();
+        package.meta.bpf_skel.maps[0].name = "no_such_map".into();
+        package.meta.bpf_skel.progs[0].fallback = Some("no_such_prog".into());
+        package.meta.bpf_skel.progs[1].attach_target = Some("kprobe:do_unlinkat".into());
+        package.meta.bpf_skel.maps[1].tail_calls =
+            [(0, "handle_exit".into()), (1, "no_tail_call".into())].into();
+        package.meta.bpf_skel.data_sections[0].variables[0]
+            .cmdarg
+            .default = Some(json!("abc"));
+        let report = package.validate();
+        assert!(report.has_errors());
+        let find = |kind: DiagnosticKind, location: &str| {
+            report
+                .diagnostics
+                .iter()
+                .find(|v| v.kind == kind && v.location.contains(location))
+                .unwrap_or_else(|| panic!("{:?} at {} not reported: {:#?}", kind, location, report))
+        };
+        assert_eq!(
+            find(DiagnosticKind::Definition, "no_such_map").severity,
+            Severity::Error
+        );
+        // The one in ELF which is no longer described
+        assert_eq!(
+            find(DiagnosticKind::Definition, "exec_start").severity,
+            Severity::Warning
+        );
+        assert!(find(DiagnosticKind::Definition, "handle_exec")
+            .message
+            .contains("no_such_prog"));
+        assert!(find(DiagnosticKind::Definition, "program `handle_exit`")
+            .message
+            .contains("can't be attached"));
+        let tail_call_errors = report
+            .diagnostics
+            .iter()
+            .filter(|v| v.location.contains(&package.meta.bpf_skel.maps[1].name))
+            .map(|v| v.message.as_str())
+            .collect::<Vec<_>>();
+        assert!(tail_call_errors
+            .iter()
+            .any(|v| v.contains("only apply to prog arrays")));
+        assert!(tail_call_errors.iter().any(|v| v.contains("no_tail_call")));
+        assert!(find(DiagnosticKind::ExportType, "export_types[0]")
+            .message
+            .contains("type names don't match"));
+        assert!(find(DiagnosticKind::DefaultValue, "arguments")
+            .message
+            .contains("min_duration_ns"));
+    }
+
+    #[test]
+    fn test_validate_initial_entries() {
+        let mut package = load_bootstrap();
+        package.meta.bpf_skel.maps[0].initial_entries = vec![
+            MapEntry {
+                key: json!(1),
+                value: Some(json!(2)),
+            },
+            MapEntry {
+                key: json!("abc"),
+                value: None,
+            },
+        ];
+        package.meta.bpf_skel.maps[1].initial_entries = vec![MapEntry {
+            key: json!(1),
+            value: None,
+        }];
+        let report = package.validate();
+        let messages = |map: &str| {
+            report
+                .diagnostics
+                .iter()
+                .filter(|v| v.kind == DiagnosticKind::DefaultValue && v.location == map)
+                .map(|v| v.message.clone())
+                .collect::<Vec<_>>()
+        };
+        let exec_start = messages("map `exec_start`");
+        assert_eq!(exec_start.len(), 1);
+        assert!(exec_start[0].contains("abc"));
+        // Ringbufs have no keys
+        assert!(messages("map `rb`")[0].contains("BTF info"));
+    }
+
+    #[test]
+    fn test_validate_inner_maps() {
+        let mut package = load_bootstrap();
+        package.meta.bpf_skel.maps[0].inner_map = Some(
+            serde_json::from_value(json!({
+                "template": {
+                    "map_type": "no_such_type",
+                    "key_size": 4,
+                    "value_size": 4,
+                    "max_entries": 1
+                }
+            }))
+            .unwrap(),
+        );
+        let report = package.validate();
+        let messages = report
+            .diagnostics
+            .iter()
+            .filter(|v| v.kind == DiagnosticKind::Definition && v.location == "map `exec_start`")
+            .map(|v| v.message.as_str())
+            .collect::<Vec<_>>();
+        assert!(messages
+            .iter()
+            .any(|v| v.contains("only apply to map-in-maps")));
+        assert!(messages.iter().any(|v| v.contains("no_such_type")));
+    }
+    #[test]
+    fn test_validate_sockmap() {
+        let mut package = load_bootstrap();
+        let progs = &mut package.meta.bpf_skel.progs;
+        progs[0].attach = "sk_msg".into();
+        progs[0].others = json!({ "sockmap": "exec_start" });
+        progs[1].attach = "sk_skb/stream_verdict".into();
+        progs[1].others = json!({});
+        let report = package.validate();
+        let message_of = |idx: usize| {
+            let location = format!("program `{}`", package.meta.bpf_skel.progs[idx].name);
+            report
+                .diagnostics
+                .iter()
+                .find(|v| v.kind == DiagnosticKind::Definition && v.location == location)
+                .map(|v| v.message.clone())
+                .unwrap()
+        };
+        assert!(message_of(0).contains("`exec_start` is a `hash` map"));
+        assert!(message_of(1).contains("Invalid sockmap extra meta"));
+    }
+    #[test]
+    fn test_validate_usdt() {
+        let mut package = load_bootstrap();
+        let prog = &mut package.meta.bpf_skel.progs[1];
+        prog.attach = "usdt".into();
+        prog.others = json!({ "provider": "python", "probe": "function__entry" });
+        let report = package.validate();
+        let location = format!("program `{}`", package.meta.bpf_skel.progs[1].name);
+        let message = report
+            .diagnostics
+            .iter()
+            .find(|v| v.kind == DiagnosticKind::Definition && v.location == location)
+            .map(|v| v.message.clone())
+            .unwrap();
+        assert!(message.contains("Invalid usdt extra meta"));
+        assert!(message.contains("binary_path"));
+    }
+}
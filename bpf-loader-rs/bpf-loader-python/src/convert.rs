@@ -0,0 +1,138 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! Conversions between python objects and json values, so values from and to the bpf program are native python objects
+
+use anyhow::{anyhow, bail, Result};
+use pyo3::{
+    types::{PyBool, PyDict, PyFloat, PyList, PyLong, PyString, PyTuple},
+    IntoPy, PyAny, PyObject, Python,
+};
+use serde_json::{Map, Number, Value};
+
+/// Convert a python object to json. Integers that don't fit in 64 bits are converted to strings, which the bpf-loader accepts for 128-bit integers
+pub(crate) fn py_to_value(obj: &PyAny) -> Result<Value> {
+    Ok(if obj.is_none() {
+        Value::Null
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        Value::Bool(b.is_true())
+    } else if let Ok(i) = obj.downcast::<PyLong>() {
+        if let Ok(v) = i.extract::<i64>() {
+            Value::from(v)
+        } else if let Ok(v) = i.extract::<u64>() {
+            Value::from(v)
+        } else {
+            Value::String(i.str()?.to_str()?.to_string())
+        }
+    } else if let Ok(f) = obj.downcast::<PyFloat>() {
+        Number::from_f64(f.value())
+            .map(Value::Number)
+            .ok_or_else(|| anyhow!("{} can't be represented in json", f.value()))?
+    } else if let Ok(s) = obj.downcast::<PyString>() {
+        Value::String(s.to_str()?.to_string())
+    } else if let Ok(list) = obj.downcast::<PyList>() {
+        Value::Array(list.iter().map(py_to_value).collect::<Result<_>>()?)
+    } else if let Ok(tuple) = obj.downcast::<PyTuple>() {
+        Value::Array(tuple.iter().map(py_to_value).collect::<Result<_>>()?)
+    } else if let Ok(dict) = obj.downcast::<PyDict>() {
+        let mut map = Map::new();
+        for (key, value) in dict.iter() {
+            let key = key
+                .downcast::<PyString>()
+                .map_err(|_| anyhow!("Keys of dicts must be strings, found {}", key))?;
+            map.insert(key.to_str()?.to_string(), py_to_value(value)?);
+        }
+        Value::Object(map)
+    } else {
+        bail!(
+            "Unsupported python type `{}`",
+            obj.get_type().name().unwrap_or("<unknown>")
+        )
+    })
+}
+
+/// Convert a json value to python objects
+pub(crate) fn value_to_py(py: Python, value: &Value) -> PyObject {
+    match value {
+        Value::Null => py.None(),
+        Value::Bool(b) => b.into_py(py),
+        Value::Number(num) => {
+            if let Some(v) = num.as_i64() {
+                v.into_py(py)
+            } else if let Some(v) = num.as_u64() {
+                v.into_py(py)
+            } else {
+                num.as_f64().unwrap_or(f64::NAN).into_py(py)
+            }
+        }
+        Value::String(s) => s.into_py(py),
+        Value::Array(arr) => PyList::new(py, arr.iter().map(|v| value_to_py(py, v))).into(),
+        Value::Object(obj) => {
+            let dict = PyDict::new(py);
+            for (key, value) in obj.iter() {
+                // Setting items with a string key never fails
+                dict.set_item(key, value_to_py(py, value)).unwrap();
+            }
+            dict.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{types::PyDict, Python};
+    use serde_json::json;
+
+    use super::{py_to_value, value_to_py};
+
+    #[test]
+    fn test_value_to_py() {
+        // Shaped like an event decoded by the json exporter
+        let value = json!({
+            "pid": 1234,
+            "comm": "bash",
+            "ts": 18446744073709551615u64,
+            "delta": -5,
+            "ratio": 0.5,
+            "flag": true,
+            "arr": [[1, 2], [3, 4]],
+            "inner": {"a": null, "b": "x"},
+        });
+        Python::with_gil(|py| {
+            let obj = value_to_py(py, &value);
+            let expected = py
+                .eval(
+                    r#"{"pid": 1234, "comm": "bash", "ts": 2**64 - 1, "delta": -5, "ratio": 0.5,
+                        "flag": True, "arr": [[1, 2], [3, 4]], "inner": {"a": None, "b": "x"}}"#,
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert!(obj.as_ref(py).downcast::<PyDict>().is_ok());
+            assert!(obj.as_ref(py).eq(expected).unwrap());
+            assert_eq!(py_to_value(obj.as_ref(py)).unwrap(), value);
+        });
+    }
+
+    #[test]
+    fn test_py_to_value() {
+        Python::with_gil(|py| {
+            let obj = py
+                .eval(
+                    r#"{"big": 2**100, "tuple": (1, "a"), "neg": -1}"#,
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(
+                py_to_value(obj).unwrap(),
+                json!({"big": "1267650600228229401496703205376", "tuple": [1, "a"], "neg": -1})
+            );
+            assert!(py_to_value(py.eval("{1: 2}", None, None).unwrap()).is_err());
+            assert!(py_to_value(py.eval("object()", None, None).unwrap()).is_err());
+        });
+    }
+}
@@ -0,0 +1,303 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! Deliver events polled in a background thread to python iterators
+
+use std::{
+    any::Any,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use bpf_loader_lib::{
+    export_event::{EventHandler, ReceivedEventData},
+    skeleton::handle::PollingHandle,
+};
+use log::warn;
+use pyo3::{
+    exceptions::PyStopAsyncIteration, pyclass, pymethods, IntoPy, Py, PyObject, PyRef, PyResult,
+    Python,
+};
+use serde_json::Value;
+
+use crate::{convert::value_to_py, PollingHandleWrapper};
+
+/// How long to wait for events before checking python signals, so Ctrl-C works while iterating
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+const TERMINATE_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Sends events to the iterator. Json events are sent as the decoded values, texts are sent as strings
+pub(crate) struct ChannelEventHandler {
+    pub(crate) sender: Sender<Value>,
+}
+
+impl EventHandler for ChannelEventHandler {
+    fn handle_event(&self, _context: Option<Arc<dyn Any>>, data: ReceivedEventData) {
+        let value = match data {
+            ReceivedEventData::JsonText(s) => match serde_json::from_str(s) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Dropped malformed json event: {}", e);
+                    return;
+                }
+            },
+            ReceivedEventData::PlainText(s) | ReceivedEventData::KernelDebugText(s) => {
+                Value::String(s.to_string())
+            }
+            _ => return,
+        };
+        // The iterator was dropped. The poller will be terminated soon
+        let _ = self.sender.send(value);
+    }
+    fn handle_json_event(&self, _context: Option<Arc<dyn Any>>, value: &Value) {
+        let _ = self.sender.send(value.clone());
+    }
+}
+
+/// Discards events of the maps that the iterator doesn't want
+pub(crate) struct DiscardEventHandler;
+
+impl EventHandler for DiscardEventHandler {
+    fn handle_event(&self, _context: Option<Arc<dyn Any>>, _data: ReceivedEventData) {}
+}
+
+/// Clears the polling flag of the skeleton when the polling thread exits
+pub(crate) struct PollerGuard(pub(crate) Arc<AtomicBool>);
+
+impl Drop for PollerGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// An iterator of events. Each event is a dict decoded with the BTF of the program
+///
+/// The iteration stops when the poller is terminated by a `PollingHandle`
+#[pyclass(name = "EventIterator")]
+pub(crate) struct EventIterator {
+    pub(crate) receiver: Mutex<Receiver<Value>>,
+    pub(crate) handle: PollingHandle,
+    pub(crate) poller: Mutex<Option<JoinHandle<Result<()>>>>,
+}
+
+fn join_poller(poller: Option<JoinHandle<Result<()>>>) -> Result<()> {
+    match poller {
+        Some(poller) => poller
+            .join()
+            .map_err(|_| anyhow!("The polling thread panicked"))?,
+        None => Ok(()),
+    }
+}
+
+/// The poller resets the handle when it starts, so keep terminating it until it exits
+fn terminate_poller(handle: &PollingHandle, poller: Option<JoinHandle<Result<()>>>) -> Result<()> {
+    if let Some(poller) = poller.as_ref() {
+        while !poller.is_finished() {
+            handle.terminate();
+            std::thread::sleep(TERMINATE_CHECK_INTERVAL);
+        }
+    }
+    join_poller(poller)
+}
+
+impl EventIterator {
+    /// Wait for the next event. Returns None once the poller exits
+    fn next_event(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        loop {
+            let receiver = &self.receiver;
+            let result =
+                py.allow_threads(|| receiver.lock().unwrap().recv_timeout(SIGNAL_CHECK_INTERVAL));
+            match result {
+                Ok(v) => return Ok(Some(value_to_py(py, &v))),
+                Err(RecvTimeoutError::Timeout) => py.check_signals()?,
+                Err(RecvTimeoutError::Disconnected) => {
+                    let poller = self.poller.lock().unwrap().take();
+                    py.allow_threads(|| join_poller(poller))?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl EventIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.next_event(py)
+    }
+    /// A handle to pause or terminate the poller
+    fn handle(&self) -> PollingHandleWrapper {
+        PollingHandleWrapper {
+            handle: self.handle.clone(),
+        }
+    }
+    /// Terminate the poller and wait for it to exit
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        let poller = self.poller.lock().unwrap().take();
+        let handle = &self.handle;
+        py.allow_threads(|| terminate_poller(handle, poller))?;
+        Ok(())
+    }
+}
+
+impl Drop for EventIterator {
+    fn drop(&mut self) {
+        if let Err(e) = terminate_poller(&self.handle, self.poller.get_mut().unwrap().take()) {
+            warn!("Poller exited with error: {:?}", e);
+        }
+    }
+}
+
+/// An async iterator of events, for `async for`. Events are the same as `EventIterator`
+///
+/// Each event is waited in the default executor of the running event loop, so the loop isn't blocked
+#[pyclass(name = "AsyncEventIterator")]
+pub(crate) struct AsyncEventIterator {
+    pub(crate) inner: Py<EventIterator>,
+}
+
+#[pymethods]
+impl AsyncEventIterator {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    fn __anext__(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let next = slf.into_py(py).getattr(py, "_next_blocking")?;
+        let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+        Ok(Some(
+            event_loop
+                .call_method1("run_in_executor", (py.None(), next))?
+                .into(),
+        ))
+    }
+    /// Called in the executor. Raises `StopAsyncIteration` once the poller exits
+    fn _next_blocking(&self, py: Python<'_>) -> PyResult<PyObject> {
+        self.inner
+            .borrow(py)
+            .next_event(py)?
+            .ok_or_else(|| PyStopAsyncIteration::new_err(()))
+    }
+    /// A handle to pause or terminate the poller
+    fn handle(&self, py: Python<'_>) -> PollingHandleWrapper {
+        self.inner.borrow(py).handle()
+    }
+    /// Terminate the poller and wait for it to exit. A pending `__anext__` raises `StopAsyncIteration`
+    fn close(&self, py: Python<'_>) -> PyResult<()> {
+        self.inner.borrow(py).close(py)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc::channel, Mutex};
+
+    use bpf_loader_lib::{
+        export_event::{EventHandler, ReceivedEventData},
+        skeleton::handle::PollingHandle,
+    };
+    use pyo3::{types::PyDict, Py, Python};
+    use serde_json::{json, Value};
+
+    use super::{AsyncEventIterator, ChannelEventHandler, EventIterator};
+
+    /// An iterator fed by the returned handler instead of a poller
+    fn iterator_with_handler() -> (EventIterator, ChannelEventHandler) {
+        let (sender, receiver) = channel();
+        (
+            EventIterator {
+                receiver: Mutex::new(receiver),
+                handle: PollingHandle::default(),
+                poller: Mutex::new(None),
+            },
+            ChannelEventHandler { sender },
+        )
+    }
+
+    #[test]
+    fn test_channel_event_handler() {
+        let (sender, receiver) = channel();
+        let handler = ChannelEventHandler { sender };
+        handler.handle_json_event(None, &json!({"pid": 1}));
+        handler.handle_event(None, ReceivedEventData::JsonText(r#"{"pid":2}"#));
+        handler.handle_event(None, ReceivedEventData::PlainText("TIME PID"));
+        handler.handle_event(None, ReceivedEventData::JsonText("{malformed"));
+        handler.handle_event(None, ReceivedEventData::Buffer(&[1, 2]));
+        drop(handler);
+        assert_eq!(
+            receiver.iter().collect::<Vec<Value>>(),
+            vec![json!({"pid": 1}), json!({"pid": 2}), json!("TIME PID")]
+        );
+    }
+
+    #[test]
+    fn test_event_iterator() {
+        let (iter, handler) = iterator_with_handler();
+        handler.handle_json_event(None, &json!({"pid": 1, "comm": "bash"}));
+        handler.handle_json_event(None, &json!({"pid": 2, "comm": "sh"}));
+        drop(handler);
+        Python::with_gil(|py| {
+            let iter = Py::new(py, iter).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("events", iter).unwrap();
+            let events = py.eval("list(events)", None, Some(locals)).unwrap();
+            let expected = py
+                .eval(
+                    r#"[{"pid": 1, "comm": "bash"}, {"pid": 2, "comm": "sh"}]"#,
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert!(events.eq(expected).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_async_event_iterator() {
+        let (iter, handler) = iterator_with_handler();
+        let feeder = std::thread::spawn(move || {
+            for pid in 0..3 {
+                handler.handle_json_event(None, &json!({ "pid": pid }));
+            }
+        });
+        Python::with_gil(|py| {
+            let iter = AsyncEventIterator {
+                inner: Py::new(py, iter).unwrap(),
+            };
+            let globals = PyDict::new(py);
+            globals
+                .set_item("events", Py::new(py, iter).unwrap())
+                .unwrap();
+            py.run(
+                r#"
+import asyncio
+async def collect():
+    return [event["pid"] async for event in events]
+result = asyncio.run(collect())
+"#,
+                Some(globals),
+                None,
+            )
+            .unwrap();
+            let result: Vec<i64> = globals
+                .get_item("result")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(result, vec![0, 1, 2]);
+        });
+        feeder.join().unwrap();
+    }
+}
@@ -0,0 +1,408 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! # Python bindings of bpf-loader-rs
+//!
+//! The classes mirror the ones in `bpf_loader_lib::skeleton`:
+//! - `BpfSkeletonBuilder`: Create it from a package, fill the arguments, then build a `PreLoadBpfSkeleton`
+//! - `PreLoadBpfSkeleton`: Call `load_and_attach` to get a `BpfSkeleton`
+//! - `BpfSkeleton`: Iterate events with `events` or `events_async`, access maps and global variables
+//! - `PollingHandle`: Pause or terminate the poller from another thread
+//!
+//! Values passed to and received from the bpf program are native python objects, converted with the BTF of the program in the same way as the json exporter
+
+use std::{
+    fs::File,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::channel,
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{anyhow, Context};
+use bpf_loader_lib::{
+    export_event::{EventHandler, ExportFormatType},
+    meta::{arg_parser::UnpresentVariableAction, ComposedObject, EunomiaObjectMeta, RunnerConfig},
+    skeleton::{
+        builder::BpfSkeletonBuilder, handle::PollingHandle, preload::PreLoadBpfSkeleton,
+        BpfSkeleton,
+    },
+};
+use convert::{py_to_value, value_to_py};
+use events::{
+    AsyncEventIterator, ChannelEventHandler, DiscardEventHandler, EventIterator, PollerGuard,
+};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    pyclass, pymethods, pymodule,
+    types::{PyDict, PyModule},
+    Py, PyAny, PyErr, PyObject, PyResult, Python,
+};
+
+mod convert;
+mod events;
+
+/// Where the skeleton comes from
+enum SkeletonSource {
+    Package(ComposedObject),
+    Object {
+        meta: EunomiaObjectMeta,
+        bpf_object: Vec<u8>,
+    },
+}
+
+impl SkeletonSource {
+    fn meta(&self) -> &EunomiaObjectMeta {
+        match self {
+            SkeletonSource::Package(package) => &package.meta,
+            SkeletonSource::Object { meta, .. } => meta,
+        }
+    }
+    fn meta_mut(&mut self) -> &mut EunomiaObjectMeta {
+        match self {
+            SkeletonSource::Package(package) => &mut package.meta,
+            SkeletonSource::Object { meta, .. } => meta,
+        }
+    }
+}
+
+/// Builder of `PreLoadBpfSkeleton`
+#[pyclass(name = "BpfSkeletonBuilder")]
+struct BpfSkeletonBuilderWrapper {
+    source: SkeletonSource,
+    btf_archive_path: Option<String>,
+    runner_config: RunnerConfig,
+}
+
+impl BpfSkeletonBuilderWrapper {
+    fn new(source: SkeletonSource, btf_archive_path: Option<String>) -> Self {
+        Self {
+            source,
+            btf_archive_path,
+            runner_config: RunnerConfig::default(),
+        }
+    }
+}
+
+#[pymethods]
+impl BpfSkeletonBuilderWrapper {
+    /// Create a builder from the json package in a string
+    #[staticmethod]
+    #[pyo3(signature = (package, btf_archive_path = None))]
+    fn from_json_package(package: &str, btf_archive_path: Option<String>) -> PyResult<Self> {
+        let package = serde_json::from_str::<ComposedObject>(package)
+            .map_err(|e| anyhow!("Failed to parse the package: {}", e))?;
+        Ok(Self::new(
+            SkeletonSource::Package(package),
+            btf_archive_path,
+        ))
+    }
+    /// Create a builder from the json package at the path
+    #[staticmethod]
+    #[pyo3(signature = (path, btf_archive_path = None))]
+    fn from_package_file(path: &str, btf_archive_path: Option<String>) -> PyResult<Self> {
+        let file = File::open(path).with_context(|| anyhow!("Failed to open {}", path))?;
+        let package = ComposedObject::from_reader(file)
+            .with_context(|| anyhow!("Failed to read the package {}", path))?;
+        Ok(Self::new(
+            SkeletonSource::Package(package),
+            btf_archive_path,
+        ))
+    }
+    /// Create a builder from the meta json in a string and the ELF
+    #[staticmethod]
+    #[pyo3(signature = (meta, bpf_object, btf_archive_path = None))]
+    fn from_object_meta_and_object_buffer(
+        meta: &str,
+        bpf_object: &[u8],
+        btf_archive_path: Option<String>,
+    ) -> PyResult<Self> {
+        let meta = serde_json::from_str::<EunomiaObjectMeta>(meta)
+            .map_err(|e| anyhow!("Failed to parse the meta: {}", e))?;
+        Ok(Self::new(
+            SkeletonSource::Object {
+                meta,
+                bpf_object: bpf_object.to_vec(),
+            },
+            btf_archive_path,
+        ))
+    }
+    /// Fill the global variables with a dict keyed by their names, like the command line arguments of ecli
+    ///
+    /// Variables not provided keep their default values, or zero
+    fn fill_args(&mut self, args: &PyDict) -> PyResult<()> {
+        let args = py_to_value(args)?;
+        self.source.meta_mut().fill_skeleton_variables_from_json(
+            args.as_object()
+                .ok_or_else(|| anyhow!("Arguments must be a dict"))?,
+            UnpresentVariableAction::FillWithZero,
+        )?;
+        Ok(())
+    }
+    /// Set the runner config, with the same fields as the config file. It overrides the config file and env vars
+    fn set_runner_config(&mut self, config: &PyDict) -> PyResult<()> {
+        self.runner_config = serde_json::from_value(py_to_value(config)?)
+            .map_err(|e| anyhow!("Invalid runner config: {}", e))?;
+        Ok(())
+    }
+    /// Verify and open the skeleton
+    fn build(&self) -> PyResult<PreLoadBpfSkeletonWrapper> {
+        let runner_config = RunnerConfig::load_layered(None, self.runner_config.clone())
+            .with_context(|| anyhow!("Failed to load runner config"))?;
+        let btf_archive_path = self.btf_archive_path.as_deref();
+        let builder = match &self.source {
+            SkeletonSource::Package(package) => {
+                BpfSkeletonBuilder::from_json_package(package, btf_archive_path)
+            }
+            SkeletonSource::Object { meta, bpf_object } => {
+                BpfSkeletonBuilder::from_object_meta_and_object_buffer(
+                    meta,
+                    bpf_object,
+                    btf_archive_path,
+                )
+            }
+        };
+        let skel = builder
+            .set_runner_config(runner_config)
+            .build()
+            .with_context(|| anyhow!("Failed to build"))?;
+        Ok(PreLoadBpfSkeletonWrapper {
+            skel: Some(skel),
+            multiple_export: self.source.meta().enable_multiple_export_types,
+        })
+    }
+}
+
+/// An opened skeleton, ready to be loaded
+#[pyclass(name = "PreLoadBpfSkeleton", unsendable)]
+struct PreLoadBpfSkeletonWrapper {
+    skel: Option<PreLoadBpfSkeleton>,
+    /// `enable_multiple_export_types` of the package
+    multiple_export: bool,
+}
+
+#[pymethods]
+impl PreLoadBpfSkeletonWrapper {
+    /// Load the programs into the kernel and attach them. Could only be called once
+    fn load_and_attach(&mut self) -> PyResult<BpfSkeletonWrapper> {
+        let skel = self
+            .skel
+            .take()
+            .ok_or_else(|| anyhow!("The skeleton was already loaded"))?;
+        let skel = skel
+            .load_and_attach()
+            .with_context(|| anyhow!("Failed to load and attach"))?;
+        Ok(BpfSkeletonWrapper {
+            skel: Arc::new(SharedSkeleton(skel)),
+            polling: Arc::new(AtomicBool::new(false)),
+            multiple_export: self.multiple_export,
+        })
+    }
+}
+
+/// A `BpfSkeleton` shared between the python thread and the polling thread
+struct SharedSkeleton(BpfSkeleton);
+// SAFETY: The only fields of `BpfSkeleton` that aren't Send are the libbpf-rs `Object`, `Map`, `Program`
+// and `Link`, which are raw pointers to libbpf structs. libbpf has no thread affinity, so they could be
+// used and dropped from any thread, and `SharedSkeleton` is only dropped once, by the last `Arc`.
+unsafe impl Send for SharedSkeleton {}
+// SAFETY: After `load_and_attach` returns, nothing mutates the libbpf structs: every method reachable
+// through `&BpfSkeleton` only reads names and fds from them, then issues syscalls on the fds, or writes
+// to a fresh mmap of the `.bss` map. Links are only detached on drop, which requires exclusive access.
+// The state that the poller mutates is the `PollingHandle`, which is atomic.
+unsafe impl Sync for SharedSkeleton {}
+
+/// A loaded and attached skeleton
+#[pyclass(name = "BpfSkeleton")]
+struct BpfSkeletonWrapper {
+    skel: Arc<SharedSkeleton>,
+    /// Whether a poller started by `events` is running. They would share the polling handle, so only one is allowed
+    polling: Arc<AtomicBool>,
+    /// Whether events could be told apart by map. Required to poll a single map
+    multiple_export: bool,
+}
+
+/// Only packages with multiple export types give each map its own exporter, so events of one map could be picked
+fn check_map_filter(map: Option<&str>, multiple_export: bool) -> PyResult<()> {
+    match map {
+        Some(name) if !multiple_export => Err(PyValueError::new_err(format!(
+            "Can't receive events of map `{name}` only: the package doesn't enable `enable_multiple_export_types`. Omit `map` to receive events of all export maps"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+impl BpfSkeletonWrapper {
+    fn start_polling(&self, map: Option<String>) -> PyResult<EventIterator> {
+        check_map_filter(map.as_deref(), self.multiple_export)?;
+        if self
+            .polling
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(PyErr::new::<PyRuntimeError, _>(
+                "Another event iterator of this skeleton is running. Close it first",
+            ));
+        }
+        let guard = PollerGuard(self.polling.clone());
+        let (sender, receiver) = channel();
+        let skel = self.skel.clone();
+        let handle = skel.0.create_poll_handle();
+        let poller = std::thread::spawn(move || {
+            let _guard = guard;
+            let handler: Arc<dyn EventHandler> = Arc::new(ChannelEventHandler { sender });
+            match map {
+                None => {
+                    skel.0
+                        .wait_and_poll_to_handler(ExportFormatType::Json, Some(handler), None)
+                }
+                Some(name) => skel.0.wait_and_poll_to_handler_with_multiple_exporter(|v| {
+                    let handler: Arc<dyn EventHandler> = if v == name {
+                        handler.clone()
+                    } else {
+                        Arc::new(DiscardEventHandler)
+                    };
+                    Some((ExportFormatType::Json, handler, None))
+                }),
+            }
+        });
+        Ok(EventIterator {
+            receiver: Mutex::new(receiver),
+            handle,
+            poller: Mutex::new(Some(poller)),
+        })
+    }
+}
+
+#[pymethods]
+impl BpfSkeletonWrapper {
+    /// Name of the bpf program
+    #[getter]
+    fn program_name(&self) -> String {
+        self.skel.0.get_program_name().to_string()
+    }
+    /// Create a handle to pause or terminate the poller
+    fn create_poll_handle(&self) -> PollingHandleWrapper {
+        PollingHandleWrapper {
+            handle: self.skel.0.create_poll_handle(),
+        }
+    }
+    /// Start polling in a background thread, and return an iterator of events
+    ///
+    /// If `map` is provided, only events from that map are received. Otherwise events of all export maps are received.
+    /// `map` requires the package to enable `enable_multiple_export_types`, or `ValueError` is raised
+    ///
+    /// Only one iterator could run at the same time. Close the previous one, or let it finish, before calling it again
+    #[pyo3(signature = (map = None))]
+    fn events(&self, map: Option<String>) -> PyResult<EventIterator> {
+        self.start_polling(map)
+    }
+    /// Same as `events`, but return an async iterator for `async for`
+    ///
+    /// Waiting for the next event runs in the default executor of the running event loop
+    #[pyo3(signature = (map = None))]
+    fn events_async(&self, py: Python<'_>, map: Option<String>) -> PyResult<AsyncEventIterator> {
+        Ok(AsyncEventIterator {
+            inner: Py::new(py, self.start_polling(map)?)?,
+        })
+    }
+    /// Get the fd of a map
+    fn get_map_fd(&self, name: &str) -> Option<i32> {
+        self.skel.0.get_map_fd(name)
+    }
+    /// Get the fd of a program
+    fn get_prog_fd(&self, name: &str) -> Option<i32> {
+        self.skel.0.get_prog_fd(name)
+    }
+    /// List programs, as dicts
+    fn list_programs(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let programs = serde_json::to_value(self.skel.0.list_programs())
+            .map_err(|e| anyhow!("Failed to serialize: {}", e))?;
+        Ok(value_to_py(py, &programs))
+    }
+    /// List maps, as dicts
+    fn list_maps(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let maps = serde_json::to_value(self.skel.0.list_maps())
+            .map_err(|e| anyhow!("Failed to serialize: {}", e))?;
+        Ok(value_to_py(py, &maps))
+    }
+    /// Read a global variable in `.rodata` or `.bss`
+    fn get_variable(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        Ok(value_to_py(py, &self.skel.0.get_variable(name)?))
+    }
+    /// Set a global variable in `.bss`
+    fn set_variable(&self, name: &str, value: &PyAny) -> PyResult<()> {
+        self.skel.0.set_variable(name, &py_to_value(value)?)?;
+        Ok(())
+    }
+    /// Look up a key in a map. Returns None if the key doesn't exist
+    fn lookup_map(&self, py: Python<'_>, map: &str, key: &PyAny) -> PyResult<Option<PyObject>> {
+        Ok(self
+            .skel
+            .0
+            .lookup_map(map, &py_to_value(key)?)?
+            .map(|v| value_to_py(py, &v)))
+    }
+    /// Insert or update a key in a map
+    fn update_map(&self, map: &str, key: &PyAny, value: &PyAny) -> PyResult<()> {
+        self.skel
+            .0
+            .update_map(map, &py_to_value(key)?, &py_to_value(value)?)?;
+        Ok(())
+    }
+}
+
+/// A handle to control the poller
+#[pyclass(name = "PollingHandle")]
+pub(crate) struct PollingHandleWrapper {
+    pub(crate) handle: PollingHandle,
+}
+
+#[pymethods]
+impl PollingHandleWrapper {
+    /// Pause or resume the poller
+    fn set_pause(&self, pause: bool) {
+        self.handle.set_pause(pause);
+    }
+    /// Terminate the poller. The event iterator will stop
+    fn terminate(&self) {
+        self.handle.terminate();
+    }
+}
+
+#[pymodule]
+fn eunomia_bpf(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<BpfSkeletonBuilderWrapper>()?;
+    m.add_class::<PreLoadBpfSkeletonWrapper>()?;
+    m.add_class::<BpfSkeletonWrapper>()?;
+    m.add_class::<PollingHandleWrapper>()?;
+    m.add_class::<EventIterator>()?;
+    m.add_class::<AsyncEventIterator>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pyo3::{exceptions::PyValueError, Python};
+
+    use super::check_map_filter;
+
+    #[test]
+    fn test_check_map_filter() {
+        assert!(check_map_filter(None, false).is_ok());
+        assert!(check_map_filter(None, true).is_ok());
+        assert!(check_map_filter(Some("events"), true).is_ok());
+        let err = check_map_filter(Some("events"), false).unwrap_err();
+        Python::with_gil(|py| {
+            assert!(err.is_instance_of::<PyValueError>(py));
+            assert!(err
+                .value(py)
+                .to_string()
+                .contains("enable_multiple_export_types"));
+        });
+    }
+}
@@ -6,7 +6,7 @@
 use crate::bpf_compiler::standalone::build_standalone_executable;
 use crate::config::{
     fetch_btfhub_repo, generate_tailored_btf, get_base_dir_include_args, get_bpf_sys_include_args,
-    get_bpftool_path, get_eunomia_include_args, package_btfhub_tar, Options,
+    get_bpftool_path, get_eunomia_include_args, package_btfhub_tar, Options, PackageCompression,
 };
 use crate::document_parser::parse_source_documents;
 use crate::export_types::{add_unused_ptr_for_structs, find_all_export_structs};
@@ -22,6 +22,11 @@ use std::io::prelude::*;
 use std::path::PathBuf;
 use std::{fs, path::Path};
 
+/// The package envelope version that `bpf-loader-lib` understands
+const PACKAGE_ENVELOPE_VERSION: u32 = 1;
+const ZSTD_COMPRESSION_LEVEL: i32 = 19;
+
+pub(crate) mod signature;
 pub(crate) mod standalone;
 
 /// compile bpf object
@@ -192,9 +197,17 @@ fn pack_object_in_config(args: &Options) -> Result<()> {
     let output_bpf_object_path = args.get_output_object_path();
     let bpf_object = fs::read(output_bpf_object_path)?;
 
-    let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-    e.write_all(&bpf_object)?;
-    let compressed_bytes = e.finish().unwrap();
+    let compression = args.compile_opts.compression;
+    let compressed_bytes = match compression {
+        PackageCompression::Zlib => {
+            let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+            e.write_all(&bpf_object)?;
+            e.finish().unwrap()
+        }
+        PackageCompression::Zstd => zstd::encode_all(&bpf_object[..], ZSTD_COMPRESSION_LEVEL)
+            .with_context(|| anyhow!("Failed to compress the object with zstd"))?,
+        PackageCompression::Uncompressed => bpf_object.clone(),
+    };
     let encode_bpf_object = base64::encode(compressed_bytes);
     let output_json_path = args.get_output_config_path();
     let meta_json_str = fs::read_to_string(&output_json_path).unwrap();
@@ -203,11 +216,19 @@ fn pack_object_in_config(args: &Options) -> Result<()> {
     } else {
         serde_yaml::from_str(&meta_json_str).unwrap()
     };
-    let package_config = json!({
+    let mut package_config = json!({
+        "version": PACKAGE_ENVELOPE_VERSION,
+        "content_type": compression.content_type(),
         "bpf_object": encode_bpf_object,
         "bpf_object_size": bpf_object.len(),
         "meta": meta_json,
     });
+    if let Some(key_path) = &args.compile_opts.sign_key {
+        info!("Signing the package with {}..", key_path);
+        let key = signature::load_signing_key(key_path)?;
+        package_config["signature"] =
+            signature::sign_package(&bpf_object, &package_config["meta"], &key)?;
+    }
     if args.compile_opts.yaml {
         let output_package_config_path = Path::new(&output_json_path)
             .parent()
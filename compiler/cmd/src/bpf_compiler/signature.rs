@@ -0,0 +1,99 @@
+//!  SPDX-License-Identifier: MIT
+//!
+//! Copyright (c) 2023, eunomia-bpf
+//! All rights reserved.
+//!
+
+//! Sign packages in the way `bpf_loader_lib::signature` verifies them:
+//! ed25519 over `"eunomia-bpf package signature v1\0" + sha256(ELF) + sha256(canonical meta json)`
+
+use std::{io::Write, path::Path};
+
+use anyhow::{anyhow, Context, Result};
+use ed25519_dalek::{pkcs8::DecodePrivateKey, Signer, SigningKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+const SIGNATURE_DOMAIN: &[u8] = b"eunomia-bpf package signature v1\0";
+
+/// Load a PEM (PKCS#8) ed25519 private key
+pub(crate) fn load_signing_key(path: impl AsRef<Path>) -> Result<SigningKey> {
+    let path = path.as_ref();
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| anyhow!("Failed to read private key {}", path.display()))?;
+    SigningKey::from_pkcs8_pem(&pem)
+        .map_err(|e| anyhow!("Invalid ed25519 private key {}: {}", path.display(), e))
+}
+
+/// Produce the `signature` field of the package
+pub(crate) fn sign_package(bpf_object: &[u8], meta: &Value, key: &SigningKey) -> Result<Value> {
+    let mut canonical_meta = vec![];
+    write_canonical_json(meta, &mut canonical_meta)?;
+    let mut payload = SIGNATURE_DOMAIN.to_vec();
+    payload.extend_from_slice(&Sha256::digest(bpf_object));
+    payload.extend_from_slice(&Sha256::digest(&canonical_meta));
+    let sig = key.sign(&payload);
+    Ok(json!({
+        "public_key": base64::encode(key.verifying_key().as_bytes()),
+        "signature": base64::encode(sig.to_bytes()),
+    }))
+}
+
+/// Compact json, with object keys sorted
+fn write_canonical_json(value: &Value, out: &mut Vec<u8>) -> Result<()> {
+    match value {
+        Value::Array(arr) => {
+            out.push(b'[');
+            for (i, v) in arr.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_canonical_json(v, out)?;
+            }
+            out.push(b']');
+        }
+        Value::Object(obj) => {
+            let mut keys = obj.keys().collect::<Vec<_>>();
+            keys.sort();
+            out.push(b'{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                serde_json::to_writer(&mut *out, key)?;
+                out.push(b':');
+                write_canonical_json(&obj[key], out)?;
+            }
+            out.push(b'}');
+        }
+        v => out.write_all(&serde_json::to_vec(v)?)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signature, SigningKey, Verifier};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    use super::{sign_package, SIGNATURE_DOMAIN};
+
+    #[test]
+    fn test_sign_package() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let signature =
+            sign_package(b"elf", &json!({"b": [1, {"d": 1, "c": 2}], "a": "x"}), &key).unwrap();
+        let mut payload = SIGNATURE_DOMAIN.to_vec();
+        payload.extend_from_slice(&Sha256::digest(b"elf"));
+        payload.extend_from_slice(&Sha256::digest(br#"{"a":"x","b":[1,{"c":2,"d":1}]}"#));
+        let sig = base64::decode(signature["signature"].as_str().unwrap()).unwrap();
+        key.verifying_key()
+            .verify(&payload, &Signature::from_slice(&sig).unwrap())
+            .unwrap();
+        assert_eq!(
+            base64::decode(signature["public_key"].as_str().unwrap()).unwrap(),
+            key.verifying_key().as_bytes()
+        );
+    }
+}
@@ -4,7 +4,7 @@
 //! All rights reserved.
 //!
 use anyhow::{anyhow, bail, Context, Result};
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, Parser, ValueEnum};
 use fs_extra::dir::CopyOptions;
 use log::debug;
 use rust_embed::RustEmbed;
@@ -89,6 +89,20 @@ pub struct CompileArgs {
     #[arg(long, default_value_t = false, help = "generate wasm include header")]
     pub wasm_header: bool,
 
+    #[arg(
+        long,
+        help = "sign the package with the ed25519 private key (PKCS#8 PEM) at this path"
+    )]
+    pub sign_key: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = PackageCompression::Zlib,
+        help = "how the ebpf object is compressed in the package. zstd ones need a newer loader"
+    )]
+    pub compression: PackageCompression,
+
     #[arg(
         short,
         long,
@@ -104,6 +118,29 @@ pub struct CompileArgs {
     pub parameters: CompileExtraArgs,
 }
 
+/// How the ebpf object is compressed in the package, written to the `content_type` field
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PackageCompression {
+    /// zlib deflate, readable by all versions of loaders
+    #[default]
+    Zlib,
+    /// zstd, smaller and faster to decode
+    Zstd,
+    /// no compression
+    Uncompressed,
+}
+
+impl PackageCompression {
+    /// The value of `content_type` in the package
+    pub fn content_type(self) -> &'static str {
+        match self {
+            PackageCompression::Zlib => "zlib",
+            PackageCompression::Zstd => "zstd",
+            PackageCompression::Uncompressed => "uncompressed",
+        }
+    }
+}
+
 #[derive(Parser, Debug, Default, Clone)]
 pub struct CompileExtraArgs {
     #[arg(short, long, help = "custom workspace path")]
@@ -34,6 +34,12 @@ pub enum ClientSubCommand {
     #[clap(name = "stop", about = "Stop running a task on the specified endpoint")]
     Stop(StopCommand),
 
+    #[clap(
+        name = "replace",
+        about = "Replace a running task with a new version of its program, keeping the contents of compatible maps"
+    )]
+    Replace(ReplaceCommand),
+
     #[clap(name = "log", about = "Fetch logs of the given task")]
     Log(LogCommand),
 
@@ -74,6 +80,29 @@ pub struct StartCommand {
     pub prog_type: Option<ProgramType>,
 }
 
+#[derive(Parser)]
+pub struct ReplaceCommand {
+    #[clap(required = true, help = "ID of the task")]
+    pub id: ProgramHandle,
+    #[clap(
+        required = true,
+        help = "ebpf program URL or local path, set it `-` to read the program from stdin"
+    )]
+    pub prog: String,
+    #[clap(
+        long,
+        short,
+        help = "New name of the task. Keeps the old one if not set"
+    )]
+    pub name: Option<String>,
+    #[clap(long, short = 'j', help = "Use json as output format")]
+    pub export_json: bool,
+    #[clap(action = ArgAction::Append, help = "Extra args to the program")]
+    pub extra_args: Vec<String>,
+    #[clap(long, short, help = "Manually specity the program type", value_parser = crate::helper::prog_type_value_parser)]
+    pub prog_type: Option<ProgramType>,
+}
+
 #[derive(Parser)]
 pub struct LogCommand {
     #[clap(required = true, help = "ID of the task")]
@@ -127,6 +156,19 @@ pub(crate) async fn handle_client_command(cmd: ClientCmd) -> anyhow::Result<()>
         ClientSubCommand::Stop(StopCommand { id }) => {
             client.terminate_program(id).await?;
         }
+        ClientSubCommand::Replace(ReplaceCommand {
+            id,
+            prog,
+            name,
+            export_json,
+            extra_args,
+            prog_type: user_prog_type,
+        }) => {
+            let (buf, prog_type) = load_prog_buf_and_guess_type(&prog, user_prog_type).await?;
+            client
+                .replace_program(id, name, &buf, prog_type, export_json, &extra_args, None)
+                .await?;
+        }
 
         ClientSubCommand::Pause(PauseCommand { id }) => {
             client.set_program_pause_state(id, true).await?;
@@ -137,6 +179,16 @@ pub(crate) async fn handle_client_command(cmd: ClientCmd) -> anyhow::Result<()>
         ClientSubCommand::List => {
             for item in client.get_program_list().await? {
                 println!("{} {} {:?}", item.id, item.name, item.status);
+                for prog in item.stats.iter() {
+                    println!(
+                        "    {} run_cnt={} run_time_ns={} recursion_misses={} verified_insns={}",
+                        prog.name,
+                        prog.run_cnt,
+                        prog.run_time_ns,
+                        prog.recursion_misses,
+                        prog.verified_insns
+                    );
+                }
             }
         }
         ClientSubCommand::Log(LogCommand { id, follow, cursor }) => {
@@ -6,16 +6,22 @@
 use base64::Engine;
 use ecli_server_codegen::{
     client::HyperClient,
-    models::{GeneralError, GetTaskLogRequest, SimpleIdRequest, StartTaskRequest},
+    models::{
+        GeneralError, GetTaskLogRequest, ReplaceTaskRequest, SimpleIdRequest, StartTaskRequest,
+    },
     ApiNoContext, Client, ContextWrapperExt, GetTaskListResponse, GetTaskLogByIdResponse,
-    PauseTaskByIdResponse, ResumeTaskByIdResponse, StartTaskResponse, StopTaskByIdResponse,
+    PauseTaskByIdResponse, ReplaceTaskByIdResponse, ResumeTaskByIdResponse, StartTaskResponse,
+    StopTaskByIdResponse,
 };
 use swagger::{ContextBuilder, ContextWrapper, DropContextService, EmptyContext, XSpanIdString};
 
 use crate::{
     config::ProgramType,
     error::{Error, Result},
-    runner::{client::ProgramStatus, LogEntry, LogType, ProgramHandle},
+    runner::{
+        client::{ProgramStats, ProgramStatus},
+        LogEntry, LogType, ProgramHandle,
+    },
 };
 use swagger::Push;
 
@@ -109,6 +115,51 @@ impl AbstractClient for EcliHttpClient {
         }
     }
 
+    async fn replace_program(
+        &self,
+        handle: crate::runner::ProgramHandle,
+        name: Option<String>,
+        prog_buf: &[u8],
+        prog_type: ProgramType,
+        export_json: bool,
+        args: &[String],
+        btf_archive_path: Option<String>,
+    ) -> crate::error::Result<()> {
+        let file_contents = prog_buf.to_vec();
+        let b64 = tokio::task::spawn_blocking(move || {
+            base64::engine::general_purpose::STANDARD_NO_PAD.encode(file_contents)
+        })
+        .await
+        .map_err(|e| Error::Other(format!("Failed to join: {}", e)))?;
+        use ecli_server_codegen::models::ProgramType;
+        match self
+            .client
+            .replace_task_by_id(ReplaceTaskRequest {
+                id: handle,
+                btf_archive_path,
+                program_name: name,
+                export_json: Some(export_json),
+                extra_args: Some(args.to_vec()),
+                program_data_buf: b64,
+                program_type: match prog_type {
+                    crate::config::ProgramType::JsonEunomia => ProgramType::Json,
+                    crate::config::ProgramType::WasmModule => ProgramType::Wasm,
+                    crate::config::ProgramType::Tar => ProgramType::Tar,
+                },
+            })
+            .await
+            .map_err(|e| Error::Http(format!("Failed to replace program: {:?}", e)))?
+        {
+            ReplaceTaskByIdResponse::StatusOfReplacingTheTask(_) => Ok(()),
+            ReplaceTaskByIdResponse::InvalidArguments(GeneralError { message }) => {
+                Err(Error::Bpf(message))
+            }
+            ReplaceTaskByIdResponse::InvalidHandle(GeneralError { message }) => {
+                Err(Error::InvalidParam(message))
+            }
+        }
+    }
+
     async fn set_program_pause_state(
         &self,
         handle: crate::runner::ProgramHandle,
@@ -207,6 +258,18 @@ impl AbstractClient for EcliHttpClient {
                     HttpTaskStatus::Running => ProgramStatus::Running,
                     HttpTaskStatus::Paused => ProgramStatus::Paused,
                 },
+                stats: v
+                    .prog_stats
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|s| ProgramStats {
+                        name: s.name,
+                        run_cnt: s.run_cnt,
+                        run_time_ns: s.run_time_ns,
+                        recursion_misses: s.recursion_misses,
+                        verified_insns: s.verified_insns,
+                    })
+                    .collect(),
             })
             .collect())
     }
@@ -21,12 +21,23 @@ pub enum ProgramStatus {
     Running,
     Paused,
 }
+/// Runtime statistics of a bpf program in a task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramStats {
+    pub name: String,
+    pub run_cnt: u64,
+    pub run_time_ns: u64,
+    pub recursion_misses: u64,
+    pub verified_insns: u32,
+}
 /// Description of an exist program
 #[derive(Debug, Clone)]
 pub struct ProgramDesc {
     pub id: ProgramHandle,
     pub name: String,
     pub status: ProgramStatus,
+    /// Statistics of the bpf programs. Empty for wasm modules
+    pub stats: Vec<ProgramStats>,
 }
 /// Common interfaces for client
 #[async_trait::async_trait]
@@ -41,6 +52,18 @@ pub trait AbstractClient {
         btf_archive_path: Option<String>,
     ) -> Result<ProgramHandle>;
     async fn terminate_program(&self, handle: ProgramHandle) -> Result<()>;
+    /// Replace a running program with a new version of its package, keeping the contents of compatible maps
+    #[allow(clippy::too_many_arguments)]
+    async fn replace_program(
+        &self,
+        handle: ProgramHandle,
+        name: Option<String>,
+        prog_buf: &[u8],
+        prog_type: ProgramType,
+        export_json: bool,
+        args: &[String],
+        btf_archive_path: Option<String>,
+    ) -> Result<()>;
     async fn set_program_pause_state(&self, handle: ProgramHandle, pause: bool) -> Result<()>;
     async fn fetch_logs(
         &self,
@@ -60,6 +60,31 @@ impl AbstractClient for EcliNativeClient {
             .map_err(|e| Error::Other(format!("Failed to terminate: {:?}", e)))?;
         Ok(())
     }
+    async fn replace_program(
+        &self,
+        handle: ProgramHandle,
+        name: Option<String>,
+        prog_buf: &[u8],
+        prog_type: ProgramType,
+        export_json: bool,
+        args: &[String],
+        btf_archive_path: Option<String>,
+    ) -> Result<()> {
+        self.manager
+            .write()
+            .unwrap()
+            .replace_task(
+                handle,
+                name,
+                prog_buf,
+                prog_type,
+                export_json,
+                args,
+                btf_archive_path,
+            )
+            .map_err(|e| Error::Other(format!("Failed to replace: {:?}", e)))?;
+        Ok(())
+    }
     async fn set_program_pause_state(&self, handle: ProgramHandle, pause: bool) -> Result<()> {
         let task_handle = {
             let guard = self.manager.read().unwrap();
@@ -12,14 +12,18 @@ use ecli_server_codegen::{
         TaskListResponseTasksInner,
     },
     Api, GetTaskListResponse, GetTaskLogByIdResponse, PauseTaskByIdResponse,
-    ResumeTaskByIdResponse, StartTaskResponse, StopTaskByIdResponse,
+    ReplaceTaskByIdResponse, ResumeTaskByIdResponse, StartTaskResponse, StopTaskByIdResponse,
 };
 use serde_json::json;
 use swagger::{ApiError, Has, XSpanIdString};
 
 use crate::{config::ProgramType, runner::DEFAULT_MAXIMUM_LOG_ENTRIES};
 
-use super::{client::ProgramStatus, task_manager::NativeTaskManager, LogType};
+use super::{
+    client::{ProgramDesc, ProgramStatus},
+    task_manager::NativeTaskManager,
+    LogType,
+};
 /// The AppState
 #[derive(Clone)]
 pub struct HttpServerState {
@@ -49,6 +53,29 @@ impl Has<XSpanIdString> for HttpServerState {
     }
 }
 
+fn program_desc_to_http(desc: ProgramDesc) -> TaskListResponseTasksInner {
+    TaskListResponseTasksInner {
+        id: desc.id,
+        name: desc.name,
+        status: match desc.status {
+            ProgramStatus::Running => models::TaskStatus::Running,
+            ProgramStatus::Paused => models::TaskStatus::Paused,
+        },
+        prog_stats: (!desc.stats.is_empty()).then(|| {
+            desc.stats
+                .into_iter()
+                .map(|v| models::ProgStats {
+                    name: v.name,
+                    run_cnt: v.run_cnt,
+                    run_time_ns: v.run_time_ns,
+                    recursion_misses: v.recursion_misses,
+                    verified_insns: v.verified_insns,
+                })
+                .collect()
+        }),
+    }
+}
+
 /// The server api implementations
 #[derive(Clone)]
 pub struct EcliHttpServerAPI;
@@ -63,14 +90,7 @@ impl Api<HttpServerState> for EcliHttpServerAPI {
         let task_list = guard
             .get_task_list()
             .into_iter()
-            .map(|v| TaskListResponseTasksInner {
-                id: v.id,
-                name: v.name,
-                status: match v.status {
-                    super::client::ProgramStatus::Running => models::TaskStatus::Running,
-                    super::client::ProgramStatus::Paused => models::TaskStatus::Paused,
-                },
-            })
+            .map(program_desc_to_http)
             .collect();
         Ok(GetTaskListResponse::ListOfRunningTasks(
             models::TaskListResponse { tasks: task_list },
@@ -151,6 +171,64 @@ impl Api<HttpServerState> for EcliHttpServerAPI {
         ))
     }
 
+    /// Replace a running task with a new version of its package
+    async fn replace_task_by_id(
+        &self,
+        replace_task_request: models::ReplaceTaskRequest,
+        context: &HttpServerState,
+    ) -> Result<ReplaceTaskByIdResponse, ApiError> {
+        let models::ReplaceTaskRequest {
+            id,
+            program_data_buf,
+            program_type,
+            program_name,
+            btf_archive_path,
+            extra_args,
+            export_json,
+        } = replace_task_request;
+        log::info!("Replacing task: {}", id);
+        if context.task_manager.read().unwrap().get_task(id).is_none() {
+            return Ok(ReplaceTaskByIdResponse::InvalidHandle(GeneralError {
+                message: format!("Invalid handle: {}", id),
+            }));
+        }
+        let extra_args = extra_args.unwrap_or_default();
+        let export_json = export_json.unwrap_or(false);
+
+        let buf = match tokio::task::spawn_blocking(move || {
+            base64::engine::general_purpose::STANDARD_NO_PAD.decode(program_data_buf)
+        })
+        .await
+        .map_err(|e| ApiError(format!("Failed to await blocking task: {}", e)))?
+        {
+            Ok(v) => v,
+            Err(e) => {
+                return Ok(ReplaceTaskByIdResponse::InvalidArguments(GeneralError {
+                    message: format!("Invalid base64: {}", e),
+                }));
+            }
+        };
+        use ecli_server_codegen::models::ProgramType as HttpProgramType;
+        if let Err(e) = context.task_manager.write().unwrap().replace_task(
+            id,
+            program_name,
+            &buf,
+            match program_type {
+                HttpProgramType::Json => ProgramType::JsonEunomia,
+                HttpProgramType::Wasm => ProgramType::WasmModule,
+                HttpProgramType::Tar => ProgramType::Tar,
+            },
+            export_json,
+            &extra_args,
+            btf_archive_path,
+        ) {
+            return Ok(ReplaceTaskByIdResponse::InvalidArguments(GeneralError {
+                message: format!("Failed to replace task: {}", e),
+            }));
+        }
+        Ok(ReplaceTaskByIdResponse::StatusOfReplacingTheTask(json!({})))
+    }
+
     /// Resume a task by id
     async fn resume_task_by_id(
         &self,
@@ -233,21 +311,13 @@ impl Api<HttpServerState> for EcliHttpServerAPI {
             }
         };
         let running_tasks = guard.get_task_list();
-        use ecli_server_codegen::models::TaskStatus as HttpTaskStatus;
         Ok(StartTaskResponse::ListOfRunningTasks(
             models::StartTask200Response {
                 id: handle as u64,
                 task_list: TaskListResponse {
                     tasks: running_tasks
                         .into_iter()
-                        .map(|v| TaskListResponseTasksInner {
-                            status: match v.status {
-                                ProgramStatus::Paused => HttpTaskStatus::Paused,
-                                ProgramStatus::Running => HttpTaskStatus::Running,
-                            },
-                            id: v.id,
-                            name: v.name,
-                        })
+                        .map(program_desc_to_http)
                         .collect(),
                 },
             },
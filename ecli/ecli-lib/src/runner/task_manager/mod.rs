@@ -17,8 +17,15 @@ use std::{
 use bpf_compatible_rs::{tempfile::TempDir, unpack_tar};
 use bpf_loader_lib::{
     export_event::{EventHandler, ExportFormatType, ReceivedEventData},
-    meta::ComposedObject,
-    skeleton::{builder::BpfSkeletonBuilder, handle::PollingHandle},
+    meta::{ComposedObject, RunnerConfig},
+    signature::TrustStore,
+    skeleton::{
+        builder::BpfSkeletonBuilder,
+        handle::PollingHandle,
+        handover::{HandoverHandle, SkeletonHandover},
+        stats::StatsHandle,
+        verifier::VerifierError,
+    },
 };
 use wasm_bpf_rs::{
     handle::WasmProgramHandle, pipe::ReadableWritePipe, run_wasm_bpf_module_async, Config,
@@ -30,7 +37,7 @@ use crate::{
 };
 
 use super::{
-    client::{ProgramDesc, ProgramStatus},
+    client::{ProgramDesc, ProgramStats, ProgramStatus},
     LogEntry, ProgramHandle,
 };
 
@@ -104,6 +111,7 @@ impl NativeTaskManager {
                     } else {
                         ProgramStatus::Paused
                     },
+                    stats: guard.prog_stats(),
                 }
             })
             .collect()
@@ -124,50 +132,81 @@ impl NativeTaskManager {
                 ));
             }
         };
-        match task.inner_impl {
-            TaskImpl::Wasm {
-                thread_handle,
-                should_exit,
-                prog_handle, // Drop it freely!!!!!!
-            } => {
-                prog_handle
-                    .terminate()
-                    .map_err(|e| Error::Wasm(format!("Failed to terminate wasm program: {}", e)))?;
-
-                // Notify the copying thread to exit
-                should_exit.store(true, std::sync::atomic::Ordering::Relaxed);
-
-                if let Err(e) = thread_handle
-                    .join()
-                    .map_err(|_| Error::ThreadJoin("Failed to join".to_string()))?
-                {
-                    let formatted_str = format!("{:?}", e);
-                    if formatted_str.contains("Wasm program terminated")
-                        || formatted_str.contains("receiving on a closed channel")
-                    {
-                    } else {
-                        return Err(Error::Bpf(format!(
-                            "Failed to wait for the worker: {:?}",
-                            e
-                        )));
-                    }
+        task.terminate()
+    }
+    /// Replace a running task with a new version of the package, keeping its id and logs. If `name` is not provided, the old one is kept
+    ///
+    /// Compatible maps of the old task are reused, and its links are updated to the new programs if the kernel supports it. The old task is terminated once the new one is running
+    #[allow(clippy::too_many_arguments)]
+    pub fn replace_task(
+        &mut self,
+        handle: ProgramHandle,
+        name: Option<String>,
+        prog_buf: &[u8],
+        prog_type: ProgramType,
+        export_json: bool,
+        args: &[String],
+        btf_archive_path: Option<String>,
+    ) -> Result<()> {
+        let id = handle as usize;
+        let (name, replacing) = {
+            let old = self
+                .tasks
+                .get(&id)
+                .ok_or_else(|| Error::Bpf(format!("Invalid handle: {}", handle)))?
+                .lock()
+                .unwrap();
+            let TaskImpl::BpfLoader {
+                handover_handle, ..
+            } = &old.inner_impl
+            else {
+                return Err(Error::InvalidParam(
+                    "Only tasks of json packages could be replaced".to_string(),
+                ));
+            };
+            let handover_handle = handover_handle.as_ref().ok_or_else(|| {
+                Error::Bpf(format!(
+                    "Task `{}` can't be replaced, since its maps and links couldn't be recorded",
+                    old.name
+                ))
+            })?;
+            (
+                name.unwrap_or_else(|| old.name.clone()),
+                Replacing {
+                    handover: handover_handle.acquire().map_err(|e| {
+                        Error::Bpf(format!("Failed to acquire maps and links: {:?}", e))
+                    })?,
+                    log_buffer: old.log_buffer.clone(),
+                    log_cursor: old.next_cursor.clone(),
+                },
+            )
+        };
+        let task = Self::create_task(
+            name,
+            prog_buf,
+            prog_type,
+            export_json,
+            args,
+            btf_archive_path,
+            Some(replacing),
+        )?;
+        let old = self
+            .tasks
+            .insert(id, Arc::new(Mutex::new(task)))
+            .expect("The task was checked above");
+        match Arc::try_unwrap(old) {
+            Ok(old) => old.into_inner().unwrap().terminate(),
+            Err(old) => {
+                // Keep the old one, since nobody could stop it
+                if let Ok(new) = Arc::try_unwrap(self.tasks.insert(id, old).unwrap()) {
+                    new.into_inner().unwrap().terminate()?;
                 }
-            }
-            TaskImpl::BpfLoader {
-                polling_handle,
-                join_handle,
-                ..
-            } => {
-                polling_handle.terminate();
-                join_handle
-                    .join()
-                    .map_err(|_| Error::ThreadJoin("Failed to join".to_string()))?
-                    .map_err(|e| {
-                        Error::Bpf(format!("Failed to wait for the thread's exiting: {:?}", e))
-                    })?;
+                Err(Error::Bpf(
+                    "Some others is holding a reference to the Task, cannot replace the program"
+                        .to_string(),
+                ))
             }
         }
-        Ok(())
     }
     /// Start a task
     pub fn start_task(
@@ -182,6 +221,27 @@ impl NativeTaskManager {
         let id = self
             .next_task_id
             .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let task = Self::create_task(
+            name,
+            prog_buf,
+            prog_type,
+            export_json,
+            args,
+            btf_archive_path,
+            None,
+        )?;
+        self.tasks.insert(id, Arc::new(Mutex::new(task)));
+        Ok(id)
+    }
+    fn create_task(
+        name: impl Into<String>,
+        prog_buf: &[u8],
+        prog_type: ProgramType,
+        export_json: bool,
+        args: &[String],
+        btf_archive_path: Option<String>,
+        replacing: Option<Replacing>,
+    ) -> Result<Task> {
         let (buf, ty, btf) = if matches!(prog_type, ProgramType::Tar) {
             let v =
                 unpack_tar(prog_buf).map_err(|e| Error::Tar(format!("Failed to unpack: {}", e)))?;
@@ -203,16 +263,44 @@ impl NativeTaskManager {
                 },
             )
         };
-        let log_buffer = Arc::new(RwLock::new(Vec::<(usize, LogEntry)>::new()));
-        let log_cursor = Arc::new(AtomicUsize::new(0));
+        let (log_buffer, log_cursor, handover) = match replacing {
+            Some(v) => (v.log_buffer, v.log_cursor, Some(v.handover)),
+            None => (
+                Arc::new(RwLock::new(Vec::<(usize, LogEntry)>::new())),
+                Arc::new(AtomicUsize::new(0)),
+                None,
+            ),
+        };
+        // ecli has no runner config flags of its own, so only the config file and env vars apply
+        let runner_config = RunnerConfig::load_layered(None, RunnerConfig::default())
+            .map_err(|e| Error::InvalidParam(format!("Failed to load runner config: {:?}", e)))?;
+        let trust_store = runner_config
+            .trust_store
+            .as_ref()
+            .map(|v| {
+                TrustStore::load(v).map(Arc::new).map_err(|e| {
+                    Error::InvalidParam(format!("Failed to load trust store: {:?}", e))
+                })
+            })
+            .transpose()?;
         let task = match ty {
             ProgramType::JsonEunomia => {
                 let log_buffer_inner = log_buffer.clone();
                 let log_cursor_inner = log_cursor.clone();
-                let (tx, rx) = std::sync::mpsc::channel::<PollingHandle>();
+                let (tx, rx) = std::sync::mpsc::channel::<(
+                    PollingHandle,
+                    StatsHandle,
+                    Option<HandoverHandle>,
+                )>();
                 let mut package = serde_json::from_slice::<ComposedObject>(&buf).map_err(|e| {
                     Error::InvalidParam(format!("Failed to deserialize package to object: {}", e))
                 })?;
+                // Reject untrusted packages here, so the client gets a clear error instead of a failed thread
+                if let Some(trust_store) = &trust_store {
+                    package.verify_signature(trust_store).map_err(|e| {
+                        Error::InvalidParam(format!("Failed to verify the package: {:?}", e))
+                    })?;
+                }
                 let arg_parser = package
                     .meta
                     .build_argument_parser()
@@ -231,12 +319,39 @@ impl NativeTaskManager {
                     .map_err(|e| Error::Bpf(format!("Failed to parse arguments: {}", e)))?;
                 let btf_path = btf.extract_archive_path().map(|e| e.to_string());
                 let join_handle = std::thread::spawn(move || {
-                    let skel = BpfSkeletonBuilder::from_json_package(&package, btf_path.as_deref())
+                    let mut builder =
+                        BpfSkeletonBuilder::from_json_package(&package, btf_path.as_deref())
+                            .set_runner_config(runner_config);
+                    if let Some(trust_store) = trust_store {
+                        builder = builder.set_trust_store(trust_store);
+                    }
+                    if let Some(handover) = handover {
+                        builder = builder.set_handover(handover);
+                    }
+                    let skel = builder
                         .build()
                         .map_err(|e| Error::Bpf(format!("Failed to build skeleton: {:?}", e)))?
                         .load_and_attach()
-                        .map_err(|e| Error::Bpf(format!("Failed to load and attach: {:?}", e)))?;
-                    tx.send(skel.create_poll_handle()).unwrap();
+                        .map_err(|e| {
+                            Error::Bpf(match e.downcast_ref::<VerifierError>() {
+                                Some(verifier_error) => format!(
+                                    "Failed to load and attach: {:?}\n{}",
+                                    e,
+                                    verifier_error.report()
+                                ),
+                                None => format!("Failed to load and attach: {:?}", e),
+                            })
+                        })?;
+                    let stats_handle = skel.create_stats_handle().map_err(|e| {
+                        Error::Bpf(format!("Failed to create stats handle: {:?}", e))
+                    })?;
+                    // It's only needed to replace the task, so don't fail the task if it's unavailable
+                    let handover_handle = skel
+                        .create_handover_handle()
+                        .map_err(|e| log::warn!("Failed to create handover handle: {:?}", e))
+                        .ok();
+                    tx.send((skel.create_poll_handle(), stats_handle, handover_handle))
+                        .unwrap();
                     skel.wait_and_poll_to_handler(
                         if export_json {
                             ExportFormatType::Json
@@ -253,11 +368,13 @@ impl NativeTaskManager {
                     Result::Ok(())
                 });
                 match rx.recv() {
-                    Ok(v) => {
+                    Ok((polling_handle, stats_handle, handover_handle)) => {
                         let done = join_handle.is_finished();
                         Task {
                             inner_impl: TaskImpl::BpfLoader {
-                                polling_handle: v,
+                                polling_handle,
+                                stats_handle,
+                                handover_handle,
                                 join_handle,
                                 btf_archive_tempdir: btf.extract_tempdir(),
                             },
@@ -277,6 +394,17 @@ impl NativeTaskManager {
                 }
             }
             ProgramType::WasmModule => {
+                if handover.is_some() {
+                    return Err(Error::InvalidParam(
+                        "A json package can only be replaced by another one".to_string(),
+                    ));
+                }
+                if trust_store.is_some() {
+                    return Err(Error::InvalidParam(
+                        "Wasm modules can't be signed, so they are refused when a trust store is set"
+                            .to_string(),
+                    ));
+                }
                 let log_buffer_inner = log_buffer.clone();
                 let log_cursor_inner = log_cursor.clone();
                 let should_exit = Arc::new(AtomicBool::new(false));
@@ -347,18 +475,23 @@ impl NativeTaskManager {
             }
             _ => unreachable!(),
         };
-        self.tasks.insert(id, Arc::new(Mutex::new(task)));
-        Ok(id)
+        Ok(task)
     }
 }
 
+/// What a task takes over from the one it replaces
+struct Replacing {
+    handover: SkeletonHandover,
+    log_buffer: Arc<RwLock<Vec<(usize, LogEntry)>>>,
+    log_cursor: Arc<AtomicUsize>,
+}
+
 /// A running task
 pub struct Task {
     inner_impl: TaskImpl,
     running: bool,
     log_buffer: Arc<RwLock<Vec<(usize, LogEntry)>>>,
     name: String,
-    #[allow(unused)]
     next_cursor: Arc<AtomicUsize>,
 }
 
@@ -383,6 +516,74 @@ impl Task {
         let max_count = maximum.min(guard.len());
         guard[..max_count].to_vec()
     }
+    /// Get runtime statistics of the bpf programs. Wasm modules have none
+    pub fn prog_stats(&self) -> Vec<ProgramStats> {
+        let TaskImpl::BpfLoader { stats_handle, .. } = &self.inner_impl else {
+            return vec![];
+        };
+        match stats_handle.prog_stats() {
+            Ok(stats) => stats
+                .into_iter()
+                .map(|v| ProgramStats {
+                    name: v.name,
+                    run_cnt: v.run_cnt,
+                    run_time_ns: v.run_time_ns,
+                    recursion_misses: v.recursion_misses,
+                    verified_insns: v.verified_insns,
+                })
+                .collect(),
+            Err(e) => {
+                log::warn!("Failed to query stats of task `{}`: {:?}", self.name, e);
+                vec![]
+            }
+        }
+    }
+    fn terminate(self) -> Result<()> {
+        match self.inner_impl {
+            TaskImpl::Wasm {
+                thread_handle,
+                should_exit,
+                prog_handle, // Drop it freely!!!!!!
+            } => {
+                prog_handle
+                    .terminate()
+                    .map_err(|e| Error::Wasm(format!("Failed to terminate wasm program: {}", e)))?;
+
+                // Notify the copying thread to exit
+                should_exit.store(true, std::sync::atomic::Ordering::Relaxed);
+
+                if let Err(e) = thread_handle
+                    .join()
+                    .map_err(|_| Error::ThreadJoin("Failed to join".to_string()))?
+                {
+                    let formatted_str = format!("{:?}", e);
+                    if formatted_str.contains("Wasm program terminated")
+                        || formatted_str.contains("receiving on a closed channel")
+                    {
+                    } else {
+                        return Err(Error::Bpf(format!(
+                            "Failed to wait for the worker: {:?}",
+                            e
+                        )));
+                    }
+                }
+            }
+            TaskImpl::BpfLoader {
+                polling_handle,
+                join_handle,
+                ..
+            } => {
+                polling_handle.terminate();
+                join_handle
+                    .join()
+                    .map_err(|_| Error::ThreadJoin("Failed to join".to_string()))?
+                    .map_err(|e| {
+                        Error::Bpf(format!("Failed to wait for the thread's exiting: {:?}", e))
+                    })?;
+            }
+        }
+        Ok(())
+    }
     fn died(&self) -> bool {
         match &self.inner_impl {
             TaskImpl::BpfLoader { join_handle, .. } => join_handle.is_finished(),
@@ -418,6 +619,9 @@ enum TaskImpl {
     },
     BpfLoader {
         polling_handle: PollingHandle,
+        stats_handle: StatsHandle,
+        /// None if the maps and links couldn't be recorded, then the task can't be replaced
+        handover_handle: Option<HandoverHandle>,
         join_handle: JoinHandle<Result<()>>,
         #[allow(unused)]
         /// It's only be used to keep liveness
@@ -438,7 +642,10 @@ impl EventHandler for MyEventHandler {
     ) {
         let mut guard = self.log_buffer.write().unwrap();
         match data {
-            ReceivedEventData::JsonText(j) | ReceivedEventData::PlainText(j) => {
+            // bpf_printk lines are logged along with the regular events
+            ReceivedEventData::JsonText(j)
+            | ReceivedEventData::PlainText(j)
+            | ReceivedEventData::KernelDebugText(j) => {
                 guard.push((
                     self.log_cursor
                         .fetch_add(1, std::sync::atomic::Ordering::Relaxed),
@@ -119,6 +119,9 @@ async fn test_load_json_prog() {
     assert_eq!(progs[0].id, json_handle);
     assert_eq!(progs[0].name, "my-json-program");
     assert_eq!(progs[0].status, ProgramStatus::Running);
+    // Stats of both programs of bootstrap should be reported
+    assert_eq!(progs[0].stats.len(), 2);
+    assert!(progs[0].stats.iter().all(|v| v.verified_insns > 0));
 
     tokio::time::sleep(Duration::from_secs(5)).await;
     // Let's see the logs
@@ -307,6 +310,68 @@ async fn test_terminate_json_program() {
     stop_tx.send(()).await.unwrap();
 }
 #[tokio::test(flavor = "multi_thread")]
+async fn test_replace_json_program() {
+    let (client, stop_tx) = prepare_server_and_client(8569).await;
+    let json_buf = std::fs::read(get_local_dir().join("bootstrap.json")).unwrap();
+    let wasm_buf = std::fs::read(get_local_dir().join("bootstrap.wasm")).unwrap();
+    let json_handle = client
+        .start_program(
+            Some("my-json-program".to_string()),
+            &json_buf,
+            ProgramType::JsonEunomia,
+            false,
+            &[],
+            None,
+        )
+        .await
+        .unwrap();
+    client
+        .replace_program(
+            json_handle,
+            None,
+            &json_buf,
+            ProgramType::JsonEunomia,
+            false,
+            &[],
+            None,
+        )
+        .await
+        .unwrap();
+    // The id and the name are kept
+    let list = client.get_program_list().await.unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].id, json_handle);
+    assert_eq!(list[0].name, "my-json-program");
+    assert_eq!(list[0].status, ProgramStatus::Running);
+    // Only json packages could take over
+    assert!(client
+        .replace_program(
+            json_handle,
+            None,
+            &wasm_buf,
+            ProgramType::WasmModule,
+            false,
+            &[],
+            None,
+        )
+        .await
+        .is_err());
+    assert!(client
+        .replace_program(
+            json_handle + 1,
+            None,
+            &json_buf,
+            ProgramType::JsonEunomia,
+            false,
+            &[],
+            None,
+        )
+        .await
+        .is_err());
+    client.terminate_program(json_handle).await.unwrap();
+    stop_tx.send(()).await.unwrap();
+}
+#[tokio::test(flavor = "multi_thread")]
 async fn test_running_multiple_programs() {
     let (client, stop_tx) = prepare_server_and_client(8568).await;
     let json_buf = std::fs::read(get_local_dir().join("bootstrap.json")).unwrap();
@@ -4,8 +4,8 @@ use clap::{App, Arg};
 #[allow(unused_imports)]
 use ecli_server_codegen::{
     models, Api, ApiNoContext, Client, ContextWrapperExt, GetTaskListResponse,
-    GetTaskLogByIdResponse, PauseTaskByIdResponse, ResumeTaskByIdResponse, StartTaskResponse,
-    StopTaskByIdResponse,
+    GetTaskLogByIdResponse, PauseTaskByIdResponse, ReplaceTaskByIdResponse, ResumeTaskByIdResponse,
+    StartTaskResponse, StopTaskByIdResponse,
 };
 #[allow(unused_imports)]
 use futures::{future, stream, Stream};
@@ -113,6 +113,14 @@ fn main() {
         },
         */
         /* Disabled because there's no example.
+        Some("ReplaceTaskById") => {
+            let result = rt.block_on(client.replace_task_by_id(
+                  ???
+            ));
+            info!("{:?} (X-Span-ID: {:?})", result, (client.context() as &dyn Has<XSpanIdString>).get().clone());
+        },
+        */
+        /* Disabled because there's no example.
         Some("ResumeTaskById") => {
             let result = rt.block_on(client.resume_task_by_id(
                   ???
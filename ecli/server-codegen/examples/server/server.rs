@@ -101,7 +101,7 @@ impl<C> Server<C> {
 use ecli_server_codegen::server::MakeService;
 use ecli_server_codegen::{
     Api, GetTaskListResponse, GetTaskLogByIdResponse, PauseTaskByIdResponse,
-    ResumeTaskByIdResponse, StartTaskResponse, StopTaskByIdResponse,
+    ReplaceTaskByIdResponse, ResumeTaskByIdResponse, StartTaskResponse, StopTaskByIdResponse,
 };
 use std::error::Error;
 use swagger::ApiError;
@@ -148,6 +148,21 @@ where
         Err(ApiError("Generic failure".into()))
     }
 
+    /// Replace a running task with a new version of its package
+    async fn replace_task_by_id(
+        &self,
+        replace_task_request: models::ReplaceTaskRequest,
+        context: &C,
+    ) -> Result<ReplaceTaskByIdResponse, ApiError> {
+        let context = context.clone();
+        info!(
+            "replace_task_by_id({:?}) - X-Span-ID: {:?}",
+            replace_task_request,
+            context.get().0.clone()
+        );
+        Err(ApiError("Generic failure".into()))
+    }
+
     /// Resume a task by id
     async fn resume_task_by_id(
         &self,
@@ -48,6 +48,17 @@ pub enum PauseTaskByIdResponse {
     StatusOfPausingTheTask(models::TaskStatus),
 }
 
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[must_use]
+pub enum ReplaceTaskByIdResponse {
+    /// Status of replacing the task
+    StatusOfReplacingTheTask(serde_json::Value),
+    /// Invalid arguments
+    InvalidArguments(models::GeneralError),
+    /// Invalid handle
+    InvalidHandle(models::GeneralError),
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[must_use]
 pub enum ResumeTaskByIdResponse {
@@ -107,6 +118,13 @@ pub trait Api<C: Send + Sync> {
         context: &C,
     ) -> Result<PauseTaskByIdResponse, ApiError>;
 
+    /// Replace a running task with a new version of its package
+    async fn replace_task_by_id(
+        &self,
+        replace_task_request: models::ReplaceTaskRequest,
+        context: &C,
+    ) -> Result<ReplaceTaskByIdResponse, ApiError>;
+
     /// Resume a task by id
     async fn resume_task_by_id(
         &self,
@@ -155,6 +173,12 @@ pub trait ApiNoContext<C: Send + Sync> {
         simple_id_request: models::SimpleIdRequest,
     ) -> Result<PauseTaskByIdResponse, ApiError>;
 
+    /// Replace a running task with a new version of its package
+    async fn replace_task_by_id(
+        &self,
+        replace_task_request: models::ReplaceTaskRequest,
+    ) -> Result<ReplaceTaskByIdResponse, ApiError>;
+
     /// Resume a task by id
     async fn resume_task_by_id(
         &self,
@@ -227,6 +251,17 @@ impl<T: Api<C> + Send + Sync, C: Clone + Send + Sync> ApiNoContext<C> for Contex
             .await
     }
 
+    /// Replace a running task with a new version of its package
+    async fn replace_task_by_id(
+        &self,
+        replace_task_request: models::ReplaceTaskRequest,
+    ) -> Result<ReplaceTaskByIdResponse, ApiError> {
+        let context = self.context().clone();
+        self.api()
+            .replace_task_by_id(replace_task_request, &context)
+            .await
+    }
+
     /// Resume a task by id
     async fn resume_task_by_id(
         &self,
@@ -715,6 +715,462 @@ impl std::str::FromStr for ProgramType {
     }
 }
 
+/// Runtime statistics of a bpf program, read from bpf_prog_info
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "conversion", derive(frunk::LabelledGeneric))]
+pub struct ProgStats {
+    /// The name of the program
+    #[serde(rename = "name")]
+    pub name: String,
+
+    /// How many times the program has run. Only counted if run time stats are enabled
+    #[serde(rename = "run_cnt")]
+    pub run_cnt: u64,
+
+    /// Total time spent in the program, in nanoseconds. Only counted if run time stats are enabled
+    #[serde(rename = "run_time_ns")]
+    pub run_time_ns: u64,
+
+    /// How many times the program was skipped due to recursion
+    #[serde(rename = "recursion_misses")]
+    pub recursion_misses: u64,
+
+    /// Count of instructions processed by the verifier
+    #[serde(rename = "verified_insns")]
+    pub verified_insns: u32,
+}
+
+impl ProgStats {
+    #[allow(clippy::new_without_default)]
+    pub fn new(
+        name: String,
+        run_cnt: u64,
+        run_time_ns: u64,
+        recursion_misses: u64,
+        verified_insns: u32,
+    ) -> ProgStats {
+        ProgStats {
+            name,
+            run_cnt,
+            run_time_ns,
+            recursion_misses,
+            verified_insns,
+        }
+    }
+}
+
+/// Converts the ProgStats value to the Query Parameters representation (style=form, explode=false)
+/// specified in https://swagger.io/docs/specification/serialization/
+/// Should be implemented in a serde serializer
+impl std::string::ToString for ProgStats {
+    fn to_string(&self) -> String {
+        let params: Vec<Option<String>> = vec![
+            Some("name".to_string()),
+            Some(self.name.to_string()),
+            Some("run_cnt".to_string()),
+            Some(self.run_cnt.to_string()),
+            Some("run_time_ns".to_string()),
+            Some(self.run_time_ns.to_string()),
+            Some("recursion_misses".to_string()),
+            Some(self.recursion_misses.to_string()),
+            Some("verified_insns".to_string()),
+            Some(self.verified_insns.to_string()),
+        ];
+
+        params.into_iter().flatten().collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Converts Query Parameters representation (style=form, explode=false) to a ProgStats value
+/// as specified in https://swagger.io/docs/specification/serialization/
+/// Should be implemented in a serde deserializer
+impl std::str::FromStr for ProgStats {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        /// An intermediate representation of the struct to use for parsing.
+        #[derive(Default)]
+        #[allow(dead_code)]
+        struct IntermediateRep {
+            pub name: Vec<String>,
+            pub run_cnt: Vec<u64>,
+            pub run_time_ns: Vec<u64>,
+            pub recursion_misses: Vec<u64>,
+            pub verified_insns: Vec<u32>,
+        }
+
+        let mut intermediate_rep = IntermediateRep::default();
+
+        // Parse into intermediate representation
+        let mut string_iter = s.split(',');
+        let mut key_result = string_iter.next();
+
+        while key_result.is_some() {
+            let val = match string_iter.next() {
+                Some(x) => x,
+                None => {
+                    return std::result::Result::Err(
+                        "Missing value while parsing ProgStats".to_string(),
+                    )
+                }
+            };
+
+            if let Some(key) = key_result {
+                #[allow(clippy::match_single_binding)]
+                match key {
+                    #[allow(clippy::redundant_clone)]
+                    "name" => intermediate_rep.name.push(
+                        <String as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "run_cnt" => intermediate_rep.run_cnt.push(
+                        <u64 as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "run_time_ns" => intermediate_rep.run_time_ns.push(
+                        <u64 as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "recursion_misses" => intermediate_rep.recursion_misses.push(
+                        <u64 as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "verified_insns" => intermediate_rep.verified_insns.push(
+                        <u32 as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    _ => {
+                        return std::result::Result::Err(
+                            "Unexpected key while parsing ProgStats".to_string(),
+                        )
+                    }
+                }
+            }
+
+            // Get the next key
+            key_result = string_iter.next();
+        }
+
+        // Use the intermediate representation to return the struct
+        std::result::Result::Ok(ProgStats {
+            name: intermediate_rep
+                .name
+                .into_iter()
+                .next()
+                .ok_or_else(|| "name missing in ProgStats".to_string())?,
+            run_cnt: intermediate_rep
+                .run_cnt
+                .into_iter()
+                .next()
+                .ok_or_else(|| "run_cnt missing in ProgStats".to_string())?,
+            run_time_ns: intermediate_rep
+                .run_time_ns
+                .into_iter()
+                .next()
+                .ok_or_else(|| "run_time_ns missing in ProgStats".to_string())?,
+            recursion_misses: intermediate_rep
+                .recursion_misses
+                .into_iter()
+                .next()
+                .ok_or_else(|| "recursion_misses missing in ProgStats".to_string())?,
+            verified_insns: intermediate_rep
+                .verified_insns
+                .into_iter()
+                .next()
+                .ok_or_else(|| "verified_insns missing in ProgStats".to_string())?,
+        })
+    }
+}
+
+// Methods for converting between header::IntoHeaderValue<ProgStats> and hyper::header::HeaderValue
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl std::convert::TryFrom<header::IntoHeaderValue<ProgStats>> for hyper::header::HeaderValue {
+    type Error = String;
+
+    fn try_from(
+        hdr_value: header::IntoHeaderValue<ProgStats>,
+    ) -> std::result::Result<Self, Self::Error> {
+        let hdr_value = hdr_value.to_string();
+        match hyper::header::HeaderValue::from_str(&hdr_value) {
+            std::result::Result::Ok(value) => std::result::Result::Ok(value),
+            std::result::Result::Err(e) => std::result::Result::Err(format!(
+                "Invalid header value for ProgStats - value: {} is invalid {}",
+                hdr_value, e
+            )),
+        }
+    }
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl std::convert::TryFrom<hyper::header::HeaderValue> for header::IntoHeaderValue<ProgStats> {
+    type Error = String;
+
+    fn try_from(hdr_value: hyper::header::HeaderValue) -> std::result::Result<Self, Self::Error> {
+        match hdr_value.to_str() {
+            std::result::Result::Ok(value) => {
+                match <ProgStats as std::str::FromStr>::from_str(value) {
+                    std::result::Result::Ok(value) => {
+                        std::result::Result::Ok(header::IntoHeaderValue(value))
+                    }
+                    std::result::Result::Err(err) => std::result::Result::Err(format!(
+                        "Unable to convert header value '{}' into ProgStats - {}",
+                        value, err
+                    )),
+                }
+            }
+            std::result::Result::Err(e) => std::result::Result::Err(format!(
+                "Unable to convert header: {:?} to string: {}",
+                hdr_value, e
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "conversion", derive(frunk::LabelledGeneric))]
+pub struct ReplaceTaskRequest {
+    /// The ID of the task to replace
+    #[serde(rename = "id")]
+    pub id: u64,
+
+    /// Base64-encoded program data
+    #[serde(rename = "program_data_buf")]
+    pub program_data_buf: String,
+
+    #[serde(rename = "program_type")]
+    pub program_type: models::ProgramType,
+
+    /// The new name of the task. If not provided, the old one will be used
+    #[serde(rename = "program_name")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub program_name: Option<String>,
+
+    /// Btf archive path in the server.
+    #[serde(rename = "btf_archive_path")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub btf_archive_path: Option<String>,
+
+    /// Command line arguments to the eBPF program
+    #[serde(rename = "extra_args")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extra_args: Option<Vec<String>>,
+
+    /// Whether to let the bpf-loader program dumps json. Only works for json & tar program
+    #[serde(rename = "export_json")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub export_json: Option<bool>,
+}
+
+impl ReplaceTaskRequest {
+    #[allow(clippy::new_without_default)]
+    pub fn new(
+        id: u64,
+        program_data_buf: String,
+        program_type: models::ProgramType,
+    ) -> ReplaceTaskRequest {
+        ReplaceTaskRequest {
+            id,
+            program_data_buf,
+            program_type,
+            program_name: None,
+            btf_archive_path: None,
+            extra_args: None,
+            export_json: None,
+        }
+    }
+}
+
+/// Converts the ReplaceTaskRequest value to the Query Parameters representation (style=form, explode=false)
+/// specified in https://swagger.io/docs/specification/serialization/
+/// Should be implemented in a serde serializer
+impl std::string::ToString for ReplaceTaskRequest {
+    fn to_string(&self) -> String {
+        let params: Vec<Option<String>> = vec![
+            Some("id".to_string()),
+            Some(self.id.to_string()),
+            Some("program_data_buf".to_string()),
+            Some(self.program_data_buf.to_string()),
+            // Skipping program_type in query parameter serialization
+            self.program_name.as_ref().map(|program_name| {
+                vec!["program_name".to_string(), program_name.to_string()].join(",")
+            }),
+            self.btf_archive_path.as_ref().map(|btf_archive_path| {
+                vec!["btf_archive_path".to_string(), btf_archive_path.to_string()].join(",")
+            }),
+            self.extra_args.as_ref().map(|extra_args| {
+                vec![
+                    "extra_args".to_string(),
+                    extra_args
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ]
+                .join(",")
+            }),
+            self.export_json.as_ref().map(|export_json| {
+                vec!["export_json".to_string(), export_json.to_string()].join(",")
+            }),
+        ];
+
+        params.into_iter().flatten().collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Converts Query Parameters representation (style=form, explode=false) to a ReplaceTaskRequest value
+/// as specified in https://swagger.io/docs/specification/serialization/
+/// Should be implemented in a serde deserializer
+impl std::str::FromStr for ReplaceTaskRequest {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        /// An intermediate representation of the struct to use for parsing.
+        #[derive(Default)]
+        #[allow(dead_code)]
+        struct IntermediateRep {
+            pub id: Vec<u64>,
+            pub program_data_buf: Vec<String>,
+            pub program_type: Vec<models::ProgramType>,
+            pub program_name: Vec<String>,
+            pub btf_archive_path: Vec<String>,
+            pub extra_args: Vec<Vec<String>>,
+            pub export_json: Vec<bool>,
+        }
+
+        let mut intermediate_rep = IntermediateRep::default();
+
+        // Parse into intermediate representation
+        let mut string_iter = s.split(',');
+        let mut key_result = string_iter.next();
+
+        while key_result.is_some() {
+            let val = match string_iter.next() {
+                Some(x) => x,
+                None => {
+                    return std::result::Result::Err(
+                        "Missing value while parsing ReplaceTaskRequest".to_string(),
+                    )
+                }
+            };
+
+            if let Some(key) = key_result {
+                #[allow(clippy::match_single_binding)]
+                match key {
+                    #[allow(clippy::redundant_clone)]
+                    "id" => intermediate_rep.id.push(
+                        <u64 as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "program_data_buf" => intermediate_rep.program_data_buf.push(
+                        <String as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "program_type" => intermediate_rep.program_type.push(
+                        <models::ProgramType as std::str::FromStr>::from_str(val)
+                            .map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "program_name" => intermediate_rep.program_name.push(
+                        <String as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "btf_archive_path" => intermediate_rep.btf_archive_path.push(
+                        <String as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    "extra_args" => return std::result::Result::Err(
+                        "Parsing a container in this style is not supported in ReplaceTaskRequest"
+                            .to_string(),
+                    ),
+                    #[allow(clippy::redundant_clone)]
+                    "export_json" => intermediate_rep.export_json.push(
+                        <bool as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
+                    ),
+                    _ => {
+                        return std::result::Result::Err(
+                            "Unexpected key while parsing ReplaceTaskRequest".to_string(),
+                        )
+                    }
+                }
+            }
+
+            // Get the next key
+            key_result = string_iter.next();
+        }
+
+        // Use the intermediate representation to return the struct
+        std::result::Result::Ok(ReplaceTaskRequest {
+            id: intermediate_rep
+                .id
+                .into_iter()
+                .next()
+                .ok_or_else(|| "id missing in ReplaceTaskRequest".to_string())?,
+            program_data_buf: intermediate_rep
+                .program_data_buf
+                .into_iter()
+                .next()
+                .ok_or_else(|| "program_data_buf missing in ReplaceTaskRequest".to_string())?,
+            program_type: intermediate_rep
+                .program_type
+                .into_iter()
+                .next()
+                .ok_or_else(|| "program_type missing in ReplaceTaskRequest".to_string())?,
+            program_name: intermediate_rep.program_name.into_iter().next(),
+            btf_archive_path: intermediate_rep.btf_archive_path.into_iter().next(),
+            extra_args: intermediate_rep.extra_args.into_iter().next(),
+            export_json: intermediate_rep.export_json.into_iter().next(),
+        })
+    }
+}
+
+// Methods for converting between header::IntoHeaderValue<ReplaceTaskRequest> and hyper::header::HeaderValue
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl std::convert::TryFrom<header::IntoHeaderValue<ReplaceTaskRequest>>
+    for hyper::header::HeaderValue
+{
+    type Error = String;
+
+    fn try_from(
+        hdr_value: header::IntoHeaderValue<ReplaceTaskRequest>,
+    ) -> std::result::Result<Self, Self::Error> {
+        let hdr_value = hdr_value.to_string();
+        match hyper::header::HeaderValue::from_str(&hdr_value) {
+            std::result::Result::Ok(value) => std::result::Result::Ok(value),
+            std::result::Result::Err(e) => std::result::Result::Err(format!(
+                "Invalid header value for ReplaceTaskRequest - value: {} is invalid {}",
+                hdr_value, e
+            )),
+        }
+    }
+}
+
+#[cfg(any(feature = "client", feature = "server"))]
+impl std::convert::TryFrom<hyper::header::HeaderValue>
+    for header::IntoHeaderValue<ReplaceTaskRequest>
+{
+    type Error = String;
+
+    fn try_from(hdr_value: hyper::header::HeaderValue) -> std::result::Result<Self, Self::Error> {
+        match hdr_value.to_str() {
+            std::result::Result::Ok(value) => {
+                match <ReplaceTaskRequest as std::str::FromStr>::from_str(value) {
+                    std::result::Result::Ok(value) => {
+                        std::result::Result::Ok(header::IntoHeaderValue(value))
+                    }
+                    std::result::Result::Err(err) => std::result::Result::Err(format!(
+                        "Unable to convert header value '{}' into ReplaceTaskRequest - {}",
+                        value, err
+                    )),
+                }
+            }
+            std::result::Result::Err(e) => std::result::Result::Err(format!(
+                "Unable to convert header: {:?} to string: {}",
+                hdr_value, e
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[cfg_attr(feature = "conversion", derive(frunk::LabelledGeneric))]
 pub struct SimpleIdRequest {
@@ -1375,12 +1831,22 @@ pub struct TaskListResponseTasksInner {
     /// The name of the task
     #[serde(rename = "name")]
     pub name: String,
+
+    /// Runtime statistics of the programs. Only provided for json & tar programs
+    #[serde(rename = "prog_stats")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prog_stats: Option<Vec<models::ProgStats>>,
 }
 
 impl TaskListResponseTasksInner {
     #[allow(clippy::new_without_default)]
     pub fn new(status: models::TaskStatus, id: u64, name: String) -> TaskListResponseTasksInner {
-        TaskListResponseTasksInner { status, id, name }
+        TaskListResponseTasksInner {
+            status,
+            id,
+            name,
+            prog_stats: None,
+        }
     }
 }
 
@@ -1395,6 +1861,7 @@ impl std::string::ToString for TaskListResponseTasksInner {
             Some(self.id.to_string()),
             Some("name".to_string()),
             Some(self.name.to_string()),
+            // Skipping prog_stats in query parameter serialization
         ];
 
         params.into_iter().flatten().collect::<Vec<_>>().join(",")
@@ -1415,6 +1882,7 @@ impl std::str::FromStr for TaskListResponseTasksInner {
             pub status: Vec<models::TaskStatus>,
             pub id: Vec<u64>,
             pub name: Vec<String>,
+            pub prog_stats: Vec<Vec<models::ProgStats>>,
         }
 
         let mut intermediate_rep = IntermediateRep::default();
@@ -1449,6 +1917,10 @@ impl std::str::FromStr for TaskListResponseTasksInner {
                     "name" => intermediate_rep.name.push(
                         <String as std::str::FromStr>::from_str(val).map_err(|x| x.to_string())?,
                     ),
+                    "prog_stats" => return std::result::Result::Err(
+                        "Parsing a container in this style is not supported in TaskListResponseTasksInner"
+                            .to_string(),
+                    ),
                     _ => {
                         return std::result::Result::Err(
                             "Unexpected key while parsing TaskListResponseTasksInner".to_string(),
@@ -1478,6 +1950,7 @@ impl std::str::FromStr for TaskListResponseTasksInner {
                 .into_iter()
                 .next()
                 .ok_or_else(|| "name missing in TaskListResponseTasksInner".to_string())?,
+            prog_stats: intermediate_rep.prog_stats.into_iter().next(),
         })
     }
 }
@@ -23,7 +23,7 @@ type ServiceFuture = BoxFuture<'static, Result<Response<Body>, crate::ServiceErr
 
 use crate::{
     Api, GetTaskListResponse, GetTaskLogByIdResponse, PauseTaskByIdResponse,
-    ResumeTaskByIdResponse, StartTaskResponse, StopTaskByIdResponse,
+    ReplaceTaskByIdResponse, ResumeTaskByIdResponse, StartTaskResponse, StopTaskByIdResponse,
 };
 
 mod paths {
@@ -33,6 +33,7 @@ mod paths {
         pub static ref GLOBAL_REGEX_SET: regex::RegexSet = regex::RegexSet::new(vec![
             r"^/log$",
             r"^/pause$",
+            r"^/replace$",
             r"^/resume$",
             r"^/stop$",
             r"^/task$"
@@ -41,9 +42,10 @@ mod paths {
     }
     pub(crate) static ID_LOG: usize = 0;
     pub(crate) static ID_PAUSE: usize = 1;
-    pub(crate) static ID_RESUME: usize = 2;
-    pub(crate) static ID_STOP: usize = 3;
-    pub(crate) static ID_TASK: usize = 4;
+    pub(crate) static ID_REPLACE: usize = 2;
+    pub(crate) static ID_RESUME: usize = 3;
+    pub(crate) static ID_STOP: usize = 4;
+    pub(crate) static ID_TASK: usize = 5;
 }
 
 pub struct MakeService<T, C>
@@ -390,6 +392,108 @@ where
                         }
                 }
 
+                // ReplaceTaskById - POST /replace
+                hyper::Method::POST if path.matched(paths::ID_REPLACE) => {
+                    // Body parameters (note that non-required body parameters will ignore garbage
+                    // values, rather than causing a 400 response). Produce warning header and logs for
+                    // any unused fields.
+                    let result = body.into_raw().await;
+                    match result {
+                            Ok(body) => {
+                                let mut unused_elements = Vec::new();
+                                let param_replace_task_request: Option<models::ReplaceTaskRequest> = if !body.is_empty() {
+                                    let deserializer = &mut serde_json::Deserializer::from_slice(&*body);
+                                    match serde_ignored::deserialize(deserializer, |path| {
+                                            warn!("Ignoring unknown field in body: {}", path);
+                                            unused_elements.push(path.to_string());
+                                    }) {
+                                        Ok(param_replace_task_request) => param_replace_task_request,
+                                        Err(e) => return Ok(Response::builder()
+                                                        .status(StatusCode::BAD_REQUEST)
+                                                        .body(Body::from(format!("Couldn't parse body parameter ReplaceTaskRequest - doesn't match schema: {}", e)))
+                                                        .expect("Unable to create Bad Request response for invalid body parameter ReplaceTaskRequest due to schema")),
+                                    }
+                                } else {
+                                    None
+                                };
+                                let param_replace_task_request = match param_replace_task_request {
+                                    Some(param_replace_task_request) => param_replace_task_request,
+                                    None => return Ok(Response::builder()
+                                                        .status(StatusCode::BAD_REQUEST)
+                                                        .body(Body::from("Missing required body parameter ReplaceTaskRequest"))
+                                                        .expect("Unable to create Bad Request response for missing body parameter ReplaceTaskRequest")),
+                                };
+
+                                let result = api_impl.replace_task_by_id(
+                                            param_replace_task_request,
+                                        &context
+                                    ).await;
+                                let mut response = Response::new(Body::empty());
+                                response.headers_mut().insert(
+                                            HeaderName::from_static("x-span-id"),
+                                            HeaderValue::from_str((&context as &dyn Has<XSpanIdString>).get().0.clone().as_str())
+                                                .expect("Unable to create X-Span-ID header value"));
+
+                                        if !unused_elements.is_empty() {
+                                            response.headers_mut().insert(
+                                                HeaderName::from_static("warning"),
+                                                HeaderValue::from_str(format!("Ignoring unknown fields in body: {:?}", unused_elements).as_str())
+                                                    .expect("Unable to create Warning header value"));
+                                        }
+
+                                        match result {
+                                            Ok(rsp) => match rsp {
+                                                ReplaceTaskByIdResponse::StatusOfReplacingTheTask
+                                                    (body)
+                                                => {
+                                                    *response.status_mut() = StatusCode::from_u16(200).expect("Unable to turn 200 into a StatusCode");
+                                                    response.headers_mut().insert(
+                                                        CONTENT_TYPE,
+                                                        HeaderValue::from_str("application/json")
+                                                            .expect("Unable to create Content-Type header for REPLACE_TASK_BY_ID_STATUS_OF_REPLACING_THE_TASK"));
+                                                    let body = serde_json::to_string(&body).expect("impossible to fail to serialize");
+                                                    *response.body_mut() = Body::from(body);
+                                                },
+                                                ReplaceTaskByIdResponse::InvalidArguments
+                                                    (body)
+                                                => {
+                                                    *response.status_mut() = StatusCode::from_u16(400).expect("Unable to turn 400 into a StatusCode");
+                                                    response.headers_mut().insert(
+                                                        CONTENT_TYPE,
+                                                        HeaderValue::from_str("application/json")
+                                                            .expect("Unable to create Content-Type header for REPLACE_TASK_BY_ID_INVALID_ARGUMENTS"));
+                                                    let body = serde_json::to_string(&body).expect("impossible to fail to serialize");
+                                                    *response.body_mut() = Body::from(body);
+                                                },
+                                                ReplaceTaskByIdResponse::InvalidHandle
+                                                    (body)
+                                                => {
+                                                    *response.status_mut() = StatusCode::from_u16(404).expect("Unable to turn 404 into a StatusCode");
+                                                    response.headers_mut().insert(
+                                                        CONTENT_TYPE,
+                                                        HeaderValue::from_str("application/json")
+                                                            .expect("Unable to create Content-Type header for REPLACE_TASK_BY_ID_INVALID_HANDLE"));
+                                                    let body = serde_json::to_string(&body).expect("impossible to fail to serialize");
+                                                    *response.body_mut() = Body::from(body);
+                                                },
+                                            },
+                                            Err(_) => {
+                                                // Application code returned an error. This should not happen, as the implementation should
+                                                // return a valid response.
+                                                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                                                *response.body_mut() = Body::from("An internal error occurred");
+                                            },
+                                        }
+
+                                        Ok(response)
+                            },
+                            Err(e) => Ok(Response::builder()
+                                                .status(StatusCode::BAD_REQUEST)
+                                                .body(Body::from(format!("Couldn't read body parameter ReplaceTaskRequest: {}", e)))
+                                                .expect("Unable to create Bad Request response due to unable to read body parameter ReplaceTaskRequest")),
+                        }
+                }
+
                 // ResumeTaskById - POST /resume
                 hyper::Method::POST if path.matched(paths::ID_RESUME) => {
                     // Body parameters (note that non-required body parameters will ignore garbage
@@ -687,6 +791,7 @@ where
 
                 _ if path.matched(paths::ID_LOG) => method_not_allowed(),
                 _ if path.matched(paths::ID_PAUSE) => method_not_allowed(),
+                _ if path.matched(paths::ID_REPLACE) => method_not_allowed(),
                 _ if path.matched(paths::ID_RESUME) => method_not_allowed(),
                 _ if path.matched(paths::ID_STOP) => method_not_allowed(),
                 _ if path.matched(paths::ID_TASK) => method_not_allowed(),
@@ -712,6 +817,8 @@ impl<T> RequestParser<T> for ApiRequestParser {
             hyper::Method::POST if path.matched(paths::ID_LOG) => Some("GetTaskLogById"),
             // PauseTaskById - POST /pause
             hyper::Method::POST if path.matched(paths::ID_PAUSE) => Some("PauseTaskById"),
+            // ReplaceTaskById - POST /replace
+            hyper::Method::POST if path.matched(paths::ID_REPLACE) => Some("ReplaceTaskById"),
             // ResumeTaskById - POST /resume
             hyper::Method::POST if path.matched(paths::ID_RESUME) => Some("ResumeTaskById"),
             // StartTask - POST /task